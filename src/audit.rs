@@ -0,0 +1,147 @@
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Output};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static AUDIT_LOG: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+const SECRET_FLAGS: [&str; 5] = [
+    "--captcha",
+    "--pin",
+    "--password",
+    "--token",
+    "--passphrase",
+];
+const MASK: &str = "***";
+
+pub fn set_audit_log(path: Option<PathBuf>) -> Result<()> {
+    if let Some(path) = &path {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("failed to open audit log {}", path.display()))?;
+    }
+    *AUDIT_LOG
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = path;
+    Ok(())
+}
+
+pub trait AuditedCommand {
+    fn audited_status(&mut self) -> io::Result<ExitStatus>;
+    fn audited_output(&mut self) -> io::Result<Output>;
+    fn audited_spawn(&mut self) -> io::Result<Child>;
+}
+
+impl AuditedCommand for Command {
+    fn audited_status(&mut self) -> io::Result<ExitStatus> {
+        let result = self.status();
+        record_run(self, result.as_ref().map(|status| *status));
+        result
+    }
+
+    fn audited_output(&mut self) -> io::Result<Output> {
+        let result = self.output();
+        record_run(self, result.as_ref().map(|output| output.status));
+        result
+    }
+
+    fn audited_spawn(&mut self) -> io::Result<Child> {
+        let result = self.spawn();
+        let mut entry = command_entry(self, "spawn");
+        match &result {
+            Ok(child) => entry["pid"] = Value::from(child.id()),
+            Err(err) => entry["error"] = Value::from(err.to_string()),
+        }
+        append(entry);
+        result
+    }
+}
+
+/// Matched to the spawn entry by pid.
+pub fn record_exit(pid: u32, status: &ExitStatus) {
+    append(json!({
+        "time": unix_time(),
+        "event": "exit",
+        "pid": pid,
+        "exit": status.code(),
+    }));
+}
+
+fn record_run(cmd: &Command, result: Result<ExitStatus, &io::Error>) {
+    let mut entry = command_entry(cmd, "run");
+    match result {
+        Ok(status) => entry["exit"] = json!(status.code()),
+        Err(err) => entry["error"] = Value::from(err.to_string()),
+    }
+    append(entry);
+}
+
+fn command_entry(cmd: &Command, event: &str) -> Value {
+    let args: Vec<String> = cmd
+        .get_args()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    json!({
+        "time": unix_time(),
+        "event": event,
+        "program": cmd.get_program().to_string_lossy(),
+        "args": mask_args(&args),
+    })
+}
+
+pub fn mask_args(args: &[String]) -> Vec<String> {
+    let mut masked = Vec::with_capacity(args.len());
+    let mut mask_next = false;
+    for arg in args {
+        if mask_next {
+            masked.push(MASK.to_string());
+            mask_next = false;
+            continue;
+        }
+        mask_next = SECRET_FLAGS.contains(&arg.as_str()) || arg == "verify" || arg == "setPin";
+        let lower = arg.to_ascii_lowercase();
+        if let Some((flag, _)) = arg
+            .split_once('=')
+            .filter(|(flag, _)| SECRET_FLAGS.contains(flag))
+        {
+            masked.push(format!("{flag}={MASK}"));
+        } else if lower.starts_with("authorization:") {
+            masked.push(format!("Authorization: {MASK}"));
+        } else if lower.starts_with("signalcaptcha://") || looks_like_captcha_token(arg) {
+            masked.push(MASK.to_string());
+        } else {
+            masked.push(arg.clone());
+        }
+    }
+    masked
+}
+
+fn looks_like_captcha_token(arg: &str) -> bool {
+    arg.starts_with("signal-") && arg.matches('.').count() >= 3
+}
+
+fn append(entry: Value) {
+    let path = AUDIT_LOG
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone();
+    let Some(path) = path else {
+        return;
+    };
+    if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(&path) {
+        let _ = writeln!(file, "{entry}");
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,313 @@
+#[cfg(target_os = "macos")]
+use anyhow::Context;
+use anyhow::{bail, Result};
+use std::fs::File;
+use std::io::Write;
+use std::os::unix::io::FromRawFd;
+use std::path::{Path, PathBuf};
+#[cfg(target_os = "macos")]
+use std::process::Command;
+
+#[cfg(target_os = "macos")]
+use crate::audit::AuditedCommand;
+use crate::bundle::account_registered;
+use crate::config::Config;
+use crate::docker::{linked_devices, runtime_is_ready, signal_cli_version, LinkedDevice};
+use crate::link_uri::percent_decode;
+use crate::vault::VaultPaths;
+
+pub const URL_SCHEME: &str = "x-signal-setup";
+pub const PORCELAIN_VERSION: u32 = 1;
+/// Any web page can open an x-signal-setup:// URL, so results only go back to these apps.
+pub const CALLBACK_SCHEMES: [&str; 3] = ["shortcuts", "alfred", "raycast"];
+
+pub type Fields = Vec<(String, String)>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutomationAction {
+    CaptchaToken,
+    LinkDesktop,
+    Status,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AutomationRequest {
+    pub action: AutomationAction,
+    pub account: Option<String>,
+    pub x_success: Option<String>,
+    pub x_error: Option<String>,
+}
+
+pub fn parse_automation_url(url: &str) -> Result<AutomationRequest> {
+    let Some(rest) = url.trim().strip_prefix(&format!("{URL_SCHEME}://")) else {
+        bail!("expected a {URL_SCHEME}:// URL")
+    };
+    let (action, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let action = match action.trim_end_matches('/') {
+        "captcha" | "captcha-token" => AutomationAction::CaptchaToken,
+        "link" | "link-desktop" => AutomationAction::LinkDesktop,
+        "status" => AutomationAction::Status,
+        other => bail!("unknown {URL_SCHEME} action '{other}' (expected captcha, link or status)"),
+    };
+
+    let mut request = AutomationRequest {
+        action,
+        account: None,
+        x_success: None,
+        x_error: None,
+    };
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = percent_decode(value)?;
+        match name {
+            "account" => request.account = Some(value),
+            "x-success" => request.x_success = Some(check_callback(name, value)?),
+            "x-error" => request.x_error = Some(check_callback(name, value)?),
+            _ => {}
+        }
+    }
+    Ok(request)
+}
+
+fn check_callback(name: &str, url: String) -> Result<String> {
+    let scheme = url.split_once("://").map(|(scheme, _)| scheme);
+    if scheme.is_some_and(|scheme| {
+        CALLBACK_SCHEMES
+            .iter()
+            .any(|allowed| scheme.eq_ignore_ascii_case(allowed))
+    }) {
+        return Ok(url);
+    }
+    bail!("{name} must be a shortcuts://, alfred:// or raycast:// URL, not '{url}'")
+}
+
+/// What to ask before acting on a URL; None for the status of the configured account.
+pub fn confirmation_prompt(request: &AutomationRequest) -> Option<String> {
+    let account = request
+        .account
+        .as_deref()
+        .map(|account| format!(" for {account}"))
+        .unwrap_or_default();
+    match request.action {
+        AutomationAction::LinkDesktop => Some(format!(
+            "An {URL_SCHEME}:// link asks to link a new Signal Desktop{account}. Allow it?"
+        )),
+        AutomationAction::CaptchaToken => Some(format!(
+            "An {URL_SCHEME}:// link asks to open the Signal captcha window{account}. Allow it?"
+        )),
+        AutomationAction::Status if request.account.is_some() => Some(format!(
+            "An {URL_SCHEME}:// link asks for the status{account}. Allow it?"
+        )),
+        AutomationAction::Status => None,
+    }
+}
+
+/// The captcha token registers a number, so a callback only gets the path of a file holding it.
+pub fn callback_fields(fields: &[(String, String)]) -> Result<Fields> {
+    fields
+        .iter()
+        .map(|(key, value)| match key.as_str() {
+            "token" => Ok(field(
+                "tokenFile",
+                write_token_file(value)?.display().to_string(),
+            )),
+            _ => Ok((key.clone(), value.clone())),
+        })
+        .collect()
+}
+
+/// Readable by this user only, and left for the shortcut to read and delete.
+pub fn write_token_file(token: &str) -> Result<PathBuf> {
+    let mut file = tempfile::Builder::new()
+        .prefix("signal-setup-captcha-")
+        .suffix(".txt")
+        .tempfile()?;
+    writeln!(file, "{token}")?;
+    let (_, path) = file.keep()?;
+    Ok(path)
+}
+
+pub fn callback_url(base: &str, fields: &[(String, String)]) -> String {
+    let query = fields
+        .iter()
+        .map(|(key, value)| format!("{}={}", percent_encode(key), percent_encode(value)))
+        .collect::<Vec<_>>()
+        .join("&");
+    let separator = if base.contains('?') { '&' } else { '?' };
+    format!("{base}{separator}{query}")
+}
+
+pub fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+pub fn render_porcelain(fields: &[(String, String)]) -> String {
+    let mut out = format!("porcelain={PORCELAIN_VERSION}\n");
+    for (key, value) in fields {
+        out.push_str(&format!("{key}={}\n", value.replace('\n', "\\n")));
+    }
+    out
+}
+
+/// Keeps the real stdout for the porcelain contract and sends everything else to stderr.
+pub struct Porcelain {
+    out: File,
+}
+
+impl Porcelain {
+    pub fn begin() -> Result<Self> {
+        std::io::stdout().flush()?;
+        let saved = unsafe { libc::dup(libc::STDOUT_FILENO) };
+        if saved < 0 {
+            bail!("failed to set up porcelain output")
+        }
+        if unsafe { libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) } < 0 {
+            unsafe { libc::close(saved) };
+            bail!("failed to set up porcelain output")
+        }
+        Ok(Porcelain {
+            out: unsafe { File::from_raw_fd(saved) },
+        })
+    }
+
+    pub fn emit(&mut self, fields: &[(String, String)]) -> Result<()> {
+        std::io::stdout().flush()?;
+        self.out.write_all(render_porcelain(fields).as_bytes())?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+pub fn field(key: &str, value: impl ToString) -> (String, String) {
+    (key.to_string(), value.to_string())
+}
+
+pub fn status_fields(cfg: &Config) -> Fields {
+    let mut fields = vec![
+        field("account", &cfg.account),
+        field("data_dir", cfg.data_dir.display()),
+    ];
+
+    let ready = match &cfg.daemon_addr {
+        Some(addr) => {
+            fields.push(field("runtime", "daemon"));
+            fields.push(field("daemon_addr", addr));
+            signal_cli_version(cfg).is_ok()
+        }
+        None => {
+            fields.push(field("runtime", cfg.runtime.as_str()));
+            runtime_is_ready(cfg.runtime).unwrap_or(false)
+        }
+    };
+    fields.push(field("runtime_ready", ready));
+
+    // Reading an encrypted data dir would prompt for the passphrase, so status reports it as is.
+    let account_data = if VaultPaths::for_data_dir(&cfg.data_dir).exists() {
+        "encrypted"
+    } else if account_registered(&cfg.data_dir, &cfg.account) {
+        "present"
+    } else {
+        "missing"
+    };
+    fields.push(field("account_data", account_data));
+
+    let can_query = cfg.daemon_addr.is_some() || account_data == "present";
+    if ready && can_query {
+        if let Some(devices) = linked_devices(cfg) {
+            fields.push(field("linked_devices", devices.len()));
+            let names: Vec<String> = devices
+                .iter()
+                .filter(|device| device.id != crate::PRIMARY_DEVICE_ID)
+                .map(LinkedDevice::named)
+                .collect();
+            if !names.is_empty() {
+                fields.push(field("desktop_devices", names.join(", ")));
+            }
+        }
+    }
+    fields
+}
+
+/// The URL handler app runs without a terminal, so it asks in a dialog instead.
+#[cfg(target_os = "macos")]
+pub fn confirm_in_dialog(prompt: &str) -> Result<bool> {
+    let escaped = prompt.replace('\\', "\\\\").replace('"', "\\\"");
+    let status = Command::new("osascript")
+        .arg("-e")
+        .arg(format!(
+            "display dialog \"{escaped}\" with title \"Signal Setup\" buttons {{\"Deny\", \"Allow\"}} default button \"Deny\" cancel button \"Deny\""
+        ))
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .audited_status()
+        .context("failed to run osascript")?;
+    Ok(status.success())
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn confirm_in_dialog(_prompt: &str) -> Result<bool> {
+    bail!("{URL_SCHEME}:// links that link a device or open the captcha window must be confirmed in a terminal")
+}
+
+#[cfg(target_os = "macos")]
+pub fn install_url_handler(exe: &Path) -> Result<PathBuf> {
+    let mut app = dirs::home_dir().context("could not resolve the home directory")?;
+    app.push("Applications");
+    std::fs::create_dir_all(&app)?;
+    app.push("Signal Setup URL Handler.app");
+
+    let command = format!(
+        "do shell script quoted form of \"{}\" & \" open-url \" & quoted form of this_URL",
+        exe.display().to_string().replace('"', "\\\"")
+    );
+    let status = Command::new("osacompile")
+        .arg("-o")
+        .arg(&app)
+        .arg("-e")
+        .arg("on open location this_URL")
+        .arg("-e")
+        .arg(command)
+        .arg("-e")
+        .arg("end open location")
+        .audited_status()
+        .context("failed to run osacompile")?;
+    if !status.success() {
+        bail!("osacompile failed to build the URL handler app")
+    }
+
+    let plist = app.join("Contents").join("Info.plist");
+    let url_types = format!(
+        "[{{\"CFBundleURLName\":\"Signal Setup\",\"CFBundleURLSchemes\":[\"{URL_SCHEME}\"]}}]"
+    );
+    let status = Command::new("plutil")
+        .arg("-replace")
+        .arg("CFBundleURLTypes")
+        .arg("-json")
+        .arg(url_types)
+        .arg(&plist)
+        .audited_status()
+        .context("failed to run plutil")?;
+    if !status.success() {
+        bail!(
+            "failed to register the {URL_SCHEME} scheme in {}",
+            plist.display()
+        )
+    }
+
+    // Launch Services picks up the scheme once the app has been opened.
+    let _ = Command::new("open").arg("-g").arg(&app).audited_status();
+    Ok(app)
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn install_url_handler(_exe: &Path) -> Result<PathBuf> {
+    bail!("the {URL_SCHEME}:// handler can only be installed on macOS")
+}
@@ -0,0 +1,236 @@
+use anyhow::{bail, Context, Result};
+use std::io::IsTerminal;
+use std::process::Command;
+
+use crate::audit::AuditedCommand;
+use crate::runtime::ContainerRuntime;
+use crate::system::{command_exists, signal_desktop_installed};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageManager {
+    Homebrew,
+    Apt,
+}
+
+impl PackageManager {
+    pub fn detect() -> Option<Self> {
+        if cfg!(target_os = "macos") {
+            command_exists("brew").then_some(PackageManager::Homebrew)
+        } else {
+            command_exists("apt-get").then_some(PackageManager::Apt)
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PackageManager::Homebrew => "Homebrew",
+            PackageManager::Apt => "apt",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Prerequisite {
+    Docker,
+    Nerdctl,
+    SignalDesktop,
+}
+
+impl Prerequisite {
+    pub fn for_runtime(runtime: ContainerRuntime) -> Self {
+        match runtime {
+            ContainerRuntime::Docker => Prerequisite::Docker,
+            ContainerRuntime::Nerdctl => Prerequisite::Nerdctl,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Prerequisite::Docker => "Docker",
+            Prerequisite::Nerdctl => "nerdctl",
+            Prerequisite::SignalDesktop => "Signal Desktop",
+        }
+    }
+
+    fn is_installed(self) -> bool {
+        match self {
+            Prerequisite::Docker => ContainerRuntime::Docker.is_installed(),
+            Prerequisite::Nerdctl => ContainerRuntime::Nerdctl.is_installed(),
+            Prerequisite::SignalDesktop => signal_desktop_installed(),
+        }
+    }
+}
+
+pub fn missing_prerequisites(runtime: ContainerRuntime) -> Vec<Prerequisite> {
+    [
+        Prerequisite::for_runtime(runtime),
+        Prerequisite::SignalDesktop,
+    ]
+    .into_iter()
+    .filter(|prerequisite| !prerequisite.is_installed())
+    .collect()
+}
+
+/// On macOS Docker comes as Colima, which needs no license and starts without a GUI.
+pub fn install_steps(prerequisite: Prerequisite, manager: PackageManager) -> Vec<Vec<String>> {
+    let brew = |args: &[&str]| {
+        std::iter::once("brew")
+            .chain(args.iter().copied())
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+    match (prerequisite, manager) {
+        (Prerequisite::Docker, PackageManager::Homebrew) => {
+            vec![brew(&["install", "colima", "docker"])]
+        }
+        (Prerequisite::Nerdctl, PackageManager::Homebrew) => vec![brew(&["install", "lima"])],
+        (Prerequisite::SignalDesktop, PackageManager::Homebrew) => {
+            vec![brew(&["install", "--cask", "signal"])]
+        }
+        (Prerequisite::Docker, PackageManager::Apt) => vec![
+            privileged(&["apt-get", "update"]),
+            privileged(&["apt-get", "install", "-y", "docker.io"]),
+        ],
+        (Prerequisite::Nerdctl, PackageManager::Apt) => vec![
+            privileged(&["apt-get", "update"]),
+            privileged(&["apt-get", "install", "-y", "nerdctl"]),
+        ],
+        (Prerequisite::SignalDesktop, PackageManager::Apt) => vec![
+            privileged(&[
+                "sh",
+                "-c",
+                &format!(
+                    "curl -fsSL {} | gpg --dearmor --yes -o {}",
+                    crate::SIGNAL_DESKTOP_APT_KEY_URL,
+                    crate::SIGNAL_DESKTOP_APT_KEYRING
+                ),
+            ]),
+            privileged(&[
+                "sh",
+                "-c",
+                &format!(
+                    "echo '{}' > {}",
+                    crate::SIGNAL_DESKTOP_APT_SOURCE,
+                    crate::SIGNAL_DESKTOP_APT_SOURCE_LIST
+                ),
+            ]),
+            privileged(&["apt-get", "update"]),
+            privileged(&["apt-get", "install", "-y", "signal-desktop"]),
+        ],
+    }
+}
+
+fn privileged(args: &[&str]) -> Vec<String> {
+    let root = unsafe { libc::geteuid() } == 0;
+    (!root)
+        .then_some("sudo")
+        .into_iter()
+        .chain(args.iter().copied())
+        .map(str::to_string)
+        .collect()
+}
+
+pub fn run_bootstrap(runtime: ContainerRuntime, assume_yes: bool) -> Result<()> {
+    let missing = missing_prerequisites(runtime);
+    if missing.is_empty() {
+        println!(
+            "{} and Signal Desktop are already installed.",
+            Prerequisite::for_runtime(runtime).name()
+        );
+        return Ok(());
+    }
+    let Some(manager) = PackageManager::detect() else {
+        let names: Vec<&str> = missing.iter().map(|missing| missing.name()).collect();
+        bail!(
+            "{} missing and no supported package manager was found ({}); install {} manually and retry",
+            names.join(" and "),
+            if cfg!(target_os = "macos") {
+                "Homebrew, see https://brew.sh"
+            } else {
+                "apt-get"
+            },
+            if names.len() == 1 { "it" } else { "them" }
+        )
+    };
+    let mut skipped = Vec::new();
+    for prerequisite in missing {
+        if !install(prerequisite, manager, assume_yes)? {
+            skipped.push(prerequisite.name());
+        }
+    }
+    if !skipped.is_empty() {
+        bail!(
+            "{} still missing; run 'bootstrap' again or install it manually",
+            skipped.join(" and ")
+        )
+    }
+    Ok(())
+}
+
+/// Does nothing without a terminal or a package manager.
+pub fn offer_runtime_install(runtime: ContainerRuntime) -> Result<()> {
+    if runtime.is_installed() || !std::io::stdin().is_terminal() {
+        return Ok(());
+    }
+    let Some(manager) = PackageManager::detect() else {
+        return Ok(());
+    };
+    install(Prerequisite::for_runtime(runtime), manager, false)?;
+    Ok(())
+}
+
+pub fn install(
+    prerequisite: Prerequisite,
+    manager: PackageManager,
+    assume_yes: bool,
+) -> Result<bool> {
+    let steps = install_steps(prerequisite, manager);
+    println!(
+        "{} is not installed. {} can install it with:",
+        prerequisite.name(),
+        manager.name()
+    );
+    for step in &steps {
+        println!("  {}", step.join(" "));
+    }
+    if !assume_yes && !confirm_install(prerequisite)? {
+        println!("Skipped {}.", prerequisite.name());
+        return Ok(false);
+    }
+    for step in &steps {
+        let Some((program, args)) = step.split_first() else {
+            continue;
+        };
+        let status = Command::new(program)
+            .args(args)
+            .audited_status()
+            .with_context(|| format!("failed to run {program}"))?;
+        if !status.success() {
+            bail!(
+                "'{}' failed ({status}); install {} manually and retry",
+                step.join(" "),
+                prerequisite.name()
+            )
+        }
+    }
+    println!("Installed {}.", prerequisite.name());
+    Ok(true)
+}
+
+#[cfg(not(test))]
+fn confirm_install(prerequisite: Prerequisite) -> Result<bool> {
+    crate::system::require_terminal(
+        &format!("Install {}?", prerequisite.name()),
+        "Pass --yes to install without asking.",
+    )?;
+    crate::ui::confirm(
+        &crate::ui::theme(),
+        &format!("Install {} now?", prerequisite.name()),
+        true,
+    )
+}
+
+#[cfg(test)]
+fn confirm_install(_prerequisite: Prerequisite) -> Result<bool> {
+    Ok(false)
+}
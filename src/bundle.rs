@@ -0,0 +1,290 @@
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempfile::TempDir;
+
+use crate::config::Config;
+use crate::vault::{pipe_commands, require_age};
+
+const MANIFEST_FILE: &str = "manifest.json";
+const ACCOUNTS_FILE: &str = "data/accounts.json";
+
+pub fn export_account(cfg: &Config, output: &Path, force: bool) -> Result<()> {
+    require_age()?;
+    if output.exists() && !force {
+        bail!(
+            "{} already exists (use --force to overwrite)",
+            output.display()
+        )
+    }
+
+    let accounts = read_accounts(&cfg.data_dir)?;
+    let Some(entry) = find_account(&accounts, &cfg.account) else {
+        bail!(
+            "account {} not found in {}",
+            cfg.account,
+            cfg.data_dir.display()
+        )
+    };
+    let account_path = account_path(&entry)?;
+    let members = account_members(&account_path);
+    let files = list_files(&cfg.data_dir, &members)?;
+    if files.is_empty() {
+        bail!("no signal-cli data found for {}", cfg.account)
+    }
+
+    let staging = TempDir::new().context("failed to create export staging dir")?;
+    fs::create_dir_all(staging.path().join("data"))?;
+    let bundled_accounts = json!({
+        "accounts": [entry],
+        "version": accounts.get("version").cloned().unwrap_or(Value::from(2)),
+    });
+    write_json(&staging.path().join(ACCOUNTS_FILE), &bundled_accounts)?;
+    write_json(
+        &staging.path().join(MANIFEST_FILE),
+        &json!({
+            "account": cfg.account,
+            "path": account_path,
+            "files": files_to_json(&files),
+        }),
+    )?;
+
+    let mut archive = Command::new("tar");
+    archive
+        .arg("-cf")
+        .arg("-")
+        .arg("-C")
+        .arg(staging.path())
+        .arg(MANIFEST_FILE)
+        .arg(ACCOUNTS_FILE)
+        .arg("-C")
+        .arg(&cfg.data_dir);
+    for member in members.iter().filter(|m| cfg.data_dir.join(m).exists()) {
+        archive.arg(member);
+    }
+
+    println!("Choose a passphrase for the bundle.");
+    let mut encrypt = Command::new("age");
+    encrypt.arg("-p").arg("-o").arg(output);
+    pipe_commands(archive, encrypt).context("failed to write the account bundle")
+}
+
+pub fn import_account(cfg: &Config, bundle: &Path, force: bool) -> Result<String> {
+    require_age()?;
+    if !bundle.is_file() {
+        bail!("bundle not found: {}", bundle.display())
+    }
+
+    let staging = TempDir::new().context("failed to create import staging dir")?;
+    let mut decrypt = Command::new("age");
+    decrypt.arg("-d").arg(bundle);
+    let mut extract = Command::new("tar");
+    extract.arg("-xf").arg("-").arg("-C").arg(staging.path());
+    pipe_commands(decrypt, extract).context("failed to decrypt the account bundle")?;
+
+    let manifest = read_json(&staging.path().join(MANIFEST_FILE))?;
+    let account = manifest
+        .get("account")
+        .and_then(Value::as_str)
+        .context("bundle manifest has no account")?
+        .to_string();
+    if !cfg.account.is_empty() && cfg.account != account {
+        bail!(
+            "bundle contains {account}, not {} (omit --account to import it as is)",
+            cfg.account
+        )
+    }
+    verify_manifest(staging.path(), &manifest)?;
+
+    let bundled = read_json(&staging.path().join(ACCOUNTS_FILE))?;
+    let Some(entry) = find_account(&bundled, &account) else {
+        bail!("bundle accounts.json does not list {account}")
+    };
+    let account_path = account_path(&entry)?;
+
+    let mut accounts = match read_accounts(&cfg.data_dir) {
+        Ok(accounts) => accounts,
+        Err(_) if !cfg.data_dir.join(ACCOUNTS_FILE).exists() => {
+            json!({"accounts": [], "version": bundled.get("version").cloned().unwrap_or(Value::from(2))})
+        }
+        Err(err) => return Err(err),
+    };
+    let existing = accounts_list(&accounts);
+    if existing
+        .iter()
+        .any(|e| e.get("number").and_then(Value::as_str) == Some(account.as_str()))
+        && !force
+    {
+        bail!(
+            "{account} already exists in {} (use --force to replace it)",
+            cfg.data_dir.display()
+        )
+    }
+    if existing.iter().any(|e| {
+        e.get("path").and_then(Value::as_str) == Some(account_path.as_str())
+            && e.get("number").and_then(Value::as_str) != Some(account.as_str())
+    }) {
+        bail!(
+            "another account already uses data path '{account_path}' in {}",
+            cfg.data_dir.display()
+        )
+    }
+
+    for member in account_members(&account_path) {
+        let target = cfg.data_dir.join(&member);
+        if target.is_dir() {
+            fs::remove_dir_all(&target)?;
+        } else if target.exists() {
+            fs::remove_file(&target)?;
+        }
+        let source = staging.path().join(&member);
+        if source.exists() {
+            copy_recursive(&source, &target)?;
+        }
+    }
+
+    let mut merged: Vec<Value> = existing
+        .into_iter()
+        .filter(|e| e.get("number").and_then(Value::as_str) != Some(account.as_str()))
+        .collect();
+    merged.push(entry);
+    accounts["accounts"] = Value::Array(merged);
+    write_json(&cfg.data_dir.join(ACCOUNTS_FILE), &accounts)?;
+    Ok(account)
+}
+
+fn verify_manifest(root: &Path, manifest: &Value) -> Result<()> {
+    let files = manifest
+        .get("files")
+        .and_then(Value::as_array)
+        .context("bundle manifest has no file list")?;
+    for file in files {
+        let name = file.get("name").and_then(Value::as_str).unwrap_or("");
+        let size = file.get("size").and_then(Value::as_u64);
+        let path = root.join(name);
+        let actual = fs::metadata(&path).map(|m| m.len()).ok();
+        if name.is_empty() || name.contains("..") || actual.is_none() || actual != size {
+            bail!("bundle is incomplete or corrupted: {name}")
+        }
+    }
+    Ok(())
+}
+
+pub fn account_registered(data_dir: &Path, number: &str) -> bool {
+    read_accounts(data_dir)
+        .map(|accounts| find_account(&accounts, number).is_some())
+        .unwrap_or(false)
+}
+
+pub fn registered_accounts(data_dir: &Path) -> Vec<String> {
+    read_accounts(data_dir)
+        .map(|accounts| {
+            accounts_list(&accounts)
+                .iter()
+                .filter_map(|entry| entry.get("number").and_then(Value::as_str))
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn read_accounts(data_dir: &Path) -> Result<Value> {
+    read_json(&data_dir.join(ACCOUNTS_FILE))
+}
+
+fn accounts_list(accounts: &Value) -> Vec<Value> {
+    accounts
+        .get("accounts")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn find_account(accounts: &Value, number: &str) -> Option<Value> {
+    accounts_list(accounts)
+        .into_iter()
+        .find(|entry| entry.get("number").and_then(Value::as_str) == Some(number))
+}
+
+fn account_path(entry: &Value) -> Result<String> {
+    let path = entry
+        .get("path")
+        .and_then(Value::as_str)
+        .context("account entry has no data path")?;
+    if path.is_empty() || path.contains('/') || path.contains("..") {
+        bail!("unexpected account data path '{path}'")
+    }
+    Ok(path.to_string())
+}
+
+fn account_members(account_path: &str) -> [String; 2] {
+    [
+        format!("data/{account_path}"),
+        format!("data/{account_path}.d"),
+    ]
+}
+
+fn list_files(root: &Path, members: &[String]) -> Result<Vec<(String, u64)>> {
+    let mut files = Vec::new();
+    let mut pending: Vec<PathBuf> = members.iter().map(|m| root.join(m)).collect();
+    while let Some(path) = pending.pop() {
+        if path.is_dir() {
+            for entry in fs::read_dir(&path)? {
+                pending.push(entry?.path());
+            }
+        } else if path.is_file() {
+            let name = path
+                .strip_prefix(root)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .to_string();
+            files.push((name, fs::metadata(&path)?.len()));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn files_to_json(files: &[(String, u64)]) -> Value {
+    Value::Array(
+        files
+            .iter()
+            .map(|(name, size)| json!({"name": name, "size": size}))
+            .collect(),
+    )
+}
+
+fn copy_recursive(source: &Path, target: &Path) -> Result<()> {
+    if source.is_dir() {
+        fs::create_dir_all(target)
+            .with_context(|| format!("failed to create {}", target.display()))?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &target.join(entry.file_name()))?;
+        }
+        return Ok(());
+    }
+
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(source, target).with_context(|| format!("failed to copy {}", target.display()))?;
+    Ok(())
+}
+
+fn read_json(path: &Path) -> Result<Value> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("invalid JSON in {}", path.display()))
+}
+
+fn write_json(path: &Path, value: &Value) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let content = serde_json::to_string_pretty(value).context("failed to serialize JSON")?;
+    fs::write(path, format!("{content}\n"))
+        .with_context(|| format!("failed to write {}", path.display()))
+}
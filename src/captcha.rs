@@ -1,98 +1,458 @@
-use anyhow::Result;
 #[cfg(not(test))]
-use anyhow::{anyhow, bail, Context};
+use anyhow::anyhow;
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
 use dialoguer::theme::ColorfulTheme;
-#[cfg(not(test))]
-use dialoguer::Input;
-#[cfg(not(test))]
-use std::process::{Command, Stdio};
+use qrcode::render::unicode::Dense1x2;
+use qrcode::{EcLevel, QrCode};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use crate::audit::{self, AuditedCommand};
+use crate::automation::percent_encode;
+#[cfg(not(test))]
+use crate::config::Config;
 #[cfg(not(test))]
 use crate::docker::extract_signal_captcha_token_from_output;
+use crate::errors::SignalSetupError;
+use crate::link_uri::percent_decode;
+#[cfg(not(test))]
+use crate::net::{ensure_online, http_get};
+use crate::shutdown;
 #[cfg(not(test))]
-use crate::system::open_url_in_default_browser;
+use crate::system::{open_url_in_default_browser, require_terminal};
+#[cfg(not(test))]
+use crate::ui;
 
 #[cfg(not(test))]
-pub fn get_captcha_token_for_wizard(theme: &ColorfulTheme) -> Result<String> {
-    match capture_captcha_token_subprocess() {
+pub fn get_captcha_token_for_wizard(cfg: &Config, theme: &ColorfulTheme) -> Result<String> {
+    ensure_online()?;
+    let timeout = cfg
+        .captcha_timeout
+        .unwrap_or(Duration::from_secs(crate::CAPTCHA_TIMEOUT_SECS));
+    if let Some(problem) = webview_dependency_problem() {
+        eprintln!("{problem}");
+        return captcha_browser_fallback(theme);
+    }
+    match capture_captcha_token_subprocess(timeout).and_then(|token| parse_captcha_token(&token)) {
         Ok(token) => Ok(token),
+        // Ctrl+C and SIGTERM stop the wizard rather than switch to the browser.
+        Err(err) if is_captcha_cancelled(&err) => Err(err),
         Err(err) => {
             eprintln!("Embedded captcha capture failed: {err}");
-            eprintln!("Falling back to browser + manual token paste.");
-            open_url_in_default_browser(crate::CAPTCHA_URL);
-            let pasted: String = Input::with_theme(theme)
-                .with_prompt("Paste signalcaptcha:// token")
-                .interact_text()?;
-            if pasted.starts_with("signalcaptcha://") {
-                Ok(pasted)
-            } else {
-                bail!("invalid captcha token format")
-            }
+            captcha_browser_fallback(theme)
+        }
+    }
+}
+
+#[cfg(not(test))]
+pub fn captcha_browser_fallback(theme: &ColorfulTheme) -> Result<String> {
+    require_terminal(
+        "Paste signalcaptcha:// token",
+        "Solve the captcha elsewhere and pass it with register --token - or --token-file.",
+    )?;
+    eprintln!("Falling back to browser + manual token paste.");
+    open_url_in_default_browser(crate::CAPTCHA_URL);
+    eprintln!("If the browser does not open the token link, copy the page URL from the address bar instead.");
+    loop {
+        let pasted: String = ui::input(
+            theme,
+            "Paste signalcaptcha:// token or the captcha page URL",
+        )?;
+        match parse_captcha_token(&pasted) {
+            Ok(token) => return Ok(token),
+            Err(err) => eprintln!("{err}"),
         }
     }
 }
 
 #[cfg(test)]
-pub fn get_captcha_token_for_wizard(_theme: &ColorfulTheme) -> Result<String> {
+pub fn get_captcha_token_for_wizard(
+    _cfg: &crate::config::Config,
+    _theme: &ColorfulTheme,
+) -> Result<String> {
     Ok("signalcaptcha://test-token".to_string())
 }
 
+pub fn is_captcha_cancelled(err: &anyhow::Error) -> bool {
+    shutdown::is_terminated(err)
+        || matches!(
+            err.downcast_ref::<SignalSetupError>(),
+            Some(SignalSetupError::CaptchaInterrupted)
+        )
+}
+
 #[cfg(not(test))]
-pub fn capture_captcha_token_subprocess() -> Result<String> {
+pub fn capture_captcha_token_subprocess(timeout: Duration) -> Result<String> {
     let exe = std::env::current_exe().context("failed to resolve current executable path")?;
-    let output = Command::new(exe)
-        .arg("captcha-token")
-        .arg("--quiet")
-        .stdin(Stdio::inherit())
+    let mut cmd = Command::new(exe);
+    cmd.arg("captcha-token").arg("--quiet");
+    let stdout = supervise_captcha_child(cmd, timeout)?;
+    extract_signal_captcha_token_from_output(&stdout)
+}
+
+#[cfg(test)]
+pub fn capture_captcha_token_subprocess(_timeout: Duration) -> Result<String> {
+    Ok("signalcaptcha://test-subprocess-token".to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CaptchaStop {
+    Interrupted,
+    Terminated,
+    TimedOut,
+}
+
+/// Stopped after `timeout`, on Ctrl+C or on SIGTERM, so an unsolved window cannot block.
+pub fn supervise_captcha_child(mut cmd: Command, timeout: Duration) -> Result<Vec<u8>> {
+    cmd.stdin(Stdio::inherit())
         .stdout(Stdio::piped())
-        .stderr(Stdio::inherit())
-        .output()
+        .stderr(Stdio::piped());
+    let _interrupt = shutdown::catch_interrupt();
+    let mut child = cmd
+        .audited_spawn()
         .context("failed to spawn captcha-token subprocess")?;
+    let stdout = Arc::new(Mutex::new(Vec::<u8>::new()));
+    let stderr_tail = Arc::new(Mutex::new(VecDeque::<String>::new()));
+    let (done, readers_done) = mpsc::channel();
+    if let Some(mut out) = child.stdout.take() {
+        let stdout = stdout.clone();
+        let done = done.clone();
+        thread::spawn(move || {
+            let mut chunk = [0u8; 4096];
+            while let Ok(read @ 1..) = out.read(&mut chunk) {
+                lock(&stdout).extend_from_slice(chunk.get(..read).unwrap_or_default());
+            }
+            let _ = done.send(());
+        });
+    }
+    if let Some(err) = child.stderr.take() {
+        let tail = stderr_tail.clone();
+        let done = done.clone();
+        thread::spawn(move || {
+            tee_stderr_tail(err, &tail);
+            let _ = done.send(());
+        });
+    }
+    drop(done);
 
-    if !output.status.success() {
-        bail!(
-            "captcha-token subprocess failed with status {}",
-            output
-                .status
-                .code()
-                .map(|v| v.to_string())
-                .unwrap_or_else(|| "unknown".to_string())
-        );
+    let pid = child.id();
+    let deadline = Instant::now() + timeout;
+    let (status, stopped) = loop {
+        if let Some(status) = child
+            .try_wait()
+            .context("failed to wait for captcha-token subprocess")?
+        {
+            break (status, None);
+        }
+        let stop = if shutdown::interrupt_requested() {
+            Some(CaptchaStop::Interrupted)
+        } else if shutdown::terminate_requested() {
+            Some(CaptchaStop::Terminated)
+        } else if Instant::now() >= deadline {
+            Some(CaptchaStop::TimedOut)
+        } else {
+            None
+        };
+        if let Some(stop) = stop {
+            let signal = match stop {
+                CaptchaStop::Interrupted => libc::SIGINT,
+                CaptchaStop::Terminated | CaptchaStop::TimedOut => libc::SIGTERM,
+            };
+            break (stop_child(&mut child, signal)?, Some(stop));
+        }
+        thread::sleep(Duration::from_millis(crate::TERMINATE_POLL_MS));
+    };
+    audit::record_exit(pid, &status);
+    // Ctrl+C reaches the child too, which may exit before the loop saw the flag.
+    let stopped =
+        stopped.or_else(|| shutdown::interrupt_requested().then_some(CaptchaStop::Interrupted));
+    // WebKit helpers can outlive the window and keep the pipes open; do not wait on them.
+    let wait_readers = Instant::now() + Duration::from_millis(crate::CHILD_STOP_GRACE_MS);
+    for _ in 0..2 {
+        let left = wait_readers.saturating_duration_since(Instant::now());
+        if readers_done.recv_timeout(left).is_err() {
+            break;
+        }
     }
 
-    extract_signal_captcha_token_from_output(&output.stdout)
+    match stopped {
+        Some(CaptchaStop::Interrupted) => return Err(SignalSetupError::CaptchaInterrupted.into()),
+        Some(CaptchaStop::Terminated) => {
+            shutdown::check_terminated()?;
+        }
+        Some(CaptchaStop::TimedOut) => {
+            return Err(SignalSetupError::CaptchaTimedOut {
+                secs: timeout.as_secs(),
+            }
+            .into())
+        }
+        None => {}
+    }
+    if !status.success() {
+        let tail: Vec<String> = lock(&stderr_tail).iter().cloned().collect();
+        if let Some(err) = classify_captcha_failure(&tail.join("\n")) {
+            return Err(err.into());
+        }
+        match status.signal() {
+            Some(signal) => bail!("captcha-token subprocess was killed by signal {signal}"),
+            None => bail!(
+                "captcha-token subprocess failed with status {}",
+                status
+                    .code()
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|| "unknown".to_string())
+            ),
+        }
+    }
+    let mut output = lock(&stdout);
+    Ok(std::mem::take(&mut *output))
 }
 
-#[cfg(test)]
-pub fn capture_captcha_token_subprocess() -> Result<String> {
-    Ok("signalcaptcha://test-subprocess-token".to_string())
+fn stop_child(child: &mut Child, signal: libc::c_int) -> Result<ExitStatus> {
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, signal);
+    }
+    let grace = Instant::now() + Duration::from_millis(crate::CHILD_STOP_GRACE_MS);
+    while Instant::now() < grace {
+        if let Some(status) = child
+            .try_wait()
+            .context("failed to wait for captcha-token subprocess")?
+        {
+            return Ok(status);
+        }
+        thread::sleep(Duration::from_millis(crate::TERMINATE_POLL_MS));
+    }
+    let _ = child.kill();
+    child
+        .wait()
+        .context("failed to wait for captcha-token subprocess")
+}
+
+fn tee_stderr_tail(stderr: impl Read, tail: &Mutex<VecDeque<String>>) {
+    let mut reader = BufReader::new(stderr);
+    let mut line = Vec::new();
+    while let Ok(1..) = reader.read_until(b'\n', &mut line) {
+        let _ = std::io::stderr().write_all(&line);
+        let mut tail = lock(tail);
+        if tail.len() == crate::CAPTCHA_STDERR_TAIL_LINES {
+            tail.pop_front();
+        }
+        tail.push_back(String::from_utf8_lossy(&line).trim_end().to_string());
+        line.clear();
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+const WEBKITGTK_INSTALL_FIX: &str = "Install WebKitGTK 4.1 (Debian/Ubuntu: 'sudo apt install libwebkit2gtk-4.1-0', Fedora: 'sudo dnf install webkit2gtk4.1', Arch: 'sudo pacman -S webkit2gtk-4.1'), or solve the captcha in a browser.";
+
+const WEBVIEW_LIBRARIES: [&str; 3] = [
+    "libwebkit2gtk-4.1.so.0",
+    "libjavascriptcoregtk-4.1.so.0",
+    "libgtk-3.so.0",
+];
+
+pub fn webview_dependency_problem() -> Option<SignalSetupError> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    let os_release = std::fs::read_to_string("/etc/os-release").ok();
+    webview_dependency_problem_from(
+        |key| std::env::var(key).ok().filter(|value| !value.is_empty()),
+        library_loads,
+        os_release.as_deref(),
+    )
+}
+
+pub fn webview_dependency_problem_from(
+    env: impl Fn(&str) -> Option<String>,
+    library_loads: impl Fn(&str) -> bool,
+    os_release: Option<&str>,
+) -> Option<SignalSetupError> {
+    if let Some(library) = WEBVIEW_LIBRARIES
+        .into_iter()
+        .find(|library| !library_loads(library))
+    {
+        return Some(SignalSetupError::CaptchaWebviewUnavailable {
+            reason: format!("{library} is not installed"),
+            fix: webkitgtk_install_fix(os_release),
+        });
+    }
+    if env("DISPLAY").is_none() && env("WAYLAND_DISPLAY").is_none() {
+        return Some(SignalSetupError::CaptchaWebviewUnavailable {
+            reason: "there is no graphical session (DISPLAY and WAYLAND_DISPLAY are unset)"
+                .to_string(),
+            fix: "Solve the captcha in a browser, or run the wizard from a desktop session."
+                .to_string(),
+        });
+    }
+    None
+}
+
+pub fn webkitgtk_install_fix(os_release: Option<&str>) -> String {
+    let field = |key: &str| {
+        os_release?
+            .lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+            .map(|value| value.trim().trim_matches('"').to_ascii_lowercase())
+    };
+    let ids: Vec<String> = [field("ID"), field("ID_LIKE")]
+        .into_iter()
+        .flatten()
+        .flat_map(|value| {
+            value
+                .split_whitespace()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    let command = ids.iter().find_map(|id| match id.as_str() {
+        "debian" | "ubuntu" => Some("sudo apt install libwebkit2gtk-4.1-0"),
+        "fedora" | "rhel" | "centos" => Some("sudo dnf install webkit2gtk4.1"),
+        "arch" => Some("sudo pacman -S webkit2gtk-4.1"),
+        "suse" | "opensuse" => Some("sudo zypper install libwebkit2gtk-4_1-0"),
+        "alpine" => Some("sudo apk add webkit2gtk-4.1"),
+        _ => None,
+    });
+    match command {
+        Some(command) => {
+            format!("Install WebKitGTK 4.1 with '{command}', or solve the captcha in a browser.")
+        }
+        None => WEBKITGTK_INSTALL_FIX.to_string(),
+    }
+}
+
+fn library_loads(name: &str) -> bool {
+    let Ok(name) = std::ffi::CString::new(name) else {
+        return false;
+    };
+    let handle = unsafe { libc::dlopen(name.as_ptr(), libc::RTLD_LAZY) };
+    if handle.is_null() {
+        return false;
+    }
+    unsafe { libc::dlclose(handle) };
+    true
+}
+
+pub fn classify_captcha_failure(stderr: &str) -> Option<SignalSetupError> {
+    let unavailable = |reason: String, fix: &str| SignalSetupError::CaptchaWebviewUnavailable {
+        reason,
+        fix: fix.to_string(),
+    };
+    for line in stderr.lines().rev() {
+        if let Some((_, rest)) = line.split_once("error while loading shared libraries: ") {
+            let library = rest.split(':').next().unwrap_or(rest).trim();
+            return Some(unavailable(
+                format!("{library} could not be loaded"),
+                WEBKITGTK_INSTALL_FIX,
+            ));
+        }
+        if line.contains("WebKit")
+            && (line.contains("Failed to execute child process")
+                || line.contains("Unable to spawn a new child process"))
+        {
+            return Some(unavailable(
+                "WebKitGTK's helper processes are missing".to_string(),
+                WEBKITGTK_INSTALL_FIX,
+            ));
+        }
+        let lower = line.to_ascii_lowercase();
+        if lower.contains("cannot open display") || lower.contains("failed to initialize gtk") {
+            return Some(unavailable(
+                "GTK could not open a display".to_string(),
+                "Run the wizard from a desktop session, or solve the captcha in a browser.",
+            ));
+        }
+    }
+    None
+}
+
+#[cfg(not(test))]
+enum CaptchaEvent {
+    Token(String),
+    LoadStarted(String),
+    LoadFinished,
+    UseBrowser,
 }
 
 #[cfg(not(test))]
 pub fn capture_captcha_token(quiet: bool) -> Result<String> {
-    use tao::event::{Event, WindowEvent};
+    use std::time::{Duration, Instant};
+    use tao::dpi::{LogicalSize, PhysicalPosition};
+    use tao::event::{Event, StartCause, WindowEvent};
     use tao::event_loop::{ControlFlow, EventLoopBuilder};
     use tao::platform::run_return::EventLoopExtRunReturn;
     use tao::window::WindowBuilder;
-    use wry::WebViewBuilder;
+    use wry::{PageLoadEvent, WebViewBuilder};
+
+    // Catch offline/DNS/HTTP errors before opening a window that would stay blank.
+    ensure_online()?;
+    if let Err(err) = http_get(crate::CAPTCHA_URL, crate::HTTP_TIMEOUT_SECS) {
+        return Err(SignalSetupError::CaptchaPageUnavailable {
+            reason: err.to_string(),
+        }
+        .into());
+    }
 
-    let mut event_loop = EventLoopBuilder::<String>::with_user_event().build();
+    let mut event_loop = EventLoopBuilder::<CaptchaEvent>::with_user_event().build();
     let proxy = event_loop.create_proxy();
+    let load_proxy = event_loop.create_proxy();
 
-    let window = WindowBuilder::new()
+    let (width, height) = crate::CAPTCHA_WINDOW_SIZE;
+    let mut builder = WindowBuilder::new()
         .with_title("Signal Captcha")
+        .with_inner_size(LogicalSize::new(width, height))
+        .with_resizable(false)
+        .with_always_on_top(true)
+        .with_focused(true);
+    if let Some(monitor) = event_loop.primary_monitor() {
+        let scale = monitor.scale_factor();
+        let window_size = (
+            (width * scale).round() as u32,
+            (height * scale).round() as u32,
+        );
+        let origin = monitor.position();
+        let size = monitor.size();
+        let (x, y) =
+            centered_position((origin.x, origin.y), (size.width, size.height), window_size);
+        builder = builder.with_position(PhysicalPosition::new(x, y));
+    }
+    let window = builder
         .build(&event_loop)
         .context("failed to create captcha window")?;
+    window.set_focus();
 
     let webview = WebViewBuilder::new(&window)
         .with_url(crate::CAPTCHA_URL)
+        .with_initialization_script(CAPTCHA_BANNER_SCRIPT)
         .with_navigation_handler(move |url: String| {
             if url.starts_with("signalcaptcha://") {
-                let _ = proxy.send_event(url);
+                let _ = proxy.send_event(CaptchaEvent::Token(url));
+                return false;
+            }
+            if url.starts_with(CAPTCHA_USE_BROWSER_URL) {
+                let _ = proxy.send_event(CaptchaEvent::UseBrowser);
                 return false;
             }
             true
         })
+        .with_on_page_load_handler(move |event, url| {
+            let _ = load_proxy.send_event(match event {
+                PageLoadEvent::Started => CaptchaEvent::LoadStarted(url),
+                PageLoadEvent::Finished => CaptchaEvent::LoadFinished,
+            });
+        })
         .build()
         .context("failed to build captcha webview")?;
 
@@ -101,32 +461,244 @@ pub fn capture_captcha_token(quiet: bool) -> Result<String> {
         eprintln!("The window closes automatically when signalcaptcha:// is captured.");
     }
 
+    let load_timeout = Duration::from_secs(crate::CAPTCHA_LOAD_TIMEOUT_SECS);
+    let mut load_deadline = Some(Instant::now() + load_timeout);
     let mut captured: Option<String> = None;
+    let mut use_browser = false;
     event_loop.run_return(|event, _, control_flow| {
-        *control_flow = ControlFlow::Wait;
         match event {
-            Event::UserEvent(token) => {
+            Event::UserEvent(CaptchaEvent::Token(token)) => {
                 captured = Some(token);
                 window.set_visible(false);
                 *control_flow = ControlFlow::Exit;
+                return;
+            }
+            Event::UserEvent(CaptchaEvent::UseBrowser) => {
+                use_browser = true;
+                window.set_visible(false);
+                *control_flow = ControlFlow::Exit;
+                return;
+            }
+            Event::UserEvent(CaptchaEvent::LoadStarted(url)) => {
+                if !url.starts_with("data:") {
+                    load_deadline = Some(Instant::now() + load_timeout);
+                }
+            }
+            Event::UserEvent(CaptchaEvent::LoadFinished) => load_deadline = None,
+            Event::NewEvents(StartCause::ResumeTimeReached { .. }) => {
+                if load_deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    load_deadline = None;
+                    if !quiet {
+                        eprintln!("The captcha page did not load. Use the window to reload it or switch to your browser.");
+                    }
+                    let _ = webview.load_url(&load_failure_page_url(crate::CAPTCHA_URL));
+                }
             }
             Event::WindowEvent {
                 event: WindowEvent::CloseRequested,
                 ..
             } => {
                 *control_flow = ControlFlow::Exit;
+                return;
             }
             _ => {}
         }
+        *control_flow = match load_deadline {
+            Some(deadline) => ControlFlow::WaitUntil(deadline),
+            None => ControlFlow::Wait,
+        };
     });
 
     drop(webview);
     drop(window);
     drop(event_loop);
 
+    if use_browser {
+        return Err(SignalSetupError::CaptchaPageUnavailable {
+            reason: "the page did not load in the embedded window".to_string(),
+        }
+        .into());
+    }
     captured.ok_or_else(|| anyhow!("captcha window was closed before token capture"))
 }
 
+pub fn normalize_captcha_token(input: &str) -> Option<String> {
+    const SCHEME: &str = "signalcaptcha://";
+    let trimmed = input
+        .trim()
+        .trim_matches(|c: char| c == '"' || c == '\'' || c == '<' || c == '>');
+    let decoded;
+    let text = if trimmed.contains("signalcaptcha%3A") || trimmed.contains("signalcaptcha%3a") {
+        decoded = percent_decode(trimmed).ok()?;
+        decoded.as_str()
+    } else {
+        trimmed
+    };
+
+    let body = match text.find(SCHEME) {
+        Some(start) => &text[start + SCHEME.len()..],
+        None if text.starts_with("http://") || text.starts_with("https://") => {
+            text.split_once('#')?.1
+        }
+        None => text,
+    };
+    let body = body.split_whitespace().next()?;
+    if body.is_empty() || body.contains("://") {
+        return None;
+    }
+    Some(format!("{SCHEME}{body}"))
+}
+
+pub fn parse_captcha_token(input: &str) -> Result<String> {
+    let token =
+        normalize_captcha_token(input).ok_or_else(|| SignalSetupError::InvalidCaptchaToken {
+            reason: "no signalcaptcha:// token found".to_string(),
+        })?;
+    validate_captcha_token(&token)?;
+    Ok(token)
+}
+
+pub fn validate_captcha_token(token: &str) -> Result<()> {
+    let invalid = |reason: String| SignalSetupError::InvalidCaptchaToken { reason };
+    let Some(body) = token.strip_prefix("signalcaptcha://") else {
+        return Err(invalid("it does not start with signalcaptcha://".to_string()).into());
+    };
+    if let Some(bad) = body
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-')))
+    {
+        return Err(invalid(format!("it contains the unexpected character '{bad}'")).into());
+    }
+
+    // signal-<provider>.<site key>.<action>.<provider token>
+    let mut parts = body.splitn(4, '.');
+    let (Some(provider), Some(site_key), Some(action), Some(solution)) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(invalid("it is missing the provider, site key or action".to_string()).into());
+    };
+    if !provider.starts_with("signal-") || site_key.is_empty() || action.is_empty() {
+        return Err(invalid(format!(
+            "unexpected prefix '{provider}.{site_key}.{action}'"
+        ))
+        .into());
+    }
+    if !KNOWN_CAPTCHA_PROVIDERS.contains(&provider) {
+        eprintln!("Warning: unknown captcha provider '{provider}', trying the token anyway.");
+    }
+    if solution.len() < crate::CAPTCHA_TOKEN_MIN_SOLUTION_LEN {
+        return Err(invalid(format!(
+            "it looks truncated ({} characters after the prefix)",
+            solution.len()
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+const KNOWN_CAPTCHA_PROVIDERS: &[&str] = &["signal-hcaptcha", "signal-recaptcha-v2"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum TokenOutput {
+    /// The bare token on one line
+    #[default]
+    Raw,
+    /// `{"token": "...", "captured_at": <unix seconds>}` on one line
+    Json,
+    /// A QR code of the token drawn in the terminal, to scan on another device
+    Qrterm,
+}
+
+pub fn render_captcha_token(token: &str, output: TokenOutput, captured_at: u64) -> Result<String> {
+    match output {
+        TokenOutput::Raw => Ok(token.to_string()),
+        TokenOutput::Json => Ok(serde_json::json!({
+            "token": token,
+            "captured_at": captured_at,
+        })
+        .to_string()),
+        TokenOutput::Qrterm => terminal_qr(token, crate::ui::ascii()),
+    }
+}
+
+pub fn print_captcha_token(token: &str, output: TokenOutput) -> Result<()> {
+    let captured_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0);
+    let rendered = render_captcha_token(token, output, captured_at)?;
+    if output == TokenOutput::Qrterm {
+        eprintln!("Scan this QR code on the machine that registers, then paste the text it reads into register --token -.");
+    }
+    println!("{rendered}");
+    Ok(())
+}
+
+/// Light modules are drawn and dark ones left blank, which scans on the usual light-on-dark
+/// terminal. Tokens are long, so the lowest error correction keeps the code small.
+pub fn terminal_qr(data: &str, ascii: bool) -> Result<String> {
+    let code = QrCode::with_error_correction_level(data, EcLevel::L)
+        .context("the token is too long for a QR code")?;
+    if ascii {
+        return Ok(code
+            .render::<char>()
+            .dark_color(' ')
+            .light_color('#')
+            .module_dimensions(2, 1)
+            .build());
+    }
+    Ok(code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build())
+}
+
+pub(crate) const CAPTCHA_USE_BROWSER_URL: &str = "signal-setup://use-browser";
+
+pub(crate) fn load_failure_page_url(reload_url: &str) -> String {
+    let html = format!(
+        "<!doctype html><html><body style=\"font:15px -apple-system,system-ui,sans-serif;text-align:center;padding:80px 24px\">\
+<h2>The captcha page could not be loaded</h2>\
+<p>Check your connection, then reload. If it keeps failing, continue in your browser and paste the token in the terminal.</p>\
+<p><a href=\"{reload_url}\">Reload</a> &middot; <a href=\"{CAPTCHA_USE_BROWSER_URL}\">Use my browser instead</a></p>\
+</body></html>"
+    );
+    format!("data:text/html;charset=utf-8,{}", percent_encode(&html))
+}
+
+pub(crate) fn is_captcha_page_unavailable(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<SignalSetupError>(),
+        Some(SignalSetupError::CaptchaPageUnavailable { .. })
+    )
+}
+
+// Injected into the captcha page so the window explains itself even when the terminal is hidden.
+#[cfg(not(test))]
+const CAPTCHA_BANNER_SCRIPT: &str = r#"
+window.addEventListener("DOMContentLoaded", () => {
+  if (location.protocol === "data:") return;
+  const banner = document.createElement("div");
+  banner.textContent = "Solve the captcha below. This window closes by itself and returns to the terminal.";
+  banner.style.cssText = "position:fixed;top:0;left:0;right:0;z-index:2147483647;padding:10px 14px;background:#3a76f0;color:#fff;font:14px -apple-system,system-ui,sans-serif;text-align:center";
+  document.body.appendChild(banner);
+  document.body.style.paddingTop = banner.offsetHeight + "px";
+});
+"#;
+
+pub(crate) fn centered_position(
+    monitor_origin: (i32, i32),
+    monitor_size: (u32, u32),
+    window_size: (u32, u32),
+) -> (i32, i32) {
+    let offset = |monitor: u32, window: u32| (monitor.saturating_sub(window) / 2) as i32;
+    (
+        monitor_origin.0 + offset(monitor_size.0, window_size.0),
+        monitor_origin.1 + offset(monitor_size.1, window_size.1),
+    )
+}
+
 #[cfg(test)]
 pub fn capture_captcha_token(_quiet: bool) -> Result<String> {
     Ok("signalcaptcha://test-webview-token".to_string())
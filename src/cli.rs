@@ -1,6 +1,14 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use std::path::PathBuf;
 
+use crate::captcha::TokenOutput;
+use crate::daemon::DbusBus;
+use crate::image_ref::ImageFlavor;
+use crate::qr::CaptureBackend;
+use crate::runtime::ContainerRuntime;
+use crate::sync::SyncOptions;
+use crate::ui::Charset;
+
 #[derive(Parser, Debug)]
 #[command(
     author,
@@ -17,25 +25,188 @@ pub struct Cli {
     #[arg(long, global = true)]
     pub data_dir: Option<PathBuf>,
 
-    #[arg(long, global = true, default_value = crate::DEFAULT_IMAGE)]
-    pub image: String,
+    /// Create the data dir when it does not exist; `--create-data-dir=false` fails instead
+    #[arg(
+        long,
+        global = true,
+        default_value_t = true,
+        num_args = 0..=1,
+        default_missing_value = "true",
+        action = clap::ArgAction::Set
+    )]
+    pub create_data_dir: bool,
+
+    /// signal-cli image (defaults to the image of the selected flavor)
+    #[arg(long, global = true)]
+    pub image: Option<String>,
+
+    /// Published signal-cli image flavor; remembered in the config file for later runs
+    #[arg(long, global = true, value_enum)]
+    pub image_flavor: Option<ImageFlavor>,
+
+    /// Registry mirror/prefix replacing registry.gitlab.com (empty string clears it); remembered in the config file
+    #[arg(long, global = true)]
+    pub registry_mirror: Option<String>,
+
+    /// Verify the image's cosign signature against this public key (a file, or a key reference cosign accepts) before running it, and refuse to run it otherwise (empty string turns it off); remembered in the config file
+    #[arg(long, global = true)]
+    pub verify_signature: Option<String>,
+
+    /// signal-cli config dir inside the container, mounted from the data dir and passed as --config (empty string goes back to /var/lib/signal-cli); remembered per account in the config file
+    #[arg(long, global = true)]
+    pub container_config: Option<String>,
+
+    /// Memory limit of signal-cli containers, such as 1g
+    #[arg(long, global = true)]
+    pub memory: Option<String>,
+
+    /// CPUs signal-cli containers may use, such as 1.5
+    #[arg(long, global = true)]
+    pub cpus: Option<String>,
+
+    /// Maximum number of processes and threads in a signal-cli container
+    #[arg(long, global = true)]
+    pub pids_limit: Option<String>,
+
+    /// HTTP or SOCKS proxy signal-cli containers reach Signal through, such as http://proxy:3128 (default: HTTPS_PROXY; an empty string goes direct)
+    #[arg(long, global = true)]
+    pub proxy: Option<String>,
+
+    /// Docker network signal-cli containers join, such as host
+    #[arg(long, global = true)]
+    pub network: Option<String>,
+
+    /// Container runtime used to run signal-cli
+    #[arg(long, global = true, value_enum)]
+    pub runtime: Option<ContainerRuntime>,
+
+    /// Docker context to run containers in (see `docker context ls`); overrides DOCKER_CONTEXT
+    #[arg(long, global = true)]
+    pub docker_context: Option<String>,
+
+    /// POST JSON event notifications to this URL (empty string clears it); remembered in the config file
+    #[arg(long, global = true)]
+    pub webhook_url: Option<String>,
+
+    /// Send commands to a running signal-cli daemon's JSON-RPC TCP port (host:port) instead of starting containers
+    #[arg(long, global = true)]
+    pub daemon_addr: Option<String>,
+
+    /// Displays decoded in parallel while scanning for the linking QR code
+    #[arg(long, global = true, default_value_t = crate::DEFAULT_DECODE_THREADS)]
+    pub decode_threads: usize,
+
+    /// Niceness (0-19) of QR decoding threads; by default macOS runs them at background QoS
+    #[arg(long, global = true, value_parser = clap::value_parser!(i32).range(0..=19))]
+    pub nice: Option<i32>,
+
+    /// How screens are captured for the linking QR code; `xcap` avoids the `screencapture` binary (default: auto)
+    #[arg(long, global = true, value_enum)]
+    pub capture_backend: Option<CaptureBackend>,
+
+    /// Only capture and decode this screen region, as x,y,w,h in points (empty string clears it); remembered in the config file
+    #[arg(long, global = true)]
+    pub region: Option<String>,
+
+    /// Only capture this display when scanning for the QR code, numbered from 1 as listed by `windows`
+    #[arg(long, global = true, value_parser = clap::value_parser!(u32).range(1..))]
+    pub display: Option<u32>,
+
+    /// Keep every QR scan capture in this dir, plus a copy with the located QR codes outlined
+    #[arg(long, global = true)]
+    pub debug_artifacts: Option<PathBuf>,
+
+    /// How often linking rescans the QR and retries after an expired QR or a network failure, before giving up (default: 3)
+    #[arg(long, global = true)]
+    pub link_retries: Option<u32>,
+
+    /// How long the wizard's captcha window stays open before it is closed unsolved (e.g. 5m; default: 10m)
+    #[arg(long, global = true, value_parser = crate::timing::parse_duration)]
+    pub captcha_timeout: Option<std::time::Duration>,
+
+    /// Append a JSON line for every external process this tool runs (arguments with secrets masked, exit codes)
+    #[arg(long, global = true)]
+    pub audit_log: Option<PathBuf>,
+
+    /// Screen-reader friendly output: no spinners or progress bars, numbered text prompts instead of arrow-key menus
+    #[arg(long, global = true, default_value_t = false)]
+    pub accessible: bool,
+
+    /// Characters used by prompts, spinners and progress bars; remembered in the config file
+    #[arg(long, global = true, value_enum)]
+    pub charset: Option<Charset>,
+
+    /// Print a stable key=value result on stdout and send all other output to stderr (captcha-token, link-desktop-live, status, open-url)
+    #[arg(long, global = true, default_value_t = false)]
+    pub porcelain: bool,
+
+    /// Length of the registration lock PIN generated by the wizard and set-pin (6 to 64 digits)
+    #[arg(long, global = true, default_value_t = crate::GENERATED_REGISTRATION_PIN_DIGITS)]
+    pub pin_digits: usize,
+
+    /// Digits per group when the generated PIN is shown, for example 4 for 1234-5678 (0 shows it ungrouped)
+    #[arg(long, global = true, default_value_t = crate::PIN_DISPLAY_GROUP)]
+    pub pin_group: usize,
+
+    /// Print the signal-cli invocations the command would run (secrets masked) and exit without running any
+    #[arg(long, global = true, default_value_t = false)]
+    pub print_args: bool,
+
+    /// Do not set a registration lock PIN in the wizard (not recommended: the number alone is enough to take the account over)
+    #[arg(long, global = true, default_value_t = false)]
+    pub no_reglock: bool,
 }
 
 #[derive(Subcommand, Debug, Clone)]
 pub enum Commands {
     /// Full interactive flow: captcha -> register -> verify -> link desktop
-    Wizard,
+    Wizard {
+        /// Continue a run that restarted to pick up the Screen Recording permission, at desktop linking
+        #[arg(long)]
+        resume: Option<String>,
+
+        /// Stop the whole run after this long (e.g. 90s, 30m, 1h30m), printing the timing summary and a resume token once registration is done
+        #[arg(long, value_parser = crate::timing::parse_duration)]
+        max_duration: Option<std::time::Duration>,
+
+        /// Run signal-cli as a daemon in one container and send each step over its JSON-RPC socket (Linux, local Docker engine)
+        #[arg(long, default_value_t = false)]
+        jsonrpc: bool,
+    },
 
     /// Open captcha in a WebView and print captured signalcaptcha:// token
     CaptchaToken {
         #[arg(long, default_value_t = false)]
         quiet: bool,
+
+        /// How the token is printed
+        #[arg(long, value_enum, default_value_t = TokenOutput::Raw)]
+        output: TokenOutput,
+
+        /// Hand the token to `register --token-listen` at host:port instead of printing it; the
+        /// pairing code is asked for, or read from stdin
+        #[arg(long, conflicts_with = "output")]
+        send: Option<String>,
     },
 
     /// Register account with a captcha token
     Register {
+        /// Captcha token; `-` reads it from stdin
+        #[arg(
+            long,
+            required_unless_present_any = ["token_file", "token_listen"],
+            conflicts_with_all = ["token_file", "token_listen"]
+        )]
+        token: Option<String>,
+
+        /// Read the captcha token from this file
+        #[arg(long, conflicts_with = "token_listen")]
+        token_file: Option<PathBuf>,
+
+        /// Wait for `captcha-token --send` from another machine: a port (all interfaces) or a
+        /// host:port
         #[arg(long)]
-        token: String,
+        token_listen: Option<String>,
 
         #[arg(long, default_value_t = false)]
         voice: bool,
@@ -46,12 +217,25 @@ pub enum Commands {
 
     /// Verify registration code
     Verify {
-        code: String,
+        /// Verification code; `-` reads it from stdin
+        #[arg(required_unless_present = "code_file", conflicts_with = "code_file")]
+        code: Option<String>,
+
+        /// Read the verification code from this file
+        #[arg(long)]
+        code_file: Option<PathBuf>,
 
         #[arg(long)]
         pin: Option<String>,
+
+        /// When Signal answers with a registration lock, retry with the PIN the wizard or set-pin stored in the keychain
+        #[arg(long, conflicts_with = "pin")]
+        pin_from_keychain: bool,
     },
 
+    /// Generate and set a registration lock PIN, for accounts where the wizard skipped or deferred it
+    SetPin,
+
     /// Open Signal Desktop, scan full-screen screenshots until QR is found, then link device
     LinkDesktopLive {
         #[arg(long, default_value_t = crate::DEFAULT_SCAN_INTERVAL)]
@@ -59,8 +243,261 @@ pub enum Commands {
 
         #[arg(long, default_value_t = crate::DEFAULT_SCAN_ATTEMPTS)]
         attempts: u32,
+
+        /// Pick the scan region with the mouse before the first attempt (macOS); kept for this run only
+        #[arg(long, default_value_t = false)]
+        pick_region: bool,
+
+        #[command(flatten)]
+        sync: SyncArgs,
+    },
+
+    /// Link Signal Desktop from a sgnl://linkdevice URI copied by hand
+    LinkDesktopUri {
+        uri: String,
+
+        #[command(flatten)]
+        sync: SyncArgs,
     },
 
+    /// Print the sgnl:// linking URI found in a PNG/JPEG screenshot (`-` reads the image from stdin)
+    DecodeQr { image: PathBuf },
+
     /// List linked devices
     ListDevices,
+
+    /// Periodically run `receive` so the account and its linked devices stay active
+    Keepalive {
+        /// Seconds between receive passes
+        #[arg(long, default_value_t = crate::DEFAULT_KEEPALIVE_INTERVAL_SECS)]
+        interval: u64,
+
+        /// Stop after this many passes (default: run until stopped)
+        #[arg(long)]
+        passes: Option<u32>,
+
+        /// Serve Prometheus /metrics and /healthz on this address (for example 127.0.0.1:9464)
+        #[arg(long)]
+        metrics_addr: Option<String>,
+    },
+
+    /// Run `receive` continuously and print incoming envelopes as they arrive
+    Tail {
+        /// Stop after this many envelopes
+        #[arg(long)]
+        count: Option<u64>,
+
+        /// Print the raw signal-cli JSON instead of a summary line
+        #[arg(long, default_value_t = false)]
+        raw: bool,
+    },
+
+    /// Promote the configured account to a long-running signal-cli daemon
+    Daemon {
+        #[command(subcommand)]
+        command: DaemonCommands,
+    },
+
+    /// Inspect and pin signal-cli images
+    Image {
+        #[command(subcommand)]
+        command: ImageCommands,
+    },
+
+    /// Pull the signal-cli image now, with progress, instead of during the first command
+    PullImage,
+
+    /// Check the registry for a newer signal-cli image, pull it and verify the new version
+    UpgradeImage {
+        /// Also move a pinned version tag to the newest published version
+        #[arg(long, default_value_t = false)]
+        pin_newest: bool,
+    },
+
+    /// Configure ntfy/Pushover push notifications for steps that need you
+    Notify {
+        #[command(subcommand)]
+        command: NotifyCommands,
+    },
+
+    /// Write an age-encrypted bundle of the account's data for moving it to another machine
+    ExportAccount {
+        #[arg(long)]
+        output: PathBuf,
+
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+
+    /// Import an account bundle written by export-account into the data dir
+    ImportAccount {
+        bundle: PathBuf,
+
+        /// Replace the account if it already exists in the data dir
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+
+    /// Keep the data dir age-encrypted at rest, unlocked only while a command runs
+    Vault {
+        #[command(subcommand)]
+        command: VaultCommands,
+    },
+
+    /// Choose whether signal-cli automatically trusts new identities (safety numbers) of contacts
+    Trust {
+        /// Always trust new identities, so sends keep working after contacts reinstall Signal
+        #[arg(long, default_value_t = false, conflicts_with = "on_first_use")]
+        all_new: bool,
+
+        /// Go back to signal-cli's default of only trusting the first identity seen
+        #[arg(long, default_value_t = false)]
+        on_first_use: bool,
+    },
+
+    /// Show the account, runtime and linked-device state
+    Status,
+
+    /// Run an x-signal-setup:// automation URL (captcha, link or status) with x-callback-url support
+    OpenUrl { url: String },
+
+    /// Manage the macOS x-signal-setup:// URL scheme handler
+    UrlHandler {
+        #[command(subcommand)]
+        command: UrlHandlerCommands,
+    },
+
+    /// Check internet access, the system clock and the container runtime
+    Doctor,
+
+    /// Offer to install a missing container runtime and Signal Desktop with Homebrew (macOS) or apt (Linux)
+    Bootstrap {
+        /// Install without asking
+        #[arg(long, short = 'y', default_value_t = false)]
+        yes: bool,
+    },
+
+    /// Check why Signal Desktop stays on "Syncing contacts and groups": the primary's receive, a contacts sync message and when the linked devices were last seen
+    DiagnoseSync,
+
+    /// List the displays and windows the capture backends see, to debug QR scans that miss Signal's window
+    Windows,
+
+    /// Print the signal-cli version of the image and check it against the known-good minimum
+    Version {
+        /// Fail instead of warning when the version is too old
+        #[arg(long, default_value_t = false)]
+        strict: bool,
+    },
+}
+
+/// Post-link sync tuning shared by the linking commands.
+#[derive(Args, Debug, Clone)]
+pub struct SyncArgs {
+    /// Seconds each post-link receive pass waits for sync messages
+    #[arg(long, default_value_t = crate::POST_LINK_RECEIVE_TIMEOUT_SECS)]
+    pub sync_timeout: u64,
+
+    /// Messages fetched by each post-link receive pass
+    #[arg(long, default_value_t = crate::POST_LINK_RECEIVE_MAX_MESSAGES)]
+    pub sync_max_messages: u32,
+
+    /// Skip the post-link receive passes and contacts sync message
+    #[arg(long, default_value_t = false, conflicts_with_all = ["sync_timeout", "sync_max_messages"])]
+    pub no_sync: bool,
+
+    /// Quit and reopen Signal Desktop after linking without asking; a restart often clears "Syncing contacts and groups"
+    #[arg(long, default_value_t = false)]
+    pub restart_desktop: bool,
+}
+
+impl SyncArgs {
+    pub fn options(&self) -> SyncOptions {
+        SyncOptions {
+            enabled: !self.no_sync,
+            timeout_secs: self.sync_timeout,
+            max_messages: self.sync_max_messages,
+            restart_desktop: self.restart_desktop,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ImageCommands {
+    /// List published tags of the configured image and pin one in the config file
+    Tags {
+        /// Pin this tag without prompting ("latest" goes back to tracking latest)
+        #[arg(long)]
+        pin: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum DaemonCommands {
+    /// Run signal-cli in daemon mode in the foreground for the account (Ctrl+C to stop)
+    Run {
+        /// JSON-RPC TCP port published on 127.0.0.1
+        #[arg(long, default_value_t = crate::DEFAULT_DAEMON_PORT)]
+        port: u16,
+
+        /// Do not expose the JSON-RPC TCP port
+        #[arg(long, default_value_t = false)]
+        no_tcp: bool,
+
+        /// Also expose signal-cli's DBus interface on this bus (Linux only)
+        #[arg(long, value_enum)]
+        dbus: Option<DbusBus>,
+
+        /// Serve /healthz on this address, probing the daemon over JSON-RPC
+        #[arg(long)]
+        health_addr: Option<String>,
+    },
+
+    /// Write a docker-compose.yml running signal-cli as a JSON-RPC daemon for the account
+    GenerateCompose {
+        #[arg(long, default_value = "docker-compose.yml")]
+        output: PathBuf,
+
+        /// JSON-RPC TCP port published on 127.0.0.1
+        #[arg(long, default_value_t = crate::DEFAULT_DAEMON_PORT)]
+        port: u16,
+
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum NotifyCommands {
+    /// Send push notifications to an ntfy topic (name on ntfy.sh or full topic URL)
+    Ntfy { topic: String },
+
+    /// Send push notifications through Pushover; the API token comes from PUSHOVER_TOKEN, a
+    /// hidden prompt or stdin
+    Pushover {
+        /// Pushover user key
+        #[arg(long)]
+        user: String,
+    },
+
+    /// Stop sending push notifications
+    Disable,
+
+    /// Send a test push notification
+    Test,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum VaultCommands {
+    /// Encrypt the data dir with a new passphrase-protected age key
+    Enable,
+
+    /// Decrypt the data dir permanently and remove the encrypted copy
+    Disable,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum UrlHandlerCommands {
+    /// Install a small app in ~/Applications that forwards x-signal-setup:// URLs to this binary
+    Install,
 }
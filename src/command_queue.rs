@@ -0,0 +1,134 @@
+use anyhow::{Context, Result};
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::shutdown::{check_terminated, sleep_unless_terminated};
+
+static QUEUES: Mutex<Vec<(String, Arc<AccountQueue>)>> = Mutex::new(Vec::new());
+
+#[derive(Default)]
+struct AccountQueue {
+    state: Mutex<QueueState>,
+    turn_over: Condvar,
+}
+
+#[derive(Default)]
+struct QueueState {
+    waiting: VecDeque<u64>,
+    next_ticket: u64,
+    busy: bool,
+}
+
+impl AccountQueue {
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+pub struct Turn {
+    queue: Arc<AccountQueue>,
+    lock_file: Option<File>,
+}
+
+impl Drop for Turn {
+    fn drop(&mut self) {
+        // Closing the file releases the lock for other processes before the next one here
+        // tries to take it.
+        self.lock_file.take();
+        self.queue.lock().busy = false;
+        self.queue.turn_over.notify_all();
+    }
+}
+
+/// Other processes are waited out through a lock file in the data dir.
+pub fn wait_turn(cfg: &Config) -> Result<Turn> {
+    let queue = account_queue(&cfg.account);
+    let mut announced = false;
+    {
+        let mut state = queue.lock();
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        state.waiting.push_back(ticket);
+        loop {
+            if !state.busy && state.waiting.front() == Some(&ticket) {
+                state.waiting.pop_front();
+                state.busy = true;
+                break;
+            }
+            if let Err(err) = check_terminated() {
+                state.waiting.retain(|waiting| *waiting != ticket);
+                drop(state);
+                queue.turn_over.notify_all();
+                return Err(err);
+            }
+            if !announced {
+                announce_wait(&cfg.account);
+                announced = true;
+            }
+            state = queue
+                .turn_over
+                .wait_timeout(state, Duration::from_millis(crate::TERMINATE_POLL_MS))
+                .map(|(state, _)| state)
+                .unwrap_or_else(|err| err.into_inner().0);
+        }
+    }
+
+    let mut turn = Turn {
+        queue,
+        lock_file: None,
+    };
+    let path = lock_path(cfg);
+    crate::vault::ensure_unlocked(cfg)?;
+    fs::create_dir_all(&cfg.data_dir)
+        .with_context(|| format!("failed to create data dir {}", cfg.data_dir.display()))?;
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("failed to open {}", path.display()))?;
+    while !try_lock(&file).with_context(|| format!("failed to lock {}", path.display()))? {
+        if !announced {
+            announce_wait(&cfg.account);
+            announced = true;
+        }
+        sleep_unless_terminated(Duration::from_millis(crate::TERMINATE_POLL_MS))?;
+    }
+    turn.lock_file = Some(file);
+    Ok(turn)
+}
+
+pub fn lock_path(cfg: &Config) -> PathBuf {
+    cfg.data_dir
+        .join(format!(".signal-setup-{}.lock", cfg.account))
+}
+
+fn account_queue(account: &str) -> Arc<AccountQueue> {
+    let mut queues = QUEUES.lock().unwrap_or_else(|err| err.into_inner());
+    if let Some((_, queue)) = queues.iter().find(|(known, _)| known == account) {
+        return queue.clone();
+    }
+    let queue = Arc::new(AccountQueue::default());
+    queues.push((account.to_string(), queue.clone()));
+    queue
+}
+
+pub fn try_lock(file: &File) -> std::io::Result<bool> {
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let err = std::io::Error::last_os_error();
+    if err.raw_os_error() == Some(libc::EWOULDBLOCK) {
+        return Ok(false);
+    }
+    Err(err)
+}
+
+fn announce_wait(account: &str) {
+    eprintln!("Waiting for another signal-cli command on {account} to finish...");
+}
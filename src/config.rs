@@ -1,21 +1,99 @@
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use dialoguer::theme::ColorfulTheme;
-#[cfg(not(test))]
-use dialoguer::Input;
 use dirs::home_dir;
-use std::path::PathBuf;
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use crate::cli::Cli;
+use crate::container::{JsonRpcDaemon, SessionContainer};
+use crate::errors::SignalSetupError;
+use crate::image_ref::{
+    apply_registry_mirror, image_flavor_of, normalize_registry_mirror, with_image_tag, ImageFlavor,
+};
+use crate::notify::{push_target, PushTarget};
+use crate::proxy::{self, resolve_proxy, Proxy};
+use crate::qr::{CaptureOptions, DecodeLimits, Region};
+use crate::reglock::PinFormat;
+use crate::runtime::{ContainerRuntime, ResourceLimits};
+use crate::settings::{load_settings, update_settings, Settings};
+use crate::sync::SyncOptions;
+#[cfg(not(test))]
+use crate::ui;
+use crate::vault::VaultSession;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Config {
     pub account: String,
     pub data_dir: PathBuf,
     pub image: String,
+    pub image_flavor: ImageFlavor,
+    pub registry_mirror: Option<String>,
+    pub signature_key: Option<String>,
+    pub mirrored_from: Option<String>,
+    pub runtime: ContainerRuntime,
+    pub webhook_url: Option<String>,
+    pub push_target: Option<PushTarget>,
+    /// Container signal-cli commands are exec'd into instead of each getting its own; dropped
+    /// before `vault` so the data dir is only re-encrypted once nothing has it mounted
+    pub session: Option<Arc<SessionContainer>>,
+    pub jsonrpc: Option<Arc<JsonRpcDaemon>>,
+    pub vault: Option<Arc<VaultSession>>,
+    pub daemon_addr: Option<String>,
+    pub trust_new_identities: bool,
+    pub decode_limits: DecodeLimits,
+    pub capture: CaptureOptions,
+    pub link_retries: Option<u32>,
+    pub captcha_timeout: Option<std::time::Duration>,
+    pub sync: SyncOptions,
+    pub pin: PinFormat,
+    pub container_config: Option<String>,
+    pub limits: ResourceLimits,
+    pub proxy: Option<Proxy>,
+    pub network: Option<String>,
+    pub data_volume: Option<String>,
+}
+
+pub fn resolve_image(
+    explicit: Option<&str>,
+    flavor: ImageFlavor,
+    tag: Option<&str>,
+    mirror: Option<&str>,
+) -> (String, Option<String>) {
+    let upstream_image = match (explicit, tag) {
+        (Some(image), _) => image.to_string(),
+        (None, Some(tag)) => with_image_tag(flavor.image(), tag),
+        (None, None) => flavor.image().to_string(),
+    };
+    match mirror.and_then(|mirror| apply_registry_mirror(&upstream_image, mirror)) {
+        Some(image) => (image, Some(upstream_image)),
+        None => (upstream_image, None),
+    }
 }
 
 pub fn config_from_cli(cli: &Cli, require_account: bool) -> Result<Config> {
     let data_dir = cli.data_dir.clone().unwrap_or_else(default_data_dir);
+    ensure_data_dir_volume(&data_dir)?;
+    let fs_type = crate::system::filesystem_type(&absolute_path(&data_dir));
+    check_data_dir(
+        &data_dir,
+        cli.create_data_dir,
+        fs_type.as_deref(),
+        cfg!(target_os = "macos"),
+    )?;
+    if cli.decode_threads == 0 {
+        bail!("--decode-threads must be > 0")
+    }
+    if !(crate::MIN_GENERATED_PIN_DIGITS..=crate::MAX_GENERATED_PIN_DIGITS)
+        .contains(&cli.pin_digits)
+    {
+        bail!(
+            "--pin-digits must be between {} and {}",
+            crate::MIN_GENERATED_PIN_DIGITS,
+            crate::MAX_GENERATED_PIN_DIGITS
+        )
+    }
 
     let account = match &cli.account {
         Some(v) => {
@@ -26,13 +104,378 @@ pub fn config_from_cli(cli: &Cli, require_account: bool) -> Result<Config> {
         None => String::new(),
     };
 
+    let container_config = cli
+        .container_config
+        .as_deref()
+        .map(normalize_container_config)
+        .transpose()?;
+    let settings = if cli.image_flavor.is_some()
+        || cli.registry_mirror.is_some()
+        || cli.verify_signature.is_some()
+        || cli.webhook_url.is_some()
+        || cli.container_config.is_some()
+        || cli.region.is_some()
+    {
+        let region = cli.region.as_deref().map(normalize_region).transpose()?;
+        let before = load_settings()?;
+        let settings = update_settings(|settings| {
+            if let Some(flavor) = cli.image_flavor {
+                settings.image_flavor = Some(flavor);
+            }
+            if let Some(mirror) = &cli.registry_mirror {
+                settings.registry_mirror = normalize_registry_mirror(mirror);
+            }
+            if let Some(key) = &cli.verify_signature {
+                settings.signature_key = normalize_signature_key(key);
+            }
+            if let Some(url) = &cli.webhook_url {
+                let url = url.trim();
+                settings.webhook_url = (!url.is_empty()).then(|| url.to_string());
+            }
+            // Saved without an account, it is the default of accounts without their own.
+            if let Some(path) = container_config.clone() {
+                settings
+                    .container_configs
+                    .retain(|(saved, _)| saved != &account);
+                if let Some(path) = path {
+                    settings.container_configs.push((account.clone(), path));
+                }
+            }
+            if let Some(region) = region {
+                settings.scan_region = region;
+            }
+        })?;
+        for message in saved_settings_messages(&before, &settings) {
+            eprintln!("{message}");
+        }
+        settings
+    } else {
+        load_settings()?
+    };
+    let limits = ResourceLimits {
+        memory: cli
+            .memory
+            .as_deref()
+            .map(normalize_memory_limit)
+            .transpose()?
+            .flatten(),
+        cpus: cli
+            .cpus
+            .as_deref()
+            .map(normalize_cpu_limit)
+            .transpose()?
+            .flatten(),
+        pids_limit: cli
+            .pids_limit
+            .as_deref()
+            .map(normalize_pids_limit)
+            .transpose()?
+            .flatten(),
+    };
+    let network = cli
+        .network
+        .as_deref()
+        .map(normalize_network)
+        .transpose()?
+        .flatten();
+
+    let image_flavor = settings.image_flavor.unwrap_or_default();
+    let (image, mirrored_from) = resolve_image(
+        cli.image.as_deref(),
+        image_flavor,
+        settings.image_tag.as_deref(),
+        settings.registry_mirror.as_deref(),
+    );
+
+    let runtime = cli.runtime.unwrap_or_default();
+    // Without an account yet (the wizard asks for it), the wizard sets the volume later.
+    let data_volume = (cli.daemon_addr.is_none() && !account.is_empty())
+        .then(|| crate::remote::data_volume(runtime, &account))
+        .flatten();
+    if runtime == ContainerRuntime::Docker && data_volume.is_none() && cfg!(target_os = "macos") {
+        let shares = docker_desktop_shares();
+        if let Some(warning) =
+            check_docker_desktop_sharing(&absolute_path(&data_dir), shares.as_deref())?
+        {
+            eprintln!("{warning}");
+        }
+    }
+
+    let proxy = match cli.proxy.as_deref().map(normalize_proxy).transpose()? {
+        // `--proxy ""` goes direct even when HTTPS_PROXY is set.
+        Some(None) => None,
+        Some(Some(url)) => {
+            proxy::check_flavor(image_flavor_of(&image))?;
+            let proxy = resolve_proxy(Some(&url), |_| None)?;
+            if let Some(warning) = proxy
+                .as_ref()
+                .and_then(|proxy| proxy::loopback_warning(proxy, network.as_deref()))
+            {
+                eprintln!("{warning}");
+            }
+            proxy
+        }
+        None => {
+            let proxy = resolve_proxy(None, |key| {
+                std::env::var(key).ok().filter(|value| !value.is_empty())
+            })?;
+            if let Some(proxy) = &proxy {
+                if image_flavor_of(&image) == Some(ImageFlavor::Native)
+                    && settings.native_proxy_warned.as_deref() != Some(proxy.url.as_str())
+                {
+                    eprintln!("{}", proxy::native_image_warning(proxy));
+                    update_settings(|settings| {
+                        settings.native_proxy_warned = Some(proxy.url.clone());
+                    })?;
+                }
+            }
+            proxy
+        }
+    };
+
+    let trust_new_identities = settings
+        .trust_new_identities
+        .iter()
+        .any(|known| *known == account);
     Ok(Config {
         account,
         data_dir,
-        image: cli.image.clone(),
+        image,
+        image_flavor,
+        registry_mirror: settings.registry_mirror,
+        signature_key: settings.signature_key,
+        mirrored_from,
+        runtime,
+        push_target: push_target(&settings),
+        webhook_url: settings.webhook_url,
+        session: None,
+        jsonrpc: None,
+        vault: None,
+        daemon_addr: cli.daemon_addr.clone(),
+        trust_new_identities,
+        decode_limits: DecodeLimits {
+            threads: cli.decode_threads,
+            nice: cli.nice,
+        },
+        capture: CaptureOptions {
+            backend: cli.capture_backend.unwrap_or_default(),
+            region: settings
+                .scan_region
+                .as_deref()
+                .map(Region::parse)
+                .transpose()
+                .context("invalid scan_region in the config file")?,
+            display: cli.display,
+            debug_artifacts: cli.debug_artifacts.clone(),
+        },
+        link_retries: cli.link_retries,
+        captcha_timeout: cli.captcha_timeout,
+        sync: SyncOptions::default(),
+        pin: PinFormat {
+            digits: cli.pin_digits,
+            group: cli.pin_group,
+        },
+        container_config: container_config
+            .unwrap_or_else(|| saved_container_config(&settings, &account)),
+        limits,
+        proxy,
+        network,
+        data_volume,
     })
 }
 
+pub fn saved_settings_messages(before: &Settings, after: &Settings) -> Vec<String> {
+    let path = crate::settings::settings_path();
+    let mut messages = Vec::new();
+    if let Some(flavor) = after
+        .image_flavor
+        .filter(|_| before.image_flavor != after.image_flavor)
+    {
+        messages.push(format!(
+            "Saved image flavor {} in {}; later commands use it too. Pass --image-flavor native to go back.",
+            flavor.as_str(),
+            path.display()
+        ));
+    }
+    for (what, old, new, flag) in [
+        (
+            "registry mirror",
+            &before.registry_mirror,
+            &after.registry_mirror,
+            "--registry-mirror",
+        ),
+        (
+            "signature key",
+            &before.signature_key,
+            &after.signature_key,
+            "--verify-signature",
+        ),
+        (
+            "webhook URL",
+            &before.webhook_url,
+            &after.webhook_url,
+            "--webhook-url",
+        ),
+        (
+            "scan region",
+            &before.scan_region,
+            &after.scan_region,
+            "--region",
+        ),
+    ] {
+        if old == new {
+            continue;
+        }
+        messages.push(match new {
+            Some(value) => format!(
+                "Saved {what} {value} in {}; later commands use it too. Pass {flag} \"\" to clear it.",
+                path.display()
+            ),
+            None => format!("Cleared the saved {what} in {}.", path.display()),
+        });
+    }
+    let mut accounts: Vec<&String> = before
+        .container_configs
+        .iter()
+        .chain(&after.container_configs)
+        .map(|(account, _)| account)
+        .collect();
+    accounts.sort();
+    accounts.dedup();
+    for account in accounts {
+        let new = saved_container_config(after, account);
+        if saved_container_config(before, account) == new {
+            continue;
+        }
+        messages.push(match new {
+            Some(value) => format!(
+                "Saved container config {value} for {} in {}; later commands for it use it too. Pass --container-config \"\" to clear it.",
+                account_label(account),
+                path.display()
+            ),
+            None => format!(
+                "Cleared the saved container config for {} in {}.",
+                account_label(account),
+                path.display()
+            ),
+        });
+    }
+    messages
+}
+
+fn account_label(account: &str) -> &str {
+    if account.is_empty() {
+        "accounts without their own"
+    } else {
+        account
+    }
+}
+
+pub fn saved_container_config(settings: &Settings, account: &str) -> Option<String> {
+    settings
+        .container_configs
+        .iter()
+        .find(|(saved, _)| saved == account)
+        .or_else(|| {
+            settings
+                .container_configs
+                .iter()
+                .find(|(saved, _)| saved.is_empty())
+        })
+        .map(|(_, path)| path.clone())
+}
+
+pub fn normalize_container_config(path: &str) -> Result<Option<String>> {
+    let trimmed = path.trim();
+    if trimmed.is_empty() {
+        return Ok(None);
+    }
+    if !trimmed.starts_with('/') {
+        bail!("--container-config must be an absolute path inside the container, got '{trimmed}'")
+    }
+    let trimmed = trimmed.trim_end_matches('/');
+    if trimmed.is_empty() {
+        bail!("--container-config cannot be the container root")
+    }
+    Ok((trimmed != crate::CONTAINER_CONFIG_DIR).then(|| trimmed.to_string()))
+}
+
+/// Key files are stored as absolute paths so runs from another directory find them.
+pub fn normalize_signature_key(value: &str) -> Option<String> {
+    let trimmed = value.trim();
+    if trimmed.is_empty() {
+        return None;
+    }
+    let path = Path::new(trimmed);
+    if path.is_file() {
+        return Some(absolute_path(path).display().to_string());
+    }
+    Some(trimmed.to_string())
+}
+
+pub fn normalize_memory_limit(value: &str) -> Result<Option<String>> {
+    let trimmed = value.trim().to_ascii_lowercase();
+    if trimmed.is_empty() {
+        return Ok(None);
+    }
+    let digits = trimmed.strip_suffix('b').unwrap_or(&trimmed);
+    let digits = digits.strip_suffix(['k', 'm', 'g']).unwrap_or(digits);
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        bail!("invalid --memory '{value}', expected a size such as 512m or 2g")
+    }
+    Ok(Some(trimmed))
+}
+
+pub fn normalize_cpu_limit(value: &str) -> Result<Option<String>> {
+    let trimmed = value.trim();
+    if trimmed.is_empty() {
+        return Ok(None);
+    }
+    match trimmed.parse::<f64>() {
+        Ok(cpus) if cpus.is_finite() && cpus > 0.0 => Ok(Some(trimmed.to_string())),
+        _ => bail!("invalid --cpus '{value}', expected a positive number such as 1.5"),
+    }
+}
+
+pub fn normalize_pids_limit(value: &str) -> Result<Option<u32>> {
+    let trimmed = value.trim();
+    if trimmed.is_empty() {
+        return Ok(None);
+    }
+    match trimmed.parse::<u32>() {
+        Ok(limit) if limit > 0 => Ok(Some(limit)),
+        _ => bail!("invalid --pids-limit '{value}', expected a positive number"),
+    }
+}
+
+pub fn normalize_proxy(value: &str) -> Result<Option<String>> {
+    if value.trim().is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Proxy::parse(value)?.url))
+}
+
+pub fn normalize_network(value: &str) -> Result<Option<String>> {
+    let trimmed = value.trim();
+    if trimmed.is_empty() {
+        return Ok(None);
+    }
+    let valid = |c: char| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | ':');
+    if !trimmed.chars().all(valid) {
+        bail!("invalid --network '{value}', expected a network name such as host or bridge")
+    }
+    Ok(Some(trimmed.to_string()))
+}
+
+pub fn normalize_region(value: &str) -> Result<Option<String>> {
+    let trimmed = value.trim();
+    if trimmed.is_empty() {
+        return Ok(None);
+    }
+    let region = Region::parse(trimmed).context("invalid --region")?;
+    Ok(Some(region.spec()))
+}
+
 pub fn default_data_dir() -> PathBuf {
     match home_dir() {
         Some(mut p) => {
@@ -43,6 +486,44 @@ pub fn default_data_dir() -> PathBuf {
     }
 }
 
+pub fn read_secret(value: Option<&str>, file: Option<&Path>, what: &str) -> Result<String> {
+    read_secret_from(value, file, what, &mut std::io::stdin())
+}
+
+pub fn read_secret_from(
+    value: Option<&str>,
+    file: Option<&Path>,
+    what: &str,
+    stdin: &mut impl Read,
+) -> Result<String> {
+    let secret = match (value, file) {
+        (Some("-"), _) => {
+            let mut found = None;
+            for line in BufReader::new(stdin).lines() {
+                let line = line.with_context(|| format!("failed to read the {what} from stdin"))?;
+                if !line.trim().is_empty() {
+                    found = Some(line.trim().to_string());
+                    break;
+                }
+            }
+            found
+        }
+        (Some(value), _) => Some(value.trim().to_string()).filter(|value| !value.is_empty()),
+        (None, Some(path)) => fs::read_to_string(path)
+            .with_context(|| format!("failed to read the {what} from {}", path.display()))?
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .map(str::to_string),
+        (None, None) => bail!("no {what} given"),
+    };
+
+    match secret {
+        Some(secret) => Ok(secret),
+        None => bail!("the {what} is empty"),
+    }
+}
+
 pub fn validate_account(account: &str) -> Result<()> {
     if !account.starts_with('+') {
         bail!("account must start with '+' in international format")
@@ -50,9 +531,198 @@ pub fn validate_account(account: &str) -> Result<()> {
     Ok(())
 }
 
+/// A missing data dir on an external or encrypted volume usually means the volume is not mounted
+/// (yet); creating it would leave an empty account dir on the boot disk at the same path.
+pub fn ensure_data_dir_volume(data_dir: &Path) -> Result<()> {
+    if data_dir.exists() {
+        return Ok(());
+    }
+    let Some(volume) = crate::system::volume_root(data_dir) else {
+        return Ok(());
+    };
+    if crate::system::is_volume_mounted(&volume) {
+        return Ok(());
+    }
+    wait_for_data_dir_volume(&volume, data_dir)
+}
+
+fn unmounted_volume_error(volume: &Path, data_dir: &Path) -> anyhow::Error {
+    SignalSetupError::DataDirVolumeUnmounted {
+        volume: volume.display().to_string(),
+        data_dir: data_dir.display().to_string(),
+    }
+    .into()
+}
+
+/// `vm_runtime`: containers run in a VM (Docker Desktop, Lima), which only sees local disks.
+pub fn check_data_dir(
+    data_dir: &Path,
+    create: bool,
+    fs_type: Option<&str>,
+    vm_runtime: bool,
+) -> Result<()> {
+    if data_dir.exists() && !data_dir.is_dir() {
+        bail!(
+            "the data dir {} is a file, not a directory; pass another --data-dir",
+            data_dir.display()
+        )
+    }
+    if let Some(fs_type) = fs_type.filter(|fs_type| crate::system::is_network_filesystem(fs_type)) {
+        if vm_runtime {
+            bail!(
+                "the data dir {} is on a network share ({fs_type}), which Docker Desktop and Lima cannot mount into containers; signal-cli would write to an empty directory instead. Use a local --data-dir",
+                data_dir.display()
+            )
+        }
+    }
+    if !create && !data_dir.exists() {
+        bail!(
+            "the data dir {} does not exist, and --create-data-dir=false was given",
+            data_dir.display()
+        )
+    }
+    Ok(())
+}
+
+const DOCKER_DESKTOP_DEFAULT_SHARES: &[&str] =
+    &["/Users", "/Volumes", "/private", "/tmp", "/var/folders"];
+
+pub fn docker_desktop_shares() -> Option<Vec<PathBuf>> {
+    let dir = home_dir()?.join("Library/Group Containers/group.com.docker");
+    ["settings-store.json", "settings.json"]
+        .iter()
+        .filter_map(|name| fs::read_to_string(dir.join(name)).ok())
+        .find_map(|raw| parse_docker_desktop_shares(&raw))
+}
+
+/// `FilesharingDirectories` (settings-store.json, Docker Desktop 4.35+) or
+/// `filesharingDirectories` (settings.json, older releases).
+pub fn parse_docker_desktop_shares(raw: &str) -> Option<Vec<PathBuf>> {
+    let value: serde_json::Value = serde_json::from_str(raw).ok()?;
+    let dirs = value
+        .get("FilesharingDirectories")
+        .or_else(|| value.get("filesharingDirectories"))?
+        .as_array()?;
+    Some(
+        dirs.iter()
+            .filter_map(serde_json::Value::as_str)
+            .map(PathBuf::from)
+            .collect(),
+    )
+}
+
+/// Bind mounts of unshared paths are silently replaced by an empty directory in the VM.
+pub fn check_docker_desktop_sharing(
+    data_dir: &Path,
+    shares: Option<&[PathBuf]>,
+) -> Result<Option<String>> {
+    if !data_dir.is_absolute() {
+        return Ok(None);
+    }
+    let resolved = resolve_existing_prefix(data_dir);
+    let is_shared = |share: &Path| data_dir.starts_with(share) || resolved.starts_with(share);
+    match shares {
+        Some(shares) if shares.iter().any(|share| is_shared(share)) => Ok(None),
+        Some(shares) => Err(SignalSetupError::DataDirNotShared {
+            data_dir: data_dir.display().to_string(),
+            shares: shares
+                .iter()
+                .map(|share| share.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+        }
+        .into()),
+        None if DOCKER_DESKTOP_DEFAULT_SHARES
+            .iter()
+            .any(|share| is_shared(Path::new(share))) =>
+        {
+            Ok(None)
+        }
+        None => Ok(Some(format!(
+            "Warning: {} is outside the directories Docker Desktop shares by default ({}). Unless it was added under Settings > Resources > File sharing, signal-cli cannot see it.",
+            data_dir.display(),
+            DOCKER_DESKTOP_DEFAULT_SHARES.join(", ")
+        ))),
+    }
+}
+
+/// `path` with its longest existing ancestor resolved, so `/tmp/x` matches a `/private/tmp`
+/// share even before `x` exists.
+fn resolve_existing_prefix(path: &Path) -> PathBuf {
+    for ancestor in path.ancestors() {
+        if let Ok(resolved) = fs::canonicalize(ancestor) {
+            let rest = path.strip_prefix(ancestor).unwrap_or(Path::new(""));
+            return resolved.join(rest);
+        }
+    }
+    path.to_path_buf()
+}
+
+fn absolute_path(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+    std::env::current_dir()
+        .map(|dir| dir.join(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(not(test))]
+fn wait_for_data_dir_volume(volume: &Path, data_dir: &Path) -> Result<()> {
+    use std::io::IsTerminal;
+    if !(std::io::stdin().is_terminal() && std::io::stderr().is_terminal()) {
+        return Err(unmounted_volume_error(volume, data_dir));
+    }
+
+    eprintln!(
+        "The data dir {} is on {}, which is not mounted.",
+        data_dir.display(),
+        volume.display()
+    );
+    let theme = ui::theme();
+    loop {
+        let choice = ui::select(
+            &theme,
+            "Mount or unlock the volume, then",
+            &["Check again", "Wait until it is mounted", "Cancel"],
+            0,
+        )?;
+        match choice {
+            0 if crate::system::is_volume_mounted(volume) => return Ok(()),
+            0 => eprintln!("{} is still not mounted.", volume.display()),
+            1 => {
+                eprintln!("Waiting for {} (Ctrl+C to stop)...", volume.display());
+                while !crate::system::is_volume_mounted(volume) {
+                    crate::shutdown::sleep_unless_terminated(std::time::Duration::from_secs(
+                        crate::VOLUME_POLL_SECS,
+                    ))?;
+                }
+                return Ok(());
+            }
+            _ => return Err(unmounted_volume_error(volume, data_dir)),
+        }
+    }
+}
+
+#[cfg(test)]
+fn wait_for_data_dir_volume(volume: &Path, data_dir: &Path) -> Result<()> {
+    Err(unmounted_volume_error(volume, data_dir))
+}
+
+pub fn account_candidates(recent: &[String], in_data_dir: &[String]) -> Vec<String> {
+    let mut candidates: Vec<String> = Vec::new();
+    for account in recent.iter().chain(in_data_dir) {
+        if validate_account(account).is_ok() && !candidates.contains(account) {
+            candidates.push(account.clone());
+        }
+    }
+    candidates
+}
+
 #[cfg(not(test))]
 pub fn ensure_account_interactive(
     existing: Option<String>,
+    known: &[String],
     theme: &ColorfulTheme,
 ) -> Result<String> {
     if let Some(value) = existing {
@@ -60,10 +730,20 @@ pub fn ensure_account_interactive(
         return Ok(value);
     }
 
+    crate::system::require_terminal("Account number", "Pass --account +<number>.")?;
+    if !known.is_empty() {
+        let mut items = known.to_vec();
+        items.push("Enter another number...".to_string());
+        let choice = ui::fuzzy_select(theme, "Account", &items, 0)?;
+        if let Some(account) = known.get(choice) {
+            return Ok(account.clone());
+        }
+    }
     loop {
-        let value: String = Input::with_theme(theme)
-            .with_prompt("Account number (international format, e.g. +33612345678)")
-            .interact_text()?;
+        let value: String = ui::input(
+            theme,
+            "Account number (international format, e.g. +33612345678)",
+        )?;
         if validate_account(&value).is_ok() {
             return Ok(value);
         }
@@ -74,6 +754,7 @@ pub fn ensure_account_interactive(
 #[cfg(test)]
 pub fn ensure_account_interactive(
     existing: Option<String>,
+    known: &[String],
     _theme: &ColorfulTheme,
 ) -> Result<String> {
     match existing {
@@ -81,6 +762,10 @@ pub fn ensure_account_interactive(
             validate_account(&value)?;
             Ok(value)
         }
-        None => Ok("+10000000000".to_string()),
+        None => Ok(known
+            .first()
+            .map(String::as_str)
+            .unwrap_or("+10000000000")
+            .to_string()),
     }
 }
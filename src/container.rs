@@ -0,0 +1,246 @@
+use anyhow::{bail, Context, Result};
+use rand::rngs::OsRng;
+use rand::Rng;
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+use crate::audit::AuditedCommand;
+use crate::config::Config;
+use crate::daemon::jsonrpc_call;
+use crate::docker::{base_docker_run_cmd, config_args, trust_args};
+use crate::runtime::ContainerRuntime;
+use crate::shutdown::sleep_unless_terminated;
+
+/// Containers this process started and has not removed yet, for exits that skip `Drop`.
+static RUNNING: Mutex<Vec<(ContainerRuntime, String, Option<u64>)>> = Mutex::new(Vec::new());
+
+/// Removed when the last config clone is dropped.
+#[derive(Debug)]
+pub struct SessionContainer {
+    runtime: ContainerRuntime,
+    name: String,
+}
+
+impl SessionContainer {
+    pub fn start(cfg: &Config) -> Result<Self> {
+        let name = container_name(std::process::id(), OsRng.gen());
+        let mut cmd = base_docker_run_cmd(cfg);
+        cmd.arg("--detach")
+            .arg("--name")
+            .arg(&name)
+            .arg("--entrypoint")
+            .arg("sleep")
+            .arg(&cfg.image)
+            .arg("infinity")
+            .stdin(Stdio::null());
+        let output = cmd
+            .audited_output()
+            .context("failed to start the signal-cli container")?;
+        if !output.status.success() {
+            bail!(
+                "failed to start the signal-cli container: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+        }
+        track(cfg.runtime, &name, None);
+        let session = SessionContainer {
+            runtime: cfg.runtime,
+            name,
+        };
+        let mut check = session.exec_cmd(&[]);
+        check.arg("sh").arg("-c").arg(":").stdin(Stdio::null());
+        let ready = check
+            .audited_output()
+            .is_ok_and(|output| output.status.success());
+        if !ready {
+            bail!(
+                "the signal-cli container {} stopped right after starting",
+                session.name
+            )
+        }
+        Ok(session)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn exec_cmd(&self, env: &[(&str, &str)]) -> Command {
+        let mut cmd = self.runtime.command();
+        cmd.arg("exec").arg("-i");
+        for (key, value) in env {
+            cmd.arg("--env").arg(format!("{key}={value}"));
+        }
+        cmd.arg(&self.name);
+        cmd
+    }
+}
+
+impl Drop for SessionContainer {
+    fn drop(&mut self) {
+        remove(self.runtime, &self.name, None);
+    }
+}
+
+/// Removed when the last config clone is dropped.
+#[derive(Debug)]
+pub struct JsonRpcDaemon {
+    runtime: ContainerRuntime,
+    name: String,
+    socket_dir: TempDir,
+    pub trust_new_identities: bool,
+}
+
+impl JsonRpcDaemon {
+    pub fn start(cfg: &Config) -> Result<Self> {
+        // Docker Desktop and remote engines cannot hand a socket in a mount back to the host.
+        if !cfg!(target_os = "linux") || cfg.data_volume.is_some() {
+            bail!("the JSON-RPC socket can only be shared with a local Docker engine on Linux")
+        }
+        let socket_dir = tempfile::Builder::new()
+            .prefix("signal-setup-rpc-")
+            .tempdir()
+            .context("failed to create a directory for the JSON-RPC socket")?;
+        let name = container_name(std::process::id(), OsRng.gen());
+        let mut cmd = daemon_cmd(cfg, &name, socket_dir.path());
+        cmd.stdin(Stdio::null());
+        let output = cmd
+            .audited_output()
+            .context("failed to start the signal-cli daemon")?;
+        if !output.status.success() {
+            bail!(
+                "failed to start the signal-cli daemon: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+        }
+        track(cfg.runtime, &name, Some(crate::DAEMON_STOP_GRACE_SECS));
+        let daemon = JsonRpcDaemon {
+            runtime: cfg.runtime,
+            name,
+            socket_dir,
+            trust_new_identities: cfg.trust_new_identities,
+        };
+        let deadline = Instant::now() + Duration::from_secs(crate::JSONRPC_DAEMON_START_SECS);
+        loop {
+            if daemon.socket().exists()
+                && jsonrpc_call(&daemon.addr(), "version", json!({})).is_ok()
+            {
+                return Ok(daemon);
+            }
+            if Instant::now() >= deadline {
+                bail!(
+                    "the signal-cli daemon {} did not answer on its JSON-RPC socket within {}s",
+                    daemon.name,
+                    crate::JSONRPC_DAEMON_START_SECS
+                )
+            }
+            sleep_unless_terminated(Duration::from_millis(crate::JSONRPC_DAEMON_POLL_MS))?;
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn socket(&self) -> PathBuf {
+        self.socket_dir.path().join(JSONRPC_SOCKET_NAME)
+    }
+
+    pub fn addr(&self) -> String {
+        format!("unix:{}", self.socket().display())
+    }
+}
+
+impl Drop for JsonRpcDaemon {
+    fn drop(&mut self) {
+        // The daemon may be writing its account store; SIGKILL could leave it half written.
+        remove(
+            self.runtime,
+            &self.name,
+            Some(crate::DAEMON_STOP_GRACE_SECS),
+        );
+    }
+}
+
+const JSONRPC_SOCKET_NAME: &str = "jsonrpc.sock";
+const JSONRPC_SOCKET_MOUNT: &str = "/run/signal-setup";
+
+/// Multi-account, so `register` and `verify` work for an account that is not registered yet.
+pub(crate) fn daemon_cmd(cfg: &Config, name: &str, socket_dir: &Path) -> Command {
+    let mut cmd = base_docker_run_cmd(cfg);
+    cmd.arg("--detach")
+        .arg("--name")
+        .arg(name)
+        .arg("--volume")
+        .arg(format!("{}:{JSONRPC_SOCKET_MOUNT}", socket_dir.display()))
+        .arg(&cfg.image)
+        .args(config_args(cfg))
+        .args(trust_args(cfg))
+        .arg("daemon")
+        .arg("--socket")
+        .arg(format!("{JSONRPC_SOCKET_MOUNT}/{JSONRPC_SOCKET_NAME}"))
+        .arg("--receive-mode")
+        .arg("manual");
+    cmd
+}
+
+pub fn container_name(pid: u32, nonce: u32) -> String {
+    format!("signal-setup-{pid}-{nonce:08x}")
+}
+
+pub fn remove_all() {
+    let running = std::mem::take(
+        &mut *RUNNING
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()),
+    );
+    for (runtime, name, stop_grace) in running {
+        remove(runtime, &name, stop_grace);
+    }
+}
+
+fn track(runtime: ContainerRuntime, name: &str, stop_grace: Option<u64>) {
+    RUNNING
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .push((runtime, name.to_string(), stop_grace));
+}
+
+/// `stop_grace` gives the container that many seconds to exit on SIGTERM before it is removed.
+fn remove(runtime: ContainerRuntime, name: &str, stop_grace: Option<u64>) {
+    RUNNING
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .retain(|(_, running, _)| running != name);
+    let stopped = stop_grace.is_some_and(|grace| {
+        runtime
+            .command()
+            .arg("stop")
+            .arg("-t")
+            .arg(grace.to_string())
+            .arg(name)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .audited_status()
+            .is_ok_and(|status| status.success())
+    });
+    // `rm -f` kills right away: the idle `sleep` ignores the SIGTERM of `docker stop`.
+    let removed = runtime
+        .command()
+        .arg("rm")
+        .arg("-f")
+        .arg(name)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .audited_status();
+    // A stopped `--rm` container may already be gone.
+    if !stopped && !removed.is_ok_and(|status| status.success()) {
+        eprintln!(
+            "Warning: failed to remove the signal-cli container {name}; run 'docker rm -f {name}'."
+        );
+    }
+}
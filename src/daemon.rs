@@ -0,0 +1,347 @@
+use anyhow::{anyhow, bail, Context, Result};
+use clap::ValueEnum;
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::os::unix::net::UnixStream;
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::process::{ExitStatus, Output};
+use std::time::Duration;
+
+use crate::audit::AuditedCommand;
+use crate::config::Config;
+use crate::docker::{
+    base_docker_run_cmd, config_args, container_config_dir, container_java_opts, container_user,
+    trust_args,
+};
+use crate::errors::SignalSetupError;
+use crate::proxy::Proxy;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DbusBus {
+    /// The desktop session bus (DBUS_SESSION_BUS_ADDRESS)
+    Session,
+    /// The system bus (requires a DBus policy allowing org.asamk.Signal)
+    System,
+}
+
+pub fn render_compose(cfg: &Config, port: u16) -> String {
+    let mut out = String::new();
+    out.push_str("services:\n");
+    out.push_str("  signal-cli:\n");
+    out.push_str(&format!("    image: {}\n", yaml_string(&cfg.image)));
+    out.push_str("    restart: unless-stopped\n");
+    let trust = trust_args(cfg)
+        .iter()
+        .map(|arg| format!("{}, ", yaml_string(arg)))
+        .collect::<String>();
+    let config = config_args(cfg)
+        .iter()
+        .map(|arg| format!("{}, ", yaml_string(arg)))
+        .collect::<String>();
+    out.push_str(&format!(
+        "    command: [{config}\"-a\", {}, {trust}\"daemon\", \"--tcp\", {}]\n",
+        yaml_string(&cfg.account),
+        yaml_string(&daemon_listen_addr(cfg, port))
+    ));
+    let java_opts = container_java_opts(cfg);
+    let proxy_env = cfg
+        .proxy
+        .as_ref()
+        .map(Proxy::container_env)
+        .unwrap_or_default();
+    if java_opts.is_some() || !proxy_env.is_empty() {
+        out.push_str("    environment:\n");
+    }
+    if let Some(java_opts) = &java_opts {
+        out.push_str(&format!("      JAVA_OPTS: {}\n", yaml_string(java_opts)));
+    }
+    for (key, value) in &proxy_env {
+        out.push_str(&format!("      {key}: {}\n", yaml_string(value)));
+    }
+    if let Some(user) = container_user() {
+        out.push_str(&format!("    user: {}\n", yaml_string(&user)));
+    }
+    if let Some(memory) = &cfg.limits.memory {
+        out.push_str(&format!("    mem_limit: {}\n", yaml_string(memory)));
+    }
+    if let Some(cpus) = &cfg.limits.cpus {
+        out.push_str(&format!("    cpus: {}\n", yaml_string(cpus)));
+    }
+    if let Some(pids) = cfg.limits.pids_limit {
+        out.push_str(&format!("    pids_limit: {pids}\n"));
+    }
+    match cfg.network.as_deref() {
+        // Nothing to publish on the host network; the daemon listens on the host's loopback.
+        Some("host") => out.push_str("    network_mode: \"host\"\n"),
+        network => {
+            if let Some(network) = network {
+                out.push_str(&format!("    network_mode: {}\n", yaml_string(network)));
+            }
+            out.push_str("    ports:\n");
+            out.push_str(&format!("      - \"127.0.0.1:{port}:{port}\"\n"));
+        }
+    }
+    out.push_str("    volumes:\n");
+    out.push_str(&format!(
+        "      - {}\n",
+        yaml_string(&format!(
+            "{}:{}",
+            cfg.data_dir.display(),
+            container_config_dir(cfg)
+        ))
+    ));
+    out.push_str("    tmpfs:\n");
+    out.push_str("      - /tmp:exec\n");
+    out
+}
+
+pub fn write_compose_file(cfg: &Config, path: &Path, port: u16, force: bool) -> Result<()> {
+    if path.exists() && !force {
+        bail!(
+            "{} already exists (use --force to overwrite)",
+            path.display()
+        )
+    }
+
+    fs::write(path, render_compose(cfg, port))
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+fn host_network(cfg: &Config) -> bool {
+    cfg.network.as_deref() == Some("host")
+}
+
+/// On the host network this is the host itself, so it stays on loopback.
+fn daemon_listen_addr(cfg: &Config, port: u16) -> String {
+    if host_network(cfg) {
+        format!("127.0.0.1:{port}")
+    } else {
+        format!("0.0.0.0:{port}")
+    }
+}
+
+pub fn run_daemon(cfg: &Config, port: Option<u16>, dbus: Option<DbusBus>) -> Result<()> {
+    if port.is_none() && dbus.is_none() {
+        bail!("nothing to expose: enable the TCP port or --dbus")
+    }
+
+    let session_address = std::env::var("DBUS_SESSION_BUS_ADDRESS").ok();
+    let dbus_args = match dbus {
+        Some(bus) => dbus_container_args(bus, session_address.as_deref())?,
+        None => Vec::new(),
+    };
+
+    fs::create_dir_all(&cfg.data_dir)
+        .with_context(|| format!("failed to create data dir {}", cfg.data_dir.display()))?;
+    crate::remote::refresh_data_volume(cfg)?;
+
+    let mut cmd = base_docker_run_cmd(cfg);
+    if let Some(port) = port.filter(|_| !host_network(cfg)) {
+        cmd.arg("--publish").arg(format!("127.0.0.1:{port}:{port}"));
+    }
+    cmd.args(&dbus_args)
+        .arg(&cfg.image)
+        .args(config_args(cfg))
+        .arg("-a")
+        .arg(&cfg.account)
+        .args(trust_args(cfg))
+        .arg("daemon");
+    if let Some(port) = port {
+        cmd.arg("--tcp").arg(daemon_listen_addr(cfg, port));
+    }
+    match dbus {
+        Some(DbusBus::Session) => {
+            cmd.arg("--dbus");
+        }
+        Some(DbusBus::System) => {
+            cmd.arg("--dbus-system");
+        }
+        None => {}
+    }
+
+    let status = cmd
+        .audited_status()
+        .context("failed to run signal-cli 'daemon' command")?;
+    crate::remote::mirror_to_data_dir(cfg);
+    if !status.success() {
+        return Err(SignalSetupError::SignalCliCommandFailed {
+            command: "daemon".to_string(),
+            reason: format!("exited with {status}"),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+pub fn jsonrpc_call(addr: &str, method: &str, params: Value) -> Result<Value> {
+    // A receive waits up to its own timeout before answering.
+    let waits = params.get("timeout").and_then(Value::as_u64).unwrap_or(0);
+    let timeout = Duration::from_secs(crate::JSONRPC_TIMEOUT_SECS + waits);
+    if let Some(path) = addr.strip_prefix("unix:") {
+        let stream = UnixStream::connect(path)
+            .with_context(|| format!("failed to connect to signal-cli JSON-RPC at {addr}"))?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        return jsonrpc_exchange(stream, method, params);
+    }
+    let stream = TcpStream::connect(addr)
+        .with_context(|| format!("failed to connect to signal-cli JSON-RPC at {addr}"))?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    jsonrpc_exchange(stream, method, params)
+}
+
+fn jsonrpc_exchange(mut stream: impl Read + Write, method: &str, params: Value) -> Result<Value> {
+    let request = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+    writeln!(stream, "{request}")?;
+
+    let mut reader = BufReader::new(stream);
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            bail!("signal-cli JSON-RPC connection closed before answering '{method}'")
+        }
+
+        let Ok(response) = serde_json::from_str::<Value>(line.trim()) else {
+            continue;
+        };
+        // Skip notifications (incoming messages) until our response arrives.
+        if response.get("id") != Some(&Value::from(1)) {
+            continue;
+        }
+        if let Some(error) = response.get("error") {
+            let message = error
+                .get("message")
+                .and_then(Value::as_str)
+                .unwrap_or("unknown error");
+            return Err(anyhow!("signal-cli JSON-RPC '{method}' failed: {message}"));
+        }
+        return Ok(response.get("result").cloned().unwrap_or(Value::Null));
+    }
+}
+
+pub fn jsonrpc_request(account: &str, args: &[String]) -> (String, Value) {
+    let method = args.first().cloned().unwrap_or_default();
+    let mut params = serde_json::Map::new();
+    params.insert("account".to_string(), Value::from(account));
+
+    let mut rest = args.iter().skip(1).peekable();
+    while let Some(arg) = rest.next() {
+        let Some(flag) = arg.strip_prefix("--") else {
+            let key = match method.as_str() {
+                "verify" => "verificationCode",
+                "setPin" => "pin",
+                _ => "recipient",
+            };
+            params.insert(key.to_string(), Value::from(arg.as_str()));
+            continue;
+        };
+
+        let key = camel_case(flag);
+        let value = match rest.next_if(|next| !next.starts_with("--")) {
+            Some(value) if JSONRPC_NUMERIC_PARAMS.contains(&key.as_str()) => value
+                .parse::<i64>()
+                .map(Value::from)
+                .unwrap_or_else(|_| Value::from(value.as_str())),
+            Some(value) => Value::from(value.as_str()),
+            None => Value::Bool(true),
+        };
+        params.insert(key, value);
+    }
+
+    (method, Value::Object(params))
+}
+
+const JSONRPC_NUMERIC_PARAMS: [&str; 2] = ["timeout", "maxMessages"];
+
+fn camel_case(flag: &str) -> String {
+    let mut out = String::new();
+    let mut upper = false;
+    for c in flag.chars() {
+        if c == '-' {
+            upper = true;
+        } else if upper {
+            out.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+pub fn run_signal_cli_jsonrpc(addr: &str, account: &str, args: &[String]) -> Output {
+    let (method, params) = jsonrpc_request(account, args);
+    match jsonrpc_call(addr, &method, params) {
+        Ok(result) => {
+            // Mirror the CLI's JSON output: one line per envelope for receive.
+            let stdout = match result {
+                Value::Array(items) if method == "receive" => items
+                    .iter()
+                    .map(|item| format!("{item}\n"))
+                    .collect::<String>(),
+                Value::Null => String::new(),
+                other => format!("{other}\n"),
+            };
+            Output {
+                status: ExitStatus::from_raw(0),
+                stdout: stdout.into_bytes(),
+                stderr: Vec::new(),
+            }
+        }
+        Err(err) => Output {
+            status: ExitStatus::from_raw(1 << 8),
+            stdout: Vec::new(),
+            stderr: format!("{err:#}\n").into_bytes(),
+        },
+    }
+}
+
+pub fn dbus_container_args(bus: DbusBus, session_address: Option<&str>) -> Result<Vec<String>> {
+    if !cfg!(target_os = "linux") {
+        bail!("DBus integration is only available on Linux")
+    }
+
+    match bus {
+        DbusBus::System => Ok(vec![
+            "--volume".to_string(),
+            format!("{SYSTEM_BUS_SOCKET}:{SYSTEM_BUS_SOCKET}"),
+            "--env".to_string(),
+            format!("DBUS_SYSTEM_BUS_ADDRESS=unix:path={SYSTEM_BUS_SOCKET}"),
+        ]),
+        DbusBus::Session => {
+            let address = session_address.context(
+                "DBUS_SESSION_BUS_ADDRESS is not set; run the daemon from a desktop session",
+            )?;
+            let socket = session_bus_socket(address).with_context(|| {
+                format!("session bus address '{address}' is not a unix:path= socket that can be mounted into the container")
+            })?;
+            Ok(vec![
+                "--volume".to_string(),
+                format!("{socket}:{socket}"),
+                "--env".to_string(),
+                format!("DBUS_SESSION_BUS_ADDRESS=unix:path={socket}"),
+            ])
+        }
+    }
+}
+
+pub fn session_bus_socket(address: &str) -> Option<String> {
+    address.split(';').find_map(|entry| {
+        entry
+            .strip_prefix("unix:")?
+            .split(',')
+            .find_map(|pair| pair.strip_prefix("path="))
+            .map(str::to_string)
+    })
+}
+
+const SYSTEM_BUS_SOCKET: &str = "/run/dbus/system_bus_socket";
+
+// JSON strings are valid YAML double-quoted scalars.
+fn yaml_string(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_else(|_| format!("\"{value}\""))
+}
@@ -1,15 +1,33 @@
 use anyhow::{anyhow, bail, Context, Result};
-use indicatif::{ProgressBar, ProgressStyle};
-use serde_json::Value;
+use rand::rngs::OsRng;
+use rand::Rng;
+use serde_json::{json, Value};
 use std::fs;
 use std::io::Write;
-use std::process::{Command, Stdio};
+use std::process::{Child, Command, Stdio};
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use crate::audit::{self, AuditedCommand};
+use crate::command_queue;
 use crate::config::Config;
+use crate::container;
+use crate::daemon::{jsonrpc_call, jsonrpc_request, run_signal_cli_jsonrpc};
 use crate::errors::SignalSetupError;
+use crate::hints;
+use crate::image_ref::split_image_tag;
+use crate::ip::{self, PublicIp};
+use crate::journal;
+use crate::notify::{notify, Event};
+use crate::proxy::{unreachable_hint, Proxy};
+use crate::remote;
+use crate::runtime::{lima_instance, ContainerRuntime};
+use crate::shutdown::{check_terminated, sleep_unless_terminated};
+use crate::signal_args;
+use crate::signal_output::SignalCliOutput;
 use crate::system::command_exists;
+use crate::ui::Progress;
+use crate::vault;
 
 pub fn ensure_docker_ready() -> Result<()> {
     if !command_exists("docker") {
@@ -25,20 +43,21 @@ pub fn ensure_docker_ready() -> Result<()> {
         return Err(SignalSetupError::DockerStartFailed.into());
     }
 
-    let wait_pb = ProgressBar::new(crate::DOCKER_START_TIMEOUT_SECS);
-    let wait_style = ProgressStyle::with_template(
+    let wait_pb = Progress::new(
+        crate::DOCKER_START_TIMEOUT_SECS,
         "{spinner:.green} [{bar:30.cyan/blue}] {pos}/{len}s waiting for Docker daemon...",
-    )
-    .unwrap_or_else(|_| ProgressStyle::default_bar())
-    .progress_chars("=> ");
-    wait_pb.set_style(wait_style);
-    wait_pb.enable_steady_tick(Duration::from_millis(120));
+    );
+    wait_pb.set_message("Waiting for the Docker daemon...");
 
     let start = Instant::now();
     let timeout = Duration::from_secs(crate::DOCKER_START_TIMEOUT_SECS);
     let mut sleep_ms = 150_u64;
 
     while start.elapsed() < timeout {
+        if let Err(err) = check_terminated() {
+            wait_pb.abandon_with_message("Stopped while waiting for Docker.");
+            return Err(err);
+        }
         if docker_daemon_is_ready()? {
             wait_pb.finish_with_message("Docker daemon is ready.");
             return Ok(());
@@ -65,18 +84,56 @@ pub fn docker_daemon_is_ready() -> Result<bool> {
         .arg("info")
         .stdout(Stdio::null())
         .stderr(Stdio::null())
-        .status()
+        .audited_status()
         .context("failed to run docker info")?;
     Ok(status.success())
 }
 
+pub fn ensure_nerdctl_ready() -> Result<()> {
+    let runtime = ContainerRuntime::Nerdctl;
+    if !runtime.is_installed() {
+        return Err(SignalSetupError::NerdctlNotInstalled.into());
+    }
+
+    if runtime_is_ready(runtime)? {
+        return Ok(());
+    }
+
+    let instance = lima_instance();
+    if !command_exists("limactl") {
+        return Err(SignalSetupError::LimaStartFailed { instance }.into());
+    }
+
+    println!("nerdctl is not responding. Starting Lima VM '{instance}'...");
+    let started = Command::new("limactl")
+        .args(["start", &instance])
+        .audited_status()
+        .is_ok_and(|status| status.success());
+    if started && runtime_is_ready(runtime)? {
+        return Ok(());
+    }
+
+    Err(SignalSetupError::LimaStartFailed { instance }.into())
+}
+
+pub fn runtime_is_ready(runtime: ContainerRuntime) -> Result<bool> {
+    let status = runtime
+        .command()
+        .arg("info")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .audited_status()
+        .with_context(|| format!("failed to run {} info", runtime.as_str()))?;
+    Ok(status.success())
+}
+
 pub fn try_start_docker() -> bool {
     #[cfg(target_os = "macos")]
     {
         if command_exists("open")
             && Command::new("open")
                 .args(["-a", "Docker"])
-                .status()
+                .audited_status()
                 .is_ok_and(|s| s.success())
         {
             return true;
@@ -84,7 +141,12 @@ pub fn try_start_docker() -> bool {
         if open::that("/Applications/Docker.app").is_ok() || open::that("Docker").is_ok() {
             return true;
         }
-        false
+        // Colima, as installed by `bootstrap`, has no app to open.
+        command_exists("colima")
+            && Command::new("colima")
+                .arg("start")
+                .audited_status()
+                .is_ok_and(|s| s.success())
     }
 
     #[cfg(target_os = "linux")]
@@ -92,14 +154,17 @@ pub fn try_start_docker() -> bool {
         if command_exists("systemctl") {
             if let Ok(status) = Command::new("systemctl")
                 .args(["--user", "start", "docker-desktop"])
-                .status()
+                .audited_status()
             {
                 if status.success() {
                     return true;
                 }
             }
 
-            if let Ok(status) = Command::new("systemctl").args(["start", "docker"]).status() {
+            if let Ok(status) = Command::new("systemctl")
+                .args(["start", "docker"])
+                .audited_status()
+            {
                 if status.success() {
                     return true;
                 }
@@ -114,151 +179,629 @@ pub fn try_start_docker() -> bool {
     }
 }
 
-pub fn register_with_mode(cfg: &Config, token: &str, voice: bool) -> Result<()> {
-    let mut args = vec![
-        "register".to_string(),
-        "--captcha".to_string(),
-        token.to_string(),
+pub fn host_docker_arch() -> &'static str {
+    match std::env::consts::ARCH {
+        "aarch64" => "arm64",
+        "x86_64" => "amd64",
+        other => other,
+    }
+}
+
+pub fn inspect_image_manifest(image: &str) -> Option<Value> {
+    let output = Command::new("docker")
+        .args(["manifest", "inspect", image])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .audited_output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    serde_json::from_slice::<Value>(&output.stdout).ok()
+}
+
+pub fn image_supports_arch(image: &str, arch: &str) -> Option<bool> {
+    let manifest = inspect_image_manifest(image)?;
+    Some(manifest_supports_arch(&manifest, arch))
+}
+
+// Manifests are content-addressed, so identical manifests mean identical digests.
+pub fn image_manifests_match(upstream: &str, mirrored: &str) -> Option<bool> {
+    let upstream_manifest = inspect_image_manifest(upstream)?;
+    let mirrored_manifest = inspect_image_manifest(mirrored)?;
+    Some(upstream_manifest == mirrored_manifest)
+}
+
+/// Fails closed, and checks the pulled digest rather than the tag.
+pub fn verify_image_signature(cfg: &Config) -> Result<()> {
+    let Some(key) = &cfg.signature_key else {
+        return Ok(());
+    };
+    if !command_exists("cosign") {
+        return Err(SignalSetupError::CosignNotInstalled.into());
+    }
+    let invalid = |reason: String| SignalSetupError::ImageSignatureInvalid {
+        image: cfg.image.clone(),
+        reason,
+    };
+    let Some(digest) = crate::pull::image_digest(cfg) else {
+        return Err(invalid("the local image has no registry digest to verify".to_string()).into());
+    };
+    let (repository, _) = split_image_tag(&cfg.image);
+    let reference = format!("{repository}@{digest}");
+    let output = Command::new("cosign")
+        .args(["verify", "--key", key, &reference])
+        .stdin(Stdio::null())
+        .audited_output()
+        .context("failed to run cosign")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr
+            .lines()
+            .map(str::trim)
+            .rev()
+            .find(|line| !line.is_empty())
+            .unwrap_or("cosign verify failed")
+            .to_string();
+        return Err(invalid(reason).into());
+    }
+    println!("Verified the cosign signature of {reference}.");
+    Ok(())
+}
+
+pub fn verify_registry_mirror(cfg: &Config) -> Result<()> {
+    let Some(upstream) = &cfg.mirrored_from else {
+        return Ok(());
+    };
+
+    match image_manifests_match(upstream, &cfg.image) {
+        Some(true) => Ok(()),
+        Some(false) => Err(SignalSetupError::RegistryMirrorDigestMismatch {
+            mirror_image: cfg.image.clone(),
+            upstream_image: upstream.clone(),
+        }
+        .into()),
+        None => {
+            eprintln!(
+                "Warning: could not compare {} with {upstream}; using the mirror without digest validation.",
+                cfg.image
+            );
+            Ok(())
+        }
+    }
+}
+
+fn manifest_supports_arch(manifest: &Value, arch: &str) -> bool {
+    match manifest.get("manifests").and_then(Value::as_array) {
+        Some(entries) => entries.iter().any(|entry| {
+            entry
+                .pointer("/platform/architecture")
+                .and_then(Value::as_str)
+                == Some(arch)
+        }),
+        // A plain (non-list) manifest carries no platform; published single-arch images are amd64.
+        None => arch == "amd64",
+    }
+}
+
+pub fn image_requires_emulation(image: &str, host_arch: &str) -> bool {
+    host_arch == "arm64" && image_supports_arch(image, host_arch) == Some(false)
+}
+
+pub fn emulation_warning(image: &str) -> String {
+    format!(
+        "Warning: {image} has no arm64 variant. It will run under emulation, which can be slow enough for registration to time out."
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistrationMode {
+    Sms,
+    Voice,
+    /// SMS attempt first, then a voice call after a pause; for numbers that cannot get SMS.
+    Landline,
+}
+
+impl RegistrationMode {
+    pub const ALL: [RegistrationMode; 3] = [
+        RegistrationMode::Sms,
+        RegistrationMode::Voice,
+        RegistrationMode::Landline,
     ];
-    if voice {
-        args.push("--voice".to_string());
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RegistrationMode::Sms => "sms",
+            RegistrationMode::Voice => "voice",
+            RegistrationMode::Landline => "landline",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.as_str() == name)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            RegistrationMode::Sms => "SMS",
+            RegistrationMode::Voice => "Voice call",
+            RegistrationMode::Landline => "Landline (SMS attempt, then voice call)",
+        }
+    }
+
+    pub fn places_calls(self) -> bool {
+        matches!(self, RegistrationMode::Voice | RegistrationMode::Landline)
+    }
+}
+
+pub fn register(cfg: &Config, token: &str, mode: RegistrationMode) -> Result<SignalCliOutput> {
+    match mode {
+        RegistrationMode::Sms => register_with_mode(cfg, token, false),
+        RegistrationMode::Voice => register_with_mode(cfg, token, true),
+        RegistrationMode::Landline => register_landline(cfg, token),
     }
+}
+
+pub fn register_with_mode(cfg: &Config, token: &str, voice: bool) -> Result<SignalCliOutput> {
+    journal::warn_about_recent_attempts(&cfg.account);
+    let public_ip = ip::check_public_ip();
+    let args = signal_args::register(token, voice);
 
     run_signal_cli_with_retries(
         cfg,
         &args,
+        Some(token),
         crate::REGISTER_RETRY_ATTEMPTS,
         crate::REGISTER_RETRY_DELAY_SECS,
         "registration",
-    )?;
+        public_ip,
+    )
+}
+
+/// Signal accepts the registration's captcha token for a while; after that a new token is needed.
+pub fn request_voice_call(cfg: &Config, token: &str) -> Result<()> {
+    run_signal_cli_hiding(cfg, &signal_args::register(token, true), token, false)?;
     Ok(())
 }
 
-pub fn register_landline(cfg: &Config, token: &str) -> Result<()> {
+pub fn register_landline(cfg: &Config, token: &str) -> Result<SignalCliOutput> {
+    journal::warn_about_recent_attempts(&cfg.account);
+    let public_ip = ip::check_public_ip();
     println!("Step 1/3: SMS registration attempt...");
-    let sms_args = vec![
-        "register".to_string(),
-        "--captcha".to_string(),
-        token.to_string(),
-    ];
-    let sms_ok = run_signal_cli(cfg, &sms_args, true)?;
-    if !sms_ok {
-        println!("SMS failed (expected for voice-only numbers). Continuing...");
+    let sms_args = signal_args::register(token, false);
+    let sms = run_signal_cli_hiding(cfg, &sms_args, token, true)?;
+    if !sms.succeeded {
+        println!(
+            "SMS failed (expected for voice-only numbers): {}. Continuing...",
+            sms.failure_reason()
+        );
     }
 
     println!("Step 2/3: waiting {} seconds...", crate::LANDLINE_WAIT_SECS);
-    let wait_pb = ProgressBar::new(crate::LANDLINE_WAIT_SECS);
-    let wait_style =
-        ProgressStyle::with_template("{spinner:.green} [{bar:30.magenta/blue}] {pos}/{len}s")
-            .unwrap_or_else(|_| ProgressStyle::default_bar())
-            .progress_chars("=> ");
-    wait_pb.set_style(wait_style);
-    wait_pb.enable_steady_tick(Duration::from_millis(120));
+    let wait_pb = Progress::new(
+        crate::LANDLINE_WAIT_SECS,
+        "{spinner:.green} [{bar:30.magenta/blue}] {pos}/{len}s",
+    );
     for _ in 0..crate::LANDLINE_WAIT_SECS {
+        if let Err(err) = sleep_unless_terminated(Duration::from_secs(1)) {
+            wait_pb.abandon_with_message("Stopped during the wait.");
+            eprintln!("The SMS attempt was already made. Continue later with: register --voice");
+            return Err(err);
+        }
         wait_pb.inc(1);
-        thread::sleep(Duration::from_secs(1));
     }
     wait_pb.finish_with_message("Wait complete.");
+    notify(cfg, Event::WaitFinished, None);
 
     println!("Step 3/3: voice registration...");
-    let voice_args = vec![
-        "register".to_string(),
-        "--voice".to_string(),
-        "--captcha".to_string(),
-        token.to_string(),
-    ];
+    let voice_args = signal_args::register(token, true);
     run_signal_cli_with_retries(
         cfg,
         &voice_args,
+        Some(token),
         crate::REGISTER_RETRY_ATTEMPTS,
         crate::REGISTER_RETRY_DELAY_SECS,
         "voice registration",
-    )?;
-    Ok(())
+        public_ip,
+    )
 }
 
 pub fn run_signal_cli_with_retries(
     cfg: &Config,
     args: &[String],
+    secret: Option<&str>,
     attempts: u32,
     delay_secs: u64,
     label: &str,
-) -> Result<()> {
+    mut public_ip: Option<PublicIp>,
+) -> Result<SignalCliOutput> {
     if attempts == 0 {
         bail!("{label} attempts must be > 0")
     }
 
+    let mut unreachable = false;
     for attempt in 1..=attempts {
-        let ok = run_signal_cli(cfg, args, true)?;
-        if ok {
-            return Ok(());
+        let output = match secret {
+            Some(secret) => run_signal_cli_hiding(cfg, args, secret, true)?,
+            None => run_signal_cli(cfg, args, true)?,
+        };
+        if output.succeeded {
+            return Ok(output);
         }
+        unreachable = is_network_failure(&output.stdout, &output.stderr);
 
         if attempt < attempts {
-            println!("{label} failed (attempt {attempt}/{attempts}). Retrying in {delay_secs}s...");
-            thread::sleep(Duration::from_secs(delay_secs));
+            println!(
+                "{label} failed (attempt {attempt}/{attempts}): {}. Retrying in {delay_secs}s...",
+                output.failure_reason()
+            );
+            sleep_unless_terminated(Duration::from_secs(delay_secs))?;
+            if let Some(previous) = &public_ip {
+                public_ip = Some(ip::recheck_public_ip(previous));
+            }
         }
     }
 
+    if unreachable {
+        let command_name = args.first().map(String::as_str).unwrap_or("unknown");
+        return Err(signal_unreachable(cfg, command_name).into());
+    }
     bail!(
         "{label} failed after {attempts} attempts. {}",
-        registration_failure_hint()
+        hints::registration_failure().render()
     )
 }
 
-pub fn verify_code(cfg: &Config, code: &str, pin: Option<&str>) -> Result<()> {
-    if let Some(pin_value) = pin {
+pub fn verify_code(cfg: &Config, code: &str, pin: Option<&str>) -> Result<SignalCliOutput> {
+    if let (Some(pin_value), Some(_)) = (pin, jsonrpc_addr(cfg)) {
+        run_signal_cli(cfg, &signal_args::verify(code, Some(pin_value)), false)
+    } else if let Some(pin_value) = pin {
         run_signal_cli_with_stdin_secret(
             cfg,
             "verify",
-            "read -r SIGNAL_VERIFY_CODE; read -r SIGNAL_PIN; signal-cli -o json -a \"$SIGNAL_ACCOUNT\" verify \"$SIGNAL_VERIFY_CODE\" --pin \"$SIGNAL_PIN\"",
+            "read -r SIGNAL_VERIFY_CODE; read -r SIGNAL_PIN; signal-cli ${SIGNAL_CONFIG:+--config \"$SIGNAL_CONFIG\"} -o json -a \"$SIGNAL_ACCOUNT\" verify \"$SIGNAL_VERIFY_CODE\" --pin \"$SIGNAL_PIN\"",
             &format!("{code}\n{pin_value}\n"),
             false,
-        )?;
+        )
     } else {
-        let args = vec!["verify".to_string(), code.to_string()];
-        run_signal_cli(cfg, &args, false)?;
+        run_signal_cli_hiding(cfg, &signal_args::verify(code, None), code, false)
     }
-    Ok(())
 }
 
 pub fn set_registration_lock_pin(cfg: &Config, pin: &str) -> Result<()> {
+    if jsonrpc_addr(cfg).is_some() {
+        run_signal_cli(cfg, &signal_args::set_pin(pin), false)?;
+        return Ok(());
+    }
+
     run_signal_cli_with_stdin_secret(
         cfg,
         "setPin",
-        "read -r SIGNAL_PIN; signal-cli -o json -a \"$SIGNAL_ACCOUNT\" setPin \"$SIGNAL_PIN\"",
+        "read -r SIGNAL_PIN; signal-cli ${SIGNAL_CONFIG:+--config \"$SIGNAL_CONFIG\"} -o json -a \"$SIGNAL_ACCOUNT\" setPin \"$SIGNAL_PIN\"",
         &format!("{pin}\n"),
         false,
     )?;
     Ok(())
 }
 
-pub fn list_devices(cfg: &Config) -> Result<()> {
-    let args = vec!["listDevices".to_string()];
-    run_signal_cli(cfg, &args, false)?;
-    Ok(())
+pub fn list_devices(cfg: &Config) -> Result<SignalCliOutput> {
+    run_signal_cli(cfg, &signal_args::list_devices(), false)
 }
 
-pub fn run_signal_cli(cfg: &Config, args: &[String], allow_failure: bool) -> Result<bool> {
-    fs::create_dir_all(&cfg.data_dir)
-        .with_context(|| format!("failed to create data dir {}", cfg.data_dir.display()))?;
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkedDevice {
+    pub id: u64,
+    pub name: Option<String>,
+    /// `lastSeen` as reported by Signal, which rounds it to the day
+    pub last_seen_ms: Option<u64>,
+}
+
+impl LinkedDevice {
+    pub fn label(&self) -> String {
+        match &self.name {
+            Some(name) => format!("#{} ({name})", self.id),
+            None => format!("#{}", self.id),
+        }
+    }
 
+    pub fn named(&self) -> String {
+        match self.name.as_deref().map(str::trim) {
+            Some(name) if !name.is_empty() => format!("'{name}' (device #{})", self.id),
+            _ => format!("device #{} (no name reported)", self.id),
+        }
+    }
+}
+
+pub fn linked_devices(cfg: &Config) -> Option<Vec<LinkedDevice>> {
+    let output = run_signal_cli_captured(cfg, &signal_args::list_devices()).ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_linked_devices(&String::from_utf8_lossy(&output.stdout))
+}
+
+pub fn parse_linked_devices(stdout: &str) -> Option<Vec<LinkedDevice>> {
+    let devices = stdout
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line.trim()).ok())
+        .find_map(|json| json.as_array().cloned())?;
+    Some(
+        devices
+            .iter()
+            .filter_map(|device| {
+                Some(LinkedDevice {
+                    id: device.get("id")?.as_u64()?,
+                    name: device
+                        .get("name")
+                        .and_then(Value::as_str)
+                        .map(str::to_string),
+                    last_seen_ms: device.get("lastSeen").and_then(Value::as_u64),
+                })
+            })
+            .collect(),
+    )
+}
+
+pub fn signal_cli_version(cfg: &Config) -> Result<String> {
+    if let Some(addr) = jsonrpc_addr(cfg) {
+        let result = jsonrpc_call(&addr, "version", json!({}))?;
+        return Ok(format!(
+            "signal-cli {}",
+            result
+                .get("version")
+                .and_then(Value::as_str)
+                .unwrap_or("unknown")
+        ));
+    }
+
+    // `--version` reads no account data, so a new container gets no data dir mount; a locked
+    // vault must not end up next to an empty plaintext data dir.
+    let mut cmd = match &cfg.session {
+        Some(_) => signal_cli_container_cmd(cfg).0,
+        None => {
+            let mut cmd = cfg.runtime.command();
+            cmd.arg("run").arg("--rm").arg(&cfg.image);
+            cmd
+        }
+    };
+    cmd.arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let output = cmd
+        .audited_output()
+        .context("failed to run signal-cli '--version' command")?;
+    if !output.status.success() {
+        return Err(SignalSetupError::SignalCliCommandFailed {
+            command: "--version".to_string(),
+            reason: SignalCliOutput::from_process("--version", &output)
+                .failure_reason()
+                .to_string(),
+        }
+        .into());
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default()
+        .to_string())
+}
+
+pub fn parse_signal_cli_version(text: &str) -> Option<(u32, u32, u32)> {
+    let version = text
+        .split_whitespace()
+        .find(|word| word.starts_with(|c: char| c.is_ascii_digit()))?;
+    let mut parts = version
+        .split(|c: char| !c.is_ascii_digit())
+        .map(|part| part.parse::<u32>().ok());
+    let major = parts.next()??;
+    let minor = parts.next().flatten().unwrap_or(0);
+    let patch = parts.next().flatten().unwrap_or(0);
+    Some((major, minor, patch))
+}
+
+pub fn check_signal_cli_version(cfg: &Config, strict: bool) -> Result<String> {
+    let raw = signal_cli_version(cfg)?;
+    let (min_major, min_minor, min_patch) = crate::MIN_SIGNAL_CLI_VERSION;
+    let minimum = format!("{min_major}.{min_minor}.{min_patch}");
+
+    match parse_signal_cli_version(&raw) {
+        Some(version) if version < crate::MIN_SIGNAL_CLI_VERSION => {
+            let err = SignalSetupError::SignalCliTooOld {
+                version: raw.clone(),
+                minimum,
+            };
+            if strict {
+                return Err(err.into());
+            }
+            eprintln!("Warning: {err}");
+        }
+        Some(_) => {}
+        None => {
+            eprintln!("Warning: could not parse the signal-cli version from '{raw}'.");
+        }
+    }
+    Ok(raw)
+}
+
+pub fn run_signal_cli(
+    cfg: &Config,
+    args: &[String],
+    allow_failure: bool,
+) -> Result<SignalCliOutput> {
     let command_name = args.first().map(String::as_str).unwrap_or("unknown");
-    let mut cmd = base_docker_run_cmd(cfg);
-    cmd.arg(&cfg.image)
-        .arg("-o")
-        .arg("json")
-        .arg("-a")
-        .arg(&cfg.account)
-        .args(args)
-        .stdin(Stdio::inherit())
+    let _turn = command_queue::wait_turn(cfg)?;
+    if let Some(addr) = jsonrpc_addr(cfg) {
+        let output = run_signal_cli_jsonrpc(&addr, &cfg.account, args);
+        journal::record_attempt(&cfg.account, args, attempt_outcome(&output));
+        return handle_signal_cli_output(command_name, output, allow_failure, cfg, None);
+    }
+
+    ensure_data_dir(cfg)?;
+
+    let (mut cmd, container) = signal_cli_cmd(cfg, args);
+    cmd.stdin(Stdio::inherit())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped());
 
+    let since = unix_now();
     let output = cmd
-        .output()
+        .audited_output()
         .with_context(|| format!("failed to run signal-cli '{command_name}' command"))?;
-    handle_signal_cli_output(command_name, output, allow_failure)
+    remote::mirror_to_data_dir(cfg);
+    journal::record_attempt(&cfg.account, args, attempt_outcome(&output));
+    handle_signal_cli_output(
+        command_name,
+        output,
+        allow_failure,
+        cfg,
+        Some((&container, since)),
+    )
+}
+
+pub fn run_signal_cli_captured(cfg: &Config, args: &[String]) -> Result<std::process::Output> {
+    let _turn = command_queue::wait_turn(cfg)?;
+    if let Some(addr) = jsonrpc_addr(cfg) {
+        return Ok(run_signal_cli_jsonrpc(&addr, &cfg.account, args));
+    }
+
+    ensure_data_dir(cfg)?;
+
+    let command_name = args.first().map(String::as_str).unwrap_or("unknown");
+    let (mut cmd, _) = signal_cli_cmd(cfg, args);
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let output = cmd
+        .audited_output()
+        .with_context(|| format!("failed to run signal-cli '{command_name}' command"))?;
+    remote::mirror_to_data_dir(cfg);
+    Ok(output)
+}
+
+pub fn spawn_signal_cli_streaming(cfg: &Config, args: &[String]) -> Result<Child> {
+    if cfg.daemon_addr.is_some() {
+        bail!("streaming receive is not available with --daemon-addr; the daemon already receives messages")
+    }
+
+    ensure_data_dir(cfg)?;
+
+    let command_name = args.first().map(String::as_str).unwrap_or("unknown");
+    let (mut cmd, _) = signal_cli_cmd(cfg, args);
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit());
+
+    cmd.audited_spawn()
+        .with_context(|| format!("failed to run signal-cli '{command_name}' command"))
+}
+
+pub fn describe_invocation(cfg: &Config, args: &[String]) -> String {
+    let masked = audit::mask_args(args);
+    if let Some(addr) = &cfg.daemon_addr {
+        let (method, params) = jsonrpc_request(&cfg.account, &masked);
+        return format!(
+            "JSON-RPC {addr}: {}",
+            json!({ "method": method, "params": params })
+        );
+    }
+    let (cmd, _) = signal_cli_cmd(cfg, &masked);
+    let line: Vec<String> = std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    signal_args::shell_join(&line)
+}
+
+fn signal_cli_cmd(cfg: &Config, args: &[String]) -> (Command, String) {
+    let (mut cmd, container) = signal_cli_container_cmd(cfg);
+    cmd.args(config_args(cfg))
+        .arg("-o")
+        .arg("json")
+        .arg("-a")
+        .arg(&cfg.account)
+        .args(trust_args(cfg))
+        .args(args);
+    (cmd, container)
+}
+
+pub fn jsonrpc_addr(cfg: &Config) -> Option<String> {
+    cfg.daemon_addr
+        .clone()
+        .or_else(|| cfg.jsonrpc.as_ref().map(|daemon| daemon.addr()))
+}
+
+fn signal_cli_container_cmd(cfg: &Config) -> (Command, String) {
+    match &cfg.session {
+        Some(session) => {
+            let mut cmd = session.exec_cmd(&[]);
+            cmd.arg("signal-cli");
+            (cmd, session.name().to_string())
+        }
+        None => {
+            let name = container::container_name(std::process::id(), OsRng.gen());
+            let mut cmd = base_docker_run_cmd(cfg);
+            cmd.arg("--name").arg(&name).arg(&cfg.image);
+            (cmd, name)
+        }
+    }
+}
+
+pub(crate) fn container_config_dir(cfg: &Config) -> &str {
+    cfg.container_config
+        .as_deref()
+        .unwrap_or(crate::CONTAINER_CONFIG_DIR)
+}
+
+pub(crate) fn config_args(cfg: &Config) -> Vec<String> {
+    match &cfg.container_config {
+        Some(path) => vec!["--config".to_string(), path.clone()],
+        None => Vec::new(),
+    }
+}
+
+pub(crate) fn trust_args(cfg: &Config) -> &'static [&'static str] {
+    if cfg.trust_new_identities {
+        &["--trust-new-identities", "always"]
+    } else {
+        &[]
+    }
+}
+
+/// Keeps the captcha token or code out of host process listings.
+fn run_signal_cli_hiding(
+    cfg: &Config,
+    args: &[String],
+    secret: &str,
+    allow_failure: bool,
+) -> Result<SignalCliOutput> {
+    if jsonrpc_addr(cfg).is_some() {
+        return run_signal_cli(cfg, args, allow_failure);
+    }
+    let command_name = args.first().map(String::as_str).unwrap_or("unknown");
+    let script_args = args
+        .iter()
+        .map(|arg| {
+            if arg == secret {
+                "\"$SIGNAL_SECRET\"".to_string()
+            } else {
+                signal_args::shell_join(std::slice::from_ref(arg))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    run_signal_cli_with_stdin_secret(
+        cfg,
+        command_name,
+        &format!("read -r SIGNAL_SECRET; signal-cli ${{SIGNAL_CONFIG:+--config \"$SIGNAL_CONFIG\"}} -o json -a \"$SIGNAL_ACCOUNT\" {script_args}"),
+        &format!("{secret}\n"),
+        allow_failure,
+    )
 }
 
 fn run_signal_cli_with_stdin_secret(
@@ -267,24 +810,44 @@ fn run_signal_cli_with_stdin_secret(
     shell_script: &str,
     stdin_payload: &str,
     allow_failure: bool,
-) -> Result<bool> {
-    fs::create_dir_all(&cfg.data_dir)
-        .with_context(|| format!("failed to create data dir {}", cfg.data_dir.display()))?;
+) -> Result<SignalCliOutput> {
+    let _turn = command_queue::wait_turn(cfg)?;
+    ensure_data_dir(cfg)?;
 
-    let mut cmd = base_docker_run_cmd(cfg);
-    cmd.arg("--env")
-        .arg(format!("SIGNAL_ACCOUNT={}", cfg.account))
-        .arg("--entrypoint")
-        .arg("sh")
-        .arg(&cfg.image)
-        .arg("-c")
+    let mut env = Vec::new();
+    if let Some(path) = &cfg.container_config {
+        env.push(("SIGNAL_CONFIG", path.as_str()));
+    }
+    env.push(("SIGNAL_ACCOUNT", cfg.account.as_str()));
+    let (mut cmd, container) = match &cfg.session {
+        Some(session) => {
+            let mut cmd = session.exec_cmd(&env);
+            cmd.arg("sh");
+            (cmd, session.name().to_string())
+        }
+        None => {
+            let name = container::container_name(std::process::id(), OsRng.gen());
+            let mut cmd = base_docker_run_cmd(cfg);
+            for (key, value) in &env {
+                cmd.arg("--env").arg(format!("{key}={value}"));
+            }
+            cmd.arg("--name")
+                .arg(&name)
+                .arg("--entrypoint")
+                .arg("sh")
+                .arg(&cfg.image);
+            (cmd, name)
+        }
+    };
+    cmd.arg("-c")
         .arg(shell_script)
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped());
 
+    let since = unix_now();
     let mut child = cmd
-        .spawn()
+        .audited_spawn()
         .with_context(|| format!("failed to run signal-cli '{command_name}' command"))?;
 
     if let Some(mut stdin) = child.stdin.take() {
@@ -293,15 +856,83 @@ fn run_signal_cli_with_stdin_secret(
             .with_context(|| format!("failed to send secret input to '{command_name}' command"))?;
     }
 
+    let pid = child.id();
     let output = child
         .wait_with_output()
         .with_context(|| format!("failed to wait for signal-cli '{command_name}' command"))?;
-    handle_signal_cli_output(command_name, output, allow_failure)
+    audit::record_exit(pid, &output.status);
+    remote::mirror_to_data_dir(cfg);
+    journal::record_attempt(
+        &cfg.account,
+        &[command_name.to_string()],
+        attempt_outcome(&output),
+    );
+    handle_signal_cli_output(
+        command_name,
+        output,
+        allow_failure,
+        cfg,
+        Some((&container, since)),
+    )
+}
+
+/// A `--rm` container is gone once it exits, so its `oom` event is looked up instead.
+fn oom_killed(runtime: ContainerRuntime, name: &str, since: u64) -> Option<bool> {
+    let inspected = runtime
+        .command()
+        .args(["inspect", "-f", "{{.State.OOMKilled}}", name])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .audited_output()
+        .ok()
+        .filter(|output| output.status.success());
+    if let Some(output) = inspected {
+        return match String::from_utf8_lossy(&output.stdout).trim() {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        };
+    }
+    if runtime != ContainerRuntime::Docker {
+        return None;
+    }
+    let output = runtime
+        .command()
+        .arg("events")
+        .arg("--since")
+        .arg(since.to_string())
+        .arg("--until")
+        .arg(unix_now().saturating_add(1).to_string())
+        .arg("--filter")
+        .arg(format!("container={name}"))
+        .arg("--filter")
+        .arg("event=oom")
+        .arg("--format")
+        .arg("{{.Action}}")
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .audited_output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| !String::from_utf8_lossy(&output.stdout).trim().is_empty())
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
 }
 
-fn base_docker_run_cmd(cfg: &Config) -> Command {
-    let volume = format!("{}:/var/lib/signal-cli", cfg.data_dir.display());
-    let mut cmd = Command::new("docker");
+pub(crate) fn base_docker_run_cmd(cfg: &Config) -> Command {
+    let source = match &cfg.data_volume {
+        Some(volume) => volume.clone(),
+        None => cfg.data_dir.display().to_string(),
+    };
+    let volume = format!("{source}:{}", container_config_dir(cfg));
+    let mut cmd = cfg.runtime.command();
     cmd.arg("run")
         .arg("--rm")
         .arg("-i")
@@ -309,56 +940,152 @@ fn base_docker_run_cmd(cfg: &Config) -> Command {
         .arg(volume)
         .arg("--tmpfs")
         .arg("/tmp:exec");
-    add_linux_user_mapping(&mut cmd);
+    cmd.args(cfg.limits.args());
+    if let Some(network) = &cfg.network {
+        cmd.arg("--network").arg(network);
+    }
+    if let Some(java_opts) = container_java_opts(cfg) {
+        cmd.arg("--env").arg(format!("JAVA_OPTS={java_opts}"));
+    }
+    for (key, value) in cfg.proxy.iter().flat_map(Proxy::container_env) {
+        cmd.arg("--env").arg(format!("{key}={value}"));
+    }
+    // Local uids mean nothing on a remote engine; `docker cp` hands the volume to root there.
+    if cfg.data_volume.is_none() {
+        add_linux_user_mapping(&mut cmd);
+    }
     cmd
 }
 
-#[cfg(target_os = "linux")]
+pub(crate) fn container_java_opts(cfg: &Config) -> Option<String> {
+    let proxy = cfg.proxy.as_ref().map(Proxy::java_opts);
+    let opts: Vec<&str> = [cfg.image_flavor.java_opts(), proxy.as_deref()]
+        .into_iter()
+        .flatten()
+        .collect();
+    (!opts.is_empty()).then(|| opts.join(" "))
+}
+
+fn ensure_data_dir(cfg: &Config) -> Result<()> {
+    vault::ensure_unlocked(cfg)?;
+    fs::create_dir_all(&cfg.data_dir)
+        .with_context(|| format!("failed to create data dir {}", cfg.data_dir.display()))?;
+    remote::refresh_data_volume(cfg)
+}
+
 fn add_linux_user_mapping(cmd: &mut Command) {
+    if let Some(user) = container_user() {
+        cmd.arg("--user").arg(user);
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn container_user() -> Option<String> {
     let uid = unsafe { libc::geteuid() };
     let gid = unsafe { libc::getegid() };
-    cmd.arg("--user").arg(format!("{uid}:{gid}"));
+    Some(format!("{uid}:{gid}"))
 }
 
 #[cfg(not(target_os = "linux"))]
-fn add_linux_user_mapping(_cmd: &mut Command) {}
+pub fn container_user() -> Option<String> {
+    None
+}
 
 fn handle_signal_cli_output(
     command_name: &str,
     output: std::process::Output,
     allow_failure: bool,
-) -> Result<bool> {
-    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-
-    if output.status.success() {
-        emit_signal_output(command_name, &stdout, &stderr, true);
-        return Ok(true);
+    cfg: &Config,
+    container: Option<(&str, u64)>,
+) -> Result<SignalCliOutput> {
+    let result = SignalCliOutput::from_process(command_name, &output);
+    if result.succeeded || allow_failure {
+        return Ok(result);
     }
 
-    emit_signal_output(command_name, &stdout, &stderr, false);
-
-    if allow_failure {
-        return Ok(false);
+    // Any SIGKILL, from the OOM killer or from `docker kill`, exits with 128 + 9.
+    if output.status.code() == Some(crate::CONTAINER_KILLED_EXIT_CODE) {
+        let oom = container.and_then(|(name, since)| oom_killed(cfg.runtime, name, since));
+        let cause = match oom {
+            Some(true) => format!(
+                ": its container ran out of memory{}. Raise --memory or free memory on the host, then retry.",
+                cfg.limits
+                    .memory
+                    .as_deref()
+                    .map(|limit| format!(" (--memory {limit})"))
+                    .unwrap_or_default()
+            ),
+            Some(false) => " before it finished; its container was stopped, for example by 'docker kill' or an interrupted run.".to_string(),
+            None => " before it finished. If it ran out of memory, raise --memory.".to_string(),
+        };
+        return Err(SignalSetupError::SignalCliKilled {
+            command: command_name.to_string(),
+            cause,
+        }
+        .into());
     }
 
+    let (stdout, stderr) = (result.stdout.as_str(), result.stderr.as_str());
+    let reason = result.failure_reason().to_string();
+
     if command_name == "register" {
-        if is_rate_limited(&stdout, &stderr) {
+        if is_rate_limited(stdout, stderr) {
             return Err(SignalSetupError::SignalCliRateLimited.into());
         }
-        return Err(SignalSetupError::RegisterFailed.into());
+        if is_network_failure(stdout, stderr) {
+            return Err(signal_unreachable(cfg, command_name).into());
+        }
+        return Err(SignalSetupError::RegisterFailed { reason }.into());
     }
 
-    if is_rate_limited(&stdout, &stderr) {
+    if is_rate_limited(stdout, stderr) {
         return Err(SignalSetupError::SignalCliRateLimited.into());
     }
 
+    if command_name == "verify" {
+        if is_registration_locked(stdout, stderr) {
+            return Err(SignalSetupError::RegistrationLocked.into());
+        }
+        if is_registration_pin_incorrect(stdout, stderr) {
+            return Err(SignalSetupError::RegistrationPinIncorrect.into());
+        }
+    }
+
+    if command_name == "addDevice" {
+        if let Some(err) = classify_add_device_failure(stdout, stderr) {
+            return Err(err.into());
+        }
+    }
+
+    if is_network_failure(stdout, stderr) {
+        return Err(signal_unreachable(cfg, command_name).into());
+    }
+
     Err(SignalSetupError::SignalCliCommandFailed {
         command: command_name.to_string(),
+        reason,
     }
     .into())
 }
 
+fn attempt_outcome(output: &std::process::Output) -> &'static str {
+    if output.status.success() {
+        return "ok";
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if is_rate_limited(&stdout, &stderr) {
+        "rate_limited"
+    } else if format!("{stdout}\n{stderr}")
+        .to_lowercase()
+        .contains("captcha")
+    {
+        "captcha_rejected"
+    } else {
+        "failed"
+    }
+}
+
 fn is_rate_limited(stdout: &str, stderr: &str) -> bool {
     let content = format!("{stdout}\n{stderr}");
     content.contains("ExternalServiceFailureException")
@@ -367,64 +1094,70 @@ fn is_rate_limited(stdout: &str, stderr: &str) -> bool {
         || content.contains("RateLimit")
 }
 
-fn emit_signal_output(command_name: &str, stdout: &str, stderr: &str, success: bool) {
-    let stdout_trimmed = stdout.trim();
-    if !stdout_trimmed.is_empty() {
-        if let Ok(json) = serde_json::from_str::<Value>(stdout_trimmed) {
-            print_json_output(command_name, &json);
-        } else {
-            println!("{stdout_trimmed}");
-        }
-    }
+/// Without a typed error this reads like a rate limit.
+pub fn is_network_failure(stdout: &str, stderr: &str) -> bool {
+    let content = format!("{stdout}\n{stderr}");
+    let lower = content.to_lowercase();
+    content.contains("UnknownHostException")
+        || content.contains("ConnectException")
+        || content.contains("SocketTimeoutException")
+        || content.contains("SSLHandshakeException")
+        || content.contains("PushNetworkException")
+        || lower.contains("network is unreachable")
+        || lower.contains("connection refused")
+}
 
-    let stderr_trimmed = stderr.trim();
-    if stderr_trimmed.is_empty() {
-        return;
+fn signal_unreachable(cfg: &Config, command_name: &str) -> SignalSetupError {
+    SignalSetupError::SignalServerUnreachable {
+        command: command_name.to_string(),
+        hint: unreachable_hint(cfg.proxy.as_ref(), cfg.network.as_deref(), cfg.image_flavor),
     }
+}
 
-    if success {
-        // Keep useful info messages from signal-cli when successful.
-        eprintln!("{stderr_trimmed}");
-        return;
-    }
+pub fn is_registration_locked(stdout: &str, stderr: &str) -> bool {
+    format!("{stdout}\n{stderr}")
+        .to_lowercase()
+        .contains("locked with a pin")
+}
 
-    let first_meaningful = stderr_trimmed
-        .lines()
-        .map(str::trim)
-        .find(|line| !line.is_empty())
-        .unwrap_or(stderr_trimmed);
-    eprintln!("{first_meaningful}");
+pub fn is_registration_pin_incorrect(stdout: &str, stderr: &str) -> bool {
+    let content = format!("{stdout}\n{stderr}").to_lowercase();
+    content.contains("invalid pin") || content.contains("incorrectpinexception")
 }
 
-fn print_json_output(command_name: &str, json: &Value) {
-    match command_name {
-        "listDevices" => {
-            if let Ok(pretty) = serde_json::to_string_pretty(json) {
-                println!("{pretty}");
-            } else {
-                println!("{json}");
-            }
-        }
-        _ => {
-            if json.is_null() {
-                return;
-            }
-            if let Some(obj) = json.as_object() {
-                if obj.is_empty() {
-                    return;
-                }
-            }
-            if let Ok(pretty) = serde_json::to_string_pretty(json) {
-                println!("{pretty}");
-            } else {
-                println!("{json}");
-            }
-        }
+/// Specific causes first, since an expired link also answers with a 4xx status.
+pub fn classify_add_device_failure(stdout: &str, stderr: &str) -> Option<SignalSetupError> {
+    let content = format!("{stdout}\n{stderr}");
+    let lower = content.to_lowercase();
+    if content.contains("DeviceLimitExceeded")
+        || content.contains("StatusCode: 411")
+        || lower.contains("device limit")
+        || lower.contains("too many devices")
+    {
+        return Some(SignalSetupError::LinkDeviceLimitReached);
+    }
+    if content.contains("InvalidKeyException")
+        || content.contains("UntrustedIdentityException")
+        || lower.contains("invalid public key")
+        || lower.contains("bad key type")
+    {
+        return Some(SignalSetupError::LinkKeyUntrusted);
+    }
+    if is_link_qr_expired(stdout, stderr) {
+        return Some(SignalSetupError::LinkQrExpired);
     }
+    if is_network_failure(stdout, stderr) {
+        return Some(SignalSetupError::LinkNetworkFailed);
+    }
+    None
 }
 
-fn registration_failure_hint() -> &'static str {
-    "If this persists: the number/operator may be blocked, or your current IP may be rate-limited. Try another network/IP (for example mobile hotspot) or another number/operator."
+fn is_link_qr_expired(stdout: &str, stderr: &str) -> bool {
+    let content = format!("{stdout}\n{stderr}");
+    content.contains("StatusCode: 404")
+        || content.contains("NotFoundException")
+        || content.contains("InvalidDeviceLinkException")
+        || content.to_lowercase().contains("expired")
 }
 
 pub fn extract_signal_captcha_token_from_output(output: &[u8]) -> Result<String> {
@@ -0,0 +1,337 @@
+use anyhow::{bail, Result};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::Config;
+use crate::docker::{
+    linked_devices, run_signal_cli, run_signal_cli_captured, runtime_is_ready, LinkedDevice,
+};
+use crate::image_update::registry_state;
+use crate::monitor::unix_now;
+use crate::net::{ensure_online, http_date};
+use crate::signal_args;
+use crate::signal_output::SignalCliOutput;
+use crate::sync::parse_sync_progress;
+use crate::system::{desktop_version_problem, signal_desktop_version};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Ok,
+    Warn,
+    Fail,
+}
+
+impl CheckStatus {
+    fn label(self) -> &'static str {
+        match self {
+            CheckStatus::Ok => "ok",
+            CheckStatus::Warn => "warn",
+            CheckStatus::Fail => "FAIL",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Check {
+            name,
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+pub fn run_doctor(cfg: &Config) -> Result<()> {
+    let mut checks = vec![internet_check(), clock_check(), runtime_check(cfg)];
+    if cfg.daemon_addr.is_none() {
+        checks.push(image_check(cfg));
+    }
+    print_checks(&checks);
+    fail_on_problems("doctor", &checks)
+}
+
+fn print_checks(checks: &[Check]) {
+    for check in checks {
+        println!(
+            "[{:>4}] {:<9} {}",
+            check.status.label(),
+            check.name,
+            check.detail
+        );
+    }
+}
+
+fn fail_on_problems(command: &str, checks: &[Check]) -> Result<()> {
+    let failed = checks
+        .iter()
+        .filter(|check| check.status == CheckStatus::Fail)
+        .count();
+    if failed > 0 {
+        bail!("{command} found {failed} problem(s)")
+    }
+    Ok(())
+}
+
+pub fn run_diagnose_sync(cfg: &Config) -> Result<()> {
+    let checks = vec![
+        receive_check(cfg),
+        contacts_sync_check(cfg),
+        desktop_activity_check(linked_devices(cfg).as_deref(), unix_now()),
+        desktop_version_check(signal_desktop_version().as_deref()),
+    ];
+    print_checks(&checks);
+    let steps = sync_remediation(&checks);
+    if !steps.is_empty() {
+        println!("\nWhat to do:");
+        for (index, step) in steps.iter().enumerate() {
+            println!("  {}. {step}", index + 1);
+        }
+    }
+    fail_on_problems("diagnose-sync", &checks)
+}
+
+fn receive_check(cfg: &Config) -> Check {
+    if cfg.daemon_addr.is_some() {
+        return Check::new(
+            "receive",
+            CheckStatus::Ok,
+            "the signal-cli daemon receives continuously",
+        );
+    }
+    let args = signal_args::receive(cfg.sync.timeout_secs as i64, Some(cfg.sync.max_messages));
+    match run_signal_cli_captured(cfg, &args) {
+        Ok(output) if output.status.success() => Check::new(
+            "receive",
+            CheckStatus::Ok,
+            format!(
+                "the primary received {}",
+                parse_sync_progress(&String::from_utf8_lossy(&output.stdout)).summary()
+            ),
+        ),
+        Ok(output) => Check::new(
+            "receive",
+            CheckStatus::Fail,
+            SignalCliOutput::from_process("receive", &output)
+                .failure_reason()
+                .to_string(),
+        ),
+        Err(err) => Check::new("receive", CheckStatus::Fail, format!("{err:#}")),
+    }
+}
+
+fn contacts_sync_check(cfg: &Config) -> Check {
+    match run_signal_cli(cfg, &signal_args::send_contacts(), true) {
+        Ok(output) if output.succeeded => Check::new(
+            "sync",
+            CheckStatus::Ok,
+            "contacts sync message sent to linked devices",
+        ),
+        Ok(output) => Check::new("sync", CheckStatus::Fail, output.failure_reason()),
+        Err(err) => Check::new("sync", CheckStatus::Fail, format!("{err:#}")),
+    }
+}
+
+/// Signal rounds `lastSeen` to the day.
+pub fn desktop_activity_check(devices: Option<&[LinkedDevice]>, now_secs: u64) -> Check {
+    let Some(devices) = devices else {
+        return Check::new(
+            "desktop",
+            CheckStatus::Fail,
+            "could not list linked devices",
+        );
+    };
+    let linked: Vec<&LinkedDevice> = devices
+        .iter()
+        .filter(|device| device.id != crate::PRIMARY_DEVICE_ID)
+        .collect();
+    if linked.is_empty() {
+        return Check::new("desktop", CheckStatus::Fail, "no linked device");
+    }
+    let Some((device, last_seen_ms)) = linked
+        .iter()
+        .filter_map(|device| Some((*device, device.last_seen_ms?)))
+        .max_by_key(|(_, last_seen_ms)| *last_seen_ms)
+    else {
+        return Check::new(
+            "desktop",
+            CheckStatus::Warn,
+            "Signal does not report when the linked devices were last seen",
+        );
+    };
+    let days = now_secs.saturating_sub(last_seen_ms / 1000) / 86_400;
+    let seen = match days {
+        0 => "today".to_string(),
+        1 => "yesterday".to_string(),
+        days => format!("{days} days ago"),
+    };
+    let detail = format!("{} last seen {seen}", device.label());
+    let status = if days <= crate::DESKTOP_RECENT_DAYS {
+        CheckStatus::Ok
+    } else if days < crate::LINKED_DEVICE_UNLINK_DAYS {
+        CheckStatus::Warn
+    } else {
+        CheckStatus::Fail
+    };
+    Check::new("desktop", status, detail)
+}
+
+pub fn desktop_version_check(version: Option<&str>) -> Check {
+    let Some(version) = version else {
+        return Check::new(
+            "version",
+            CheckStatus::Warn,
+            "could not read the installed Signal Desktop version",
+        );
+    };
+    match desktop_version_problem(version) {
+        Some(problem) => Check::new("version", CheckStatus::Fail, problem),
+        None => Check::new(
+            "version",
+            CheckStatus::Ok,
+            format!("Signal Desktop {version}"),
+        ),
+    }
+}
+
+pub fn sync_remediation(checks: &[Check]) -> Vec<&'static str> {
+    let failing = |name: &str| {
+        checks
+            .iter()
+            .any(|check| check.name == name && check.status != CheckStatus::Ok)
+    };
+    let mut steps = Vec::new();
+    if failing("receive") {
+        steps.push("The primary cannot receive, so Desktop's sync requests go unanswered. Run 'doctor', wait out any rate limit, and check the account with 'status' before anything else.");
+    }
+    if failing("sync") {
+        steps.push("The contacts sync message did not go out. Once 'receive' works, run 'diagnose-sync' again.");
+    }
+    if failing("version") {
+        steps.push("Make sure Signal Desktop is up to date (Help > Check for updates, or your package manager), then quit and reopen it.");
+    }
+    if failing("desktop") {
+        steps.push("Open Signal Desktop and keep it online on the syncing screen for a few minutes; it is only marked as seen once it connects.");
+        steps.push("Quit Signal Desktop completely and reopen it, then run 'diagnose-sync' again to answer its new sync requests.");
+        steps.push("If it is still stuck, or the device is missing, unlink it in Desktop (or on the phone) and link again with 'link-desktop-live'.");
+    }
+    steps
+}
+
+fn internet_check() -> Check {
+    match ensure_online() {
+        Ok(()) => Check::new("internet", CheckStatus::Ok, "reachable"),
+        Err(err) => Check::new("internet", CheckStatus::Fail, err.to_string()),
+    }
+}
+
+fn runtime_check(cfg: &Config) -> Check {
+    let runtime = cfg.runtime.as_str();
+    if !cfg.runtime.is_installed() {
+        return Check::new(
+            "runtime",
+            CheckStatus::Fail,
+            format!("{runtime} is not installed"),
+        );
+    }
+    match runtime_is_ready(cfg.runtime) {
+        Ok(true) => Check::new("runtime", CheckStatus::Ok, format!("{runtime} is running")),
+        Ok(false) => Check::new(
+            "runtime",
+            CheckStatus::Warn,
+            format!("{runtime} is installed but not running; it is started when needed"),
+        ),
+        Err(err) => Check::new("runtime", CheckStatus::Fail, format!("{err:#}")),
+    }
+}
+
+/// Only warns: the current image keeps working until Signal stops accepting its version.
+fn image_check(cfg: &Config) -> Check {
+    match registry_state(cfg) {
+        Ok(Some(state)) => match state.updates().first() {
+            Some(update) => Check::new(
+                "image",
+                CheckStatus::Warn,
+                format!("{} {}", update.describe(&cfg.image), update.fix()),
+            ),
+            None if state.local_digest.is_none() => Check::new(
+                "image",
+                CheckStatus::Ok,
+                format!(
+                    "{} is not pulled yet; the newest one is pulled when needed",
+                    cfg.image
+                ),
+            ),
+            None => Check::new(
+                "image",
+                CheckStatus::Ok,
+                format!("{} is up to date", cfg.image),
+            ),
+        },
+        Ok(None) => Check::new(
+            "image",
+            CheckStatus::Ok,
+            format!(
+                "{} is not on {}; not checked for updates",
+                cfg.image,
+                crate::UPSTREAM_REGISTRY
+            ),
+        ),
+        Err(err) => Check::new(
+            "image",
+            CheckStatus::Warn,
+            format!("could not check the registry for a newer image: {err:#}"),
+        ),
+    }
+}
+
+pub fn measure_clock_skew() -> Result<i64> {
+    let remote = http_date(crate::CLOCK_CHECK_URL, crate::CONNECTIVITY_TIMEOUT_SECS)?;
+    let local = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    Ok(local as i64 - remote as i64)
+}
+
+pub fn clock_check() -> Check {
+    let skew = match measure_clock_skew() {
+        Ok(skew) => skew,
+        Err(err) => {
+            return Check::new(
+                "clock",
+                CheckStatus::Warn,
+                format!("could not compare with {}: {err}", crate::CLOCK_CHECK_URL),
+            )
+        }
+    };
+    match describe_clock_skew(skew) {
+        Some(warning) => Check::new("clock", CheckStatus::Warn, warning),
+        None => Check::new(
+            "clock",
+            CheckStatus::Ok,
+            format!("within {skew:+}s of Signal's servers"),
+        ),
+    }
+}
+
+pub fn describe_clock_skew(skew_secs: i64) -> Option<String> {
+    let magnitude = skew_secs.unsigned_abs();
+    if magnitude <= crate::CLOCK_SKEW_WARN_SECS {
+        return None;
+    }
+    let direction = if skew_secs > 0 { "ahead of" } else { "behind" };
+    let amount = match magnitude {
+        secs if secs >= 3600 => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+        secs if secs >= 60 => format!("{}m {}s", secs / 60, secs % 60),
+        secs => format!("{secs}s"),
+    };
+    Some(format!(
+        "the system clock is {amount} {direction} Signal's servers; registration and verification can fail until it is fixed (enable automatic date & time)"
+    ))
+}
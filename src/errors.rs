@@ -2,7 +2,7 @@ use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum SignalSetupError {
-    #[error("Docker is not installed. Install Docker Desktop/Engine and retry.")]
+    #[error("Docker is not installed. Run 'bootstrap' to install it, or install Docker Desktop/Engine and retry.")]
     DockerNotInstalled,
 
     #[error("Docker is installed but could not be started automatically. Start Docker manually and retry.")]
@@ -11,11 +11,123 @@ pub enum SignalSetupError {
     #[error("Docker start timed out after {seconds} seconds. Open Docker Desktop and retry.")]
     DockerStartTimeout { seconds: u64 },
 
-    #[error("signal-cli 'register' command failed")]
-    RegisterFailed,
+    #[error(
+        "nerdctl is not installed. Run 'bootstrap --runtime nerdctl' to install it, install nerdctl (or Lima) and retry, or use --runtime docker."
+    )]
+    NerdctlNotInstalled,
 
-    #[error("signal-cli '{command}' command failed")]
-    SignalCliCommandFailed { command: String },
+    #[error("nerdctl is installed but not usable, and the Lima VM '{instance}' could not be started. Run 'limactl start {instance}' and retry.")]
+    LimaStartFailed { instance: String },
+
+    #[error("signal-cli 'register' command failed: {reason}")]
+    RegisterFailed { reason: String },
+
+    #[error("signal-cli '{command}' command failed: {reason}")]
+    SignalCliCommandFailed { command: String, reason: String },
+
+    #[error("signal-cli '{command}' was killed (exit 137){cause}")]
+    SignalCliKilled { command: String, cause: String },
+
+    #[error("signal-cli '{command}' could not connect to the Signal servers. {hint}")]
+    SignalServerUnreachable { command: String, hint: String },
+
+    #[error("This number has a registration lock. Verify again with its PIN (--pin, or --pin-from-keychain when the wizard stored it).")]
+    RegistrationLocked,
+
+    #[error("The registration lock PIN is wrong. Signal only allows a few wrong PINs before the number stays locked until the lock expires.")]
+    RegistrationPinIncorrect,
+
+    #[error("Mirrored image {mirror_image} does not match the digest of {upstream_image}. Refusing to run it.")]
+    RegistryMirrorDigestMismatch {
+        mirror_image: String,
+        upstream_image: String,
+    },
+
+    #[error("signal-cli {version} is older than the minimum known to register reliably ({minimum}). Use a newer image.")]
+    SignalCliTooOld { version: String, minimum: String },
+
+    #[error(
+        "The Signal Desktop QR code expired or was already used. Show a fresh QR code and retry."
+    )]
+    LinkQrExpired,
+
+    #[error("This account already has the maximum number of linked devices. Unlink one on your phone (Settings > Linked devices) and retry.")]
+    LinkDeviceLimitReached,
+
+    #[error("Could not reach the Signal servers while linking. Check your network, VPN or proxy and retry.")]
+    LinkNetworkFailed,
+
+    #[error("The public key in the QR code was rejected. Restart linking in Signal Desktop to show a new QR code, and only scan QR codes shown by Signal Desktop itself.")]
+    LinkKeyUntrusted,
+
+    #[error("Screen capture is not available: {reason}. Save a screenshot of the Signal Desktop QR code and run 'decode-qr <file>', then 'link-desktop-uri <uri>'.")]
+    ScreenCaptureUnavailable { reason: String },
+
+    #[error("addDevice succeeded but no new linked device appeared after {checks} checks. Check Signal Desktop and retry linking.")]
+    LinkedDeviceMissing { checks: u32 },
+
+    #[error("Pulling {image} from {registry} needs authentication, and no working credentials were found. {hint}")]
+    RegistryAuthFailed {
+        image: String,
+        registry: String,
+        hint: String,
+    },
+
+    #[error("Could not reach {registry} to pull {image} after {attempts} attempts. Check your network, proxy or VPN and retry.")]
+    RegistryUnreachable {
+        image: String,
+        registry: String,
+        attempts: u32,
+    },
+
+    #[error(
+        "{image} does not exist on {registry}. Check the image name and tag (see 'image tags')."
+    )]
+    ImageNotFound { image: String, registry: String },
+
+    #[error("cosign is not installed, and --verify-signature refuses to run an unverified image. Install cosign (https://docs.sigstore.dev) or turn verification off with --verify-signature ''.")]
+    CosignNotInstalled,
+
+    #[error(
+        "The cosign signature of {image} could not be verified ({reason}). Refusing to run it."
+    )]
+    ImageSignatureInvalid { image: String, reason: String },
+
+    #[error("age is not installed. Install age (https://age-encryption.org) to use an encrypted data dir.")]
+    AgeNotInstalled,
+
+    #[error("No internet connection: none of {probed} could be reached. Check Wi-Fi, VPN or proxy settings and retry.")]
+    Offline { probed: String },
+
+    #[error("The captcha page could not be loaded ({reason}).")]
+    CaptchaPageUnavailable { reason: String },
+
+    #[error("Invalid captcha token: {reason}. Copy the whole token and retry.")]
+    InvalidCaptchaToken { reason: String },
+
+    #[error("No captcha was solved within {secs}s, so the captcha window was closed. Allow more time with --captcha-timeout.")]
+    CaptchaTimedOut { secs: u64 },
+
+    #[error("The captcha window could not start: {reason}. {fix}")]
+    CaptchaWebviewUnavailable { reason: String, fix: String },
+
+    #[error("Captcha cancelled with Ctrl+C.")]
+    CaptchaInterrupted,
+
+    #[error("'{prompt}' needs an interactive terminal, but stdin or stderr is not a TTY. {hint}")]
+    NotInteractive { prompt: String, hint: String },
+
+    #[error("The data dir {data_dir} is on {volume}, which is not mounted. Mount or unlock the volume and retry; an empty data dir is not created in its place.")]
+    DataDirVolumeUnmounted { volume: String, data_dir: String },
+
+    #[error("The data dir {data_dir} is not shared with Docker Desktop (shared: {shares}); signal-cli would write to an empty directory instead. Add it under Docker Desktop > Settings > Resources > File sharing, or pick a --data-dir inside a shared directory.")]
+    DataDirNotShared { data_dir: String, shares: String },
+
+    #[error("Stopped by SIGTERM.")]
+    Terminated,
+
+    #[error("Stopped after running longer than --max-duration.")]
+    MaxDurationExceeded,
 
     #[error("signal-cli rate limited request (StatusCode 429/502). Try again with a fresh captcha and network/IP change if needed.")]
     SignalCliRateLimited,
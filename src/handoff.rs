@@ -0,0 +1,279 @@
+use anyhow::{bail, Context, Result};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use rand::rngs::OsRng;
+use rand::Rng;
+use spake2::{Ed25519Group, Identity, Password, Spake2};
+use std::io::{BufRead, BufReader, ErrorKind, IsTerminal, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use crate::captcha::parse_captcha_token;
+use crate::shutdown::{check_terminated, sleep_unless_terminated};
+
+/// Lets a stray connection be told apart from a sender with a wrong code.
+const HANDOFF_PROTOCOL: &str = "signal-setup-token/2";
+/// No 0/O or 1/I/L, so the code survives being read aloud or retyped.
+const PAIRING_ALPHABET: &[u8] = b"ABCDEFGHJKMNPQRSTUVWXYZ23456789";
+const SENDER_ID: &[u8] = b"signal-setup captcha-token --send";
+const RECEIVER_ID: &[u8] = b"signal-setup register --token-listen";
+const NONCE_LEN: usize = 12;
+
+pub fn pairing_code() -> String {
+    let mut rng = OsRng;
+    (0..crate::PAIRING_CODE_LEN)
+        .map(|_| char::from(PAIRING_ALPHABET[rng.gen_range(0..PAIRING_ALPHABET.len())]))
+        .collect()
+}
+
+/// Typed in at a prompt, or the first line on stdin, so it never shows up in the process list.
+pub fn read_pairing_code() -> Result<String> {
+    let code = if std::io::stdin().is_terminal() {
+        crate::ui::input(
+            &crate::ui::theme(),
+            "Pairing code shown by register --token-listen",
+        )?
+    } else {
+        crate::config::read_secret(Some("-"), None, "pairing code")?
+    };
+    Ok(normalize_code(&code))
+}
+
+pub fn normalize_code(code: &str) -> String {
+    code.trim().to_ascii_uppercase()
+}
+
+/// A bare port listens on every interface: the pairing code keys the channel, not the network.
+pub fn listen_address(spec: &str) -> Result<String> {
+    let spec = spec.trim();
+    if spec.contains(':') {
+        spec.to_socket_addrs().with_context(|| {
+            format!("invalid --token-listen '{spec}', expected a port or host:port")
+        })?;
+        return Ok(spec.to_string());
+    }
+    let port: u16 = spec.parse().with_context(|| {
+        format!("invalid --token-listen '{spec}', expected a port or host:port")
+    })?;
+    Ok(format!("0.0.0.0:{port}"))
+}
+
+pub fn listen_for_token(spec: &str) -> Result<String> {
+    let addr = listen_address(spec)?;
+    let listener =
+        TcpListener::bind(&addr).with_context(|| format!("failed to listen on {addr}"))?;
+    let local = listener
+        .local_addr()
+        .context("failed to resolve the token listen address")?;
+    let code = pairing_code();
+    let host = if local.ip().is_unspecified() {
+        "<this host>".to_string()
+    } else {
+        local.ip().to_string()
+    };
+    eprintln!("Waiting for the captcha token on {local}. On the machine with a browser, run:");
+    eprintln!("  captcha-token --send {host}:{}", local.port());
+    eprintln!("and enter the pairing code {code} when asked.");
+    receive_token(
+        &listener,
+        &code,
+        Duration::from_secs(crate::TOKEN_HANDOFF_TIMEOUT_SECS),
+    )
+}
+
+/// After a few wrong codes listening stops, so the code cannot be guessed.
+pub fn receive_token(listener: &TcpListener, code: &str, timeout: Duration) -> Result<String> {
+    listener
+        .set_nonblocking(true)
+        .context("failed to configure the token listener")?;
+    let deadline = Instant::now() + timeout;
+    let mut wrong_codes = 0;
+    loop {
+        check_terminated()?;
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                if Instant::now() >= deadline {
+                    bail!(
+                        "no captcha token received within {}s",
+                        timeout.as_secs().max(1)
+                    )
+                }
+                sleep_unless_terminated(Duration::from_millis(crate::TERMINATE_POLL_MS))?;
+                continue;
+            }
+            Err(err) => return Err(err).context("failed to accept a token connection"),
+        };
+        match handle_sender(stream, code) {
+            Ok(Some(token)) => return Ok(token),
+            Ok(None) => {}
+            Err(HandoffRejection::WrongCode) => {
+                wrong_codes += 1;
+                eprintln!("Rejected a sender with a wrong pairing code.");
+                if wrong_codes >= crate::PAIRING_MAX_WRONG_CODES {
+                    bail!("stopped listening after {wrong_codes} wrong pairing codes")
+                }
+            }
+            Err(HandoffRejection::Invalid(reason)) => {
+                eprintln!("Rejected a handed-over token: {reason}");
+            }
+        }
+    }
+}
+
+enum HandoffRejection {
+    WrongCode,
+    Invalid(String),
+}
+
+fn handle_sender(
+    stream: TcpStream,
+    code: &str,
+) -> std::result::Result<Option<String>, HandoffRejection> {
+    let timeout = Some(Duration::from_secs(crate::TOKEN_HANDOFF_IO_SECS));
+    if stream.set_nonblocking(false).is_err()
+        || stream.set_read_timeout(timeout).is_err()
+        || stream.set_write_timeout(timeout).is_err()
+    {
+        return Ok(None);
+    }
+    let mut reader = BufReader::new(&stream);
+    let mut writer = &stream;
+    let Some(line) = read_line(&mut reader) else {
+        return Ok(None);
+    };
+    let Some(message) = line.strip_prefix(HANDOFF_PROTOCOL).map(str::trim) else {
+        return Ok(None);
+    };
+    let (spake, outbound) = Spake2::<Ed25519Group>::start_b(
+        &Password::new(normalize_code(code).as_bytes()),
+        &Identity::new(SENDER_ID),
+        &Identity::new(RECEIVER_ID),
+    );
+    let Some(key) = from_hex(message).and_then(|inbound| spake.finish(&inbound).ok()) else {
+        let _ = writeln!(writer, "error malformed request");
+        return Ok(None);
+    };
+    if writeln!(writer, "{}", to_hex(&outbound)).is_err() {
+        return Ok(None);
+    }
+    let Some(sealed) = read_line(&mut reader) else {
+        return Ok(None);
+    };
+    // With another code the sender derived another key, so nothing it sealed opens here.
+    let Some(token) = open(&key, b"token", &sealed) else {
+        let _ = writeln!(writer, "error wrong pairing code");
+        return Err(HandoffRejection::WrongCode);
+    };
+    let (reply, result) = match parse_captcha_token(&token) {
+        Ok(token) => ("ok".to_string(), Ok(Some(token))),
+        Err(err) => {
+            let reason = err.to_string();
+            (
+                format!("error {reason}"),
+                Err(HandoffRejection::Invalid(reason)),
+            )
+        }
+    };
+    if let Some(sealed) = seal(&key, b"reply", &reply) {
+        let _ = writeln!(writer, "{sealed}");
+    }
+    result
+}
+
+fn read_line(reader: &mut impl BufRead) -> Option<String> {
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    Some(line.trim().to_string())
+}
+
+pub fn send_token(addr: &str, code: &str, token: &str) -> Result<()> {
+    let timeout = Duration::from_secs(crate::TOKEN_HANDOFF_IO_SECS);
+    let target = addr
+        .to_socket_addrs()
+        .with_context(|| format!("invalid --send address '{addr}', expected host:port"))?
+        .next()
+        .with_context(|| format!("'{addr}' did not resolve to an address"))?;
+    let stream = TcpStream::connect_timeout(&target, timeout).with_context(|| {
+        format!("failed to connect to {addr}; is register --token-listen running there?")
+    })?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let (spake, outbound) = Spake2::<Ed25519Group>::start_a(
+        &Password::new(normalize_code(code).as_bytes()),
+        &Identity::new(SENDER_ID),
+        &Identity::new(RECEIVER_ID),
+    );
+    let mut writer = &stream;
+    writeln!(writer, "{HANDOFF_PROTOCOL} {}", to_hex(&outbound))?;
+
+    let mut reader = BufReader::new(&stream);
+    let answer = read_line(&mut reader).with_context(|| format!("no answer from {addr}"))?;
+    let key = match from_hex(&answer).and_then(|inbound| spake.finish(&inbound).ok()) {
+        Some(key) => key,
+        None => return Err(rejection(addr, &answer)),
+    };
+    let sealed = seal(&key, b"token", token).context("failed to encrypt the token")?;
+    writeln!(writer, "{sealed}")?;
+
+    let answer = read_line(&mut reader).with_context(|| format!("no answer from {addr}"))?;
+    match open(&key, b"reply", &answer) {
+        Some(reply) if reply == "ok" => Ok(()),
+        Some(reply) => Err(rejection(addr, &reply)),
+        // Unsealed, so it is only trusted as far as saying the token was not taken.
+        None => Err(rejection(addr, &answer)),
+    }
+}
+
+fn rejection(addr: &str, answer: &str) -> anyhow::Error {
+    match answer.strip_prefix("error ") {
+        Some(reason) => anyhow::anyhow!("{addr} rejected the token: {reason}"),
+        None => anyhow::anyhow!("unexpected answer from {addr}: '{answer}'"),
+    }
+}
+
+/// The key comes from SPAKE2 over the pairing code, so each handoff has its own.
+fn seal(key: &[u8], purpose: &[u8], message: &str) -> Option<String> {
+    let cipher = ChaCha20Poly1305::new_from_slice(key).ok()?;
+    let nonce: [u8; NONCE_LEN] = OsRng.gen();
+    let sealed = cipher
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: message.as_bytes(),
+                aad: purpose,
+            },
+        )
+        .ok()?;
+    Some(to_hex(&[nonce.as_slice(), &sealed].concat()))
+}
+
+fn open(key: &[u8], purpose: &[u8], sealed: &str) -> Option<String> {
+    let cipher = ChaCha20Poly1305::new_from_slice(key).ok()?;
+    let bytes = from_hex(sealed)?;
+    let (nonce, sealed) = (bytes.get(..NONCE_LEN)?, bytes.get(NONCE_LEN..)?);
+    let message = cipher
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: sealed,
+                aad: purpose,
+            },
+        )
+        .ok()?;
+    String::from_utf8(message).ok()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|at| u8::from_str_radix(text.get(at..at + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,161 @@
+use crate::automation::{field, Fields};
+use crate::errors::SignalSetupError;
+use crate::system::TerminalApp;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Os {
+    MacOs,
+    Linux,
+    Other,
+}
+
+impl Os {
+    pub fn current() -> Self {
+        if cfg!(target_os = "macos") {
+            Os::MacOs
+        } else if cfg!(target_os = "linux") {
+            Os::Linux
+        } else {
+            Os::Other
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HintCategory {
+    RegistrationBlocked,
+    ScreenPermission,
+    LinkQrExpired,
+    LinkDeviceLimit,
+    LinkNetwork,
+    LinkKeyUntrusted,
+}
+
+impl HintCategory {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HintCategory::RegistrationBlocked => "registration_blocked",
+            HintCategory::ScreenPermission => "screen_permission",
+            HintCategory::LinkQrExpired => "link_qr_expired",
+            HintCategory::LinkDeviceLimit => "link_device_limit",
+            HintCategory::LinkNetwork => "link_network",
+            HintCategory::LinkKeyUntrusted => "link_key_untrusted",
+        }
+    }
+
+    pub fn is_link(self) -> bool {
+        matches!(
+            self,
+            HintCategory::LinkQrExpired
+                | HintCategory::LinkDeviceLimit
+                | HintCategory::LinkNetwork
+                | HintCategory::LinkKeyUntrusted
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hint {
+    pub category: HintCategory,
+    pub cause: String,
+    pub fix: String,
+}
+
+impl Hint {
+    pub fn render(&self) -> String {
+        format!("{} {}", self.cause, self.fix)
+    }
+
+    pub fn fields(&self) -> Fields {
+        vec![
+            field("hint", self.category.as_str()),
+            field("hint_cause", &self.cause),
+            field("hint_fix", &self.fix),
+        ]
+    }
+}
+
+pub fn registration_failure() -> Hint {
+    Hint {
+        category: HintCategory::RegistrationBlocked,
+        cause: "The number/operator may be blocked, or your current IP may be rate-limited."
+            .to_string(),
+        fix: "If this persists, try another network/IP (for example mobile hotspot) or another number/operator."
+            .to_string(),
+    }
+}
+
+/// The macOS permission belongs to the terminal app hosting this process.
+pub fn screen_permission(os: Os, terminal: Option<&TerminalApp>) -> Hint {
+    let (cause, fix) = match (os, terminal) {
+        (Os::MacOs, Some(app)) => (
+            format!(
+                "If you saw 'could not create image from display', {} lacks the Screen Recording permission.",
+                app.name
+            ),
+            format!(
+                "Turn on {name} in System Settings > Privacy & Security > Screen Recording (add {path} with + if it is not listed), then quit and reopen {name}.",
+                name = app.name,
+                path = app.path
+            ),
+        ),
+        (Os::MacOs, None) => (
+            "If you saw 'could not create image from display', your terminal app lacks the Screen Recording permission.".to_string(),
+            "Grant it in System Settings > Privacy & Security > Screen Recording, then restart the terminal.".to_string(),
+        ),
+        (Os::Linux, _) => (
+            "The screen could not be captured: there may be no graphical session (DISPLAY or WAYLAND_DISPLAY unset), or the Wayland compositor refused to share the screen.".to_string(),
+            "Run from a desktop session and accept the screen sharing prompt, or save a screenshot of the QR code and use it instead.".to_string(),
+        ),
+        (Os::Other, _) => (
+            "The screen could not be captured.".to_string(),
+            "Save a screenshot of the QR code and use it instead, or paste the sgnl:// URI.".to_string(),
+        ),
+    };
+    Hint {
+        category: HintCategory::ScreenPermission,
+        cause,
+        fix,
+    }
+}
+
+pub fn for_error(err: &anyhow::Error) -> Option<Hint> {
+    let (category, cause, fix) = match err.downcast_ref::<SignalSetupError>()? {
+        SignalSetupError::RegisterFailed { .. } | SignalSetupError::SignalCliRateLimited => {
+            return Some(registration_failure())
+        }
+        SignalSetupError::ScreenCaptureUnavailable { reason } => {
+            return Some(Hint {
+                category: HintCategory::ScreenPermission,
+                cause: format!("Screen capture is not available: {reason}."),
+                fix: "Save a screenshot of the Signal Desktop QR code and run 'decode-qr <file>', then 'link-desktop-uri <uri>'.".to_string(),
+            })
+        }
+        SignalSetupError::LinkQrExpired => (
+            HintCategory::LinkQrExpired,
+            "Signal Desktop shows a new QR code after a minute or so.",
+            "Keep its linking screen open and retry the live scan to pick up the new code.",
+        ),
+        SignalSetupError::LinkDeviceLimitReached => (
+            HintCategory::LinkDeviceLimit,
+            "The account already has the maximum number of linked devices.",
+            "Remove one on your phone under Settings > Linked devices, then retry.",
+        ),
+        SignalSetupError::LinkNetworkFailed => (
+            HintCategory::LinkNetwork,
+            "Signal could not be reached while linking.",
+            "Check the internet connection; if it works, a proxy, firewall or VPN may be blocking the Signal servers. Behind a proxy, pass it with --proxy.",
+        ),
+        SignalSetupError::LinkKeyUntrusted => (
+            HintCategory::LinkKeyUntrusted,
+            "The QR code's key was rejected.",
+            "In Signal Desktop, go back and start linking again so it shows a new QR code, then retry the live scan.",
+        ),
+        _ => return None,
+    };
+    Some(Hint {
+        category,
+        cause: cause.to_string(),
+        fix: fix.to_string(),
+    })
+}
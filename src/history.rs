@@ -0,0 +1,219 @@
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::errors::SignalSetupError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WizardStage {
+    /// Docker, image, account and plan, before anything is sent to Signal
+    #[default]
+    Setup,
+    /// Captcha and registration request
+    Registration,
+    Verification,
+    Pin,
+    Linking,
+}
+
+impl WizardStage {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WizardStage::Setup => "setup",
+            WizardStage::Registration => "registration",
+            WizardStage::Verification => "verification",
+            WizardStage::Pin => "pin",
+            WizardStage::Linking => "linking",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WizardProgress {
+    /// `None` until the account is known; runs that stop before that are not recorded
+    pub account: Option<String>,
+    pub stage: WizardStage,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WizardOutcome {
+    pub time: u64,
+    pub account: String,
+    /// Last stage reached, the failing one when `succeeded` is false
+    pub stage: String,
+    pub succeeded: bool,
+    /// Error category of a failed run, see `error_category`
+    pub error: Option<String>,
+}
+
+pub fn history_path() -> PathBuf {
+    let mut path = crate::settings::settings_path();
+    path.set_file_name("wizard-history.jsonl");
+    path
+}
+
+/// Error messages are not stored: they can hold phone numbers, paths and tokens.
+pub fn error_category(err: &anyhow::Error) -> &'static str {
+    let Some(err) = err.downcast_ref::<SignalSetupError>() else {
+        return "other";
+    };
+    match err {
+        SignalSetupError::DockerNotInstalled
+        | SignalSetupError::DockerStartFailed
+        | SignalSetupError::DockerStartTimeout { .. }
+        | SignalSetupError::NerdctlNotInstalled
+        | SignalSetupError::LimaStartFailed { .. } => "runtime",
+        SignalSetupError::RegistryMirrorDigestMismatch { .. }
+        | SignalSetupError::SignalCliTooOld { .. }
+        | SignalSetupError::RegistryAuthFailed { .. }
+        | SignalSetupError::RegistryUnreachable { .. }
+        | SignalSetupError::ImageNotFound { .. }
+        | SignalSetupError::CosignNotInstalled
+        | SignalSetupError::ImageSignatureInvalid { .. } => "image",
+        SignalSetupError::CaptchaPageUnavailable { .. }
+        | SignalSetupError::InvalidCaptchaToken { .. }
+        | SignalSetupError::CaptchaTimedOut { .. }
+        | SignalSetupError::CaptchaWebviewUnavailable { .. } => "captcha",
+        SignalSetupError::SignalCliRateLimited => "rate_limited",
+        SignalSetupError::RegisterFailed { .. }
+        | SignalSetupError::SignalCliCommandFailed { .. }
+        | SignalSetupError::SignalCliKilled { .. } => "signal_cli",
+        SignalSetupError::RegistrationLocked | SignalSetupError::RegistrationPinIncorrect => {
+            "registration_lock"
+        }
+        SignalSetupError::LinkQrExpired
+        | SignalSetupError::LinkDeviceLimitReached
+        | SignalSetupError::LinkKeyUntrusted
+        | SignalSetupError::LinkedDeviceMissing { .. }
+        | SignalSetupError::ScreenCaptureUnavailable { .. } => "link",
+        SignalSetupError::LinkNetworkFailed
+        | SignalSetupError::SignalServerUnreachable { .. }
+        | SignalSetupError::Offline { .. } => "network",
+        SignalSetupError::AgeNotInstalled
+        | SignalSetupError::DataDirVolumeUnmounted { .. }
+        | SignalSetupError::DataDirNotShared { .. } => "data_dir",
+        SignalSetupError::NotInteractive { .. } => "not_interactive",
+        SignalSetupError::Terminated | SignalSetupError::CaptchaInterrupted => "terminated",
+        SignalSetupError::MaxDurationExceeded => "max_duration",
+    }
+}
+
+/// History failures only warn.
+pub fn record_wizard_outcome(progress: &WizardProgress, result: &Result<()>) {
+    let Some(account) = &progress.account else {
+        return;
+    };
+    let outcome = WizardOutcome {
+        time: now_secs(),
+        account: account.clone(),
+        stage: progress.stage.as_str().to_string(),
+        succeeded: result.is_ok(),
+        error: result
+            .as_ref()
+            .err()
+            .map(|err| error_category(err).to_string()),
+    };
+    if let Err(err) = append_outcome(&history_path(), &outcome) {
+        eprintln!("Warning: {err:#}");
+    }
+}
+
+pub fn append_outcome(path: &Path, outcome: &WizardOutcome) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    let line = json!({
+        "time": outcome.time,
+        "account": outcome.account,
+        "stage": outcome.stage,
+        "succeeded": outcome.succeeded,
+        "error": outcome.error,
+    });
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("failed to open wizard history {}", path.display()))?;
+    writeln!(file, "{line}")
+        .with_context(|| format!("failed to write wizard history {}", path.display()))
+}
+
+pub fn account_outcomes(path: &Path, account: &str) -> Vec<WizardOutcome> {
+    let Ok(raw) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    raw.lines()
+        .filter_map(parse_outcome)
+        .filter(|outcome| outcome.account == account)
+        .collect()
+}
+
+fn parse_outcome(line: &str) -> Option<WizardOutcome> {
+    let value: Value = serde_json::from_str(line).ok()?;
+    Some(WizardOutcome {
+        time: value.get("time")?.as_u64()?,
+        account: value.get("account")?.as_str()?.to_string(),
+        stage: value.get("stage")?.as_str()?.to_string(),
+        succeeded: value.get("succeeded")?.as_bool()?,
+        error: value
+            .get("error")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+    })
+}
+
+pub fn describe_history(outcomes: &[WizardOutcome], now: u64) -> Option<String> {
+    let (last, earlier) = outcomes.split_last()?;
+    let ago = format_age(now.saturating_sub(last.time));
+    let mut message = if last.succeeded {
+        format!(
+            "Last wizard run for {} completed {ago} (after {}).",
+            last.account, last.stage
+        )
+    } else {
+        let mut message = format!(
+            "Last wizard run for {} failed at {} {ago}",
+            last.account, last.stage
+        );
+        if let Some(error) = &last.error {
+            message.push_str(&format!(" ({error})"));
+        }
+        message.push('.');
+        message
+    };
+    if !earlier.is_empty() {
+        let failed = earlier.iter().filter(|outcome| !outcome.succeeded).count();
+        message.push_str(&format!(
+            " {} earlier run(s), {failed} failed.",
+            earlier.len()
+        ));
+    }
+    Some(message)
+}
+
+pub fn format_age(secs: u64) -> String {
+    match secs {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86_399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86_400),
+    }
+}
+
+pub fn show_wizard_history(account: &str) {
+    let outcomes = account_outcomes(&history_path(), account);
+    if let Some(message) = describe_history(&outcomes, now_secs()) {
+        println!("{message}");
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
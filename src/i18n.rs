@@ -0,0 +1,150 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Language {
+    #[default]
+    English,
+    French,
+    German,
+    Spanish,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+    SavePinNow,
+    PinLabel,
+    StorePin,
+    PinSavedPrompt,
+    SavePinFirst,
+    PinConfigured,
+    SafetyNumberChanges,
+    TrustNewIdentitiesPrompt,
+}
+
+impl Language {
+    pub const ALL: [Language; 4] = [
+        Language::English,
+        Language::French,
+        Language::German,
+        Language::Spanish,
+    ];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::French => "fr",
+            Language::German => "de",
+            Language::Spanish => "es",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|lang| lang.code() == code)
+    }
+
+    pub fn native_name(self) -> &'static str {
+        match self {
+            Language::English => "English",
+            Language::French => "Français",
+            Language::German => "Deutsch",
+            Language::Spanish => "Español",
+        }
+    }
+
+    pub fn text(self, message: Message) -> &'static str {
+        match self {
+            Language::English => english(message),
+            Language::French => french(message),
+            Language::German => german(message),
+            Language::Spanish => spanish(message),
+        }
+    }
+}
+
+pub fn language_from_locale(locale: &str) -> Option<Language> {
+    let code = locale.split(['_', '.', '@', '-']).next()?.to_lowercase();
+    Language::from_code(&code)
+}
+
+/// The first of `LC_ALL`, `LC_MESSAGES` and `LANG` that is set decides, as for gettext.
+pub fn locale_language() -> Option<Language> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|key| std::env::var(key).ok())
+        .find(|value| !value.is_empty())
+        .and_then(|value| language_from_locale(&value))
+}
+
+fn english(message: Message) -> &'static str {
+    match message {
+        Message::SavePinNow => "IMPORTANT: Save this registration lock PIN now.",
+        Message::PinLabel => "Registration lock PIN",
+        Message::StorePin => {
+            "Store it in a password manager. You will need it to re-register this number."
+        }
+        Message::PinSavedPrompt => "Have you saved this PIN?",
+        Message::SavePinFirst => "Please save it before continuing.",
+        Message::PinConfigured => "Registration lock PIN configured.",
+        Message::SafetyNumberChanges => {
+            "When a contact reinstalls Signal, their safety number changes and sends to them fail until it is trusted."
+        }
+        Message::TrustNewIdentitiesPrompt => {
+            "Automatically trust new identities for this account?"
+        }
+    }
+}
+
+fn french(message: Message) -> &'static str {
+    match message {
+        Message::SavePinNow => "IMPORTANT : enregistrez ce code PIN de verrouillage d'inscription maintenant.",
+        Message::PinLabel => "Code PIN de verrouillage d'inscription",
+        Message::StorePin => {
+            "Conservez-le dans un gestionnaire de mots de passe. Il sera nécessaire pour réinscrire ce numéro."
+        }
+        Message::PinSavedPrompt => "Avez-vous enregistré ce code PIN ?",
+        Message::SavePinFirst => "Enregistrez-le avant de continuer.",
+        Message::PinConfigured => "Code PIN de verrouillage d'inscription configuré.",
+        Message::SafetyNumberChanges => {
+            "Quand un contact réinstalle Signal, son numéro de sécurité change et les envois vers lui échouent tant qu'il n'est pas approuvé."
+        }
+        Message::TrustNewIdentitiesPrompt => {
+            "Approuver automatiquement les nouvelles identités pour ce compte ?"
+        }
+    }
+}
+
+fn german(message: Message) -> &'static str {
+    match message {
+        Message::SavePinNow => "WICHTIG: Speichere jetzt diese PIN für die Registrierungssperre.",
+        Message::PinLabel => "PIN für die Registrierungssperre",
+        Message::StorePin => {
+            "Bewahre sie in einem Passwortmanager auf. Du brauchst sie, um diese Nummer erneut zu registrieren."
+        }
+        Message::PinSavedPrompt => "Hast du diese PIN gespeichert?",
+        Message::SavePinFirst => "Bitte speichere sie, bevor du fortfährst.",
+        Message::PinConfigured => "PIN für die Registrierungssperre eingerichtet.",
+        Message::SafetyNumberChanges => {
+            "Wenn ein Kontakt Signal neu installiert, ändert sich seine Sicherheitsnummer und Nachrichten an ihn schlagen fehl, bis ihr vertraut wird."
+        }
+        Message::TrustNewIdentitiesPrompt => {
+            "Neuen Identitäten für dieses Konto automatisch vertrauen?"
+        }
+    }
+}
+
+fn spanish(message: Message) -> &'static str {
+    match message {
+        Message::SavePinNow => "IMPORTANTE: guarda ahora este PIN de bloqueo de registro.",
+        Message::PinLabel => "PIN de bloqueo de registro",
+        Message::StorePin => {
+            "Guárdalo en un gestor de contraseñas. Lo necesitarás para volver a registrar este número."
+        }
+        Message::PinSavedPrompt => "¿Has guardado este PIN?",
+        Message::SavePinFirst => "Guárdalo antes de continuar.",
+        Message::PinConfigured => "PIN de bloqueo de registro configurado.",
+        Message::SafetyNumberChanges => {
+            "Cuando un contacto reinstala Signal, su número de seguridad cambia y los envíos a ese contacto fallan hasta que se confíe en él."
+        }
+        Message::TrustNewIdentitiesPrompt => {
+            "¿Confiar automáticamente en las nuevas identidades de esta cuenta?"
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde_json::Value;
+
+use crate::net::http_get;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ImageFlavor {
+    /// GraalVM native build (fast startup, amd64 only on some tags)
+    #[default]
+    Native,
+    /// JVM build (multi-arch, slower startup)
+    Jre,
+}
+
+impl ImageFlavor {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ImageFlavor::Native => "native",
+            ImageFlavor::Jre => "jre",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "native" => Some(ImageFlavor::Native),
+            "jre" => Some(ImageFlavor::Jre),
+            _ => None,
+        }
+    }
+
+    pub fn image(self) -> &'static str {
+        match self {
+            ImageFlavor::Native => crate::DEFAULT_IMAGE,
+            ImageFlavor::Jre => crate::JRE_IMAGE,
+        }
+    }
+
+    pub fn java_opts(self) -> Option<&'static str> {
+        match self {
+            ImageFlavor::Native => None,
+            ImageFlavor::Jre => Some(crate::JRE_JAVA_OPTS),
+        }
+    }
+}
+
+/// Flavor of a published signal-cli image, directly or through a mirror; None for other images.
+pub fn image_flavor_of(image: &str) -> Option<ImageFlavor> {
+    let name = image.rsplit('/').next()?;
+    let name = name.split(['@', ':']).next()?;
+    match name {
+        "signal-cli-native" => Some(ImageFlavor::Native),
+        "signal-cli-jre" => Some(ImageFlavor::Jre),
+        _ => None,
+    }
+}
+
+pub fn normalize_registry_mirror(mirror: &str) -> Option<String> {
+    let trimmed = mirror
+        .trim()
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_end_matches('/');
+    if trimmed.is_empty() {
+        None
+    } else {
+        Some(trimmed.to_string())
+    }
+}
+
+pub fn apply_registry_mirror(image: &str, mirror: &str) -> Option<String> {
+    let path = image
+        .strip_prefix(crate::UPSTREAM_REGISTRY)?
+        .strip_prefix('/')?;
+    Some(format!("{mirror}/{path}"))
+}
+
+pub fn image_registry(image: &str) -> &str {
+    match image.split_once('/') {
+        Some((host, _)) if host.contains('.') || host.contains(':') || host == "localhost" => host,
+        _ => "docker.io",
+    }
+}
+
+pub fn split_image_tag(image: &str) -> (&str, Option<&str>) {
+    let name_start = image.rfind('/').map(|idx| idx + 1).unwrap_or(0);
+    match image[name_start..].rfind(':') {
+        Some(idx) => (
+            &image[..name_start + idx],
+            Some(&image[name_start + idx + 1..]),
+        ),
+        None => (image, None),
+    }
+}
+
+pub fn with_image_tag(image: &str, tag: &str) -> String {
+    let (repository, _) = split_image_tag(image);
+    format!("{repository}:{tag}")
+}
+
+pub fn upstream_repository_path(image: &str) -> Option<&str> {
+    let (repository, _) = split_image_tag(image);
+    repository
+        .strip_prefix(crate::UPSTREAM_REGISTRY)?
+        .strip_prefix('/')
+}
+
+pub fn list_registry_tags(image: &str) -> Result<Vec<String>> {
+    let path = upstream_repository_path(image).with_context(|| {
+        format!(
+            "{image} is not hosted on {}; cannot list its tags",
+            crate::UPSTREAM_REGISTRY
+        )
+    })?;
+    Ok(parse_registry_tags(&fetch_registry_repositories()?, path))
+}
+
+pub fn fetch_registry_repositories() -> Result<Value> {
+    let body = http_get(crate::REGISTRY_TAGS_API_URL, crate::HTTP_TIMEOUT_SECS)?;
+    serde_json::from_str::<Value>(&body).context("registry API returned an invalid JSON response")
+}
+
+pub fn parse_registry_repository_id(json: &Value, repository_path: &str) -> Option<u64> {
+    json.as_array()?
+        .iter()
+        .find(|repo| repo.get("path").and_then(Value::as_str) == Some(repository_path))?
+        .get("id")?
+        .as_u64()
+}
+
+/// The same digest `docker pull` records in `RepoDigests`.
+pub fn registry_tag_digest(repository_id: u64, tag: &str) -> Result<Option<String>> {
+    let url = format!(
+        "{}/{repository_id}/tags/{tag}",
+        crate::REGISTRY_REPOSITORIES_API_URL
+    );
+    let body = http_get(&url, crate::HTTP_TIMEOUT_SECS)?;
+    let json = serde_json::from_str::<Value>(&body)
+        .context("registry API returned an invalid JSON response")?;
+    Ok(json
+        .get("digest")
+        .and_then(Value::as_str)
+        .map(str::to_string))
+}
+
+pub fn parse_registry_tags(json: &Value, repository_path: &str) -> Vec<String> {
+    let mut tags = json
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|repo| repo.get("path").and_then(Value::as_str) == Some(repository_path))
+        .filter_map(|repo| repo.get("tags").and_then(Value::as_array))
+        .flatten()
+        .filter_map(|tag| tag.get("name").and_then(Value::as_str))
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+
+    tags.sort_by(|a, b| tag_sort_key(b).cmp(&tag_sort_key(a)));
+    tags.dedup();
+    tags
+}
+
+fn tag_sort_key(tag: &str) -> (bool, Vec<u32>, String) {
+    (tag == "latest", tag_version(tag), tag.to_string())
+}
+
+pub fn tag_version(tag: &str) -> Vec<u32> {
+    tag.trim_start_matches('v')
+        .split(|c: char| !c.is_ascii_digit())
+        .filter_map(|part| part.parse::<u32>().ok())
+        .collect()
+}
@@ -0,0 +1,113 @@
+use anyhow::Result;
+
+use crate::config::Config;
+use crate::image_ref::{
+    fetch_registry_repositories, parse_registry_repository_id, parse_registry_tags,
+    registry_tag_digest, split_image_tag, tag_version, upstream_repository_path,
+};
+use crate::pull::image_digest;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageUpdate {
+    /// The tag was pushed again since the local copy was pulled, as `latest` is on each release
+    Republished { tag: String, digest: String },
+    /// A higher version than the pinned tag is published
+    NewerTag { pinned: String, newest: String },
+}
+
+impl ImageUpdate {
+    pub fn describe(&self, image: &str) -> String {
+        match self {
+            ImageUpdate::Republished { tag, digest } => {
+                format!("{image} is stale: the registry now serves {digest} for '{tag}'.")
+            }
+            ImageUpdate::NewerTag { pinned, newest } => {
+                format!("signal-cli {newest} is published, but the image is pinned to '{pinned}'.")
+            }
+        }
+    }
+
+    pub fn fix(&self) -> &'static str {
+        match self {
+            ImageUpdate::Republished { .. } => "Run 'upgrade-image' to pull it.",
+            ImageUpdate::NewerTag { .. } => "Run 'upgrade-image --pin-newest' to move to it.",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegistryState {
+    pub tag: String,
+    pub published_digest: Option<String>,
+    pub local_digest: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl RegistryState {
+    pub fn updates(&self) -> Vec<ImageUpdate> {
+        let mut updates = Vec::new();
+        if let (Some(local), Some(published)) = (&self.local_digest, &self.published_digest) {
+            if local != published {
+                updates.push(ImageUpdate::Republished {
+                    tag: self.tag.clone(),
+                    digest: published.clone(),
+                });
+            }
+        }
+        let pinned = tag_version(&self.tag);
+        let newest = self
+            .tags
+            .iter()
+            .filter(|tag| !tag_version(tag).is_empty())
+            .max_by_key(|tag| tag_version(tag));
+        if let Some(newest) = newest {
+            if !pinned.is_empty() && tag_version(newest) > pinned {
+                updates.push(ImageUpdate::NewerTag {
+                    pinned: self.tag.clone(),
+                    newest: newest.clone(),
+                });
+            }
+        }
+        updates
+    }
+}
+
+/// A mirrored image is checked upstream, since the mirror serves the same digests.
+pub fn registry_state(cfg: &Config) -> Result<Option<RegistryState>> {
+    let upstream = cfg.mirrored_from.as_deref().unwrap_or(&cfg.image);
+    let Some(path) = upstream_repository_path(upstream) else {
+        return Ok(None);
+    };
+    let tag = split_image_tag(upstream).1.unwrap_or("latest").to_string();
+    let repositories = fetch_registry_repositories()?;
+    let published_digest = match parse_registry_repository_id(&repositories, path) {
+        Some(id) => registry_tag_digest(id, &tag)?,
+        None => None,
+    };
+    Ok(Some(RegistryState {
+        published_digest,
+        local_digest: image_digest(cfg),
+        tags: parse_registry_tags(&repositories, path),
+        tag,
+    }))
+}
+
+/// Stays quiet when the registry cannot be asked.
+pub fn warn_if_stale(cfg: &Config) {
+    if cfg.daemon_addr.is_some() {
+        return;
+    }
+    let Ok(Some(state)) = registry_state(cfg) else {
+        return;
+    };
+    for update in state.updates() {
+        eprintln!(
+            "{}",
+            crate::ui::wrap(&format!(
+                "Warning: {} {}",
+                update.describe(&cfg.image),
+                update.fix()
+            ))
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+#[cfg(not(test))]
+use crate::net::http_get;
+
+/// Signal rate-limits registrations from these much harder than from residential lines.
+const HOSTING_ORG_KEYWORDS: &[&str] = &[
+    "amazon",
+    "google cloud",
+    "microsoft",
+    "azure",
+    "digitalocean",
+    "linode",
+    "akamai",
+    "vultr",
+    "choopa",
+    "hetzner",
+    "ovh",
+    "scaleway",
+    "online s.a.s",
+    "oracle",
+    "alibaba",
+    "tencent",
+    "contabo",
+    "leaseweb",
+    "m247",
+    "datacamp",
+    "cdn77",
+    "mullvad",
+    "31173 services",
+    "tefincom",
+    "nordvpn",
+    "proton",
+    "surfshark",
+    "expressvpn",
+    "private internet access",
+    "packethub",
+    "hosting",
+    "datacenter",
+    "data center",
+    "vpn",
+    "vps",
+    "colocation",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicIp {
+    pub address: String,
+    pub org: Option<String>,
+}
+
+impl PublicIp {
+    pub fn label(&self) -> String {
+        match &self.org {
+            Some(org) => format!("{} ({org})", self.address),
+            None => self.address.clone(),
+        }
+    }
+}
+
+pub fn parse_public_ip(body: &str) -> Result<PublicIp> {
+    let value: Value =
+        serde_json::from_str(body.trim()).context("public IP lookup returned invalid JSON")?;
+    let address = value
+        .get("ip")
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|ip| !ip.is_empty())
+        .context("public IP lookup returned no address")?
+        .to_string();
+    let org = value
+        .get("org")
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|org| !org.is_empty())
+        .map(str::to_string);
+    Ok(PublicIp { address, org })
+}
+
+pub fn is_hosting_network(org: &str) -> bool {
+    let org = org.to_lowercase();
+    HOSTING_ORG_KEYWORDS
+        .iter()
+        .any(|keyword| org.contains(keyword))
+}
+
+pub fn hosting_warning(ip: &PublicIp) -> Option<String> {
+    let org = ip.org.as_deref()?;
+    if !is_hosting_network(org) {
+        return None;
+    }
+    Some(format!(
+        "Warning: your public IP {} looks like a datacenter or VPN address. Signal often rate-limits registrations from these; a residential connection or mobile hotspot works more reliably.",
+        ip.label()
+    ))
+}
+
+#[cfg(not(test))]
+pub fn lookup_public_ip() -> Result<PublicIp> {
+    let body = http_get(crate::PUBLIC_IP_LOOKUP_URL, crate::PUBLIC_IP_TIMEOUT_SECS)?;
+    parse_public_ip(&body)
+}
+
+#[cfg(test)]
+pub fn lookup_public_ip() -> Result<PublicIp> {
+    let body = std::env::var("MOCK_PUBLIC_IP").context("no mock public IP")?;
+    parse_public_ip(&body)
+}
+
+pub fn check_public_ip() -> Option<PublicIp> {
+    let ip = lookup_public_ip().ok()?;
+    if let Some(warning) = hosting_warning(&ip) {
+        eprintln!("{warning}");
+    }
+    Some(ip)
+}
+
+pub fn recheck_public_ip(previous: &PublicIp) -> PublicIp {
+    let Ok(current) = lookup_public_ip() else {
+        return previous.clone();
+    };
+    match ip_change_message(previous, &current) {
+        Some(message) => {
+            println!("{message}");
+            if let Some(warning) = hosting_warning(&current) {
+                eprintln!("{warning}");
+            }
+        }
+        None => println!("Public IP is still {}.", current.label()),
+    }
+    current
+}
+
+pub fn ip_change_message(previous: &PublicIp, current: &PublicIp) -> Option<String> {
+    if current.address == previous.address {
+        return None;
+    }
+    Some(format!(
+        "Public IP changed from {} to {}.",
+        previous.address,
+        current.label()
+    ))
+}
+
+pub fn unchanged_ip_prompt(ip: &PublicIp) -> String {
+    format!("Still the same public IP ({}). Retry anyway?", ip.label())
+}
@@ -0,0 +1,162 @@
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attempt {
+    pub time: u64,
+    pub account: String,
+    pub action: String,
+    pub captcha: Option<String>,
+    pub outcome: String,
+}
+
+pub fn journal_path() -> PathBuf {
+    let mut path = crate::settings::settings_path();
+    path.set_file_name("attempts.jsonl");
+    path
+}
+
+pub fn attempt_action(args: &[String]) -> Option<&'static str> {
+    match args.first().map(String::as_str) {
+        Some("register") if args.iter().any(|arg| arg == "--voice") => Some("register-voice"),
+        Some("register") => Some("register"),
+        Some("verify") => Some("verify"),
+        _ => None,
+    }
+}
+
+pub fn captcha_fingerprint(token: &str) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in token.trim().bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    format!("{:012x}", hash >> 16)
+}
+
+/// Journal failures only warn.
+pub fn record_attempt(account: &str, args: &[String], outcome: &str) {
+    let Some(action) = attempt_action(args) else {
+        return;
+    };
+    let captcha = args
+        .iter()
+        .position(|arg| arg == "--captcha")
+        .and_then(|index| args.get(index + 1))
+        .map(|token| captcha_fingerprint(token));
+    let attempt = Attempt {
+        time: now_secs(),
+        account: account.to_string(),
+        action: action.to_string(),
+        captcha,
+        outcome: outcome.to_string(),
+    };
+    if let Err(err) = append_attempt(&journal_path(), &attempt) {
+        eprintln!("Warning: {err:#}");
+    }
+}
+
+pub fn append_attempt(path: &Path, attempt: &Attempt) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    let line = json!({
+        "time": attempt.time,
+        "account": attempt.account,
+        "action": attempt.action,
+        "captcha": attempt.captcha,
+        "outcome": attempt.outcome,
+    });
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("failed to open attempt journal {}", path.display()))?;
+    writeln!(file, "{line}")
+        .with_context(|| format!("failed to write attempt journal {}", path.display()))
+}
+
+/// Unreadable lines are skipped so a truncated write never hides the rest.
+pub fn recent_attempts(path: &Path, account: &str, now: u64, window_secs: u64) -> Vec<Attempt> {
+    let Ok(raw) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    raw.lines()
+        .filter_map(parse_attempt)
+        .filter(|attempt| attempt.account == account)
+        .filter(|attempt| attempt.time <= now && now - attempt.time <= window_secs)
+        .collect()
+}
+
+fn parse_attempt(line: &str) -> Option<Attempt> {
+    let value: Value = serde_json::from_str(line).ok()?;
+    Some(Attempt {
+        time: value.get("time")?.as_u64()?,
+        account: value.get("account")?.as_str()?.to_string(),
+        action: value.get("action")?.as_str()?.to_string(),
+        captcha: value
+            .get("captcha")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        outcome: value.get("outcome")?.as_str()?.to_string(),
+    })
+}
+
+pub fn recent_attempts_warning(attempts: &[Attempt]) -> Option<String> {
+    let registrations: Vec<&Attempt> = attempts
+        .iter()
+        .filter(|attempt| attempt.action.starts_with("register"))
+        .collect();
+    if registrations.len() < crate::ATTEMPT_WARN_THRESHOLD {
+        return None;
+    }
+    let rate_limited = registrations
+        .iter()
+        .filter(|attempt| attempt.outcome == "rate_limited")
+        .count();
+    let reused = registrations
+        .iter()
+        .filter_map(|attempt| attempt.captcha.as_deref())
+        .collect::<Vec<_>>();
+    let mut unique = reused.clone();
+    unique.sort_unstable();
+    unique.dedup();
+
+    let mut message = format!(
+        "Warning: you have attempted registration {} times in the last hour",
+        registrations.len()
+    );
+    if rate_limited > 0 {
+        message.push_str(&format!(" ({rate_limited} rate limited)"));
+    }
+    message.push('.');
+    if unique.len() < reused.len() {
+        message.push_str(" Some attempts reused a captcha token; each attempt needs a fresh one.");
+    }
+    message.push_str(" Signal rate-limits repeated attempts; waiting an hour before retrying is often faster than retrying now.");
+    Some(message)
+}
+
+pub fn warn_about_recent_attempts(account: &str) {
+    let attempts = recent_attempts(
+        &journal_path(),
+        account,
+        now_secs(),
+        crate::ATTEMPT_WINDOW_SECS,
+    );
+    if let Some(message) = recent_attempts_warning(&attempts) {
+        eprintln!("{message}");
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
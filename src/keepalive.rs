@@ -0,0 +1,96 @@
+use anyhow::{anyhow, bail, Result};
+use serde_json::Value;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::docker::run_signal_cli_captured;
+use crate::monitor::{snapshot_stats, unix_now, update_stats, SharedStats};
+use crate::notify::{notify, Event};
+use crate::reglock::send_due_reminder;
+use crate::shutdown::sleep_unless_terminated;
+use crate::signal_args;
+
+pub fn run_keepalive(
+    cfg: &Config,
+    interval: u64,
+    passes: Option<u32>,
+    stats: &SharedStats,
+) -> Result<()> {
+    if interval == 0 && passes.is_none() {
+        bail!("interval must be > 0")
+    }
+
+    let receive_args = signal_args::receive(
+        crate::KEEPALIVE_RECEIVE_TIMEOUT_SECS as i64,
+        Some(crate::KEEPALIVE_RECEIVE_MAX_MESSAGES),
+    );
+
+    let mut pass = 0_u32;
+    loop {
+        pass += 1;
+        match run_signal_cli_captured(cfg, &receive_args) {
+            Ok(output) if output.status.success() => {
+                let messages = count_envelopes(&String::from_utf8_lossy(&output.stdout));
+                update_stats(stats, |s| s.record_success(messages, unix_now()));
+                println!("Keepalive pass {pass}: received {messages} message(s).");
+            }
+            Ok(output) => {
+                update_stats(stats, |s| s.record_failure());
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                let reason = stderr
+                    .lines()
+                    .map(str::trim)
+                    .find(|line| !line.is_empty())
+                    .unwrap_or("receive failed");
+                eprintln!("Warning: keepalive pass {pass} failed: {reason}");
+            }
+            Err(err) => {
+                update_stats(stats, |s| s.record_failure());
+                eprintln!("Warning: keepalive pass {pass} error: {err}");
+            }
+        }
+
+        match send_due_reminder(cfg) {
+            Ok(true) => println!("Sent the registration lock PIN reminder as a note-to-self."),
+            Ok(false) => {}
+            Err(err) => eprintln!("Warning: registration lock PIN reminder not sent: {err}"),
+        }
+
+        let failures = snapshot_stats(stats).consecutive_failures;
+        if failures == crate::KEEPALIVE_UNHEALTHY_FAILURES {
+            let detail = format!("{failures} consecutive receive passes failed");
+            notify(cfg, Event::KeepaliveFailure, Some(&detail));
+        }
+
+        if passes.is_some_and(|max| pass >= max) {
+            return Ok(());
+        }
+        if sleep_unless_terminated(Duration::from_secs(interval)).is_err() {
+            println!("Keepalive stopped after {pass} pass(es).");
+            return Ok(());
+        }
+    }
+}
+
+pub fn count_envelopes(stdout: &str) -> u64 {
+    stdout
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line.trim()).ok())
+        .filter(|json| json.get("envelope").is_some())
+        .count() as u64
+}
+
+pub fn probe_account(cfg: &Config) -> Result<()> {
+    let output = run_signal_cli_captured(cfg, &signal_args::list_devices())?;
+    if output.status.success() {
+        return Ok(());
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let reason = stderr
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or("listDevices failed");
+    Err(anyhow!("account probe failed: {reason}"))
+}
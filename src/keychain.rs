@@ -0,0 +1,164 @@
+use anyhow::{bail, Context, Result};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::audit::{self, AuditedCommand};
+use crate::system::command_exists;
+
+pub const KEYCHAIN_SERVICE: &str = "signal-setup-registration-lock";
+
+const SECURITY_ITEM_NOT_FOUND: i32 = 44;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeychainBackend {
+    /// macOS login keychain through `security`
+    MacosSecurity,
+    /// Secret Service (GNOME Keyring, KWallet) through `secret-tool`
+    SecretTool,
+}
+
+impl KeychainBackend {
+    pub fn name(self) -> &'static str {
+        match self {
+            KeychainBackend::MacosSecurity => "macOS keychain",
+            KeychainBackend::SecretTool => "Secret Service keyring",
+        }
+    }
+}
+
+pub fn keychain_backend() -> Option<KeychainBackend> {
+    if cfg!(target_os = "macos") && command_exists("security") {
+        Some(KeychainBackend::MacosSecurity)
+    } else if cfg!(target_os = "linux") && command_exists("secret-tool") {
+        Some(KeychainBackend::SecretTool)
+    } else {
+        None
+    }
+}
+
+fn require_backend() -> Result<KeychainBackend> {
+    match keychain_backend() {
+        Some(backend) => Ok(backend),
+        None if cfg!(target_os = "linux") => bail!(
+            "no keychain available: install secret-tool (libsecret-tools) and a Secret Service keyring"
+        ),
+        None => bail!("no keychain available on this system"),
+    }
+}
+
+pub fn store_pin(account: &str, pin: &str) -> Result<()> {
+    let backend = require_backend()?;
+    let (mut command, input) = store_command(backend, account, pin)?;
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .audited_spawn()
+        .with_context(|| format!("failed to open the {}", backend.name()))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(input.as_bytes())
+            .with_context(|| format!("failed to send the PIN to the {}", backend.name()))?;
+    }
+    let pid = child.id();
+    let output = child
+        .wait_with_output()
+        .with_context(|| format!("failed to wait for the {}", backend.name()))?;
+    audit::record_exit(pid, &output.status);
+    if !output.status.success() {
+        bail!(
+            "failed to store the PIN in the {}: {}",
+            backend.name(),
+            first_line(&output.stderr).unwrap_or("unknown error")
+        )
+    }
+    Ok(())
+}
+
+/// The PIN goes over stdin, so it never shows up in process arguments or a command line to parse.
+pub fn store_command(
+    backend: KeychainBackend,
+    account: &str,
+    pin: &str,
+) -> Result<(Command, String)> {
+    if pin.contains(['\n', '\r']) {
+        bail!("the PIN must be a single line")
+    }
+    match backend {
+        KeychainBackend::MacosSecurity => {
+            // A trailing -w without a value makes `security` read the password, then its confirmation.
+            let mut command = Command::new("security");
+            command.args([
+                "add-generic-password",
+                "-U",
+                "-s",
+                KEYCHAIN_SERVICE,
+                "-a",
+                account,
+                "-w",
+            ]);
+            Ok((command, format!("{pin}\n{pin}\n")))
+        }
+        KeychainBackend::SecretTool => {
+            let mut command = Command::new("secret-tool");
+            command
+                .arg("store")
+                .arg("--label")
+                .arg(format!("Signal registration lock PIN for {account}"))
+                .args(["service", KEYCHAIN_SERVICE, "account", account]);
+            Ok((command, pin.to_string()))
+        }
+    }
+}
+
+pub fn load_pin(account: &str) -> Result<Option<String>> {
+    let backend = require_backend()?;
+    let mut command = match backend {
+        KeychainBackend::MacosSecurity => {
+            let mut command = Command::new("security");
+            command.args([
+                "find-generic-password",
+                "-s",
+                KEYCHAIN_SERVICE,
+                "-a",
+                account,
+                "-w",
+            ]);
+            command
+        }
+        KeychainBackend::SecretTool => {
+            let mut command = Command::new("secret-tool");
+            command.args(["lookup", "service", KEYCHAIN_SERVICE, "account", account]);
+            command
+        }
+    };
+    let output = command
+        .stdin(Stdio::null())
+        .audited_output()
+        .with_context(|| format!("failed to open the {}", backend.name()))?;
+    if !output.status.success() {
+        // Both tools fail quietly for a missing entry and explain any other failure.
+        let missing = match backend {
+            KeychainBackend::MacosSecurity => output.status.code() == Some(SECURITY_ITEM_NOT_FOUND),
+            KeychainBackend::SecretTool => first_line(&output.stderr).is_none(),
+        };
+        if missing {
+            return Ok(None);
+        }
+        bail!(
+            "failed to read the PIN from the {}: {}",
+            backend.name(),
+            first_line(&output.stderr).unwrap_or("unknown error")
+        )
+    }
+    let pin = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok((!pin.is_empty()).then_some(pin))
+}
+
+fn first_line(bytes: &[u8]) -> Option<&str> {
+    std::str::from_utf8(bytes)
+        .ok()?
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+}
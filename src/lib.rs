@@ -3,48 +3,120 @@ use anyhow::Context;
 use anyhow::{bail, Result};
 use clap::Parser;
 use dialoguer::theme::ColorfulTheme;
-#[cfg(not(test))]
-use dialoguer::{Confirm, Input, Select};
 use rand::rngs::OsRng;
 use rand::Rng;
 #[cfg(not(test))]
 use std::fs;
+#[cfg(not(test))]
+use std::io::IsTerminal;
 use std::path::Path;
 #[cfg(not(test))]
 use std::path::PathBuf;
 
+pub mod audit;
+pub mod automation;
+pub mod bootstrap;
+pub mod bundle;
 pub mod captcha;
 pub mod cli;
+pub mod command_queue;
 pub mod config;
+pub mod container;
+pub mod daemon;
 pub mod docker;
+pub mod doctor;
 pub mod errors;
+pub mod handoff;
+pub mod hints;
+pub mod history;
+pub mod i18n;
+pub mod image_ref;
+pub mod image_update;
+pub mod ip;
+pub mod journal;
+pub mod keepalive;
+pub mod keychain;
+pub mod link_uri;
+pub mod monitor;
+pub mod net;
+pub mod notify;
+pub mod proxy;
+pub mod pull;
 pub mod qr;
+pub mod qr_debug;
+pub mod reglock;
+pub mod remote;
+pub mod resume;
+pub mod runtime;
+pub mod settings;
+pub mod shutdown;
+pub mod signal_args;
+pub mod signal_output;
+pub mod sync;
 pub mod system;
+pub mod tail;
+pub mod tempfiles;
+pub mod timing;
+pub mod ui;
+pub mod vault;
+pub mod window;
 
 #[cfg(test)]
 use cli::Cli;
 #[cfg(not(test))]
-use cli::{Cli, Commands};
+use cli::{
+    Cli, Commands, DaemonCommands, ImageCommands, NotifyCommands, UrlHandlerCommands, VaultCommands,
+};
 use config::Config;
+#[cfg(not(test))]
+use i18n::{Language, Message};
+use image_ref::list_registry_tags;
+#[cfg(not(test))]
+use image_ref::ImageFlavor;
+use settings::record_image_tag;
+#[cfg(not(test))]
+use settings::{
+    record_image_flavor, record_language, record_wizard_link_choice, record_wizard_registration,
+    set_trust_new_identities,
+};
 
+#[cfg(not(test))]
+use automation::AutomationAction;
 use captcha::{capture_captcha_token, get_captcha_token_for_wizard};
 use config::{config_from_cli, ensure_account_interactive};
 use docker::{
-    ensure_docker_ready, list_devices, register_landline, register_with_mode, run_signal_cli,
-    set_registration_lock_pin, verify_code,
+    check_signal_cli_version, ensure_docker_ready, ensure_nerdctl_ready, image_requires_emulation,
+    linked_devices, list_devices, run_signal_cli, run_signal_cli_captured,
+    set_registration_lock_pin, verify_code, verify_image_signature, verify_registry_mirror,
+    LinkedDevice, RegistrationMode,
 };
+#[cfg(not(test))]
+use docker::{emulation_warning, host_docker_arch, register};
+use errors::SignalSetupError;
+use link_uri::parse_link_uri;
+use notify::{notify, Event};
 use qr::{decode_signal_qr_from_image, scan_screen_for_signal_uri};
-use system::{command_exists, open_screen_recording_settings, open_signal_desktop};
+use signal_output::SignalCliOutput;
+use sync::{parse_sync_progress, SyncProgress};
+use system::{
+    command_exists, desktop_version_problem, open_screen_recording_settings, open_signal_desktop,
+    signal_desktop_version,
+};
+#[cfg(not(test))]
+use vault::open_vault;
 
 #[cfg(test)]
 pub(crate) use captcha::capture_captcha_token_subprocess;
 #[cfg(test)]
 pub(crate) use config::{default_data_dir, validate_account};
 #[cfg(test)]
-pub(crate) use docker::{docker_daemon_is_ready, run_signal_cli_with_retries, try_start_docker};
+pub(crate) use docker::{
+    docker_daemon_is_ready, register_landline, register_with_mode, run_signal_cli_with_retries,
+    try_start_docker,
+};
 #[cfg(test)]
 pub(crate) use qr::{
-    capture_screen_image, capture_screen_images, capture_screens_for_attempt,
+    capture_screen_image, capture_screen_images, capture_screens_for_attempt, capture_window_image,
     decode_signal_qr_with_rqrr, decode_signal_qr_with_rqrr_multipass, decode_signal_qr_with_rxing,
     detect_display_count, resize_luma_to_max_dimension, scale_luma_image, threshold_luma_image,
 };
@@ -54,19 +126,116 @@ pub(crate) use system::{
     process_running_fuzzy,
 };
 
+pub(crate) const UPSTREAM_REGISTRY: &str = "registry.gitlab.com";
 pub const DEFAULT_IMAGE: &str = "registry.gitlab.com/packaging/signal-cli/signal-cli-native:latest";
+pub const JRE_IMAGE: &str = "registry.gitlab.com/packaging/signal-cli/signal-cli-jre:latest";
+pub(crate) const JRE_JAVA_OPTS: &str = "-Xms64m -Xmx512m -XX:+UseSerialGC";
 #[cfg(not(test))]
 pub(crate) const CAPTCHA_URL: &str = "https://signalcaptchas.org/registration/generate.html";
+#[cfg(not(test))]
+pub(crate) const CAPTCHA_WINDOW_SIZE: (f64, f64) = (520.0, 760.0);
 pub const DEFAULT_SCAN_INTERVAL: u64 = 2;
 pub const DEFAULT_SCAN_ATTEMPTS: u32 = 90;
+pub const DEFAULT_DECODE_THREADS: usize = 1;
+pub const DEFAULT_DAEMON_PORT: u16 = 7583;
+pub const DEFAULT_KEEPALIVE_INTERVAL_SECS: u64 = 300;
+pub(crate) const KEEPALIVE_RECEIVE_TIMEOUT_SECS: u64 = 10;
+pub(crate) const KEEPALIVE_RECEIVE_MAX_MESSAGES: u32 = 500;
+pub(crate) const KEEPALIVE_UNHEALTHY_FAILURES: u32 = 3;
+pub(crate) const TAIL_MAX_FAILURES: u32 = 3;
+#[cfg(not(test))]
+pub(crate) const TAIL_RESTART_DELAY_SECS: u64 = 5;
+#[cfg(test)]
+pub(crate) const TAIL_RESTART_DELAY_SECS: u64 = 0;
+pub(crate) const TERMINATE_POLL_MS: u64 = 100;
+#[cfg(not(test))]
+pub(crate) const CHILD_STOP_GRACE_MS: u64 = 2_000;
+#[cfg(test)]
+pub(crate) const CHILD_STOP_GRACE_MS: u64 = 300;
+pub(crate) const HEALTH_PROBE_CACHE_SECS: u64 = 30;
+pub(crate) const JSONRPC_TIMEOUT_SECS: u64 = 30;
+#[cfg(not(test))]
+pub(crate) const JSONRPC_DAEMON_START_SECS: u64 = 90;
+#[cfg(test)]
+pub(crate) const JSONRPC_DAEMON_START_SECS: u64 = 1;
+pub(crate) const JSONRPC_DAEMON_POLL_MS: u64 = 500;
+pub(crate) const PAIRING_CODE_LEN: usize = 8;
+pub(crate) const PAIRING_MAX_WRONG_CODES: u32 = 3;
+pub(crate) const TOKEN_HANDOFF_TIMEOUT_SECS: u64 = 10 * 60;
+pub(crate) const TOKEN_HANDOFF_IO_SECS: u64 = 10;
+pub(crate) const RESUME_TOKEN_LEN: usize = 16;
+pub(crate) const RESUME_TOKEN_TTL_SECS: u64 = 24 * 60 * 60;
+pub(crate) const MAX_DURATION_GRACE_SECS: u64 = 10;
+pub(crate) const DAEMON_STOP_GRACE_SECS: u64 = 10;
+// timeout(1) exits with it too
+pub(crate) const MAX_DURATION_EXIT_CODE: i32 = 124;
+pub(crate) const CONTAINER_KILLED_EXIT_CODE: i32 = 137;
+pub(crate) const REGISTRY_TAGS_API_URL: &str =
+    "https://gitlab.com/api/v4/projects/packaging%2Fsignal-cli/registry/repositories?tags=true";
+pub(crate) const REGISTRY_REPOSITORIES_API_URL: &str =
+    "https://gitlab.com/api/v4/projects/packaging%2Fsignal-cli/registry/repositories";
+#[cfg(not(test))]
+pub(crate) const CAPTCHA_LOAD_TIMEOUT_SECS: u64 = 20;
+pub(crate) const CAPTCHA_TIMEOUT_SECS: u64 = 10 * 60;
+pub(crate) const CAPTCHA_STDERR_TAIL_LINES: usize = 20;
+pub(crate) const CAPTCHA_TOKEN_MIN_SOLUTION_LEN: usize = 64;
+pub(crate) const HTTP_TIMEOUT_SECS: u64 = 15;
+pub(crate) const CONNECTIVITY_PROBE_URLS: &[&str] = &[
+    "https://signalcaptchas.org",
+    "https://chat.signal.org",
+    "https://1.1.1.1",
+];
+pub(crate) const CONNECTIVITY_TIMEOUT_SECS: u64 = 5;
+pub(crate) const CLOCK_CHECK_URL: &str = "https://chat.signal.org";
+pub(crate) const CLOCK_SKEW_WARN_SECS: u64 = 120;
+pub(crate) const NARROW_TERMINAL_COLUMNS: u16 = 100;
+pub(crate) const NARROW_BAR_WIDTH: usize = 12;
+pub(crate) const MIN_WRAP_WIDTH: usize = 20;
+pub(crate) const PRIMARY_DEVICE_ID: u64 = 1;
+pub(crate) const DESKTOP_RECENT_DAYS: u64 = 1;
+pub(crate) const LINKED_DEVICE_UNLINK_DAYS: u64 = 30;
+#[cfg(not(test))]
+pub(crate) const PUBLIC_IP_LOOKUP_URL: &str = "https://ipinfo.io/json";
+#[cfg(not(test))]
+pub(crate) const PUBLIC_IP_TIMEOUT_SECS: u64 = 5;
+pub(crate) const NTFY_DEFAULT_SERVER: &str = "https://ntfy.sh";
+pub(crate) const PUSHOVER_API_URL: &str = "https://api.pushover.net/1/messages.json";
+pub(crate) const MIN_SIGNAL_CLI_VERSION: (u32, u32, u32) = (0, 13, 0);
+pub(crate) const MIN_SIGNAL_DESKTOP_VERSION: (u32, u32, u32) = (7, 0, 0);
+pub(crate) const SIGNAL_DESKTOP_APT_KEY_URL: &str =
+    "https://updates.signal.org/desktop/apt/keys.asc";
+pub(crate) const SIGNAL_DESKTOP_APT_KEYRING: &str =
+    "/usr/share/keyrings/signal-desktop-keyring.gpg";
+pub(crate) const SIGNAL_DESKTOP_APT_SOURCE: &str = "deb [arch=amd64 signed-by=/usr/share/keyrings/signal-desktop-keyring.gpg] https://updates.signal.org/desktop/apt xenial main";
+pub(crate) const SIGNAL_DESKTOP_APT_SOURCE_LIST: &str =
+    "/etc/apt/sources.list.d/signal-xenial.list";
 pub(crate) const REGISTER_RETRY_ATTEMPTS: u32 = 3;
+pub(crate) const REGISTRATION_PIN_ATTEMPTS: u32 = 3;
 pub(crate) const REGISTER_RETRY_DELAY_SECS: u64 = 8;
+pub(crate) const PULL_RETRY_ATTEMPTS: u32 = 4;
+#[cfg(not(test))]
+pub(crate) const PULL_RETRY_BASE_DELAY_SECS: u64 = 2;
+#[cfg(test)]
+pub(crate) const PULL_RETRY_BASE_DELAY_SECS: u64 = 0;
 #[cfg(not(test))]
 pub(crate) const DOCKER_START_TIMEOUT_SECS: u64 = 90;
 #[cfg(test)]
 pub(crate) const DOCKER_START_TIMEOUT_SECS: u64 = 2;
 pub(crate) const GENERATED_REGISTRATION_PIN_DIGITS: usize = 20;
+/// Signal accepts 4 digits, but a generated PIN that short is guessed too easily by whoever
+/// gets hold of the number.
+pub(crate) const MIN_GENERATED_PIN_DIGITS: usize = 6;
+pub(crate) const MAX_GENERATED_PIN_DIGITS: usize = 64;
+pub(crate) const PIN_DISPLAY_GROUP: usize = 4;
+pub(crate) const REGLOCK_REMINDER_DELAY_SECS: u64 = 24 * 60 * 60;
+pub(crate) const DEFAULT_LINK_RETRIES: u32 = 3;
+pub(crate) const LINK_DEVICE_POLL_ATTEMPTS: u32 = 5;
+#[cfg(not(test))]
+pub(crate) const LINK_DEVICE_POLL_DELAY_SECS: u64 = 3;
+#[cfg(test)]
+pub(crate) const LINK_DEVICE_POLL_DELAY_SECS: u64 = 0;
 pub(crate) const POST_LINK_SYNC_PASSES: u32 = 3;
+pub(crate) const POST_LINK_SYNC_MAX_PASSES: u32 = 10;
 pub(crate) const POST_LINK_RECEIVE_TIMEOUT_SECS: u64 = 12;
 pub(crate) const POST_LINK_RECEIVE_MAX_MESSAGES: u32 = 100;
 #[cfg(not(test))]
@@ -77,7 +246,21 @@ pub(crate) const SCREEN_CAPTURE_TIMEOUT_SECS: u64 = 1;
 pub(crate) const QR_FAST_MAX_DIMENSION: u32 = 1600;
 #[cfg(not(test))]
 pub(crate) const QR_RXING_MAX_PIXELS: u64 = 3_000_000;
+#[cfg(not(test))]
+pub(crate) const VOLUME_POLL_SECS: u64 = 2;
+pub(crate) const CONTAINER_CONFIG_DIR: &str = "/var/lib/signal-cli";
+pub(crate) const SCAN_TEMP_PREFIX: &str = "signal-setup-scan-";
+#[cfg(not(test))]
+pub(crate) const STALE_SCAN_DIR_SECS: u64 = 30 * 60;
 pub(crate) const MAX_DETECTED_DISPLAYS: usize = 6;
+pub(crate) const MIN_SIGNAL_WINDOW_SIDE: u32 = 200;
+#[cfg(not(test))]
+pub(crate) const REGION_PICK_MARGIN: u32 = 16;
+pub(crate) const QR_DECODE_MEMORY_CEILING_BYTES: u64 = 768 * 1024 * 1024;
+pub(crate) const RECENT_ACCOUNTS_LIMIT: usize = 10;
+pub(crate) const ATTEMPT_WINDOW_SECS: u64 = 60 * 60;
+pub(crate) const ATTEMPT_WARN_THRESHOLD: usize = 3;
+pub(crate) const VOICE_CALL_AGAIN_SECS: u64 = 60;
 #[cfg(not(test))]
 pub(crate) const LANDLINE_WAIT_SECS: u64 = 60;
 #[cfg(test)]
@@ -90,48 +273,441 @@ pub(crate) const SIGNAL_LAUNCH_WAIT_LOOPS: u32 = 2;
 pub(crate) const SIGNAL_LAUNCH_WAIT_MS: u64 = 500;
 #[cfg(test)]
 pub(crate) const SIGNAL_LAUNCH_WAIT_MS: u64 = 1;
+#[cfg(not(test))]
+pub(crate) const SIGNAL_QUIT_WAIT_LOOPS: u32 = 20;
+#[cfg(test)]
+pub(crate) const SIGNAL_QUIT_WAIT_LOOPS: u32 = 2;
 
 #[cfg(not(test))]
 pub fn run() -> Result<()> {
     let cli = Cli::parse();
-    let command = cli.command.clone().unwrap_or(Commands::Wizard);
+    shutdown::install_sigterm_handler();
+    audit::set_audit_log(cli.audit_log.clone())?;
+    ui::set_accessible(cli.accessible);
+    let charset = settings::resolve_charset(cli.charset)?;
+    ui::set_ascii(ui::use_ascii(charset, ui::current_locale().as_deref()));
+    ui::set_narrow(ui::is_narrow(ui::terminal_columns()));
+    // Every docker command this process runs inherits it, like a DOCKER_CONTEXT set by hand.
+    if let Some(context) = &cli.docker_context {
+        std::env::set_var("DOCKER_CONTEXT", context);
+    }
+    tempfiles::install_panic_cleanup();
+    let removed = tempfiles::cleanup_stale_scan_dirs(
+        &std::env::temp_dir(),
+        std::time::Duration::from_secs(STALE_SCAN_DIR_SECS),
+    );
+    if removed > 0 {
+        eprintln!("Removed {removed} stale screenshot dir(s) left by earlier runs.");
+    }
+    let command = cli.command.clone().unwrap_or(Commands::Wizard {
+        resume: None,
+        max_duration: None,
+        jsonrpc: false,
+    });
+    if cli.porcelain {
+        return run_porcelain(&cli, command);
+    }
+    if cli.print_args {
+        let cfg = config_from_cli(&cli, true)?;
+        for args in signal_args::planned(&command, &cfg)? {
+            println!("{}", docker::describe_invocation(&cfg, &args));
+        }
+        return Ok(());
+    }
 
-    match command {
-        Commands::Wizard => cmd_wizard(&cli),
-        Commands::CaptchaToken { quiet } => {
-            let token = capture_captcha_token(quiet)?;
-            println!("{token}");
-            Ok(())
+    let result = match command {
+        Commands::Wizard {
+            resume,
+            max_duration,
+            jsonrpc,
+        } => cmd_wizard(&cli, resume.as_deref(), max_duration, jsonrpc),
+        Commands::CaptchaToken {
+            quiet,
+            output,
+            send,
+        } => {
+            // Read first: a solved captcha token is only valid for a short time.
+            let code = send
+                .as_ref()
+                .map(|_| handoff::read_pairing_code())
+                .transpose()?;
+            let webview_problem = (!quiet && std::io::stdin().is_terminal())
+                .then(captcha::webview_dependency_problem)
+                .flatten();
+            let token = match webview_problem {
+                Some(problem) => {
+                    eprintln!("{problem}");
+                    captcha::captcha_browser_fallback(&ui::theme())
+                }
+                None => capture_captcha_token(quiet),
+            };
+            let token = match token {
+                Err(err)
+                    if captcha::is_captcha_page_unavailable(&err)
+                        && std::io::stdin().is_terminal() =>
+                {
+                    eprintln!("{err}");
+                    captcha::captcha_browser_fallback(&ui::theme())?
+                }
+                result => result?,
+            };
+            match (send, code) {
+                (Some(addr), Some(code)) => {
+                    handoff::send_token(&addr, &code, &token)?;
+                    eprintln!("Captcha token handed over to {addr}.");
+                    Ok(())
+                }
+                _ => captcha::print_captcha_token(&token, output),
+            }
         }
         Commands::Register {
             token,
+            token_file,
+            token_listen,
             voice,
             landline,
         } => {
-            let cfg = config_from_cli(&cli, true)?;
-            ensure_docker_ready()?;
-            if landline {
-                register_landline(&cfg, &token)
-            } else {
-                register_with_mode(&cfg, &token, voice)
+            let given = match &token_listen {
+                Some(_) => None,
+                None => Some(config::read_secret(
+                    token.as_deref(),
+                    token_file.as_deref(),
+                    "captcha token",
+                )?),
+            };
+            let mut cfg = config_from_cli(&cli, true)?;
+            ensure_runtime_ready(&cfg)?;
+            image_update::warn_if_stale(&cfg);
+            open_vault(&mut cfg)?;
+            // A handed-over token is waited for only once signal-cli is ready to use it.
+            let token = match (given, &token_listen) {
+                (Some(token), _) => captcha::parse_captcha_token(&token)?,
+                (None, Some(spec)) => handoff::listen_for_token(spec)?,
+                (None, None) => bail!("no captcha token given"),
+            };
+            if cfg.daemon_addr.is_none() && image_requires_emulation(&cfg.image, host_docker_arch())
+            {
+                eprintln!("{}", ui::wrap(&emulation_warning(&cfg.image)));
+                eprintln!("Pass --image {JRE_IMAGE} to use the multi-arch JRE image.");
             }
+            let mode = if landline {
+                RegistrationMode::Landline
+            } else if voice {
+                RegistrationMode::Voice
+            } else {
+                RegistrationMode::Sms
+            };
+            print_signal_output(&register(&cfg, &token, mode)?);
+            notify(&cfg, Event::VerificationNeeded, None);
+            Ok(())
         }
-        Commands::Verify { code, pin } => {
-            let cfg = config_from_cli(&cli, true)?;
-            ensure_docker_ready()?;
-            verify_code(&cfg, &code, pin.as_deref())
+        Commands::Verify {
+            code,
+            code_file,
+            pin,
+            pin_from_keychain,
+        } => {
+            let code =
+                config::read_secret(code.as_deref(), code_file.as_deref(), "verification code")?;
+            let mut cfg = config_from_cli(&cli, true)?;
+            ensure_runtime_ready(&cfg)?;
+            open_vault(&mut cfg)?;
+            let output = if pin_from_keychain {
+                verify_code_with_keychain_pin(&cfg, &code)?
+            } else if std::io::stdin().is_terminal() {
+                let theme = ui::theme();
+                verify_answering_registration_lock(&cfg, &code, pin.as_deref(), |asked| {
+                    prompt_registration_lock_pin(&theme, &cfg.account, asked)
+                })?
+            } else {
+                verify_code(&cfg, &code, pin.as_deref())?
+            };
+            print_signal_output(&output);
+            notify(&cfg, Event::RegistrationSucceeded, None);
+            Ok(())
         }
-        Commands::LinkDesktopLive { interval, attempts } => {
-            let cfg = config_from_cli(&cli, true)?;
-            ensure_docker_ready()?;
+        Commands::SetPin => {
+            system::require_terminal(
+                "set-pin",
+                "The generated PIN is shown once and has to be confirmed as saved before it is set.",
+            )?;
+            let mut cfg = config_from_cli(&cli, true)?;
+            ensure_runtime_ready(&cfg)?;
+            open_vault(&mut cfg)?;
+            let lang = settings::load_settings()?.language.unwrap_or_default();
+            set_generated_pin(&cfg, lang, &ui::theme())
+        }
+        Commands::LinkDesktopLive {
+            interval,
+            attempts,
+            pick_region,
+            sync,
+        } => {
+            let mut cfg = config_from_cli(&cli, true)?;
+            cfg.sync = sync.options();
+            ensure_runtime_ready(&cfg)?;
+            open_vault(&mut cfg)?;
+            if pick_region {
+                cfg.capture.region = Some(window::pick_region(&ui::theme())?);
+            }
             link_desktop_live(&cfg, interval, attempts)
         }
+        Commands::LinkDesktopUri { uri, sync } => {
+            let mut cfg = config_from_cli(&cli, true)?;
+            cfg.sync = sync.options();
+            ensure_runtime_ready(&cfg)?;
+            open_vault(&mut cfg)?;
+            link_desktop_from_uri(&cfg, &uri)
+        }
+        Commands::DecodeQr { image } => {
+            println!("{}", decode_qr_input(&image, &mut std::io::stdin().lock())?);
+            Ok(())
+        }
         Commands::ListDevices => {
+            let mut cfg = config_from_cli(&cli, true)?;
+            ensure_runtime_ready(&cfg)?;
+            open_vault(&mut cfg)?;
+            print_signal_output(&list_devices(&cfg)?);
+            Ok(())
+        }
+        Commands::Keepalive {
+            interval,
+            passes,
+            metrics_addr,
+        } => {
+            let mut cfg = config_from_cli(&cli, true)?;
+            ensure_runtime_ready(&cfg)?;
+            open_vault(&mut cfg)?;
+            let stats = monitor::SharedStats::default();
+            if let Some(addr) = metrics_addr {
+                let probe_cfg = Config {
+                    vault: None,
+                    ..cfg.clone()
+                };
+                let monitor = monitor::Monitor {
+                    account: cfg.account.clone(),
+                    stats: Some(stats.clone()),
+                    probe: Some(Box::new(move || keepalive::probe_account(&probe_cfg))),
+                };
+                let bound = monitor::serve_monitor(&addr, monitor)?;
+                println!("Serving metrics on http://{bound}/metrics and health on http://{bound}/healthz");
+            }
+            println!("Keepalive running every {interval}s. Press Ctrl+C to stop.");
+            keepalive::run_keepalive(&cfg, interval, passes, &stats)
+        }
+        Commands::Tail { count, raw } => {
+            let mut cfg = config_from_cli(&cli, true)?;
+            ensure_runtime_ready(&cfg)?;
+            open_vault(&mut cfg)?;
+            println!("Waiting for incoming envelopes. Press Ctrl+C to stop.");
+            tail::run_tail(&cfg, count, raw)
+        }
+        Commands::Daemon { command } => match command {
+            DaemonCommands::Run {
+                port,
+                no_tcp,
+                dbus,
+                health_addr,
+            } => {
+                let mut cfg = config_from_cli(&cli, true)?;
+                ensure_runtime_ready(&cfg)?;
+                open_vault(&mut cfg)?;
+                let port = if no_tcp { None } else { Some(port) };
+                if let Some(port) = port {
+                    println!("signal-cli JSON-RPC daemon listening on 127.0.0.1:{port}.");
+                }
+                if let Some(addr) = health_addr {
+                    let Some(port) = port else {
+                        bail!("--health-addr needs the JSON-RPC TCP port (drop --no-tcp)")
+                    };
+                    let rpc_addr = format!("127.0.0.1:{port}");
+                    let monitor = monitor::Monitor {
+                        account: cfg.account.clone(),
+                        stats: None,
+                        probe: Some(Box::new(move || {
+                            daemon::jsonrpc_call(&rpc_addr, "listDevices", serde_json::json!({}))
+                                .map(|_| ())
+                        })),
+                    };
+                    let bound = monitor::serve_monitor(&addr, monitor)?;
+                    println!("Serving health on http://{bound}/healthz");
+                }
+                daemon::run_daemon(&cfg, port, dbus)
+            }
+            DaemonCommands::GenerateCompose {
+                output,
+                port,
+                force,
+            } => {
+                let cfg = config_from_cli(&cli, true)?;
+                vault::ensure_not_encrypted(&cfg, "a permanent daemon")?;
+                daemon::write_compose_file(&cfg, &output, port, force)?;
+                println!("Wrote {}.", output.display());
+                println!(
+                    "Start it with: docker compose -f {} up -d",
+                    output.display()
+                );
+                println!("Stop other signal-cli runs for this account first: the daemon keeps the data dir locked.");
+                Ok(())
+            }
+        },
+        Commands::Image { command } => match command {
+            ImageCommands::Tags { pin } => {
+                let cfg = config_from_cli(&cli, false)?;
+                cmd_image_tags(&cfg, pin.as_deref(), &ui::theme())
+            }
+        },
+        Commands::PullImage => {
+            let cfg = config_from_cli(&cli, false)?;
+            if cfg.daemon_addr.is_some() {
+                bail!("pull-image does not apply with --daemon-addr; the daemon runs its own image")
+            }
+            ensure_engine_ready(&cfg)?;
+            verify_registry_mirror(&cfg)?;
+            pull::pull_with_retries(&cfg)?;
+            verify_image_signature(&cfg)
+        }
+        Commands::UpgradeImage { pin_newest } => cmd_upgrade_image(&cli, pin_newest),
+        Commands::Notify { command } => match command {
+            NotifyCommands::Ntfy { topic } => {
+                notify::set_ntfy_topic(&topic)?;
+                println!(
+                    "Push notifications will be sent to {}.",
+                    notify::ntfy_url(&topic)
+                );
+                Ok(())
+            }
+            NotifyCommands::Pushover { user } => {
+                notify::set_pushover(&user, &notify::read_pushover_token()?)?;
+                println!("Push notifications will be sent through Pushover.");
+                Ok(())
+            }
+            NotifyCommands::Disable => {
+                notify::disable_push()?;
+                println!("Push notifications disabled.");
+                Ok(())
+            }
+            NotifyCommands::Test => {
+                let cfg = config_from_cli(&cli, false)?;
+                let Some(target) = &cfg.push_target else {
+                    bail!("no push notification target configured (use 'notify ntfy' or 'notify pushover')")
+                };
+                notify::send_push(
+                    target,
+                    &cfg.account,
+                    "Test notification from signal-desktop-only.",
+                )?;
+                println!("Test notification sent.");
+                Ok(())
+            }
+        },
+        Commands::ExportAccount { output, force } => {
+            let mut cfg = config_from_cli(&cli, true)?;
+            open_vault(&mut cfg)?;
+            bundle::export_account(&cfg, &output, force)?;
+            println!("Exported {} to {}.", cfg.account, output.display());
+            println!("Run this account on one machine only: after importing it elsewhere, stop using this copy. Two active copies desynchronize the session and can unregister the account.");
+            Ok(())
+        }
+        Commands::ImportAccount { bundle, force } => {
+            let mut cfg = config_from_cli(&cli, false)?;
+            open_vault(&mut cfg)?;
+            let account = bundle::import_account(&cfg, &bundle, force)?;
+            println!("Imported {account} into {}.", cfg.data_dir.display());
+            println!("Make sure signal-cli no longer runs {account} on the previous machine before using it here.");
+            Ok(())
+        }
+        Commands::Vault { command } => {
+            let cfg = config_from_cli(&cli, false)?;
+            match command {
+                VaultCommands::Enable => {
+                    vault::enable_vault(&cfg.data_dir)?;
+                    println!("Data dir encrypted. It is unlocked only while a command runs.");
+                }
+                VaultCommands::Disable => {
+                    vault::disable_vault(&cfg.data_dir)?;
+                    println!("Data dir decrypted to {}.", cfg.data_dir.display());
+                }
+            }
+            Ok(())
+        }
+        Commands::Trust {
+            all_new,
+            on_first_use,
+        } => {
             let cfg = config_from_cli(&cli, true)?;
-            ensure_docker_ready()?;
-            list_devices(&cfg)
+            if !all_new && !on_first_use {
+                print_trust_mode(&cfg.account, cfg.trust_new_identities);
+                return Ok(());
+            }
+            set_trust_new_identities(&cfg.account, all_new)?;
+            print_trust_mode(&cfg.account, all_new);
+            println!("A running signal-cli daemon for this account picks this up after a restart (regenerate docker-compose.yml too).");
+            Ok(())
         }
-    }
+        Commands::Status => {
+            print_fields(&automation_fields(
+                &cli,
+                AutomationAction::Status,
+                None,
+                (DEFAULT_SCAN_INTERVAL, DEFAULT_SCAN_ATTEMPTS),
+            )?);
+            Ok(())
+        }
+        Commands::OpenUrl { url } => {
+            print_fields(&open_automation_url(&cli, &url)?);
+            Ok(())
+        }
+        Commands::UrlHandler { command } => match command {
+            UrlHandlerCommands::Install => {
+                let exe =
+                    std::env::current_exe().context("failed to resolve current executable path")?;
+                let app = automation::install_url_handler(&exe)?;
+                println!(
+                    "Installed {} handling {}:// URLs.",
+                    app.display(),
+                    automation::URL_SCHEME
+                );
+                Ok(())
+            }
+        },
+        Commands::Doctor => {
+            let cfg = config_from_cli(&cli, false)?;
+            doctor::run_doctor(&cfg)
+        }
+        Commands::Bootstrap { yes } => {
+            let cfg = config_from_cli(&cli, false)?;
+            bootstrap::run_bootstrap(cfg.runtime, yes)
+        }
+        Commands::DiagnoseSync => {
+            let mut cfg = config_from_cli(&cli, true)?;
+            ensure_runtime_ready(&cfg)?;
+            open_vault(&mut cfg)?;
+            doctor::run_diagnose_sync(&cfg)
+        }
+        Commands::Windows => {
+            let screencapture_displays =
+                command_exists("screencapture").then(qr::detect_display_count);
+            println!(
+                "{}",
+                window::render_windows_report(
+                    &window::list_displays(),
+                    &window::list_windows(),
+                    screencapture_displays,
+                )
+            );
+            Ok(())
+        }
+        Commands::Version { strict } => {
+            let cfg = config_from_cli(&cli, false)?;
+            ensure_runtime_ready(&cfg)?;
+            let version = check_signal_cli_version(&cfg, strict)?;
+            println!("{version}");
+            Ok(())
+        }
+    };
+    remote::remove_all();
+    result
 }
 
 #[cfg(test)]
@@ -140,52 +716,774 @@ pub fn run() -> Result<()> {
 }
 
 #[cfg(not(test))]
-fn cmd_wizard(cli: &Cli) -> Result<()> {
-    ensure_docker_ready()?;
+fn run_porcelain(cli: &Cli, command: Commands) -> Result<()> {
+    let mut porcelain = automation::Porcelain::begin()?;
+    let result = match command {
+        Commands::CaptchaToken { .. } => {
+            automation_fields(cli, AutomationAction::CaptchaToken, None, (0, 0))
+        }
+        Commands::LinkDesktopLive {
+            interval, attempts, ..
+        } => automation_fields(
+            cli,
+            AutomationAction::LinkDesktop,
+            None,
+            (interval, attempts),
+        ),
+        Commands::Status => automation_fields(cli, AutomationAction::Status, None, (0, 0)),
+        Commands::OpenUrl { url } => open_automation_url(cli, &url),
+        _ => Err(anyhow::anyhow!(
+            "--porcelain is supported for captcha-token, link-desktop-live, status and open-url"
+        )),
+    };
+
+    let fields = match &result {
+        Ok(fields) => {
+            let mut all = vec![automation::field("status", "ok")];
+            all.extend(fields.iter().cloned());
+            all
+        }
+        Err(err) => {
+            let mut all = vec![
+                automation::field("status", "error"),
+                automation::field("error", format!("{err:#}")),
+            ];
+            if let Some(hint) = hints::for_error(err) {
+                all.extend(hint.fields());
+            }
+            all
+        }
+    };
+    porcelain.emit(&fields)?;
+    result.map(|_| ())
+}
+
+#[cfg(not(test))]
+fn open_automation_url(cli: &Cli, url: &str) -> Result<automation::Fields> {
+    let request = automation::parse_automation_url(url)?;
+    if let Some(prompt) = automation::confirmation_prompt(&request) {
+        let interactive = std::io::stdin().is_terminal() && std::io::stderr().is_terminal();
+        let allowed = if interactive {
+            ui::confirm(&ui::theme(), &prompt, false)?
+        } else {
+            automation::confirm_in_dialog(&prompt)?
+        };
+        if !allowed {
+            bail!("the {} link was not allowed", automation::URL_SCHEME)
+        }
+    }
+    let result = automation_fields(
+        cli,
+        request.action,
+        request.account.as_deref(),
+        (DEFAULT_SCAN_INTERVAL, DEFAULT_SCAN_ATTEMPTS),
+    );
+    let callback = match &result {
+        Ok(fields) => match request.x_success.as_deref() {
+            Some(base) => Some(automation::callback_url(
+                base,
+                &automation::callback_fields(fields)?,
+            )),
+            None => None,
+        },
+        Err(err) => request.x_error.as_deref().map(|base| {
+            automation::callback_url(
+                base,
+                &[automation::field("errorMessage", format!("{err:#}"))],
+            )
+        }),
+    };
+    if let Some(callback) = callback {
+        system::open_url_in_default_browser(&callback);
+    }
+    result
+}
+
+#[cfg(not(test))]
+fn automation_fields(
+    cli: &Cli,
+    action: AutomationAction,
+    account: Option<&str>,
+    (interval, attempts): (u64, u32),
+) -> Result<automation::Fields> {
+    if action == AutomationAction::CaptchaToken {
+        let token = capture_captcha_token(true)?;
+        return Ok(vec![automation::field("token", token)]);
+    }
+
+    let mut cfg = config_from_cli(cli, account.is_none())?;
+    if let Some(account) = account {
+        config::validate_account(account)?;
+        cfg.account = account.to_string();
+    }
+    if action == AutomationAction::Status {
+        return Ok(automation::status_fields(&cfg));
+    }
+
+    ensure_runtime_ready(&cfg)?;
+    open_vault(&mut cfg)?;
+    link_desktop_live(&cfg, interval, attempts)?;
+    Ok(vec![
+        automation::field("account", &cfg.account),
+        automation::field("result", "linked"),
+    ])
+}
+
+#[cfg(not(test))]
+fn print_trust_mode(account: &str, all_new: bool) {
+    if all_new {
+        println!("{account} automatically trusts new identities of contacts.");
+    } else {
+        println!(
+            "{account} trusts only the first identity seen for each contact (signal-cli default)."
+        );
+    }
+}
+
+#[cfg(not(test))]
+fn print_fields(fields: &[(String, String)]) {
+    for (key, value) in fields {
+        println!("{key}: {value}");
+    }
+}
+
+fn cmd_upgrade_image(cli: &Cli, pin_newest: bool) -> Result<()> {
+    let mut cfg = config_from_cli(cli, false)?;
+    if cfg.daemon_addr.is_some() {
+        bail!("upgrade-image does not apply with --daemon-addr; the daemon runs its own image")
+    }
+    ensure_engine_ready(&cfg)?;
+    let updates = match image_update::registry_state(&cfg) {
+        Ok(Some(state)) => {
+            let updates = state.updates();
+            if updates.is_empty() && state.local_digest.is_some() {
+                println!("{} is up to date.", cfg.image);
+            }
+            updates
+        }
+        Ok(None) => {
+            println!(
+                "{} is not published on {UPSTREAM_REGISTRY}; pulling it without checking for a newer version.",
+                cfg.image
+            );
+            Vec::new()
+        }
+        Err(err) => {
+            eprintln!("Warning: could not check the registry for a newer image: {err:#}");
+            Vec::new()
+        }
+    };
+    let mut repinned = false;
+    for update in &updates {
+        println!("{}", ui::wrap(&update.describe(&cfg.image)));
+        if let image_update::ImageUpdate::NewerTag { newest, .. } = update {
+            if pin_newest {
+                record_image_tag(Some(newest.clone()))?;
+                println!("Pinned signal-cli image tag '{newest}' in the config file.");
+                repinned = true;
+            } else {
+                println!("Pass --pin-newest to move to it; pulling the pinned tag again.");
+            }
+        }
+    }
+    if repinned {
+        cfg = config_from_cli(cli, false)?;
+    }
+
+    verify_registry_mirror(&cfg)?;
+    pull::pull_with_retries(&cfg)?;
+    verify_image_signature(&cfg)?;
+    let version = check_signal_cli_version(&cfg, true)?;
+    match pull::image_digest(&cfg) {
+        Some(digest) => println!("Using {version} from {}@{digest}", cfg.image),
+        None => println!("Using {version} from {} (no registry digest)", cfg.image),
+    }
+    if let Ok(Some(state)) = image_update::registry_state(&cfg) {
+        if let Some(published) = state
+            .published_digest
+            .filter(|published| state.local_digest.as_ref() != Some(published))
+        {
+            eprintln!(
+                "Warning: the pulled image does not match {published}, the digest the registry publishes; a registry mirror may not have caught up yet."
+            );
+        }
+    }
+    Ok(())
+}
+
+fn cmd_image_tags(cfg: &Config, pin: Option<&str>, theme: &ColorfulTheme) -> Result<()> {
+    let upstream = cfg.mirrored_from.as_deref().unwrap_or(&cfg.image);
+    let tags = list_registry_tags(upstream)?;
+    if tags.is_empty() {
+        bail!("no tags found for {upstream}")
+    }
+
+    println!("Available tags for {upstream}:");
+    for tag in &tags {
+        println!("  {tag}");
+    }
+
+    let selected = match pin {
+        Some(tag) => {
+            if !tags.iter().any(|candidate| candidate == tag) {
+                bail!("tag '{tag}' is not published for {upstream}")
+            }
+            Some(tag.to_string())
+        }
+        None => select_image_tag_interactive(&tags, theme)?,
+    };
+
+    match selected.as_deref() {
+        None => println!("Pinned tag left unchanged."),
+        Some("latest") => {
+            record_image_tag(None)?;
+            println!("Tracking 'latest' again.");
+        }
+        Some(tag) => {
+            record_image_tag(Some(tag.to_string()))?;
+            println!("Pinned signal-cli image tag '{tag}' in the config file.");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(test))]
+fn select_image_tag_interactive(tags: &[String], theme: &ColorfulTheme) -> Result<Option<String>> {
+    system::require_terminal("Pin which tag?", "Pass --pin <tag>.")?;
+    let mut options = tags.to_vec();
+    options.push("Keep current setting".to_string());
+    let choice = ui::select(theme, "Pin which tag?", &options, 0)?;
+    Ok(tags.get(choice).cloned())
+}
+
+#[cfg(test)]
+fn select_image_tag_interactive(tags: &[String], _theme: &ColorfulTheme) -> Result<Option<String>> {
+    Ok(tags.first().cloned())
+}
+
+fn ensure_engine_ready(cfg: &Config) -> Result<()> {
+    bootstrap::offer_runtime_install(cfg.runtime)?;
+    match cfg.runtime {
+        runtime::ContainerRuntime::Docker => ensure_docker_ready(),
+        runtime::ContainerRuntime::Nerdctl => ensure_nerdctl_ready(),
+    }
+}
+
+fn ensure_runtime_ready(cfg: &Config) -> Result<()> {
+    if cfg.daemon_addr.is_some() {
+        return Ok(());
+    }
+    ensure_engine_ready(cfg)?;
+    verify_registry_mirror(cfg)?;
+    pull::ensure_image_pulled(cfg)?;
+    verify_image_signature(cfg)?;
+    remote::prepare_data_volume(cfg)
+}
+
+#[cfg(not(test))]
+fn cmd_wizard(
+    cli: &Cli,
+    resume: Option<&str>,
+    max_duration: Option<std::time::Duration>,
+    jsonrpc: bool,
+) -> Result<()> {
+    if let Some(limit) = max_duration {
+        shutdown::start_deadline(
+            limit,
+            std::time::Duration::from_secs(MAX_DURATION_GRACE_SECS),
+            move || {
+                eprintln!("\nError: {}", errors::SignalSetupError::MaxDurationExceeded);
+                eprintln!(
+                    "A step did not stop within {MAX_DURATION_GRACE_SECS}s; exiting without waiting for it."
+                );
+                container::remove_all();
+                remote::remove_all();
+                vault::lock_all();
+                print_deadline_summary();
+                std::process::exit(MAX_DURATION_EXIT_CODE);
+            },
+        );
+    }
+    let mut progress = history::WizardProgress::default();
+    let result = wizard_flow(cli, resume, jsonrpc, &mut progress);
+    // A restart is not an outcome: the resumed run records how the wizard ends.
+    if let Ok(Some(token)) = &result {
+        return resume::restart(token);
+    }
+    let result = result.map(|_| ());
+    history::record_wizard_outcome(&progress, &result);
+    if let Err(err) = &result {
+        if shutdown::deadline_exceeded() {
+            eprintln!("Error: {err:#}");
+            print_deadline_summary();
+            std::process::exit(MAX_DURATION_EXIT_CODE);
+        }
+    }
+    let stages = timing::take_stages();
+    if !stages.is_empty() {
+        println!("\n{}", timing::render_summary(&stages));
+    }
+    result
+}
+
+#[cfg(not(test))]
+fn print_deadline_summary() {
+    println!("\n{}", deadline_resume_note(resume::checkpoint()));
+    let stages = timing::take_stages();
+    if !stages.is_empty() {
+        println!("\n{}", timing::render_summary(&stages));
+    }
+}
+
+pub(crate) fn deadline_resume_note(verified_account: Option<String>) -> String {
+    let Some(account) = verified_account else {
+        return "Stopped before registration was verified; nothing was kept, run the wizard again."
+            .to_string();
+    };
+    match resume::save(&account) {
+        Ok(token) => format!(
+            "Registration of {account} is verified. Run 'wizard --resume {token}' to continue with desktop linking, and 'set-pin' if no registration lock PIN was set yet."
+        ),
+        Err(err) => format!(
+            "Registration of {account} is verified, but the resume state could not be saved ({err:#}); run 'link-desktop-live' to link desktop."
+        ),
+    }
+}
 
-    let theme = ColorfulTheme::default();
+/// `Some(token)` when it stopped to restart the process.
+#[cfg(not(test))]
+fn wizard_flow(
+    cli: &Cli,
+    resume: Option<&str>,
+    jsonrpc: bool,
+    progress: &mut history::WizardProgress,
+) -> Result<Option<String>> {
+    system::require_terminal(
+        "wizard",
+        "Run captcha-token, register, verify and link-desktop-live with their flags instead.",
+    )?;
+    let theme = ui::theme();
     let mut cfg = config_from_cli(cli, false)?;
-    cfg.account = ensure_account_interactive(cli.account.clone(), &theme)?;
+    timing::measure("docker ready", || ensure_runtime_ready(&cfg))?;
+    if let Some(token) = resume {
+        let state = resume::load(token)?;
+        if cli
+            .account
+            .as_deref()
+            .is_some_and(|account| account != state.account)
+        {
+            bail!(
+                "the resumed wizard run is for {}, not the --account given",
+                state.account
+            )
+        }
+        cfg.trust_new_identities = settings::load_settings()
+            .unwrap_or_default()
+            .trust_new_identities
+            .contains(&state.account);
+        set_wizard_account(&mut cfg, state.account)?;
+        progress.account = Some(cfg.account.clone());
+        resume::set_checkpoint(Some(&cfg.account));
+        println!(
+            "Resuming the wizard for {}: registration is done, continuing with desktop linking.",
+            cfg.account
+        );
+        open_vault(&mut cfg)?;
+        start_signal_cli_container(&mut cfg, jsonrpc);
+        let restart = link_in_wizard(&cfg, &theme, progress)?;
+        if restart.is_none() {
+            resume::discard(token);
+        }
+        return Ok(restart);
+    }
+    let remembered = settings::load_settings().unwrap_or_default();
+    let lang = match remembered.language {
+        Some(lang) => lang,
+        None => prompt_language(&theme)?,
+    };
+    let known_accounts = config::account_candidates(
+        &remembered.recent_accounts,
+        &bundle::registered_accounts(&cfg.data_dir),
+    );
+    let account = ensure_account_interactive(cli.account.clone(), &known_accounts, &theme)?;
+    set_wizard_account(&mut cfg, account)?;
+    history::show_wizard_history(&cfg.account);
+    reglock::show_pending_reminder(&cfg.account);
+    progress.account = Some(cfg.account.clone());
+    let switch_to_jre = offer_jre_image(&cfg.image, &theme)?;
+    if switch_to_jre {
+        (cfg.image, cfg.mirrored_from) = config::resolve_image(
+            None,
+            ImageFlavor::Jre,
+            remembered.image_tag.as_deref(),
+            cfg.registry_mirror.as_deref(),
+        );
+        cfg.image_flavor = ImageFlavor::Jre;
+        record_image_flavor(ImageFlavor::Jre)?;
+        verify_registry_mirror(&cfg)?;
+    }
+    // A first-time pull takes minutes; show it instead of letting the version check hang.
+    timing::measure("image pull", || pull::ensure_image_pulled(&cfg))?;
+    if switch_to_jre {
+        verify_image_signature(&cfg)?;
+    }
+    image_update::warn_if_stale(&cfg);
+    // Also warms the image up, so registration does not pay for it.
+    match timing::measure("signal-cli version", || signal_cli_build(&cfg)) {
+        Ok(build) => println!("Using {build}"),
+        Err(err) => eprintln!("Warning: could not get the signal-cli version: {err:#}"),
+    }
 
+    let last_mode = remembered
+        .last_registration_mode
+        .unwrap_or(RegistrationMode::Sms);
+    let mut mode = prompt_registration_mode(&theme, last_mode, false)?.unwrap_or(last_mode);
+
+    println!("\n== Signal Setup Wizard ==");
+    println!("{}", render_wizard_plan(&cfg, mode));
+    let proceed = ui::confirm(&theme, "Proceed with this plan?", true)?;
+    if !proceed {
+        bail!("wizard cancelled before any changes; adjust --account/--data-dir/--image and run it again")
+    }
+
+    open_vault(&mut cfg)?;
     fs::create_dir_all(&cfg.data_dir)
         .with_context(|| format!("failed to create data dir {}", cfg.data_dir.display()))?;
+    start_signal_cli_container(&mut cfg, jsonrpc);
+    let version = check_signal_cli_version(&cfg, false)?;
+    println!("signal-cli version: {version}");
+    let skew_warning = doctor::measure_clock_skew()
+        .ok()
+        .and_then(doctor::describe_clock_skew);
+    if let Some(warning) = skew_warning {
+        eprintln!("\nWarning: {warning}.");
+        let proceed = ui::confirm(&theme, "Continue anyway?", false)?;
+        if !proceed {
+            bail!("fix the system clock and run the wizard again")
+        }
+    }
 
-    println!("\n== Signal Setup Wizard ==");
-    println!("Account : {}", cfg.account);
-    println!("Data dir: {}", cfg.data_dir.display());
-    println!("Image   : {}", cfg.image);
+    let mut step = WizardStep::Register;
+    let mut registered = false;
+    let mut token = String::new();
+    let mut last_call = None;
+    let mut code = String::new();
+    let existing_pin = loop {
+        match step {
+            WizardStep::Mode => match prompt_registration_mode(&theme, mode, registered)? {
+                Some(selected) => {
+                    mode = selected;
+                    step = WizardStep::Register;
+                }
+                None => step = WizardStep::Code,
+            },
+            WizardStep::Register => {
+                progress.stage = history::WizardStage::Registration;
+                record_wizard_registration(&cfg.account, mode)?;
+                token = register_in_wizard(&cfg, &theme, mode)?;
+                registered = true;
+                last_call = mode.places_calls().then(std::time::Instant::now);
+                step = WizardStep::Code;
+            }
+            WizardStep::Code => {
+                let call_again = if mode.places_calls() {
+                    format!(", '{CALL_AGAIN_ANSWER}' to be called again")
+                } else {
+                    String::new()
+                };
+                let answer: String = ui::input(&theme, &format!(
+                        "Verification code received by SMS/voice ('{BACK_ANSWER}' to change the registration mode{call_again})"
+                    ))?;
+                if is_back_answer(&answer) {
+                    step = WizardStep::Mode;
+                } else if mode.places_calls() && is_call_again_answer(&answer) {
+                    let wait =
+                        call_again_wait(last_call.map(|at: std::time::Instant| at.elapsed()));
+                    if !wait.is_zero() {
+                        println!(
+                            "Signal places another call {VOICE_CALL_AGAIN_SECS}s after the previous one; waiting {}s...",
+                            wait.as_secs().max(1)
+                        );
+                        shutdown::sleep_unless_terminated(wait)?;
+                    }
+                    match docker::request_voice_call(&cfg, &token) {
+                        Ok(()) => {
+                            last_call = Some(std::time::Instant::now());
+                            println!("Calling again. Answer the call and note the code.");
+                        }
+                        Err(err) => {
+                            eprintln!("Could not request another call: {err:#}");
+                            eprintln!("The captcha token may have expired; answer '{BACK_ANSWER}' to register again with a new one.");
+                        }
+                    }
+                } else {
+                    code = answer;
+                    step = WizardStep::Pin;
+                }
+            }
+            WizardStep::Pin => {
+                let choice = ui::select(
+                    &theme,
+                    "Do you already have a registration lock PIN on this number?",
+                    &["No", "Yes", "Back"],
+                    0,
+                )?;
+                match choice {
+                    0 => break None,
+                    1 => step = WizardStep::ExistingPin,
+                    _ => step = WizardStep::Code,
+                }
+            }
+            WizardStep::ExistingPin => {
+                let answer: String = ui::input(
+                    &theme,
+                    &format!("Existing registration lock PIN ('{BACK_ANSWER}' to go back)"),
+                )?;
+                if is_back_answer(&answer) {
+                    step = WizardStep::Pin;
+                } else {
+                    break Some(answer);
+                }
+            }
+        }
+    };
+
+    progress.stage = history::WizardStage::Verification;
+    let verified = timing::measure("verify", || {
+        verify_answering_registration_lock(&cfg, &code, existing_pin.as_deref(), |asked| {
+            prompt_registration_lock_pin(&theme, &cfg.account, asked)
+        })
+    })?;
+    print_signal_output(&verified);
+    println!("Registration verified.");
+    resume::set_checkpoint(Some(&cfg.account));
+    progress.stage = history::WizardStage::Pin;
+    notify(&cfg, Event::RegistrationSucceeded, None);
+
+    let pin_choice = if cli.no_reglock {
+        reglock::PinChoice::Skip
+    } else {
+        prompt_pin_choice(&theme)?
+    };
+    match pin_choice {
+        reglock::PinChoice::SetNow => set_generated_pin(&cfg, lang, &theme)?,
+        reglock::PinChoice::RemindLater => {
+            reglock::schedule_reminder(&cfg.account)?;
+            println!(
+                "No registration lock PIN set yet. A running keepalive sends a note-to-self reminder in {}h; run 'set-pin' any time before.",
+                REGLOCK_REMINDER_DELAY_SECS / 3600
+            );
+        }
+        reglock::PinChoice::Skip => {
+            eprintln!("\n{}", ui::wrap(reglock::NO_REGLOCK_WARNING));
+            reglock::clear_reminder(&cfg.account)?;
+        }
+    }
+
+    println!("\n{}", lang.text(Message::SafetyNumberChanges));
+    let trust_all_new = ui::confirm(&theme, lang.text(Message::TrustNewIdentitiesPrompt), true)?;
+    set_trust_new_identities(&cfg.account, trust_all_new)?;
+    cfg.trust_new_identities = trust_all_new;
+    if cfg
+        .jsonrpc
+        .as_ref()
+        .is_some_and(|daemon| daemon.trust_new_identities != trust_all_new)
+    {
+        // The daemon applies the trust mode it was started with to every request.
+        start_signal_cli_container(&mut cfg, true);
+    }
+    print_trust_mode(&cfg.account, trust_all_new);
+
+    let do_link = ui::confirm(
+        &theme,
+        "Link Signal Desktop now?",
+        remembered.last_link_desktop.unwrap_or(true),
+    )?;
+    record_wizard_link_choice(do_link)?;
+    if !do_link {
+        println!("Done. Registration completed without desktop linking.");
+        return Ok(None);
+    }
+    link_in_wizard(&cfg, &theme, progress)
+}
+
+/// Falls back to a container per command when neither the daemon nor the session container starts.
+#[cfg(not(test))]
+fn start_signal_cli_container(cfg: &mut Config, jsonrpc: bool) {
+    if cfg.daemon_addr.is_some() {
+        return;
+    }
+    if jsonrpc {
+        // Stop a running daemon first: only one signal-cli may have the data dir open.
+        cfg.jsonrpc = None;
+        match timing::measure("signal-cli daemon", || container::JsonRpcDaemon::start(cfg)) {
+            Ok(daemon) => {
+                cfg.jsonrpc = Some(std::sync::Arc::new(daemon));
+                return;
+            }
+            Err(err) => {
+                eprintln!("Warning: {err:#}; running signal-cli without the daemon instead.")
+            }
+        }
+    }
+    match timing::measure("signal-cli container", || {
+        container::SessionContainer::start(cfg)
+    }) {
+        Ok(session) => cfg.session = Some(std::sync::Arc::new(session)),
+        Err(err) => eprintln!(
+            "Warning: {err:#}; running each signal-cli command in its own container instead."
+        ),
+    }
+}
+
+/// A remote engine's data volume is named after the account, so it is only prepared now.
+#[cfg(not(test))]
+fn set_wizard_account(cfg: &mut Config, account: String) -> Result<()> {
+    cfg.account = account;
+    if cfg.container_config.is_none() {
+        cfg.container_config =
+            config::saved_container_config(&settings::load_settings()?, &cfg.account);
+    }
+    if cfg.daemon_addr.is_none() {
+        cfg.data_volume = remote::data_volume(cfg.runtime, &cfg.account);
+    }
+    remote::prepare_data_volume(cfg)
+}
+
+#[cfg(not(test))]
+fn link_in_wizard(
+    cfg: &Config,
+    theme: &ColorfulTheme,
+    progress: &mut history::WizardProgress,
+) -> Result<Option<String>> {
+    progress.stage = history::WizardStage::Linking;
+    let interval = DEFAULT_SCAN_INTERVAL;
+    let attempts = DEFAULT_SCAN_ATTEMPTS;
+    println!("Using default QR scan settings: every {interval}s, max {attempts} attempts.");
+
+    if link_desktop_interactive(cfg, theme, interval, attempts)? == LinkEnd::Restart {
+        let token = resume::save(&cfg.account)?;
+        println!(
+            "If linking still cannot see the screen after the restart, quit and reopen the terminal app, then run 'wizard --resume {token}' to continue from here."
+        );
+        return Ok(Some(token));
+    }
+    println!("\nSetup completed successfully.");
+    Ok(None)
+}
+
+#[cfg(not(test))]
+fn prompt_language(theme: &ColorfulTheme) -> Result<Language> {
+    let default = i18n::locale_language().unwrap_or_default();
+    let items: Vec<&str> = Language::ALL
+        .iter()
+        .map(|lang| lang.native_name())
+        .collect();
+    let choice = ui::select(
+        theme,
+        "Language",
+        &items,
+        Language::ALL
+            .iter()
+            .position(|lang| *lang == default)
+            .unwrap_or(0),
+    )?;
+    let lang = Language::ALL.get(choice).copied().unwrap_or(default);
+    record_language(lang)?;
+    Ok(lang)
+}
+
+#[cfg(not(test))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WizardStep {
+    Mode,
+    Register,
+    Code,
+    Pin,
+    ExistingPin,
+}
+
+#[cfg(not(test))]
+fn prompt_registration_mode(
+    theme: &ColorfulTheme,
+    current: RegistrationMode,
+    registered: bool,
+) -> Result<Option<RegistrationMode>> {
+    let mut items: Vec<&str> = RegistrationMode::ALL
+        .iter()
+        .map(|mode| mode.label())
+        .collect();
+    if registered {
+        items.push("Back to code entry");
+    }
+    let default = RegistrationMode::ALL
+        .iter()
+        .position(|mode| *mode == current)
+        .unwrap_or(0);
+    let choice = ui::select(
+        theme,
+        if registered {
+            "Register again with another mode? (needs a new captcha)"
+        } else {
+            "How should Signal send the verification code?"
+        },
+        &items,
+        default,
+    )?;
+    Ok(RegistrationMode::ALL.get(choice).copied())
+}
 
+#[cfg(not(test))]
+fn register_in_wizard(
+    cfg: &Config,
+    theme: &ColorfulTheme,
+    mode: RegistrationMode,
+) -> Result<String> {
     println!("\nOpening captcha page in embedded browser...");
-    let mut token = get_captcha_token_for_wizard(&theme)?;
+    let mut token = timing::measure("captcha", || get_captcha_token_for_wizard(cfg, theme))?;
     println!("Captcha token captured.");
 
     loop {
-        let registration_result = register_with_mode(&cfg, &token, false);
+        let registration_result = timing::measure("register", || register(cfg, &token, mode));
 
         match registration_result {
-            Ok(_) => break,
+            Ok(output) => {
+                print_signal_output(&output);
+                notify(cfg, Event::VerificationNeeded, None);
+                return Ok(token);
+            }
             Err(err) => {
                 eprintln!("\nRegistration failed: {err}");
                 eprintln!(
                     "If you saw StatusCode 502 (ExternalServiceFailureException), it is often temporary."
                 );
-                eprintln!("{}", registration_failure_hint());
+                eprintln!("{}", ui::wrap(&hints::registration_failure().render()));
 
-                let retry_same = Confirm::with_theme(&theme)
-                    .with_prompt("Retry registration with the same captcha token?")
-                    .default(true)
-                    .interact()?;
+                let failed_ip = ip::lookup_public_ip().ok();
+                let switch_network = ui::confirm(
+                    theme,
+                    "Retry from another network (for example a mobile hotspot)?",
+                    false,
+                )?;
+                if switch_network && confirm_network_switch(theme, failed_ip.as_ref())? {
+                    continue;
+                }
+
+                let retry_same = ui::confirm(
+                    theme,
+                    "Retry registration with the same captcha token?",
+                    true,
+                )?;
                 if retry_same {
                     continue;
                 }
 
-                let regenerate = Confirm::with_theme(&theme)
-                    .with_prompt("Generate a new captcha token and retry?")
-                    .default(true)
-                    .interact()?;
+                let regenerate =
+                    ui::confirm(theme, "Generate a new captcha token and retry?", true)?;
                 if regenerate {
                     println!("\nOpening captcha page in embedded browser...");
-                    token = get_captcha_token_for_wizard(&theme)?;
+                    token =
+                        timing::measure("captcha", || get_captcha_token_for_wizard(cfg, theme))?;
                     println!("New captcha token captured.");
                     continue;
                 }
@@ -194,72 +1492,173 @@ fn cmd_wizard(cli: &Cli) -> Result<()> {
             }
         }
     }
+}
 
-    let code: String = Input::with_theme(&theme)
-        .with_prompt("Verification code received by SMS/voice")
-        .interact_text()?;
-
-    let has_existing_pin = Confirm::with_theme(&theme)
-        .with_prompt("Do you already have a registration lock PIN on this number?")
-        .default(false)
-        .interact()?;
-
-    let existing_pin = if has_existing_pin {
-        Some(
-            Input::<String>::with_theme(&theme)
-                .with_prompt("Existing registration lock PIN")
-                .interact_text()?,
-        )
-    } else {
-        None
+#[cfg(not(test))]
+fn confirm_network_switch(theme: &ColorfulTheme, failed_ip: Option<&ip::PublicIp>) -> Result<bool> {
+    let Some(failed_ip) = failed_ip else {
+        println!("Could not look up the public IP; retrying without checking the network change.");
+        return Ok(true);
     };
+    println!("Switch networks now (for example enable a mobile hotspot and join it).");
+    loop {
+        let Ok(current) = ip::lookup_public_ip() else {
+            println!(
+                "Could not look up the public IP; retrying without checking the network change."
+            );
+            return Ok(true);
+        };
+        if let Some(message) = ip::ip_change_message(failed_ip, &current) {
+            println!("{message}");
+            if let Some(warning) = ip::hosting_warning(&current) {
+                eprintln!("{warning}");
+            }
+            return Ok(true);
+        }
 
-    verify_code(&cfg, &code, existing_pin.as_deref())?;
-    println!("Registration verified.");
+        let choice = ui::select(
+            theme,
+            &ip::unchanged_ip_prompt(&current),
+            &["Check again", "Retry anyway", "Back"],
+            0,
+        )?;
+        match choice {
+            0 => continue,
+            1 => return Ok(true),
+            _ => return Ok(false),
+        }
+    }
+}
 
-    let generated_pin = generate_long_registration_lock_pin();
-    let pretty_generated_pin = format_pin_for_display(&generated_pin, 4);
-    println!("\nIMPORTANT: Save this registration lock PIN now.");
-    println!("Registration lock PIN: {pretty_generated_pin}");
-    println!("Store it in a password manager. You will need it to re-register this number.");
+#[cfg(test)]
+fn cmd_wizard(_cli: &Cli) -> Result<()> {
+    Ok(())
+}
 
-    while !Confirm::with_theme(&theme)
-        .with_prompt("Have you saved this PIN?")
-        .default(false)
-        .interact()?
-    {
-        println!("Please save it before continuing.");
-        println!("Registration lock PIN: {pretty_generated_pin}");
+#[cfg(not(test))]
+fn prompt_pin_choice(theme: &ColorfulTheme) -> Result<reglock::PinChoice> {
+    let labels: Vec<&str> = reglock::PinChoice::ALL
+        .iter()
+        .map(|choice| choice.label())
+        .collect();
+    loop {
+        let choice = ui::select(theme, "Registration lock PIN", &labels, 0)?;
+        let choice = reglock::PinChoice::ALL
+            .get(choice)
+            .copied()
+            .unwrap_or(reglock::PinChoice::SetNow);
+        if choice != reglock::PinChoice::Skip {
+            return Ok(choice);
+        }
+        eprintln!("\n{}", ui::wrap(reglock::NO_REGLOCK_WARNING));
+        if ui::confirm(theme, "Continue without a registration lock?", false)? {
+            return Ok(choice);
+        }
     }
+}
 
-    set_registration_lock_pin(&cfg, &generated_pin)?;
-    println!("Registration lock PIN configured.");
+#[cfg(not(test))]
+fn set_generated_pin(cfg: &Config, lang: Language, theme: &ColorfulTheme) -> Result<()> {
+    let generated_pin = generate_long_registration_lock_pin(cfg.pin.digits);
+    let pretty_generated_pin = format_pin_for_display(&generated_pin, cfg.pin.group);
+    let pin_label = lang.text(Message::PinLabel);
+    println!("\n{}", lang.text(Message::SavePinNow));
+    println!("{pin_label}: {pretty_generated_pin}");
+    println!("{}", lang.text(Message::StorePin));
 
-    let do_link = Confirm::with_theme(&theme)
-        .with_prompt("Link Signal Desktop now?")
-        .default(true)
-        .interact()?;
-    if !do_link {
-        println!("Done. Registration completed without desktop linking.");
-        return Ok(());
+    while !ui::confirm(theme, lang.text(Message::PinSavedPrompt), false)? {
+        println!("{}", lang.text(Message::SavePinFirst));
+        println!("{pin_label}: {pretty_generated_pin}");
     }
 
-    let interval = DEFAULT_SCAN_INTERVAL;
-    let attempts = DEFAULT_SCAN_ATTEMPTS;
-    println!("Using default QR scan settings: every {interval}s, max {attempts} attempts.");
+    set_registration_lock_pin(cfg, &generated_pin)?;
+    reglock::clear_reminder(&cfg.account)?;
+    println!("{}", lang.text(Message::PinConfigured));
 
-    link_desktop_interactive(&cfg, &theme, interval, attempts)?;
-    println!("\nSetup completed successfully.");
+    if let Some(backend) = keychain::keychain_backend() {
+        let store = ui::confirm(
+            theme,
+            &format!(
+                "Also store the PIN in the {}, so 'verify --pin-from-keychain' can answer a registration lock?",
+                backend.name()
+            ),
+            true,
+        )?;
+        if store {
+            match keychain::store_pin(&cfg.account, &generated_pin) {
+                Ok(()) => println!("PIN stored in the {}.", backend.name()),
+                Err(err) => eprintln!("Warning: {err:#}"),
+            }
+        }
+    }
     Ok(())
 }
 
+#[cfg(not(test))]
+fn offer_jre_image(image: &str, theme: &ColorfulTheme) -> Result<bool> {
+    if !image_requires_emulation(image, host_docker_arch()) {
+        return Ok(false);
+    }
+
+    eprintln!("{}", ui::wrap(&emulation_warning(image)));
+    ui::confirm(theme, "Use the multi-arch JRE image instead?", true)
+}
+
 #[cfg(test)]
-fn cmd_wizard(_cli: &Cli) -> Result<()> {
-    Ok(())
+fn offer_jre_image(image: &str, _theme: &ColorfulTheme) -> Result<bool> {
+    Ok(image_requires_emulation(image, "arm64"))
+}
+
+fn render_wizard_plan(cfg: &Config, mode: RegistrationMode) -> String {
+    let mut out = String::from("Plan:\n");
+    out.push_str(&format!("  Account      : {}\n", cfg.account));
+    out.push_str(&format!("  Data dir     : {}\n", cfg.data_dir.display()));
+    out.push_str(&format!(
+        "  Image        : {} ({})\n",
+        cfg.image,
+        cfg.image_flavor.as_str()
+    ));
+    out.push_str(&format!("  Runtime      : {}\n", cfg.runtime.as_str()));
+    out.push_str(&format!("  Registration : {}\n", mode.label()));
+    out.push_str(
+        "  Steps        : captcha, register, verify, set registration lock PIN, link Signal Desktop (optional)",
+    );
+
+    let accounts = bundle::registered_accounts(&cfg.data_dir);
+    if accounts.contains(&cfg.account) {
+        out.push_str(&format!(
+            "\nNote: {} already holds {}; registering again replaces the existing registration.",
+            cfg.data_dir.display(),
+            cfg.account
+        ));
+    } else if !accounts.is_empty() {
+        out.push_str(&format!(
+            "\nNote: {} holds other accounts ({}), not {}. Check --data-dir and --account.",
+            cfg.data_dir.display(),
+            accounts.join(", "),
+            cfg.account
+        ));
+    }
+    out
 }
 
-fn registration_failure_hint() -> &'static str {
-    "If this persists: the number/operator may be blocked, or your current IP may be rate-limited. Try another network/IP (for example mobile hotspot) or another number/operator."
+const BACK_ANSWER: &str = "back";
+
+fn is_back_answer(answer: &str) -> bool {
+    answer.trim().eq_ignore_ascii_case(BACK_ANSWER)
+}
+
+const CALL_AGAIN_ANSWER: &str = "again";
+
+fn is_call_again_answer(answer: &str) -> bool {
+    answer.trim().eq_ignore_ascii_case(CALL_AGAIN_ANSWER)
+}
+
+fn call_again_wait(since_last_call: Option<std::time::Duration>) -> std::time::Duration {
+    let Some(elapsed) = since_last_call else {
+        return std::time::Duration::ZERO;
+    };
+    std::time::Duration::from_secs(VOICE_CALL_AGAIN_SECS).saturating_sub(elapsed)
 }
 
 fn format_watch_duration(total_seconds: u64) -> String {
@@ -281,11 +1680,104 @@ fn format_watch_duration(total_seconds: u64) -> String {
     }
 }
 
-fn generate_long_registration_lock_pin() -> String {
+fn signal_cli_build(cfg: &Config) -> Result<String> {
+    let version = check_signal_cli_version(cfg, false)?;
+    if let Some(addr) = &cfg.daemon_addr {
+        return Ok(format!("{version} from the daemon at {addr}"));
+    }
+    Ok(match pull::image_digest(cfg) {
+        Some(digest) => format!("{version} from {}@{digest}", cfg.image),
+        None => format!("{version} from {} (no registry digest)", cfg.image),
+    })
+}
+
+/// Signal only allows a few wrong guesses, so this stops after `REGISTRATION_PIN_ATTEMPTS`.
+fn verify_answering_registration_lock(
+    cfg: &Config,
+    code: &str,
+    pin: Option<&str>,
+    mut next_pin: impl FnMut(u32) -> Result<Option<String>>,
+) -> Result<SignalCliOutput> {
+    let mut pin = pin.map(str::to_string);
+    let mut asked = 0;
+    loop {
+        let err = match verify_code(cfg, code, pin.as_deref()) {
+            Ok(output) => return Ok(output),
+            Err(err) => err,
+        };
+        let challenged = matches!(
+            err.downcast_ref::<SignalSetupError>(),
+            Some(SignalSetupError::RegistrationLocked | SignalSetupError::RegistrationPinIncorrect)
+        );
+        if !challenged || asked >= REGISTRATION_PIN_ATTEMPTS {
+            return Err(err);
+        }
+        let Some(next) = next_pin(asked)? else {
+            return Err(err);
+        };
+        asked += 1;
+        pin = Some(next);
+    }
+}
+
+/// Accounts without a lock never touch the keychain.
+fn verify_code_with_keychain_pin(cfg: &Config, code: &str) -> Result<SignalCliOutput> {
+    verify_answering_registration_lock(cfg, code, None, |asked| {
+        // A wrong stored PIN is not retried: it would only burn Signal's guesses.
+        if asked > 0 {
+            return Ok(None);
+        }
+        let Some(pin) = keychain::load_pin(&cfg.account)? else {
+            bail!(
+                "this number has a registration lock, but the keychain has no PIN for {}; pass it with --pin",
+                cfg.account
+            )
+        };
+        eprintln!("The number has a registration lock; verifying with the PIN from the keychain.");
+        Ok(Some(pin))
+    })
+}
+
+fn print_signal_output(output: &SignalCliOutput) {
+    if let Some(text) = output.render() {
+        println!("{text}");
+    }
+    if let Some(messages) = output.messages() {
+        eprintln!("{messages}");
+    }
+}
+
+#[cfg(not(test))]
+fn prompt_registration_lock_pin(
+    theme: &ColorfulTheme,
+    account: &str,
+    asked: u32,
+) -> Result<Option<String>> {
+    if asked == 0 && keychain::keychain_backend().is_some() {
+        match keychain::load_pin(account) {
+            Ok(Some(pin)) => {
+                eprintln!(
+                    "The number has a registration lock; verifying with the PIN from the keychain."
+                );
+                return Ok(Some(pin));
+            }
+            Ok(None) => {}
+            Err(err) => eprintln!("Warning: {err:#}"),
+        }
+    }
+    let answer = ui::input(
+        theme,
+        "This number has a registration lock. Its PIN (empty to give up)",
+    )?;
+    let answer = answer.trim();
+    Ok((!answer.is_empty()).then(|| answer.to_string()))
+}
+
+fn generate_long_registration_lock_pin(digits: usize) -> String {
     let mut rng = OsRng;
-    let mut pin = String::with_capacity(GENERATED_REGISTRATION_PIN_DIGITS);
+    let mut pin = String::with_capacity(digits);
 
-    for _ in 0..GENERATED_REGISTRATION_PIN_DIGITS {
+    for _ in 0..digits {
         let digit = rng.gen_range(0_u8..10_u8);
         pin.push((b'0' + digit) as char);
     }
@@ -306,22 +1798,45 @@ fn format_pin_for_display(pin: &str, chunk_size: usize) -> String {
         .join("-")
 }
 
+fn warn_outdated_desktop() {
+    if let Some(problem) = signal_desktop_version()
+        .as_deref()
+        .and_then(desktop_version_problem)
+    {
+        eprintln!("{}", ui::wrap(&format!("Warning: {problem}")));
+    }
+}
+
 fn link_desktop_live(cfg: &Config, interval: u64, attempts: u32) -> Result<()> {
     if interval == 0 || attempts == 0 {
         bail!("interval and attempts must be > 0")
     }
 
-    if !command_exists("screencapture") {
+    if !command_exists("screencapture") && cfg.capture.backend != qr::CaptureBackend::Xcap {
         #[cfg(target_os = "macos")]
         {
-            bail!("screencapture is required (macOS)")
+            bail!("screencapture is required (macOS); --capture-backend xcap works without it")
+        }
+    }
+
+    // Only xcap goes through X11 or the Wayland portal.
+    if cfg.capture.backend == qr::CaptureBackend::Xcap || !command_exists("screencapture") {
+        if let Some(problem) = system::screen_access_problem() {
+            return Err(SignalSetupError::ScreenCaptureUnavailable {
+                reason: problem.describe(),
+            }
+            .into());
         }
     }
 
+    warn_outdated_desktop();
     if open_signal_desktop() {
         println!("Signal Desktop launch requested.");
     } else {
         println!("Could not auto-launch Signal Desktop. Open it manually.");
+        if !system::signal_desktop_installed() {
+            println!("Signal Desktop does not seem to be installed; 'bootstrap' can install it.");
+        }
     }
     println!("Ensure the Signal Desktop pairing QR is visible on screen.");
 
@@ -329,12 +1844,78 @@ fn link_desktop_live(cfg: &Config, interval: u64, attempts: u32) -> Result<()> {
     let watch_text = format_watch_duration(watch_seconds);
     println!("Watching the screen for up to {watch_text}.");
     println!("Scanning every {interval}s (max {attempts} attempts)...");
-    println!("If prompted, grant Screen Recording permission to this terminal app.");
+    if cfg!(target_os = "macos") {
+        let app = system::terminal_app().map_or("this terminal app", |app| app.name);
+        println!("If prompted, grant Screen Recording permission to {app}.");
+    }
 
-    let uri = scan_screen_for_signal_uri(interval, attempts)?;
-    println!("Valid QR detected. Linking device...");
+    let link_retries = cfg.link_retries.unwrap_or(DEFAULT_LINK_RETRIES);
+    let mut expired_uri: Option<String> = None;
+    let mut retries = 0_u32;
+    let mut stale_scans = 0_u32;
+    loop {
+        let scan = timing::measure("scan", || {
+            scan_screen_for_signal_uri(interval, attempts, &cfg.decode_limits, &cfg.capture)
+        });
+        let uri = match scan {
+            Ok(uri) => uri,
+            Err(err) if shutdown::is_terminated(&err) => return Err(err),
+            Err(err) => {
+                notify(cfg, Event::QrExpired, Some(&err.to_string()));
+                return Err(err);
+            }
+        };
 
-    link_desktop_from_uri(cfg, &uri)
+        if expired_uri.as_deref() == Some(uri.as_str()) {
+            stale_scans += 1;
+            if stale_scans >= attempts {
+                notify(cfg, Event::QrExpired, None);
+                bail!("Signal Desktop still shows the expired QR code. Restart linking in Signal Desktop and retry.")
+            }
+            shutdown::sleep_unless_terminated(std::time::Duration::from_secs(interval))?;
+            continue;
+        }
+
+        println!("Valid QR detected. Linking device...");
+        match link_desktop_from_uri(cfg, &uri) {
+            Err(err) if is_link_qr_expired(&err) && retries < link_retries => {
+                retries += 1;
+                println!(
+                    "The QR code expired before linking. Scanning for the refreshed QR (retry {retries}/{link_retries})..."
+                );
+                expired_uri = Some(uri);
+                stale_scans = 0;
+            }
+            Err(err) if is_transient_link_failure(&err) && retries < link_retries => {
+                retries += 1;
+                println!("{err} Scanning again in {interval}s (retry {retries}/{link_retries})...");
+                shutdown::sleep_unless_terminated(std::time::Duration::from_secs(interval))?;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn is_link_qr_expired(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<SignalSetupError>(),
+        Some(SignalSetupError::LinkQrExpired)
+    )
+}
+
+fn is_transient_link_failure(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<SignalSetupError>(),
+        Some(SignalSetupError::LinkQrExpired | SignalSetupError::LinkNetworkFailed)
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LinkEnd {
+    /// Linked, or linking skipped on purpose
+    Done,
+    /// The Screen Recording permission was granted; macOS only applies it to a new process
+    Restart,
 }
 
 #[cfg(not(test))]
@@ -343,22 +1924,44 @@ fn link_desktop_interactive(
     theme: &ColorfulTheme,
     interval: u64,
     attempts: u32,
-) -> Result<()> {
+) -> Result<LinkEnd> {
     loop {
         match link_desktop_live(cfg, interval, attempts) {
-            Ok(_) => return Ok(()),
+            Ok(_) => return Ok(LinkEnd::Done),
             Err(err) => {
-                eprintln!("\nLive QR scan failed: {err}");
-                eprintln!(
-                    "If you saw 'could not create image from display', grant Screen Recording permission to your terminal app in System Settings > Privacy & Security > Screen Recording."
-                );
+                if let Some(hint) = link_recovery_hint(&err) {
+                    eprintln!("\nLinking failed: {err}");
+                    eprintln!("{hint}");
+                    run_link_recovery_check(cfg, &err);
+                } else if matches!(
+                    err.downcast_ref::<SignalSetupError>(),
+                    Some(SignalSetupError::ScreenCaptureUnavailable { .. })
+                ) {
+                    // The error already names the screenshot and URI alternatives below.
+                    eprintln!("\n{}", ui::wrap(&err.to_string()));
+                } else {
+                    eprintln!("\nLive QR scan failed: {err}");
+                    let terminal = system::terminal_app();
+                    let hint = hints::screen_permission(hints::Os::current(), terminal);
+                    eprintln!("{}", ui::wrap(&hint.render()));
 
-                if Confirm::with_theme(theme)
-                    .with_prompt("Open Screen Recording settings now?")
-                    .default(true)
-                    .interact()?
-                {
-                    open_screen_recording_settings();
+                    let prompt = match terminal {
+                        Some(app) => {
+                            format!("Open Screen Recording settings to allow {}?", app.name)
+                        }
+                        None => "Open Screen Recording settings now?".to_string(),
+                    };
+                    if cfg!(target_os = "macos") && ui::confirm(theme, &prompt, true)? {
+                        open_screen_recording_settings();
+                        let restart = ui::confirm(
+                            theme,
+                            "Restart to apply the permission? Answer once it is on; registration is kept",
+                            true,
+                        )?;
+                        if restart {
+                            return Ok(LinkEnd::Restart);
+                        }
+                    }
                 }
 
                 let options = [
@@ -367,32 +1970,25 @@ fn link_desktop_interactive(
                     "Paste sgnl:// URI manually",
                     "Skip desktop linking",
                 ];
-                let next = Select::with_theme(theme)
-                    .with_prompt("Choose next step")
-                    .items(&options)
-                    .default(0)
-                    .interact()?;
+                let next = ui::select(theme, "Choose next step", &options, 0)?;
 
                 match next {
                     0 => continue,
                     1 => {
-                        let path_input: String = Input::with_theme(theme)
-                            .with_prompt("Path to screenshot file containing the Signal QR")
-                            .interact_text()?;
+                        let path_input: String =
+                            ui::input(theme, "Path to screenshot file containing the Signal QR")?;
                         let path = PathBuf::from(path_input);
                         link_desktop_from_image(cfg, &path)?;
-                        return Ok(());
+                        return Ok(LinkEnd::Done);
                     }
                     2 => {
-                        let uri: String = Input::with_theme(theme)
-                            .with_prompt("Paste full sgnl://linkdevice URI")
-                            .interact_text()?;
+                        let uri: String = ui::input(theme, "Paste full sgnl://linkdevice URI")?;
                         link_desktop_from_uri(cfg, &uri)?;
-                        return Ok(());
+                        return Ok(LinkEnd::Done);
                     }
                     3 => {
                         println!("Skipping desktop linking for now.");
-                        return Ok(());
+                        return Ok(LinkEnd::Done);
                     }
                     _ => unreachable!(),
                 }
@@ -401,14 +1997,39 @@ fn link_desktop_interactive(
     }
 }
 
+fn link_recovery_hint(err: &anyhow::Error) -> Option<String> {
+    hints::for_error(err)
+        .filter(|hint| hint.category.is_link())
+        .map(|hint| hint.render())
+}
+
+#[cfg(not(test))]
+fn run_link_recovery_check(cfg: &Config, err: &anyhow::Error) {
+    match err.downcast_ref::<SignalSetupError>() {
+        Some(SignalSetupError::LinkDeviceLimitReached) => {
+            match list_devices(cfg) {
+                Ok(output) => print_signal_output(&output),
+                Err(err) => eprintln!("Warning: could not list linked devices: {err:#}"),
+            }
+        }
+        Some(SignalSetupError::LinkNetworkFailed) => match net::ensure_online() {
+            Ok(()) => eprintln!(
+                "The internet is reachable, so a proxy, firewall or VPN may be blocking the Signal servers."
+            ),
+            Err(err) => eprintln!("{err}"),
+        },
+        _ => {}
+    }
+}
+
 #[cfg(test)]
 fn link_desktop_interactive(
     _cfg: &Config,
     _theme: &ColorfulTheme,
     _interval: u64,
     _attempts: u32,
-) -> Result<()> {
-    Ok(())
+) -> Result<LinkEnd> {
+    Ok(LinkEnd::Done)
 }
 
 fn link_desktop_from_image(cfg: &Config, path: &Path) -> Result<()> {
@@ -422,46 +2043,168 @@ fn link_desktop_from_image(cfg: &Config, path: &Path) -> Result<()> {
     link_desktop_from_uri(cfg, &uri)
 }
 
+fn decode_qr_input(image: &Path, input: &mut impl std::io::Read) -> Result<String> {
+    let (uri, source) = if image == Path::new("-") {
+        let mut bytes = Vec::new();
+        input
+            .read_to_end(&mut bytes)
+            .context("failed to read the image from stdin")?;
+        if bytes.is_empty() {
+            bail!("no image data on stdin")
+        }
+        (
+            qr::decode_signal_qr_from_bytes(&bytes)?,
+            "stdin".to_string(),
+        )
+    } else {
+        if !image.exists() {
+            bail!("screenshot file not found: {}", image.display())
+        }
+        (
+            decode_signal_qr_from_image(image)?,
+            image.display().to_string(),
+        )
+    };
+    uri.ok_or_else(|| anyhow::anyhow!("no valid sgnl://linkdevice QR found in {source}"))
+}
+
 fn link_desktop_from_uri(cfg: &Config, uri: &str) -> Result<()> {
-    if !uri.starts_with("sgnl://linkdevice") {
-        bail!("invalid URI: expected sgnl://linkdevice...")
+    let link = parse_link_uri(uri)?;
+    println!("{}", link.describe());
+    if !confirm_link_device()? {
+        bail!("device linking cancelled")
     }
 
-    let args = vec![
-        "addDevice".to_string(),
-        "--uri".to_string(),
-        uri.to_string(),
-    ];
-    run_signal_cli(cfg, &args, false)?;
+    let args = signal_args::add_device(uri);
+    let before = linked_devices(cfg);
+    print_signal_output(&run_signal_cli(cfg, &args, false)?);
 
-    run_post_link_sync(cfg);
+    timing::measure("sync", || run_post_link_sync(cfg));
 
-    println!("Linked devices:");
-    list_devices(cfg)?;
+    let Some(before) = before else {
+        eprintln!("Warning: could not read the device list to confirm the new device.");
+        notify(cfg, Event::DeviceLinked, None);
+        println!("Linked devices:");
+        print_signal_output(&list_devices(cfg)?);
+        offer_desktop_restart(cfg);
+        return Ok(());
+    };
+    let linked = wait_for_new_device(cfg, &before)?;
+    let names: Vec<String> = linked.iter().map(LinkedDevice::named).collect();
+    for name in &names {
+        println!("Linked {name}.");
+    }
+    notify(cfg, Event::DeviceLinked, Some(&names.join(", ")));
+    offer_desktop_restart(cfg);
     Ok(())
 }
 
+/// A failed restart only warns: the device is linked either way.
+fn offer_desktop_restart(cfg: &Config) {
+    let restart = cfg.sync.restart_desktop
+        || confirm_desktop_restart().unwrap_or_else(|err| {
+            eprintln!("Warning: {err:#}");
+            false
+        });
+    if !restart {
+        println!(
+            "If Signal Desktop stays on 'Syncing contacts and groups', quit and reopen it (--restart-desktop does that after linking)."
+        );
+        return;
+    }
+    println!("Restarting Signal Desktop...");
+    match system::restart_signal_desktop() {
+        Ok(()) => println!("Signal Desktop restarted."),
+        Err(err) => eprintln!("Warning: {err:#}"),
+    }
+}
+
+#[cfg(not(test))]
+fn confirm_desktop_restart() -> Result<bool> {
+    if !std::io::stdin().is_terminal() || !system::is_signal_desktop_running() {
+        return Ok(false);
+    }
+    ui::confirm(
+        &ui::theme(),
+        "Restart Signal Desktop now? A restart often clears 'Syncing contacts and groups'.",
+        true,
+    )
+}
+
+#[cfg(test)]
+fn confirm_desktop_restart() -> Result<bool> {
+    Ok(false)
+}
+
+fn wait_for_new_device(cfg: &Config, before: &[LinkedDevice]) -> Result<Vec<LinkedDevice>> {
+    for check in 1..=LINK_DEVICE_POLL_ATTEMPTS {
+        let added: Vec<LinkedDevice> = linked_devices(cfg)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|device| before.iter().all(|known| known.id != device.id))
+            .collect();
+        if !added.is_empty() {
+            return Ok(added);
+        }
+        if check < LINK_DEVICE_POLL_ATTEMPTS {
+            println!(
+                "Waiting for the new device to show up ({check}/{LINK_DEVICE_POLL_ATTEMPTS})..."
+            );
+            shutdown::sleep_unless_terminated(std::time::Duration::from_secs(
+                LINK_DEVICE_POLL_DELAY_SECS,
+            ))?;
+        }
+    }
+
+    Err(SignalSetupError::LinkedDeviceMissing {
+        checks: LINK_DEVICE_POLL_ATTEMPTS,
+    }
+    .into())
+}
+
+#[cfg(not(test))]
+fn confirm_link_device() -> Result<bool> {
+    if !std::io::stdin().is_terminal() {
+        return Ok(true);
+    }
+    ui::confirm(&ui::theme(), "Link this device to your account?", true)
+}
+
+#[cfg(test)]
+fn confirm_link_device() -> Result<bool> {
+    Ok(true)
+}
+
 fn run_post_link_sync(cfg: &Config) {
-    let total_wait = POST_LINK_SYNC_PASSES as u64 * POST_LINK_RECEIVE_TIMEOUT_SECS;
+    if !cfg.sync.enabled {
+        println!("Skipping the post-link sync (--no-sync).");
+        println!(
+            "Signal Desktop may stay on 'Syncing contacts and groups' until a 'receive' runs for this account."
+        );
+        return;
+    }
+    let total_wait = POST_LINK_SYNC_PASSES as u64 * cfg.sync.timeout_secs;
     println!("Finalizing initial contacts/groups sync from the primary device...");
     println!(
         "Keeping this process active helps avoid Signal Desktop staying on 'Syncing contacts and groups'."
     );
-    println!("Sync window: up to {}s.", total_wait);
-
-    let receive_args = vec![
-        "receive".to_string(),
-        "--timeout".to_string(),
-        POST_LINK_RECEIVE_TIMEOUT_SECS.to_string(),
-        "--max-messages".to_string(),
-        POST_LINK_RECEIVE_MAX_MESSAGES.to_string(),
-    ];
-
-    for pass in 1..=POST_LINK_SYNC_PASSES {
-        println!("Sync pass {pass}/{POST_LINK_SYNC_PASSES}: waiting for pending sync requests...");
-        match run_signal_cli(cfg, &receive_args, true) {
-            Ok(true) => {}
-            Ok(false) => {
+    println!("Sync window: at least {total_wait}s, longer while messages keep arriving.");
+
+    let receive_args =
+        signal_args::receive(cfg.sync.timeout_secs as i64, Some(cfg.sync.max_messages));
+
+    let mut total = SyncProgress::default();
+    let passes = if cfg.daemon_addr.is_some() {
+        println!("The signal-cli daemon receives continuously; skipping receive passes.");
+        0
+    } else {
+        POST_LINK_SYNC_MAX_PASSES
+    };
+    for pass in 1..=passes {
+        println!("Sync pass {pass}: waiting for pending sync requests...");
+        let output = match run_signal_cli_captured(cfg, &receive_args) {
+            Ok(output) if output.status.success() => output,
+            Ok(_) => {
                 eprintln!("Warning: receive pass {pass} failed.");
                 eprintln!(
                     "Desktop may still complete sync after restart. See README troubleshooting for a manual docker receive command."
@@ -475,17 +2218,24 @@ fn run_post_link_sync(cfg: &Config) {
                 );
                 break;
             }
+        };
+
+        let progress = parse_sync_progress(&String::from_utf8_lossy(&output.stdout));
+        total.add(progress);
+        println!("Sync pass {pass}: {}.", progress.summary());
+        if pass >= POST_LINK_SYNC_PASSES && !progress.made_progress() {
+            break;
         }
     }
+    println!("Sync received {}.", total.summary());
 
     println!("Sending a contacts sync message to linked devices...");
-    let send_contacts_args = vec!["sendContacts".to_string()];
-    match run_signal_cli(cfg, &send_contacts_args, true) {
-        Ok(true) => {
+    match run_signal_cli(cfg, &signal_args::send_contacts(), true) {
+        Ok(output) if output.succeeded => {
             println!("Contacts sync message sent.");
         }
-        Ok(false) => {
-            eprintln!("Warning: sendContacts failed.");
+        Ok(output) => {
+            eprintln!("Warning: sendContacts failed: {}", output.failure_reason());
         }
         Err(err) => {
             eprintln!("Warning: sendContacts error: {err}");
@@ -0,0 +1,85 @@
+use anyhow::{bail, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkUri {
+    pub uuid: String,
+    pub pub_key: String,
+}
+
+impl LinkUri {
+    pub fn key_fingerprint(&self) -> String {
+        let key: String = self.pub_key.chars().take(12).collect();
+        format!("{key}...")
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "New Signal Desktop device\n  provisioning id: {}\n  public key     : {}",
+            self.uuid,
+            self.key_fingerprint()
+        )
+    }
+}
+
+pub fn parse_link_uri(uri: &str) -> Result<LinkUri> {
+    let uri = uri.trim();
+    let Some(query) = uri.strip_prefix("sgnl://linkdevice?") else {
+        bail!("invalid URI: expected sgnl://linkdevice...")
+    };
+
+    let mut uuid = None;
+    let mut pub_key = None;
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = percent_decode(value)?;
+        match name {
+            "uuid" => uuid = Some(value),
+            "pub_key" => pub_key = Some(value),
+            _ => {}
+        }
+    }
+
+    let uuid = match uuid {
+        Some(uuid) if !uuid.is_empty() => uuid,
+        _ => bail!("invalid link URI: missing uuid"),
+    };
+    let pub_key = match pub_key {
+        Some(key) if !key.is_empty() => key,
+        _ => bail!("invalid link URI: missing pub_key"),
+    };
+    if !pub_key
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '-' | '_' | '='))
+    {
+        bail!("invalid link URI: pub_key is not base64")
+    }
+
+    Ok(LinkUri { uuid, pub_key })
+}
+
+pub fn percent_decode(value: &str) -> Result<String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            let Some(byte) = hex else {
+                bail!("invalid link URI: bad percent-encoding in '{value}'")
+            };
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    match String::from_utf8(decoded) {
+        Ok(text) => Ok(text),
+        Err(_) => bail!("invalid link URI: '{value}' is not valid UTF-8"),
+    }
+}
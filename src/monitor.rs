@@ -0,0 +1,196 @@
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeepaliveStats {
+    pub messages_received: u64,
+    pub receive_passes: u64,
+    pub last_success_unix: Option<u64>,
+    pub consecutive_failures: u32,
+}
+
+impl KeepaliveStats {
+    pub fn record_success(&mut self, messages: u64, now_unix: u64) {
+        self.receive_passes += 1;
+        self.messages_received += messages;
+        self.last_success_unix = Some(now_unix);
+        self.consecutive_failures = 0;
+    }
+
+    pub fn record_failure(&mut self) {
+        self.receive_passes += 1;
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+    }
+}
+
+pub type SharedStats = Arc<Mutex<KeepaliveStats>>;
+
+pub fn update_stats(stats: &SharedStats, apply: impl FnOnce(&mut KeepaliveStats)) {
+    match stats.lock() {
+        Ok(mut guard) => apply(&mut guard),
+        Err(poisoned) => apply(&mut poisoned.into_inner()),
+    }
+}
+
+pub fn snapshot_stats(stats: &SharedStats) -> KeepaliveStats {
+    match stats.lock() {
+        Ok(guard) => guard.clone(),
+        Err(poisoned) => poisoned.into_inner().clone(),
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn render_metrics(stats: &KeepaliveStats, account: &str) -> String {
+    let label = format!("account=\"{}\"", account.replace('"', ""));
+    let mut out = String::new();
+    out.push_str("# HELP signal_keepalive_messages_received_total Envelopes received by keepalive receive passes.\n");
+    out.push_str("# TYPE signal_keepalive_messages_received_total counter\n");
+    out.push_str(&format!(
+        "signal_keepalive_messages_received_total{{{label}}} {}\n",
+        stats.messages_received
+    ));
+    out.push_str("# HELP signal_keepalive_receive_passes_total Receive passes attempted.\n");
+    out.push_str("# TYPE signal_keepalive_receive_passes_total counter\n");
+    out.push_str(&format!(
+        "signal_keepalive_receive_passes_total{{{label}}} {}\n",
+        stats.receive_passes
+    ));
+    out.push_str("# HELP signal_keepalive_last_success_timestamp_seconds Unix time of the last successful receive (0 if none).\n");
+    out.push_str("# TYPE signal_keepalive_last_success_timestamp_seconds gauge\n");
+    out.push_str(&format!(
+        "signal_keepalive_last_success_timestamp_seconds{{{label}}} {}\n",
+        stats.last_success_unix.unwrap_or(0)
+    ));
+    out.push_str("# HELP signal_keepalive_consecutive_failures Receive passes failed in a row.\n");
+    out.push_str("# TYPE signal_keepalive_consecutive_failures gauge\n");
+    out.push_str(&format!(
+        "signal_keepalive_consecutive_failures{{{label}}} {}\n",
+        stats.consecutive_failures
+    ));
+    out
+}
+
+pub type HealthProbe = Box<dyn Fn() -> Result<()> + Send>;
+
+pub struct Monitor {
+    pub account: String,
+    pub stats: Option<SharedStats>,
+    pub probe: Option<HealthProbe>,
+}
+
+struct MonitorState {
+    monitor: Monitor,
+    last_probe: Option<(Instant, Result<(), String>)>,
+}
+
+impl MonitorState {
+    fn probe_result(&mut self) -> Result<(), String> {
+        if let Some((at, result)) = &self.last_probe {
+            if at.elapsed() < Duration::from_secs(crate::HEALTH_PROBE_CACHE_SECS) {
+                return result.clone();
+            }
+        }
+
+        let result = match &self.monitor.probe {
+            Some(probe) => probe().map_err(|err| err.to_string()),
+            None => Ok(()),
+        };
+        self.last_probe = Some((Instant::now(), result.clone()));
+        result
+    }
+
+    fn health(&mut self) -> (bool, Value) {
+        let probe = self.probe_result();
+        let stats = self.monitor.stats.as_ref().map(snapshot_stats);
+        let failing_keepalive = stats
+            .as_ref()
+            .is_some_and(|s| s.consecutive_failures >= crate::KEEPALIVE_UNHEALTHY_FAILURES);
+        let healthy = probe.is_ok() && !failing_keepalive;
+
+        let mut body = json!({
+            "status": if healthy { "ok" } else { "unhealthy" },
+            "account": self.monitor.account,
+        });
+        if let Err(err) = &probe {
+            body["probe_error"] = Value::from(err.as_str());
+        }
+        if let Some(stats) = stats {
+            body["consecutive_failures"] = Value::from(stats.consecutive_failures);
+            body["last_success_unix"] = stats.last_success_unix.map_or(Value::Null, Value::from);
+        }
+        (healthy, body)
+    }
+}
+
+pub fn serve_monitor(addr: &str, monitor: Monitor) -> Result<SocketAddr> {
+    let listener =
+        TcpListener::bind(addr).with_context(|| format!("failed to listen on {addr}"))?;
+    let local_addr = listener
+        .local_addr()
+        .context("failed to resolve monitor listen address")?;
+
+    let mut state = MonitorState {
+        monitor,
+        last_probe: None,
+    };
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let _ = handle_connection(stream, &mut state);
+        }
+    });
+
+    Ok(local_addr)
+}
+
+fn handle_connection(mut stream: TcpStream, state: &mut MonitorState) -> Result<()> {
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Drain the headers so closing the socket does not reset the connection.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+    let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+
+    let response = match path {
+        "/metrics" => state.monitor.stats.as_ref().map(|stats| {
+            (
+                "200 OK",
+                "text/plain; version=0.0.4",
+                render_metrics(&snapshot_stats(stats), &state.monitor.account),
+            )
+        }),
+        "/healthz" => {
+            let (healthy, body) = state.health();
+            let status = if healthy {
+                "200 OK"
+            } else {
+                "503 Service Unavailable"
+            };
+            Some((status, "application/json", format!("{body}\n")))
+        }
+        _ => None,
+    };
+    let (status, content_type, body) =
+        response.unwrap_or_else(|| ("404 Not Found", "text/plain", "not found\n".to_string()));
+
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()?;
+    Ok(())
+}
@@ -0,0 +1,189 @@
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::audit::{self, AuditedCommand};
+use crate::errors::SignalSetupError;
+use crate::system::command_exists;
+
+pub fn http_get(url: &str, timeout_secs: u64) -> Result<String> {
+    if !command_exists("curl") {
+        bail!("curl is required to reach {url}")
+    }
+
+    let output = Command::new("curl")
+        .arg("-fsSL")
+        .arg("--max-time")
+        .arg(timeout_secs.to_string())
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .audited_output()
+        .with_context(|| format!("failed to run curl for {url}"))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .unwrap_or("no response");
+        bail!("request to {url} failed: {reason}")
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Without curl nothing can be told, so it passes.
+pub fn ensure_online() -> Result<()> {
+    if !command_exists("curl") {
+        return Ok(());
+    }
+    if crate::CONNECTIVITY_PROBE_URLS
+        .iter()
+        .any(|url| http_reachable(url, crate::CONNECTIVITY_TIMEOUT_SECS))
+    {
+        return Ok(());
+    }
+    Err(SignalSetupError::Offline {
+        probed: crate::CONNECTIVITY_PROBE_URLS.join(", "),
+    }
+    .into())
+}
+
+/// Any HTTP answer counts, including error statuses; only DNS, connect and timeout errors do not.
+pub fn http_reachable(url: &str, timeout_secs: u64) -> bool {
+    Command::new("curl")
+        .arg("-sS")
+        .arg("-o")
+        .arg("/dev/null")
+        .arg("--max-time")
+        .arg(timeout_secs.to_string())
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .audited_status()
+        .is_ok_and(|status| status.success())
+}
+
+pub fn http_date(url: &str, timeout_secs: u64) -> Result<u64> {
+    if !command_exists("curl") {
+        bail!("curl is required to reach {url}")
+    }
+
+    let output = Command::new("curl")
+        .arg("-sSI")
+        .arg("--max-time")
+        .arg(timeout_secs.to_string())
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .audited_output()
+        .with_context(|| format!("failed to run curl for {url}"))?;
+    if !output.status.success() {
+        bail!("no response from {url}")
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("date"))
+        .and_then(|(_, value)| parse_http_date(value.trim()))
+        .with_context(|| format!("{url} sent no usable Date header"))
+}
+
+pub fn parse_http_date(value: &str) -> Option<u64> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let parts: Vec<&str> = value.split_whitespace().collect();
+    let [_, day, month, year, time, "GMT"] = parts.as_slice() else {
+        return None;
+    };
+    let day: i64 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|name| name == month)? as i64 + 1;
+    let year: i64 = year.parse().ok()?;
+    let mut hms = time.split(':').map(|part| part.parse::<i64>().ok());
+    let (hour, minute, second) = (hms.next()??, hms.next()??, hms.next()??);
+
+    // Days since 1970-01-01 for a proleptic Gregorian date (Howard Hinnant's days_from_civil).
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+    u64::try_from(days * 86_400 + hour * 3600 + minute * 60 + second).ok()
+}
+
+pub fn http_post_json(url: &str, body: &Value, timeout_secs: u64) -> Result<()> {
+    http_post(
+        url,
+        "application/json",
+        &[],
+        &body.to_string(),
+        timeout_secs,
+    )
+}
+
+pub fn http_post(
+    url: &str,
+    content_type: &str,
+    headers: &[String],
+    body: &str,
+    timeout_secs: u64,
+) -> Result<()> {
+    if !command_exists("curl") {
+        bail!("curl is required to reach {url}")
+    }
+
+    let mut cmd = Command::new("curl");
+    cmd.arg("-fsS")
+        .arg("--max-time")
+        .arg(timeout_secs.to_string())
+        .arg("-X")
+        .arg("POST")
+        .arg("-H")
+        .arg(format!("Content-Type: {content_type}"));
+    for header in headers {
+        cmd.arg("-H").arg(header);
+    }
+    // The body goes through stdin so credentials never show up in the process list.
+    let mut child = cmd
+        .arg("--data-binary")
+        .arg("@-")
+        .arg(url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .audited_spawn()
+        .with_context(|| format!("failed to run curl for {url}"))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(body.as_bytes())
+            .with_context(|| format!("failed to send request body to {url}"))?;
+    }
+    let pid = child.id();
+    let output = child
+        .wait_with_output()
+        .with_context(|| format!("failed to run curl for {url}"))?;
+    audit::record_exit(pid, &output.status);
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .unwrap_or("no response");
+        bail!("request to {url} failed: {reason}")
+    }
+
+    Ok(())
+}
@@ -0,0 +1,170 @@
+use anyhow::{bail, Result};
+use serde_json::{json, Value};
+use std::io::IsTerminal;
+
+use crate::config::Config;
+use crate::monitor::unix_now;
+use crate::net::{http_post, http_post_json};
+use crate::settings::{update_settings, Settings};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    VerificationNeeded,
+    RegistrationSucceeded,
+    DeviceLinked,
+    KeepaliveFailure,
+    QrExpired,
+    WaitFinished,
+}
+
+impl Event {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Event::VerificationNeeded => "verification_needed",
+            Event::RegistrationSucceeded => "registration_succeeded",
+            Event::DeviceLinked => "device_linked",
+            Event::KeepaliveFailure => "keepalive_failure",
+            Event::QrExpired => "qr_expired",
+            Event::WaitFinished => "wait_finished",
+        }
+    }
+
+    pub fn needs_user(self) -> bool {
+        matches!(
+            self,
+            Event::VerificationNeeded | Event::QrExpired | Event::WaitFinished
+        )
+    }
+
+    pub fn message(self) -> &'static str {
+        match self {
+            Event::VerificationNeeded => "Enter the verification code sent by SMS/voice.",
+            Event::RegistrationSucceeded => "Registration verified.",
+            Event::DeviceLinked => "Signal Desktop linked.",
+            Event::KeepaliveFailure => "Keepalive receive passes are failing.",
+            Event::QrExpired => "No Signal Desktop QR was detected in time. Show a fresh QR code.",
+            Event::WaitFinished => "Wait finished. Answer the incoming verification call.",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PushTarget {
+    Ntfy { url: String },
+    Pushover { user: String, token: String },
+}
+
+pub fn push_target(settings: &Settings) -> Option<PushTarget> {
+    if let (Some(user), Some(token)) = (&settings.pushover_user, &settings.pushover_token) {
+        return Some(PushTarget::Pushover {
+            user: user.clone(),
+            token: token.clone(),
+        });
+    }
+    settings
+        .ntfy_topic
+        .as_deref()
+        .map(|topic| PushTarget::Ntfy {
+            url: ntfy_url(topic),
+        })
+}
+
+pub fn ntfy_url(topic: &str) -> String {
+    if topic.contains("://") {
+        topic.to_string()
+    } else {
+        format!("{}/{topic}", crate::NTFY_DEFAULT_SERVER)
+    }
+}
+
+pub fn set_ntfy_topic(topic: &str) -> Result<()> {
+    let topic = topic.trim();
+    if topic.is_empty() {
+        bail!("ntfy topic must not be empty")
+    }
+    update_settings(|settings| {
+        settings.ntfy_topic = Some(topic.to_string());
+        settings.pushover_user = None;
+        settings.pushover_token = None;
+    })?;
+    Ok(())
+}
+
+/// Never taken from the command line, where other users could read it.
+pub fn read_pushover_token() -> Result<String> {
+    if let Some(token) = std::env::var("PUSHOVER_TOKEN")
+        .ok()
+        .filter(|token| !token.trim().is_empty())
+    {
+        return Ok(token);
+    }
+    if std::io::stdin().is_terminal() {
+        return Ok(dialoguer::Password::with_theme(&crate::ui::theme())
+            .with_prompt("Pushover application API token")
+            .interact()?);
+    }
+    crate::config::read_secret(Some("-"), None, "Pushover API token")
+}
+
+pub fn set_pushover(user: &str, token: &str) -> Result<()> {
+    if user.trim().is_empty() || token.trim().is_empty() {
+        bail!("Pushover user key and API token must not be empty")
+    }
+    update_settings(|settings| {
+        settings.pushover_user = Some(user.trim().to_string());
+        settings.pushover_token = Some(token.trim().to_string());
+        settings.ntfy_topic = None;
+    })?;
+    Ok(())
+}
+
+pub fn disable_push() -> Result<()> {
+    update_settings(|settings| {
+        settings.ntfy_topic = None;
+        settings.pushover_user = None;
+        settings.pushover_token = None;
+    })?;
+    Ok(())
+}
+
+pub fn event_payload(event: Event, account: &str, detail: Option<&str>) -> Value {
+    json!({
+        "event": event.as_str(),
+        "account": account,
+        "detail": detail,
+        "timestamp": unix_now(),
+    })
+}
+
+pub fn send_push(target: &PushTarget, account: &str, message: &str) -> Result<()> {
+    let title = format!("Signal setup {account}");
+    match target {
+        PushTarget::Ntfy { url } => http_post(
+            url,
+            "text/plain; charset=utf-8",
+            &[format!("Title: {title}")],
+            message,
+            crate::HTTP_TIMEOUT_SECS,
+        ),
+        PushTarget::Pushover { user, token } => http_post_json(
+            crate::PUSHOVER_API_URL,
+            &json!({"token": token, "user": user, "title": title, "message": message}),
+            crate::HTTP_TIMEOUT_SECS,
+        ),
+    }
+}
+
+pub fn notify(cfg: &Config, event: Event, detail: Option<&str>) {
+    if let Some(url) = cfg.webhook_url.as_deref() {
+        let payload = event_payload(event, &cfg.account, detail);
+        if let Err(err) = http_post_json(url, &payload, crate::HTTP_TIMEOUT_SECS) {
+            eprintln!("Warning: webhook for '{}' failed: {err}", event.as_str());
+        }
+    }
+
+    if let Some(target) = cfg.push_target.as_ref().filter(|_| event.needs_user()) {
+        if let Err(err) = send_push(target, &cfg.account, event.message()) {
+            eprintln!("Warning: push notification failed: {err}");
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use anyhow::{bail, Result};
+use std::net::IpAddr;
+
+use crate::image_ref::ImageFlavor;
+
+/// Java ignores proxy variables, so the proxy is passed both as variables and as Java properties.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proxy {
+    pub url: String,
+    pub kind: ProxyKind,
+    pub host: String,
+    pub port: u16,
+    pub no_proxy: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyKind {
+    Http,
+    Socks,
+}
+
+const PROXY_VARS: [&str; 6] = [
+    "HTTPS_PROXY",
+    "https_proxy",
+    "ALL_PROXY",
+    "all_proxy",
+    "HTTP_PROXY",
+    "http_proxy",
+];
+
+impl Proxy {
+    /// The port defaults to 80 for HTTP and 1080 for SOCKS.
+    pub fn parse(value: &str) -> Result<Self> {
+        // Checked first, so no error message repeats a password.
+        if value.contains('@') {
+            bail!("invalid proxy: proxy credentials are not supported; run a local forwarding proxy (such as cntlm) that adds them")
+        }
+        let trimmed = value.trim().trim_end_matches('/');
+        let (scheme, address) = trimmed.split_once("://").unwrap_or(("http", trimmed));
+        let kind = match scheme.to_ascii_lowercase().as_str() {
+            "http" | "https" => ProxyKind::Http,
+            "socks" | "socks5" | "socks5h" => ProxyKind::Socks,
+            _ => bail!("invalid proxy '{value}': use an http:// or socks5:// proxy"),
+        };
+        // IPv6 addresses come in brackets, as in http://[::1]:3128
+        let (host, port) = match address.strip_prefix('[') {
+            Some(rest) => match rest.split_once(']') {
+                Some((host, port)) => (host, port.strip_prefix(':')),
+                None => bail!("invalid proxy '{value}': missing ']'"),
+            },
+            None => match address.rsplit_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (address, None),
+            },
+        };
+        // Java's launch script evaluates JAVA_OPTS with the shell, so only plain host names.
+        let plain = |c: char| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | ':');
+        if host.is_empty() || !host.chars().all(plain) {
+            bail!("invalid proxy '{value}': expected a host name or IP address")
+        }
+        let port = match port {
+            Some(port) => match port.parse::<u16>() {
+                Ok(port) if port > 0 => port,
+                _ => bail!("invalid proxy '{value}': invalid port '{port}'"),
+            },
+            None if kind == ProxyKind::Socks => 1080,
+            None => 80,
+        };
+        let scheme = match kind {
+            ProxyKind::Http => "http",
+            ProxyKind::Socks => "socks5",
+        };
+        let url = if host.contains(':') {
+            format!("{scheme}://[{host}]:{port}")
+        } else {
+            format!("{scheme}://{host}:{port}")
+        };
+        Ok(Proxy {
+            url,
+            kind,
+            host: host.to_string(),
+            port,
+            no_proxy: None,
+        })
+    }
+
+    pub fn container_env(&self) -> Vec<(&'static str, String)> {
+        let names: &[&'static str] = match self.kind {
+            ProxyKind::Http => &["HTTPS_PROXY", "HTTP_PROXY", "https_proxy", "http_proxy"],
+            ProxyKind::Socks => &["ALL_PROXY", "all_proxy"],
+        };
+        let mut env: Vec<(&'static str, String)> =
+            names.iter().map(|name| (*name, self.url.clone())).collect();
+        if let Some(no_proxy) = &self.no_proxy {
+            env.push(("NO_PROXY", no_proxy.clone()));
+            env.push(("no_proxy", no_proxy.clone()));
+        }
+        env
+    }
+
+    pub fn java_opts(&self) -> String {
+        let (host, port) = (&self.host, self.port);
+        match self.kind {
+            ProxyKind::Http => format!(
+                "-Dhttp.proxyHost={host} -Dhttp.proxyPort={port} -Dhttps.proxyHost={host} -Dhttps.proxyPort={port}"
+            ),
+            ProxyKind::Socks => format!("-DsocksProxyHost={host} -DsocksProxyPort={port}"),
+        }
+    }
+
+    pub fn is_loopback(&self) -> bool {
+        self.host.eq_ignore_ascii_case("localhost")
+            || self.host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+    }
+}
+
+pub fn resolve_proxy(
+    explicit: Option<&str>,
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<Option<Proxy>> {
+    let proxy = match explicit {
+        Some(url) => Some(Proxy::parse(url)?),
+        None => {
+            match PROXY_VARS
+                .into_iter()
+                .find_map(|name| env(name).map(|value| (name, value)))
+            {
+                // Set for other programs too, so an unusable value is only skipped.
+                Some((name, value)) => match Proxy::parse(&value) {
+                    Ok(proxy) => Some(proxy),
+                    Err(err) => {
+                        eprintln!("Warning: signal-cli containers connect directly, ignoring {name}: {err}");
+                        None
+                    }
+                },
+                None => None,
+            }
+        }
+    };
+    Ok(proxy.map(|proxy| Proxy {
+        no_proxy: env("NO_PROXY").or_else(|| env("no_proxy")),
+        ..proxy
+    }))
+}
+
+/// The native image has no Java launcher to read JAVA_OPTS, so it would ignore `--proxy`.
+pub fn check_flavor(flavor: Option<ImageFlavor>) -> Result<()> {
+    if flavor == Some(ImageFlavor::Native) {
+        bail!("--proxy needs --image-flavor jre: the native signal-cli image does not read Java proxy settings and would connect directly")
+    }
+    Ok(())
+}
+
+pub fn native_image_warning(proxy: &Proxy) -> String {
+    format!(
+        "Warning: the native signal-cli image ignores the proxy {} from the environment and connects directly. Add --image-flavor jre to use it, or --proxy \"\" to stop this warning. It is shown once per proxy.",
+        proxy.url
+    )
+}
+
+/// A proxy on this machine is not `localhost` inside a container.
+pub fn loopback_warning(proxy: &Proxy, network: Option<&str>) -> Option<String> {
+    (proxy.is_loopback() && network != Some("host")).then(|| {
+        format!(
+            "Warning: the proxy {} runs on this machine, and signal-cli containers cannot reach it as {}. Add --network host (Linux), or give the proxy as host.docker.internal with Docker Desktop.",
+            proxy.url, proxy.host
+        )
+    })
+}
+
+pub fn unreachable_hint(
+    proxy: Option<&Proxy>,
+    network: Option<&str>,
+    flavor: ImageFlavor,
+) -> String {
+    if let (Some(proxy), ImageFlavor::Native) = (proxy, flavor) {
+        return format!(
+            "The native signal-cli image ignores the proxy {}; add --image-flavor jre so signal-cli uses it.",
+            proxy.url
+        );
+    }
+    match (proxy, network) {
+        (Some(proxy), Some(network)) => format!(
+            "Check that the proxy {} accepts connections from containers on the '{network}' network.",
+            proxy.url
+        ),
+        (Some(proxy), None) => format!(
+            "Check that the proxy {} accepts connections from containers; a proxy on this machine needs --network host.",
+            proxy.url
+        ),
+        (None, _) => "Check the internet connection. Behind a corporate proxy, pass it with --proxy http://host:port.".to_string(),
+    }
+}
@@ -0,0 +1,412 @@
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::thread;
+use std::time::Duration;
+
+use crate::audit::{self, AuditedCommand};
+use crate::config::Config;
+use crate::errors::SignalSetupError;
+use crate::image_ref::image_registry;
+use crate::shutdown::sleep_unless_terminated;
+use crate::ui::Progress;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PullFailure {
+    /// The registry refused the pull for lack of (valid) credentials
+    Auth,
+    /// The registry answered, but has no such repository or tag
+    NotFound,
+    /// DNS, connection or timeout errors before the registry answered
+    Unreachable,
+    Other,
+}
+
+impl PullFailure {
+    fn is_retryable(self) -> bool {
+        matches!(self, PullFailure::Unreachable | PullFailure::Other)
+    }
+}
+
+/// Registry problems get a specific error instead of surfacing as a failed signal-cli command.
+pub fn ensure_image_pulled(cfg: &Config) -> Result<()> {
+    if image_present(cfg) {
+        return Ok(());
+    }
+    pull_with_retries(cfg)
+}
+
+pub fn pull_with_retries(cfg: &Config) -> Result<()> {
+    let registry = image_registry(&cfg.image);
+    let mut logged_in = false;
+    let mut attempt = 1;
+    loop {
+        let stderr = match pull_image(cfg)? {
+            None => return Ok(()),
+            Some(stderr) => stderr,
+        };
+        let failure = classify_pull_failure(&stderr);
+        if failure.is_retryable() && attempt < crate::PULL_RETRY_ATTEMPTS {
+            let delay = pull_retry_delay(attempt);
+            eprint!("{stderr}");
+            // Layers that finished downloading are kept, so the next pull resumes from there.
+            eprintln!(
+                "Pull failed (attempt {attempt}/{}), retrying in {}s...",
+                crate::PULL_RETRY_ATTEMPTS,
+                delay.as_secs()
+            );
+            sleep_unless_terminated(delay)?;
+            attempt += 1;
+            continue;
+        }
+
+        match failure {
+            PullFailure::Auth if !logged_in && prompt_registry_login(cfg, registry)? => {
+                logged_in = true;
+            }
+            PullFailure::Auth => {
+                return Err(SignalSetupError::RegistryAuthFailed {
+                    image: cfg.image.clone(),
+                    registry: registry.to_string(),
+                    hint: registry_auth_hint(registry, credential_helper(registry).as_deref()),
+                }
+                .into())
+            }
+            PullFailure::NotFound => {
+                return Err(SignalSetupError::ImageNotFound {
+                    image: cfg.image.clone(),
+                    registry: registry.to_string(),
+                }
+                .into())
+            }
+            PullFailure::Unreachable => {
+                eprint!("{stderr}");
+                return Err(SignalSetupError::RegistryUnreachable {
+                    image: cfg.image.clone(),
+                    registry: registry.to_string(),
+                    attempts: attempt,
+                }
+                .into());
+            }
+            PullFailure::Other => {
+                eprint!("{stderr}");
+                anyhow::bail!("failed to pull {} after {attempt} attempts", cfg.image)
+            }
+        }
+    }
+}
+
+pub fn pull_retry_delay(attempt: u32) -> Duration {
+    let factor = 1_u64 << attempt.saturating_sub(1).min(16);
+    Duration::from_secs(crate::PULL_RETRY_BASE_DELAY_SECS.saturating_mul(factor))
+}
+
+fn image_present(cfg: &Config) -> bool {
+    cfg.runtime
+        .command()
+        .args(["image", "inspect", &cfg.image])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .audited_status()
+        .is_ok_and(|status| status.success())
+}
+
+pub fn image_digest(cfg: &Config) -> Option<String> {
+    let output = cfg
+        .runtime
+        .command()
+        .args([
+            "image",
+            "inspect",
+            "--format",
+            "{{json .RepoDigests}}",
+            &cfg.image,
+        ])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .audited_output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_repo_digest(&String::from_utf8_lossy(&output.stdout))
+}
+
+pub fn parse_repo_digest(json: &str) -> Option<String> {
+    let digests: Vec<String> = serde_json::from_str(json.trim()).ok()?;
+    digests
+        .iter()
+        .find_map(|entry| entry.split_once('@').map(|(_, digest)| digest.to_string()))
+}
+
+fn pull_image(cfg: &Config) -> Result<Option<String>> {
+    println!("Pulling {}...", cfg.image);
+    let mut child = cfg
+        .runtime
+        .command()
+        .args(["pull", &cfg.image])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .audited_spawn()
+        .with_context(|| format!("failed to run {} pull", cfg.runtime.as_str()))?;
+    let stderr_reader = child.stderr.take().map(|mut stderr| {
+        thread::spawn(move || {
+            let mut captured = String::new();
+            let _ = stderr.read_to_string(&mut captured);
+            captured
+        })
+    });
+
+    let progress = Progress::new(
+        0,
+        "{spinner:.green} [{elapsed_precise}] [{bar:30.cyan/blue}] {pos}/{len} layers {msg}",
+    );
+    let mut layers = PullLayers::default();
+    if let Some(stdout) = child.stdout.take() {
+        for line in BufReader::new(stdout).lines().map_while(|line| line.ok()) {
+            if layers.update(&line) {
+                let (done, total) = layers.counts();
+                progress.set_length(total);
+                progress.set_position(done);
+                progress.set_message(layers.summary());
+            }
+        }
+    }
+
+    let status = child
+        .wait()
+        .with_context(|| format!("failed to wait for {} pull", cfg.runtime.as_str()))?;
+    audit::record_exit(child.id(), &status);
+    let stderr = stderr_reader
+        .and_then(|reader| reader.join().ok())
+        .unwrap_or_default();
+    if status.success() {
+        progress.finish_with_message(format!("Pulled {}.", cfg.image));
+        return Ok(None);
+    }
+    progress.abandon_with_message("Pull failed.");
+    Ok(Some(stderr))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerState {
+    Waiting,
+    Downloading,
+    Downloaded,
+    Extracting,
+    Done,
+}
+
+pub fn parse_pull_line(line: &str) -> Option<(&str, LayerState)> {
+    let (layer, status) = line.trim().split_once(": ")?;
+    if layer.is_empty() || !layer.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let state = match status {
+        "Pulling fs layer" | "Waiting" => LayerState::Waiting,
+        "Verifying Checksum" | "Download complete" => LayerState::Downloaded,
+        "Pull complete" | "Already exists" => LayerState::Done,
+        status if status.starts_with("Downloading") => LayerState::Downloading,
+        status if status.starts_with("Extracting") => LayerState::Extracting,
+        _ => return None,
+    };
+    Some((layer, state))
+}
+
+#[derive(Debug, Default)]
+pub struct PullLayers {
+    layers: Vec<(String, LayerState)>,
+}
+
+impl PullLayers {
+    pub fn update(&mut self, line: &str) -> bool {
+        let Some((layer, state)) = parse_pull_line(line) else {
+            return false;
+        };
+        match self.layers.iter_mut().find(|(known, _)| known == layer) {
+            Some((_, known)) if *known == state => false,
+            Some((_, known)) => {
+                *known = state;
+                true
+            }
+            None => {
+                self.layers.push((layer.to_string(), state));
+                true
+            }
+        }
+    }
+
+    pub fn counts(&self) -> (u64, u64) {
+        let done = self
+            .layers
+            .iter()
+            .filter(|(_, state)| *state == LayerState::Done)
+            .count();
+        (done as u64, self.layers.len() as u64)
+    }
+
+    pub fn summary(&self) -> String {
+        let count = |wanted: LayerState| {
+            self.layers
+                .iter()
+                .filter(|(_, state)| *state == wanted)
+                .count()
+        };
+        let parts: Vec<String> = [
+            (LayerState::Downloading, "downloading"),
+            (LayerState::Extracting, "extracting"),
+            (LayerState::Waiting, "waiting"),
+        ]
+        .iter()
+        .filter(|(state, _)| count(*state) > 0)
+        .map(|(state, label)| format!("{} {label}", count(*state)))
+        .collect();
+        parts.join(", ")
+    }
+}
+
+pub fn classify_pull_failure(stderr: &str) -> PullFailure {
+    let lower = stderr.to_ascii_lowercase();
+    let auth_markers = [
+        "unauthorized",
+        "authentication required",
+        "no basic auth credentials",
+        "pull access denied",
+        "denied: access forbidden",
+        "requested access to the resource is denied",
+        "401",
+    ];
+    let not_found_markers = [
+        "manifest unknown",
+        "name unknown",
+        "not found: manifest",
+        ": not found",
+        "no such image",
+    ];
+    let unreachable_markers = [
+        "no such host",
+        "temporary failure in name resolution",
+        "dial tcp",
+        "connection refused",
+        "connection reset",
+        "network is unreachable",
+        "i/o timeout",
+        "tls handshake timeout",
+        "context deadline exceeded",
+        "client.timeout exceeded",
+        "server misbehaving",
+        "unexpected eof",
+    ];
+    if auth_markers.iter().any(|marker| lower.contains(marker)) {
+        PullFailure::Auth
+    } else if not_found_markers
+        .iter()
+        .any(|marker| lower.contains(marker))
+    {
+        PullFailure::NotFound
+    } else if unreachable_markers
+        .iter()
+        .any(|marker| lower.contains(marker))
+    {
+        PullFailure::Unreachable
+    } else {
+        PullFailure::Other
+    }
+}
+
+pub fn credential_helper(registry: &str) -> Option<String> {
+    let content = std::fs::read_to_string(docker_config_path()?).ok()?;
+    let json = serde_json::from_str::<Value>(&content).ok()?;
+    credential_helper_from(&json, registry)
+}
+
+pub fn credential_helper_from(json: &Value, registry: &str) -> Option<String> {
+    json.get("credHelpers")
+        .and_then(|helpers| helpers.get(registry))
+        .or_else(|| json.get("credsStore"))
+        .and_then(Value::as_str)
+        .filter(|helper| !helper.is_empty())
+        .map(str::to_string)
+}
+
+pub(crate) fn docker_config_path() -> Option<PathBuf> {
+    let dir = match std::env::var_os("DOCKER_CONFIG") {
+        Some(dir) => PathBuf::from(dir),
+        None => dirs::home_dir()?.join(".docker"),
+    };
+    Some(dir.join("config.json"))
+}
+
+pub fn registry_auth_hint(registry: &str, helper: Option<&str>) -> String {
+    match helper {
+        Some(helper) => format!(
+            "The credential helper docker-credential-{helper} is configured for {registry} but its credentials were rejected; refresh them and retry."
+        ),
+        None => format!(
+            "Run 'docker login {registry}' (or configure a credential helper in ~/.docker/config.json) and retry."
+        ),
+    }
+}
+
+#[cfg(not(test))]
+fn prompt_registry_login(cfg: &Config, registry: &str) -> Result<bool> {
+    use dialoguer::Password;
+    use std::io::IsTerminal;
+
+    if !(std::io::stdin().is_terminal() && std::io::stderr().is_terminal()) {
+        return Ok(false);
+    }
+
+    let theme = crate::ui::theme();
+    eprintln!("{registry} needs credentials to pull {}.", cfg.image);
+    let username: String = crate::ui::input(&theme, &format!("Username for {registry}"))?;
+    let password = Password::with_theme(&theme)
+        .with_prompt(format!("Password or access token for {registry}"))
+        .interact()?;
+    registry_login(cfg, registry, &username, &password)
+}
+
+#[cfg(test)]
+fn prompt_registry_login(cfg: &Config, registry: &str) -> Result<bool> {
+    match (
+        std::env::var("MOCK_REGISTRY_USER"),
+        std::env::var("MOCK_REGISTRY_PASSWORD"),
+    ) {
+        (Ok(username), Ok(password)) => registry_login(cfg, registry, &username, &password),
+        _ => Ok(false),
+    }
+}
+
+fn registry_login(cfg: &Config, registry: &str, username: &str, password: &str) -> Result<bool> {
+    let mut child = cfg
+        .runtime
+        .command()
+        .args([
+            "login",
+            registry,
+            "--username",
+            username,
+            "--password-stdin",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::inherit())
+        .audited_spawn()
+        .with_context(|| format!("failed to run {} login", cfg.runtime.as_str()))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(format!("{password}\n").as_bytes())
+            .context("failed to send the registry password")?;
+    }
+    let status = child
+        .wait()
+        .with_context(|| format!("failed to wait for {} login", cfg.runtime.as_str()))?;
+    audit::record_exit(child.id(), &status);
+    if !status.success() {
+        eprintln!("Login to {registry} failed.");
+    }
+    Ok(status.success())
+}
@@ -1,43 +1,227 @@
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
+use clap::ValueEnum;
 use image::imageops::FilterType;
-use image::{GrayImage, Luma};
-use indicatif::{ProgressBar, ProgressStyle};
+use image::{DynamicImage, GrayImage, Luma};
 use rqrr::PreparedImage;
 use rxing::{helpers as rxing_helpers, BarcodeFormat};
+#[cfg(not(test))]
+use std::io::{self, IsTerminal};
+use std::io::{BufRead, Cursor, Seek};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
 use std::thread;
 use std::time::{Duration, Instant};
-use tempfile::tempdir;
 use xcap::Monitor;
 
+use crate::audit::{self, AuditedCommand};
+use crate::link_uri::parse_link_uri;
+use crate::qr_debug;
+use crate::shutdown::{check_terminated, is_terminated, terminate_requested};
 use crate::system::command_exists;
+use crate::tempfiles::ScanDir;
+#[cfg(not(test))]
+use crate::ui;
+use crate::ui::Progress;
+use crate::window;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeLimits {
+    pub threads: usize,
+    /// Niceness of the decoding threads; None keeps the platform default (background QoS on macOS)
+    pub nice: Option<i32>,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits {
+            threads: crate::DEFAULT_DECODE_THREADS,
+            nice: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum CaptureBackend {
+    /// `screencapture` on macOS with xcap as the multi-display fallback; xcap first elsewhere
+    #[default]
+    Auto,
+    /// Only the macOS `screencapture` binary
+    Screencapture,
+    /// Only xcap (ScreenCaptureKit/CoreGraphics), for Macs where `screencapture` is blocked by MDM
+    Xcap,
+}
+
+impl CaptureBackend {
+    fn restricted_capture_uses_xcap(self) -> bool {
+        match self {
+            CaptureBackend::Xcap => true,
+            CaptureBackend::Screencapture => false,
+            CaptureBackend::Auto => !cfg!(target_os = "macos"),
+        }
+    }
+}
+
+/// A rectangle in global screen coordinates (points, origin at the main display's top left).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    pub fn parse(value: &str) -> Result<Self> {
+        let parts: Vec<&str> = value.split(',').map(str::trim).collect();
+        let [x, y, width, height] = parts.as_slice() else {
+            bail!("region must be x,y,w,h, got '{value}'")
+        };
+        let region = Region {
+            x: x.parse()
+                .with_context(|| format!("invalid region x '{x}'"))?,
+            y: y.parse()
+                .with_context(|| format!("invalid region y '{y}'"))?,
+            width: width
+                .parse()
+                .with_context(|| format!("invalid region width '{width}'"))?,
+            height: height
+                .parse()
+                .with_context(|| format!("invalid region height '{height}'"))?,
+        };
+        if region.width == 0 || region.height == 0 {
+            bail!("region '{value}' is empty")
+        }
+        Ok(region)
+    }
+
+    pub fn spec(&self) -> String {
+        format!("{},{},{},{}", self.x, self.y, self.width, self.height)
+    }
+
+    /// `scale` is captured pixels per point (2 on Retina displays).
+    pub fn crop_within(&self, display: &Region, scale: f64) -> Option<(u32, u32, u32, u32)> {
+        let left = i64::from(self.x).max(i64::from(display.x));
+        let top = i64::from(self.y).max(i64::from(display.y));
+        let right = (i64::from(self.x) + i64::from(self.width))
+            .min(i64::from(display.x) + i64::from(display.width));
+        let bottom = (i64::from(self.y) + i64::from(self.height))
+            .min(i64::from(display.y) + i64::from(display.height));
+        if right <= left || bottom <= top {
+            return None;
+        }
+        let to_pixels = |points: i64| (points as f64 * scale).round() as u32;
+        Some((
+            to_pixels(left - i64::from(display.x)),
+            to_pixels(top - i64::from(display.y)),
+            to_pixels(right - left).max(1),
+            to_pixels(bottom - top).max(1),
+        ))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CaptureOptions {
+    pub backend: CaptureBackend,
+    pub region: Option<Region>,
+    pub display: Option<u32>,
+    pub debug_artifacts: Option<PathBuf>,
+}
 
-pub fn scan_screen_for_signal_uri(interval: u64, attempts: u32) -> Result<String> {
-    let temp_dir = tempdir().context("failed to create temporary directory")?;
+pub fn scan_screen_for_signal_uri(
+    interval: u64,
+    attempts: u32,
+    limits: &DecodeLimits,
+    capture: &CaptureOptions,
+) -> Result<String> {
+    let temp_dir = ScanDir::create()?;
     let display_count = detect_display_count();
-    let pb = ProgressBar::new(attempts as u64);
-    let style = ProgressStyle::with_template(
+    let pb = Progress::new(
+        attempts as u64,
         "{spinner:.green} [{elapsed_precise}] [{bar:30.cyan/blue}] {pos}/{len} {msg}",
-    )
-    .unwrap_or_else(|_| ProgressStyle::default_bar())
-    .progress_chars("=> ");
-    pb.set_style(style);
-    pb.enable_steady_tick(Duration::from_millis(120));
+    );
     pb.set_message(format!(
         "Preparing first screen capture ({display_count} display(s))..."
     ));
 
-    for attempt in 1..=attempts {
-        pb.set_message(format!("Attempt {attempt}/{attempts}: capturing screen..."));
-        let screenshot_paths =
-            capture_screens_for_attempt(temp_dir.path(), attempt, display_count)?;
+    // Capture the next frame while the current one is decoded; the channel holds at most one
+    // frame so captures never run far ahead of decoding.
+    let done = AtomicBool::new(false);
+    let outcome: Result<Option<(u32, String)>> = thread::scope(|scope| {
+        let (frames_tx, frames_rx) = mpsc::sync_channel::<(u32, Result<Vec<PathBuf>>)>(1);
+        let done = &done;
+        let base_dir = temp_dir.path();
+        scope.spawn(move || {
+            for attempt in 1..=attempts {
+                if done.load(Ordering::SeqCst) || terminate_requested() {
+                    return;
+                }
+                let frames = capture_screens_for_attempt(base_dir, attempt, display_count, capture);
+                let failed = frames.is_err();
+                if frames_tx.send((attempt, frames)).is_err() || failed {
+                    return;
+                }
+                if attempt < attempts && !wait_for_next_capture(interval, done) {
+                    return;
+                }
+            }
+        });
+
+        let debug_dir = capture.debug_artifacts.as_deref();
+        let decoded = decode_captured_frames(frames_rx, attempts, limits, debug_dir, &pb);
+        done.store(true, Ordering::SeqCst);
+        decoded
+    });
+
+    match outcome {
+        Ok(Some((attempt, uris))) if uris.len() > 1 => {
+            pb.finish_with_message(format!(
+                "{} Signal QR codes detected on attempt {attempt}.",
+                uris.len()
+            ));
+            choose_signal_uri(uris)
+        }
+        Ok(Some((attempt, mut uris))) if !uris.is_empty() => {
+            pb.finish_with_message(format!("QR detected on attempt {attempt}."));
+            Ok(uris.remove(0))
+        }
+        Ok(_) => {
+            pb.abandon_with_message("No valid QR found before timeout.");
+            bail!("no valid Signal Desktop QR found after {attempts} attempts")
+        }
+        Err(err) => {
+            pb.abandon_with_message(if is_terminated(&err) {
+                "Scan stopped."
+            } else {
+                "Scan failed."
+            });
+            Err(err)
+        }
+    }
+}
 
+fn decode_captured_frames(
+    frames: mpsc::Receiver<(u32, Result<Vec<PathBuf>>)>,
+    attempts: u32,
+    limits: &DecodeLimits,
+    debug_dir: Option<&Path>,
+    pb: &Progress,
+) -> Result<Option<(u32, Vec<String>)>> {
+    for (attempt, captured) in frames {
+        check_terminated()?;
         pb.set_message(format!("Attempt {attempt}/{attempts}: decoding QR..."));
-        for screenshot_path in screenshot_paths {
-            if let Some(uri) = decode_signal_qr_from_image(&screenshot_path)? {
-                pb.finish_with_message(format!("QR detected on attempt {attempt}."));
-                return Ok(uri);
+        let captured = captured?;
+        let costs: Vec<u64> = captured
+            .iter()
+            .map(|path| fit_frame_to_ceiling(path))
+            .collect();
+        let ceiling = crate::QR_DECODE_MEMORY_CEILING_BYTES;
+        for batch in plan_decode_batches(&costs, limits.threads, ceiling) {
+            let paths: Vec<PathBuf> = batch.iter().map(|&idx| captured[idx].clone()).collect();
+            let uris = decode_frames_in_parallel(&paths, limits.nice, debug_dir)?;
+            if !uris.is_empty() {
+                return Ok(Some((attempt, uris)));
             }
         }
 
@@ -45,61 +229,353 @@ pub fn scan_screen_for_signal_uri(interval: u64, attempts: u32) -> Result<String
         pb.set_message(format!(
             "Attempt {attempt}/{attempts}: no valid Signal QR yet."
         ));
-        if attempt < attempts {
-            thread::sleep(Duration::from_secs(interval));
+    }
+    check_terminated()?;
+    Ok(None)
+}
+
+pub(crate) fn decode_frames_in_parallel(
+    paths: &[PathBuf],
+    nice: Option<i32>,
+    debug_dir: Option<&Path>,
+) -> Result<Vec<String>> {
+    let results: Vec<Result<Vec<String>>> = thread::scope(|scope| {
+        let workers: Vec<_> = paths
+            .iter()
+            .map(|path| {
+                scope.spawn(move || {
+                    lower_decode_thread_priority(nice);
+                    let decoded = decode_capture(path, debug_dir);
+                    let _ = std::fs::remove_file(path);
+                    decoded
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow!("QR decoding thread panicked")))
+            })
+            .collect()
+    });
+
+    let mut uris = Vec::new();
+    for result in results {
+        for uri in result? {
+            if !uris.contains(&uri) {
+                uris.push(uri);
+            }
         }
     }
+    Ok(uris)
+}
 
-    pb.abandon_with_message("No valid QR found before timeout.");
-    bail!("no valid Signal Desktop QR found after {attempts} attempts")
+/// Rough peak memory of decoding one frame: the RGBA decode, its luma copy and one working copy.
+pub(crate) fn estimated_decode_bytes(width: u32, height: u32) -> u64 {
+    (width as u64) * (height as u64) * 6
+}
+
+/// A frame too large on its own is decoded alone.
+pub(crate) fn plan_decode_batches(costs: &[u64], threads: usize, ceiling: u64) -> Vec<Vec<usize>> {
+    let mut batches: Vec<Vec<usize>> = Vec::new();
+    let mut current: Vec<usize> = Vec::new();
+    let mut current_cost = 0_u64;
+    for (idx, &cost) in costs.iter().enumerate() {
+        let full = current.len() >= threads.max(1) || current_cost.saturating_add(cost) > ceiling;
+        if !current.is_empty() && full {
+            batches.push(std::mem::take(&mut current));
+            current_cost = 0;
+        }
+        current.push(idx);
+        current_cost = current_cost.saturating_add(cost);
+    }
+    if !current.is_empty() {
+        batches.push(current);
+    }
+    batches
+}
+
+pub(crate) fn max_dimension_within(width: u32, height: u32, ceiling: u64) -> Option<u32> {
+    let cost = estimated_decode_bytes(width, height);
+    if cost <= ceiling || cost == 0 {
+        return None;
+    }
+    let scale = (ceiling as f64 / cost as f64).sqrt();
+    Some(((width.max(height) as f64) * scale).floor().max(1.0) as u32)
+}
+
+fn fit_frame_to_ceiling(path: &Path) -> u64 {
+    let Ok((width, height)) = image::image_dimensions(path) else {
+        return 0;
+    };
+    let ceiling = crate::QR_DECODE_MEMORY_CEILING_BYTES;
+    let Some(max_dimension) = max_dimension_within(width, height, ceiling) else {
+        return estimated_decode_bytes(width, height);
+    };
+
+    // sips (macOS) resamples without this process holding the full frame in memory.
+    if command_exists("sips") {
+        let resized = Command::new("sips")
+            .arg("-Z")
+            .arg(max_dimension.to_string())
+            .arg(path)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .audited_status()
+            .is_ok_and(|status| status.success());
+        if resized {
+            if let Ok((width, height)) = image::image_dimensions(path) {
+                return estimated_decode_bytes(width, height);
+            }
+        }
+    }
+    estimated_decode_bytes(width, height)
+}
+
+/// Runs on each decoding thread so long scans leave the rest of the machine responsive.
+fn lower_decode_thread_priority(nice: Option<i32>) {
+    match nice {
+        // On Linux this only renices the calling thread; on macOS it applies to the process.
+        Some(nice) => unsafe {
+            libc::setpriority(libc::PRIO_PROCESS, 0, nice);
+        },
+        None => {
+            #[cfg(target_os = "macos")]
+            unsafe {
+                libc::pthread_set_qos_class_self_np(libc::qos_class_t::QOS_CLASS_BACKGROUND, 0);
+            }
+        }
+    }
+}
+
+pub(crate) fn wait_for_next_capture(interval: u64, done: &AtomicBool) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(interval);
+    loop {
+        if done.load(Ordering::SeqCst) || terminate_requested() {
+            return false;
+        }
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return true;
+        }
+        thread::sleep(left.min(Duration::from_millis(crate::TERMINATE_POLL_MS)));
+    }
 }
 
-#[cfg(not(test))]
 pub fn decode_signal_qr_from_image(path: &Path) -> Result<Option<String>> {
-    let base = image::open(path)
-        .with_context(|| format!("failed to open image {}", path.display()))?
-        .to_luma8();
+    Ok(decode_signal_qr_from_luma(&load_capture_luma(path)?))
+}
+
+/// Failing to save debug artifacts only warns.
+fn decode_capture(path: &Path, debug_dir: Option<&Path>) -> Result<Vec<String>> {
+    let base = load_capture_luma(path)?;
+    if let Some(dir) = debug_dir {
+        match qr_debug::save_debug_artifacts(path, &base, dir) {
+            Ok(annotated) => eprintln!("Saved debug artifacts to {}", annotated.display()),
+            Err(err) => eprintln!("Warning: {err:#}"),
+        }
+    }
+    Ok(decode_signal_qrs_from_luma(&base))
+}
+
+/// Only a capture that holds a Signal QR code is searched for more of them.
+fn decode_signal_qrs_from_luma(base: &GrayImage) -> Vec<String> {
+    let Some(first) = decode_signal_qr_from_luma(base) else {
+        return Vec::new();
+    };
+    let mut uris = vec![first];
+    for uri in signal_uris_with_rqrr(base) {
+        if !uris.contains(&uri) {
+            uris.push(uri);
+        }
+    }
+    uris
+}
+
+pub fn signal_uris_with_rqrr(image: &GrayImage) -> Vec<String> {
+    let mut prepared = PreparedImage::prepare(image.clone());
+    prepared
+        .detect_grids()
+        .into_iter()
+        .filter_map(|grid| grid.decode().ok())
+        .map(|(_meta, content)| content)
+        .filter(|content| content.starts_with("sgnl://linkdevice"))
+        .collect()
+}
+
+pub fn signal_uri_label(uri: &str) -> String {
+    match parse_link_uri(uri) {
+        Ok(link) => format!(
+            "provisioning id {}, public key {}",
+            link.uuid,
+            link.key_fingerprint()
+        ),
+        Err(_) => uri.to_string(),
+    }
+}
+
+pub fn ambiguous_signal_uris_message(uris: &[String]) -> String {
+    let labels: Vec<String> = uris.iter().map(|uri| signal_uri_label(uri)).collect();
+    format!(
+        "found {} Signal Desktop QR codes ({}); close all but one Signal Desktop window and scan again",
+        uris.len(),
+        labels.join("; ")
+    )
+}
+
+/// Without a terminal, linking one at random would be wrong, so it fails instead.
+#[cfg(not(test))]
+fn choose_signal_uri(mut uris: Vec<String>) -> Result<String> {
+    if !io::stdin().is_terminal() {
+        bail!(ambiguous_signal_uris_message(&uris));
+    }
+    let labels: Vec<String> = uris.iter().map(|uri| signal_uri_label(uri)).collect();
+    println!("Several Signal Desktop windows are showing a linking QR code.");
+    let choice = ui::select(&ui::theme(), "Which device should be linked?", &labels, 0)?;
+    Ok(uris.swap_remove(choice))
+}
 
-    let fast = resize_luma_to_max_dimension(&base, crate::QR_FAST_MAX_DIMENSION);
+#[cfg(test)]
+fn choose_signal_uri(uris: Vec<String>) -> Result<String> {
+    bail!(ambiguous_signal_uris_message(&uris))
+}
+
+fn load_capture_luma(path: &Path) -> Result<GrayImage> {
+    let reader = image::ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .with_context(|| format!("failed to open image {}", path.display()))?;
+    decode_luma_within_ceiling(reader)
+        .with_context(|| format!("failed to decode image {}", path.display()))
+}
+
+pub fn decode_signal_qr_from_bytes(bytes: &[u8]) -> Result<Option<String>> {
+    let reader = image::ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .context("failed to read image bytes")?;
+    let base = decode_luma_within_ceiling(reader).context("failed to decode image bytes")?;
+    Ok(decode_signal_qr_from_luma(&base))
+}
+
+fn decode_luma_within_ceiling<R: BufRead + Seek>(
+    mut reader: image::ImageReader<R>,
+) -> Result<GrayImage> {
+    let mut limits = image::Limits::default();
+    limits.max_alloc = Some(crate::QR_DECODE_MEMORY_CEILING_BYTES);
+    reader.limits(limits);
+    Ok(normalize_capture(reader.decode()?))
+}
+
+/// HDR and wide-gamut captures are tone-mapped and stretched back to full contrast.
+pub fn normalize_capture(image: DynamicImage) -> GrayImage {
+    let luma = match &image {
+        DynamicImage::ImageRgb32F(buffer) => tone_map_linear(
+            buffer.width(),
+            buffer.height(),
+            buffer.pixels().map(|pixel| pixel.0),
+        ),
+        DynamicImage::ImageRgba32F(buffer) => tone_map_linear(
+            buffer.width(),
+            buffer.height(),
+            buffer.pixels().map(|pixel| [pixel[0], pixel[1], pixel[2]]),
+        ),
+        DynamicImage::ImageLuma16(_)
+        | DynamicImage::ImageLumaA16(_)
+        | DynamicImage::ImageRgb16(_)
+        | DynamicImage::ImageRgba16(_) => image.into_luma8(),
+        _ => return image.into_luma8(),
+    };
+    stretch_luma_contrast(luma)
+}
+
+fn tone_map_linear(width: u32, height: u32, pixels: impl Iterator<Item = [f32; 3]>) -> GrayImage {
+    let values: Vec<u8> = pixels
+        .map(|[r, g, b]| {
+            let luminance = (0.2126 * r + 0.7152 * g + 0.0722 * b).max(0.0);
+            let mapped = luminance / (1.0 + luminance);
+            let encoded = if mapped <= 0.003_130_8 {
+                12.92 * mapped
+            } else {
+                1.055 * mapped.powf(1.0 / 2.4) - 0.055
+            };
+            (encoded * 255.0).round().clamp(0.0, 255.0) as u8
+        })
+        .collect();
+    GrayImage::from_raw(width, height, values).unwrap_or_else(|| GrayImage::new(width, height))
+}
+
+pub fn stretch_luma_contrast(mut image: GrayImage) -> GrayImage {
+    let mut histogram = [0_u64; 256];
+    for pixel in image.pixels() {
+        histogram[usize::from(pixel[0])] += 1;
+    }
+    let total: u64 = histogram.iter().sum();
+    let cutoff = total / 100;
+    let (Some(low), Some(high)) = (
+        level_past_cutoff(&histogram, cutoff, 0..256),
+        level_past_cutoff(&histogram, cutoff, (0..256).rev()),
+    ) else {
+        return image;
+    };
+    if high <= low || high - low >= 192 {
+        return image;
+    }
+    let span = (high - low) as f32;
+    for pixel in image.pixels_mut() {
+        let level = usize::from(pixel[0]).clamp(low, high) - low;
+        pixel[0] = (level as f32 * 255.0 / span).round() as u8;
+    }
+    image
+}
+
+fn level_past_cutoff(
+    histogram: &[u64; 256],
+    cutoff: u64,
+    mut levels: impl Iterator<Item = usize>,
+) -> Option<usize> {
+    let mut seen = 0_u64;
+    levels.find(|&level| {
+        seen += histogram[level];
+        seen > cutoff
+    })
+}
+
+#[cfg(not(test))]
+fn decode_signal_qr_from_luma(base: &GrayImage) -> Option<String> {
+    let fast = resize_luma_to_max_dimension(base, crate::QR_FAST_MAX_DIMENSION);
     if let Some(uri) = decode_signal_qr_with_rxing_luma(&fast) {
-        return Ok(Some(uri));
+        return Some(uri);
     }
     if let Some(uri) = decode_signal_qr_with_rqrr_fastpass(&fast) {
-        return Ok(Some(uri));
+        return Some(uri);
     }
 
     let pixel_count = (base.width() as u64).saturating_mul(base.height() as u64);
 
     if pixel_count <= crate::QR_RXING_MAX_PIXELS {
-        if let Some(uri) = decode_signal_qr_with_rxing_luma(&base) {
-            return Ok(Some(uri));
+        if let Some(uri) = decode_signal_qr_with_rxing_luma(base) {
+            return Some(uri);
         }
-        if let Some(uri) = decode_signal_qr_with_rqrr_multipass(&base) {
-            return Ok(Some(uri));
+        if let Some(uri) = decode_signal_qr_with_rqrr_multipass(base) {
+            return Some(uri);
         }
     } else {
         let upscaled_fast = scale_luma_image(&fast, 1.15);
         if let Some(uri) = decode_signal_qr_with_rxing_luma(&upscaled_fast) {
-            return Ok(Some(uri));
+            return Some(uri);
         }
         if let Some(uri) = decode_signal_qr_with_rqrr_fastpass(&upscaled_fast) {
-            return Ok(Some(uri));
+            return Some(uri);
         }
     }
 
-    Ok(None)
+    None
 }
 
 #[cfg(test)]
-pub fn decode_signal_qr_from_image(path: &Path) -> Result<Option<String>> {
-    if let Some(uri) = decode_signal_qr_with_rxing(path)? {
-        return Ok(Some(uri));
-    }
-
-    let base = image::open(path)
-        .with_context(|| format!("failed to open image {}", path.display()))?
-        .to_luma8();
-    Ok(decode_signal_qr_with_rqrr(&base))
+fn decode_signal_qr_from_luma(base: &GrayImage) -> Option<String> {
+    decode_signal_qr_with_rxing_luma(base).or_else(|| decode_signal_qr_with_rqrr(base))
 }
 
 #[cfg(not(test))]
@@ -231,16 +707,26 @@ pub fn capture_screen_image(path: &Path) -> Result<()> {
 }
 
 pub fn capture_screen_images(paths: &[PathBuf]) -> Result<()> {
+    run_screencapture(&[], paths)
+}
+
+/// Unlike a screen capture this includes the window's contents even when other windows cover it.
+pub fn capture_window_image(window_id: u32, path: &Path) -> Result<()> {
+    run_screencapture(&[format!("-l{window_id}")], &[path.to_path_buf()])
+}
+
+fn run_screencapture(options: &[String], paths: &[PathBuf]) -> Result<()> {
     if paths.is_empty() {
         bail!("no screenshot output path provided");
     }
 
     let mut child = Command::new("screencapture")
         .arg("-x")
+        .args(options)
         .args(paths)
         .stdout(Stdio::null())
         .stderr(Stdio::null())
-        .spawn()
+        .audited_spawn()
         .context("failed to run screencapture")?;
 
     let timeout = Duration::from_secs(crate::SCREEN_CAPTURE_TIMEOUT_SECS);
@@ -252,12 +738,19 @@ pub fn capture_screen_images(paths: &[PathBuf]) -> Result<()> {
             .try_wait()
             .context("failed while waiting for screencapture")?
         {
+            audit::record_exit(child.id(), &status);
             if status.success() {
                 return Ok(());
             }
             bail!("screencapture failed (check Screen Recording permissions)");
         }
 
+        if terminate_requested() {
+            let _ = child.kill();
+            let _ = child.wait();
+            check_terminated()?;
+        }
+
         if start.elapsed() >= timeout {
             let _ = child.kill();
             let _ = child.wait();
@@ -277,7 +770,7 @@ pub fn detect_display_count() -> usize {
             .arg("SPDisplaysDataType")
             .stdout(Stdio::piped())
             .stderr(Stdio::null())
-            .output();
+            .audited_output();
 
         if let Ok(output) = output {
             if output.status.success() {
@@ -309,9 +802,30 @@ pub fn capture_screens_for_attempt(
     base_dir: &Path,
     attempt: u32,
     display_count: usize,
+    capture: &CaptureOptions,
 ) -> Result<Vec<PathBuf>> {
     let mut multi_paths = Vec::new();
 
+    if let Some(region) = &capture.region {
+        return capture_region(base_dir, attempt, region, capture.backend);
+    }
+    if let Some(display) = capture.display {
+        return capture_display(base_dir, attempt, display, capture.backend);
+    }
+
+    if capture.backend == CaptureBackend::Xcap {
+        let paths = capture_screens_with_xcap(base_dir, attempt)?;
+        if paths.is_empty() {
+            bail!("xcap found no displays to capture");
+        }
+        return Ok(paths);
+    }
+    let try_xcap = capture.backend == CaptureBackend::Auto;
+
+    if let Some(path) = capture_signal_window(base_dir, attempt) {
+        return Ok(vec![path]);
+    }
+
     if display_count > 1 {
         for display_idx in 1..=display_count {
             multi_paths.push(base_dir.join(format!("screen-{attempt}-display-{display_idx}.png")));
@@ -321,17 +835,21 @@ pub fn capture_screens_for_attempt(
             return Ok(multi_paths);
         }
 
-        if let Ok(paths) = capture_screens_with_xcap(base_dir, attempt) {
-            if !paths.is_empty() {
-                return Ok(paths);
+        if try_xcap {
+            if let Ok(paths) = capture_screens_with_xcap(base_dir, attempt) {
+                if !paths.is_empty() {
+                    return Ok(paths);
+                }
             }
         }
     } else {
         #[cfg(not(target_os = "macos"))]
         {
-            if let Ok(paths) = capture_screens_with_xcap(base_dir, attempt) {
-                if !paths.is_empty() {
-                    return Ok(paths);
+            if try_xcap {
+                if let Ok(paths) = capture_screens_with_xcap(base_dir, attempt) {
+                    if !paths.is_empty() {
+                        return Ok(paths);
+                    }
                 }
             }
         }
@@ -342,23 +860,117 @@ pub fn capture_screens_for_attempt(
     Ok(vec![single_path])
 }
 
-fn capture_screens_with_xcap(base_dir: &Path, attempt: u32) -> Result<Vec<PathBuf>> {
+/// `display` is numbered from 1, as in the `windows` command.
+fn capture_display(
+    base_dir: &Path,
+    attempt: u32,
+    display: u32,
+    backend: CaptureBackend,
+) -> Result<Vec<PathBuf>> {
+    let path = base_dir.join(format!("screen-{attempt}-display-{display}.png"));
+    if !backend.restricted_capture_uses_xcap() {
+        run_screencapture(&["-D".to_string(), display.to_string()], &[path.clone()])?;
+        return Ok(vec![path]);
+    }
+
     let monitors = Monitor::all().context("failed to enumerate displays with xcap")?;
-    if monitors.is_empty() {
-        return Ok(Vec::new());
+    let monitor = usize::try_from(display)
+        .ok()
+        .and_then(|display| display.checked_sub(1))
+        .and_then(|idx| monitors.get(idx))
+        .with_context(|| {
+            format!(
+                "display {display} not found ({} attached); `windows` lists them",
+                monitors.len()
+            )
+        })?;
+    save_monitor_capture(monitor, &path)?;
+    Ok(vec![path])
+}
+
+fn capture_region(
+    base_dir: &Path,
+    attempt: u32,
+    region: &Region,
+    backend: CaptureBackend,
+) -> Result<Vec<PathBuf>> {
+    if !backend.restricted_capture_uses_xcap() {
+        let path = base_dir.join(format!("screen-{attempt}-region.png"));
+        run_screencapture(&[format!("-R{}", region.spec())], &[path.clone()])?;
+        return Ok(vec![path]);
     }
 
+    let monitors = Monitor::all().context("failed to enumerate displays with xcap")?;
     let mut paths = Vec::new();
     for (idx, monitor) in monitors.into_iter().enumerate() {
+        let display = Region {
+            x: monitor.x()?,
+            y: monitor.y()?,
+            width: monitor.width()?,
+            height: monitor.height()?,
+        };
+        if region.crop_within(&display, 1.0).is_none() {
+            continue;
+        }
         let image = monitor
             .capture_image()
             .context("failed to capture display with xcap")?;
-        let path = base_dir.join(format!("screen-{attempt}-display-{}.png", idx + 1));
-        image
+        let scale = f64::from(image.width()) / f64::from(display.width.max(1));
+        let Some((x, y, width, height)) = region.crop_within(&display, scale) else {
+            continue;
+        };
+        let cropped = image::imageops::crop_imm(&image, x, y, width, height).to_image();
+        let cropped = DynamicImage::ImageRgba8(cropped).into_luma8();
+        let path = base_dir.join(format!("screen-{attempt}-display-{}-region.png", idx + 1));
+        cropped
             .save(&path)
             .with_context(|| format!("failed to save screenshot {}", path.display()))?;
         paths.push(path);
     }
+    if paths.is_empty() {
+        bail!("region {} is not on any display", region.spec());
+    }
+    Ok(paths)
+}
+
+fn capture_signal_window(base_dir: &Path, attempt: u32) -> Option<PathBuf> {
+    if !command_exists("screencapture") {
+        return None;
+    }
+    let windows = window::list_windows().ok()?;
+    let signal = window::find_signal_window(&windows)?;
+    let path = base_dir.join(format!("screen-{attempt}-window-{}.png", signal.id));
+    capture_window_image(signal.id, &path).ok()?;
+    Some(path)
+}
+
+fn capture_screens_with_xcap(base_dir: &Path, attempt: u32) -> Result<Vec<PathBuf>> {
+    let monitors = Monitor::all().context("failed to enumerate displays with xcap")?;
+    if monitors.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut paths = Vec::new();
+    for (idx, monitor) in monitors.into_iter().enumerate() {
+        let path = base_dir.join(format!("screen-{attempt}-display-{}.png", idx + 1));
+        save_monitor_capture(&monitor, &path)?;
+        paths.push(path);
+    }
 
     Ok(paths)
 }
+
+fn save_monitor_capture(monitor: &Monitor, path: &Path) -> Result<()> {
+    let image = monitor
+        .capture_image()
+        .context("failed to capture display with xcap")?;
+    // Keep only the luma plane so several 5K displays never sit in memory as RGBA.
+    let mut image = DynamicImage::ImageRgba8(image).into_luma8();
+    let ceiling = crate::QR_DECODE_MEMORY_CEILING_BYTES;
+    if let Some(max_dimension) = max_dimension_within(image.width(), image.height(), ceiling) {
+        image = resize_luma_to_max_dimension(&image, max_dimension);
+    }
+    image
+        .save(path)
+        .with_context(|| format!("failed to save screenshot {}", path.display()))
+}
@@ -0,0 +1,133 @@
+use anyhow::{Context, Result};
+use image::{GrayImage, Rgb, RgbImage};
+use rqrr::PreparedImage;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const LOCATED_COLOR: Rgb<u8> = Rgb([220, 30, 30]);
+const REJECTED_COLOR: Rgb<u8> = Rgb([240, 160, 0]);
+const ACCEPTED_COLOR: Rgb<u8> = Rgb([30, 200, 60]);
+const LINE_RADIUS: i32 = 2;
+const FINDER_RADIUS: i32 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetectionOutcome {
+    /// Finder patterns found, but the modules could not be read
+    Located,
+    /// Read, but the payload is not a Signal linking URI
+    Rejected,
+    Accepted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Detection {
+    /// Corners of the code, starting at the top-left finder pattern and going clockwise
+    pub corners: [(i32, i32); 4],
+    pub outcome: DetectionOutcome,
+}
+
+pub fn detect_qr_codes(image: &GrayImage) -> Vec<Detection> {
+    let mut prepared = PreparedImage::prepare(image.clone());
+    prepared
+        .detect_grids()
+        .into_iter()
+        .map(|grid| {
+            let outcome = match grid.decode() {
+                Ok((_meta, content)) if content.starts_with("sgnl://linkdevice") => {
+                    DetectionOutcome::Accepted
+                }
+                Ok(_) => DetectionOutcome::Rejected,
+                Err(_) => DetectionOutcome::Located,
+            };
+            Detection {
+                corners: grid.bounds.map(|point| (point.x, point.y)),
+                outcome,
+            }
+        })
+        .collect()
+}
+
+/// Red when only located, orange when decoded but not a linking URI, green when accepted.
+pub fn annotate_detections(image: &GrayImage, detections: &[Detection]) -> RgbImage {
+    let mut annotated = RgbImage::from_fn(image.width(), image.height(), |x, y| {
+        let level = image.get_pixel(x, y)[0];
+        Rgb([level, level, level])
+    });
+    for detection in detections {
+        let color = match detection.outcome {
+            DetectionOutcome::Located => LOCATED_COLOR,
+            DetectionOutcome::Rejected => REJECTED_COLOR,
+            DetectionOutcome::Accepted => ACCEPTED_COLOR,
+        };
+        for (idx, &corner) in detection.corners.iter().enumerate() {
+            let next = detection.corners[(idx + 1) % detection.corners.len()];
+            draw_line(&mut annotated, corner, next, color);
+        }
+        // Finder patterns sit at every corner except the bottom-right one.
+        for idx in [0, 1, 3] {
+            fill_square(&mut annotated, detection.corners[idx], FINDER_RADIUS, color);
+        }
+    }
+    annotated
+}
+
+/// Names start with the capture time in milliseconds so rescans never overwrite earlier artifacts.
+pub fn save_debug_artifacts(path: &Path, image: &GrayImage, dir: &Path) -> Result<PathBuf> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("failed to create debug artifacts dir {}", dir.display()))?;
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis())
+        .unwrap_or(0);
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "capture".to_string());
+    let copy = dir.join(format!("{millis}-{stem}.png"));
+    fs::copy(path, &copy)
+        .with_context(|| format!("failed to copy capture to {}", copy.display()))?;
+
+    let detections = detect_qr_codes(image);
+    let annotated_path = dir.join(format!("{millis}-{stem}-annotated.png"));
+    annotate_detections(image, &detections)
+        .save(&annotated_path)
+        .with_context(|| format!("failed to save {}", annotated_path.display()))?;
+    Ok(annotated_path)
+}
+
+fn draw_line(image: &mut RgbImage, from: (i32, i32), to: (i32, i32), color: Rgb<u8>) {
+    let (mut x, mut y) = from;
+    let dx = (to.0 - x).abs();
+    let dy = -(to.1 - y).abs();
+    let step_x = if x < to.0 { 1 } else { -1 };
+    let step_y = if y < to.1 { 1 } else { -1 };
+    let mut error = dx + dy;
+    loop {
+        fill_square(image, (x, y), LINE_RADIUS, color);
+        if (x, y) == to {
+            break;
+        }
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+fn fill_square(image: &mut RgbImage, center: (i32, i32), radius: i32, color: Rgb<u8>) {
+    for y in center.1 - radius..=center.1 + radius {
+        for x in center.0 - radius..=center.0 + radius {
+            if let (Ok(x), Ok(y)) = (u32::try_from(x), u32::try_from(y)) {
+                if x < image.width() && y < image.height() {
+                    image.put_pixel(x, y, color);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use anyhow::Result;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::Config;
+use crate::docker::run_signal_cli;
+use crate::settings::{load_settings, update_settings};
+use crate::signal_args;
+
+pub const NO_REGLOCK_WARNING: &str = "WARNING: no registration lock PIN will be set. Anyone who gets hold of this number (a SIM swap, a recycled or ported number, an intercepted SMS code) can register it on their own device and take over the account, and nothing warns you until it has happened. Run 'set-pin' as soon as possible.";
+
+pub const REMINDER_TEXT: &str = "Reminder from signal-setup: this account still has no registration lock PIN. Run 'signal-setup set-pin' to protect it against takeover through the phone number.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PinFormat {
+    pub digits: usize,
+    /// Digits between the `-` separators when the PIN is shown; 0 shows it in one piece
+    pub group: usize,
+}
+
+impl Default for PinFormat {
+    fn default() -> Self {
+        Self {
+            digits: crate::GENERATED_REGISTRATION_PIN_DIGITS,
+            group: crate::PIN_DISPLAY_GROUP,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinChoice {
+    SetNow,
+    RemindLater,
+    Skip,
+}
+
+impl PinChoice {
+    pub const ALL: [PinChoice; 3] = [PinChoice::SetNow, PinChoice::RemindLater, PinChoice::Skip];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PinChoice::SetNow => "Set the generated PIN now (recommended)",
+            PinChoice::RemindLater => "Remind me later (note-to-self sent by keepalive)",
+            PinChoice::Skip => "Do not set a registration lock",
+        }
+    }
+}
+
+pub fn pending_reminder(reminders: &[(String, u64)], account: &str) -> Option<u64> {
+    reminders
+        .iter()
+        .find(|(known, _)| known == account)
+        .map(|(_, due)| *due)
+}
+
+pub fn reminder_due(reminders: &[(String, u64)], account: &str, now: u64) -> bool {
+    pending_reminder(reminders, account).is_some_and(|due| due <= now)
+}
+
+pub fn schedule_reminder(account: &str) -> Result<u64> {
+    let due = now_secs() + crate::REGLOCK_REMINDER_DELAY_SECS;
+    update_settings(|settings| {
+        settings
+            .reglock_reminders
+            .retain(|(known, _)| known != account);
+        settings.reglock_reminders.push((account.to_string(), due));
+    })?;
+    Ok(due)
+}
+
+pub fn clear_reminder(account: &str) -> Result<()> {
+    update_settings(|settings| {
+        settings
+            .reglock_reminders
+            .retain(|(known, _)| known != account)
+    })?;
+    Ok(())
+}
+
+pub fn describe_pending(due: u64, now: u64) -> String {
+    if due <= now {
+        "This account still has no registration lock PIN; the reminder is due. Run 'set-pin' to set one.".to_string()
+    } else {
+        format!(
+            "This account still has no registration lock PIN; a reminder is due in {}. Run 'set-pin' to set one now.",
+            format_remaining(due - now)
+        )
+    }
+}
+
+fn format_remaining(secs: u64) -> String {
+    match secs {
+        0..=3599 => format!("{}m", secs.div_ceil(60)),
+        3600..=86_399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86_400),
+    }
+}
+
+pub fn show_pending_reminder(account: &str) {
+    let reminders = load_settings().unwrap_or_default().reglock_reminders;
+    if let Some(due) = pending_reminder(&reminders, account) {
+        println!("{}", crate::ui::wrap(&describe_pending(due, now_secs())));
+    }
+}
+
+pub fn send_due_reminder(cfg: &Config) -> Result<bool> {
+    let reminders = load_settings()?.reglock_reminders;
+    if !reminder_due(&reminders, &cfg.account, now_secs()) {
+        return Ok(false);
+    }
+    run_signal_cli(cfg, &signal_args::send_note_to_self(REMINDER_TEXT), false)?;
+    clear_reminder(&cfg.account)?;
+    Ok(true)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
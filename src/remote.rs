@@ -0,0 +1,230 @@
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::audit::AuditedCommand;
+use crate::config::Config;
+use crate::docker::container_config_dir;
+use crate::runtime::ContainerRuntime;
+
+/// Volumes this process created, removed when the run ends.
+static PREPARED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+pub fn engine_host() -> Option<String> {
+    if let Some(host) = non_empty_env("DOCKER_HOST") {
+        return Some(host);
+    }
+    let context = non_empty_env("DOCKER_CONTEXT").or_else(current_context)?;
+    if context == "default" {
+        return None;
+    }
+    let output = Command::new("docker")
+        .args([
+            "context",
+            "inspect",
+            &context,
+            "--format",
+            "{{.Endpoints.docker.Host}}",
+        ])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .audited_output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string()).filter(|host| !host.is_empty())
+}
+
+fn non_empty_env(key: &str) -> Option<String> {
+    std::env::var(key).ok().filter(|value| !value.is_empty())
+}
+
+fn current_context() -> Option<String> {
+    let text = fs::read_to_string(crate::pull::docker_config_path()?).ok()?;
+    let json: Value = serde_json::from_str(&text).ok()?;
+    json.get("currentContext")?
+        .as_str()
+        .filter(|context| !context.is_empty())
+        .map(str::to_string)
+}
+
+/// Unix sockets, named pipes and loopback TCP are local.
+pub fn is_remote_host(host: &str) -> bool {
+    let Some((scheme, rest)) = host.split_once("://") else {
+        return false;
+    };
+    match scheme {
+        "ssh" => true,
+        "tcp" | "http" | "https" => {
+            let authority = rest.split('/').next().unwrap_or_default();
+            let hostname = match authority.rsplit_once(':') {
+                Some((hostname, port)) if port.chars().all(|c| c.is_ascii_digit()) => hostname,
+                _ => authority,
+            };
+            !matches!(
+                hostname.trim_start_matches('[').trim_end_matches(']'),
+                "localhost" | "127.0.0.1" | "::1"
+            )
+        }
+        _ => false,
+    }
+}
+
+pub fn data_volume_name(account: &str) -> String {
+    format!("signal-setup-{}", account.trim_start_matches('+'))
+}
+
+pub fn data_volume(runtime: ContainerRuntime, account: &str) -> Option<String> {
+    if runtime != ContainerRuntime::Docker {
+        return None;
+    }
+    engine_host()
+        .filter(|host| is_remote_host(host))
+        .map(|_| data_volume_name(account))
+}
+
+pub fn prepare_data_volume(cfg: &Config) -> Result<()> {
+    let Some(volume) = &cfg.data_volume else {
+        return Ok(());
+    };
+    let mut prepared = prepared();
+    if prepared.contains(volume) {
+        return Ok(());
+    }
+    println!(
+        "Docker runs on another machine; {} is copied into the volume {volume} there for each command.",
+        cfg.data_dir.display()
+    );
+    docker(&["volume", "create", volume])?;
+    prepared.push(volume.clone());
+    Ok(())
+}
+
+/// Replaces the volume's contents, so data left there by an earlier run never outlives the data dir.
+pub fn refresh_data_volume(cfg: &Config) -> Result<()> {
+    let Some(volume) = &cfg.data_volume else {
+        return Ok(());
+    };
+    prepare_data_volume(cfg)?;
+    let source = format!("{}/.", cfg.data_dir.display());
+    with_copy_container(cfg, volume, |container| {
+        docker(&["start", "--attach", container])?;
+        docker(&[
+            "cp",
+            &source,
+            &format!("{container}:{}", container_config_dir(cfg)),
+        ])
+    })
+}
+
+/// Only warns on failure: the command itself already ran.
+pub fn mirror_to_data_dir(cfg: &Config) {
+    let Some(volume) = &cfg.data_volume else {
+        return;
+    };
+    let copied = with_copy_container(cfg, volume, |container| {
+        let staging = tempfile::Builder::new()
+            .prefix(".signal-setup-volume-")
+            .tempdir_in(&cfg.data_dir)
+            .context("failed to create a staging dir")?;
+        docker(&[
+            "cp",
+            &format!("{container}:{}/.", container_config_dir(cfg)),
+            &staging.path().display().to_string(),
+        ])?;
+        replace_contents(&cfg.data_dir, staging.path())
+    });
+    if let Err(err) = copied {
+        eprintln!(
+            "Warning: could not copy the volume {volume} back into {}: {err:#}",
+            cfg.data_dir.display()
+        );
+    }
+}
+
+/// Files signal-cli deleted in the volume are deleted here too; a stale SQLite journal must not return.
+fn replace_contents(dir: &Path, staging: &Path) -> Result<()> {
+    let staged: Vec<_> = fs::read_dir(staging)?.collect::<std::io::Result<_>>()?;
+    if staged.is_empty() {
+        bail!("the volume is empty; keeping the data dir as it is")
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path == staging {
+            continue;
+        }
+        if fs::symlink_metadata(&path)?.is_dir() {
+            fs::remove_dir_all(&path)?;
+        } else {
+            fs::remove_file(&path)?;
+        }
+    }
+    for entry in staged {
+        fs::rename(entry.path(), dir.join(entry.file_name()))
+            .with_context(|| format!("failed to move {} into place", entry.path().display()))?;
+    }
+    Ok(())
+}
+
+/// Also called on exits that skip the end of `run`, so no plaintext copy stays on the engine.
+pub fn remove_all() {
+    let prepared = std::mem::take(&mut *prepared());
+    for volume in prepared {
+        if let Err(err) = docker(&["volume", "rm", &volume]) {
+            eprintln!(
+                "Warning: could not remove the volume {volume} from the Docker engine: {err:#}"
+            );
+        }
+    }
+}
+
+fn prepared() -> MutexGuard<'static, Vec<String>> {
+    PREPARED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// A stopped container mounting `volume` is what `docker cp` can reach on any engine.
+/// Started, it empties the volume.
+fn with_copy_container(
+    cfg: &Config,
+    volume: &str,
+    copy: impl FnOnce(&str) -> Result<()>,
+) -> Result<()> {
+    let container = format!("{volume}-copy-{}", std::process::id());
+    docker(&[
+        "create",
+        "--name",
+        &container,
+        "--volume",
+        &format!("{volume}:{}", container_config_dir(cfg)),
+        "--entrypoint",
+        "find",
+        &cfg.image,
+        container_config_dir(cfg),
+        "-mindepth",
+        "1",
+        "-delete",
+    ])?;
+    let result = copy(&container);
+    let _ = docker(&["rm", "-f", &container]);
+    result
+}
+
+fn docker(args: &[&str]) -> Result<()> {
+    let output = Command::new("docker")
+        .args(args)
+        .stdin(Stdio::null())
+        .audited_output()
+        .with_context(|| format!("failed to run docker {}", args.join(" ")))?;
+    if !output.status.success() {
+        bail!(
+            "docker {} failed: {}",
+            args.first().copied().unwrap_or_default(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+    }
+    Ok(())
+}
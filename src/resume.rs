@@ -0,0 +1,149 @@
+use anyhow::{bail, Context, Result};
+use rand::rngs::OsRng;
+use rand::Rng;
+use serde_json::{json, Value};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Holds no secrets: the data dir has the account's keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResumeState {
+    pub account: String,
+    pub saved_at: u64,
+}
+
+static CHECKPOINT: Mutex<Option<String>> = Mutex::new(None);
+
+pub fn set_checkpoint(account: Option<&str>) {
+    *CHECKPOINT
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = account.map(str::to_string);
+}
+
+pub fn checkpoint() -> Option<String> {
+    CHECKPOINT
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone()
+}
+
+pub fn new_token() -> String {
+    let mut rng = OsRng;
+    (0..crate::RESUME_TOKEN_LEN)
+        .map(|_| char::from_digit(rng.gen_range(0..16), 16).unwrap_or('0'))
+        .collect()
+}
+
+/// Anything but a generated token is rejected, so a token cannot point outside the settings dir.
+pub fn resume_path(token: &str) -> Result<PathBuf> {
+    if token.len() != crate::RESUME_TOKEN_LEN || !token.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!("invalid resume token '{token}'")
+    }
+    let mut path = crate::settings::settings_path();
+    path.set_file_name(format!("wizard-resume-{}.json", token.to_ascii_lowercase()));
+    Ok(path)
+}
+
+pub fn save(account: &str) -> Result<String> {
+    let token = new_token();
+    let state = ResumeState {
+        account: account.to_string(),
+        saved_at: now_secs(),
+    };
+    save_to(&resume_path(&token)?, &state)?;
+    Ok(token)
+}
+
+pub fn save_to(path: &Path, state: &ResumeState) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    let content = json!({ "account": state.account, "saved_at": state.saved_at });
+    fs::write(path, format!("{content}\n"))
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+pub fn load(token: &str) -> Result<ResumeState> {
+    load_from(&resume_path(token)?, now_secs())
+}
+
+/// Kept on disk until linking finishes, so the token also works by hand after a restart.
+pub fn load_from(path: &Path, now: u64) -> Result<ResumeState> {
+    let content = fs::read_to_string(path).map_err(|_| {
+        anyhow::anyhow!("no wizard run to resume for this token; it finished already or was never saved, run the wizard without --resume")
+    })?;
+    let json: Value = serde_json::from_str(&content)
+        .with_context(|| format!("invalid JSON in {}", path.display()))?;
+    let (Some(account), Some(saved_at)) = (
+        json.get("account").and_then(Value::as_str),
+        json.get("saved_at").and_then(Value::as_u64),
+    ) else {
+        bail!("{} is missing the account or the save time", path.display())
+    };
+    if now.saturating_sub(saved_at) > crate::RESUME_TOKEN_TTL_SECS {
+        let _ = fs::remove_file(path);
+        bail!(
+            "the resume token expired after {}h; run the wizard without --resume",
+            crate::RESUME_TOKEN_TTL_SECS / 3600
+        )
+    }
+    Ok(ResumeState {
+        account: account.to_string(),
+        saved_at,
+    })
+}
+
+pub fn discard(token: &str) {
+    if let Ok(path) = resume_path(token) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+pub fn restart_args(args: Vec<OsString>, token: &str) -> Vec<OsString> {
+    let mut restarted = Vec::with_capacity(args.len() + 3);
+    let mut has_wizard = false;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--resume" {
+            args.next();
+            continue;
+        }
+        if arg.to_str().is_some_and(|arg| arg.starts_with("--resume=")) {
+            continue;
+        }
+        has_wizard |= arg == "wizard";
+        restarted.push(arg);
+    }
+    if !has_wizard {
+        restarted.push("wizard".into());
+    }
+    restarted.push("--resume".into());
+    restarted.push(token.into());
+    restarted
+}
+
+pub fn restart(token: &str) -> Result<()> {
+    use std::os::unix::process::CommandExt;
+
+    let exe = std::env::current_exe().context("failed to locate the signal-setup binary")?;
+    let args = restart_args(std::env::args_os().skip(1).collect(), token);
+    println!("Restarting to pick up the Screen Recording permission...");
+    let err = std::process::Command::new(&exe).args(&args).exec();
+    Err(err).with_context(|| {
+        format!(
+            "failed to restart {}; run it again with 'wizard --resume {token}'",
+            exe.display()
+        )
+    })
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
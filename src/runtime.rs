@@ -0,0 +1,84 @@
+use clap::ValueEnum;
+use std::process::Command;
+
+use crate::system::command_exists;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ContainerRuntime {
+    /// Docker Desktop / Docker Engine
+    #[default]
+    Docker,
+    /// nerdctl (containerd), including Lima's nerdctl.lima / `lima nerdctl`
+    Nerdctl,
+}
+
+impl ContainerRuntime {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ContainerRuntime::Docker => "docker",
+            ContainerRuntime::Nerdctl => "nerdctl",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "docker" => Some(ContainerRuntime::Docker),
+            "nerdctl" => Some(ContainerRuntime::Nerdctl),
+            _ => None,
+        }
+    }
+
+    pub fn is_installed(self) -> bool {
+        match self {
+            ContainerRuntime::Docker => command_exists("docker"),
+            ContainerRuntime::Nerdctl => {
+                command_exists("nerdctl")
+                    || command_exists("nerdctl.lima")
+                    || command_exists("lima")
+            }
+        }
+    }
+
+    pub fn command(self) -> Command {
+        match self {
+            ContainerRuntime::Docker => Command::new("docker"),
+            ContainerRuntime::Nerdctl if command_exists("nerdctl") => Command::new("nerdctl"),
+            ContainerRuntime::Nerdctl if command_exists("nerdctl.lima") => {
+                Command::new("nerdctl.lima")
+            }
+            ContainerRuntime::Nerdctl => {
+                let mut cmd = Command::new("lima");
+                cmd.arg("nerdctl");
+                cmd
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResourceLimits {
+    pub memory: Option<String>,
+    pub cpus: Option<String>,
+    pub pids_limit: Option<u32>,
+}
+
+impl ResourceLimits {
+    /// Docker and nerdctl take the same flags.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(memory) = &self.memory {
+            args.extend(["--memory".to_string(), memory.clone()]);
+        }
+        if let Some(cpus) = &self.cpus {
+            args.extend(["--cpus".to_string(), cpus.clone()]);
+        }
+        if let Some(pids) = self.pids_limit {
+            args.extend(["--pids-limit".to_string(), pids.to_string()]);
+        }
+        args
+    }
+}
+
+pub fn lima_instance() -> String {
+    std::env::var("LIMA_INSTANCE").unwrap_or_else(|_| "default".to_string())
+}
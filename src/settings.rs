@@ -0,0 +1,323 @@
+use anyhow::{Context, Result};
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::docker::RegistrationMode;
+use crate::i18n::Language;
+use crate::image_ref::ImageFlavor;
+use crate::ui::Charset;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Settings {
+    pub image_flavor: Option<ImageFlavor>,
+    pub registry_mirror: Option<String>,
+    pub signature_key: Option<String>,
+    pub image_tag: Option<String>,
+    pub webhook_url: Option<String>,
+    pub ntfy_topic: Option<String>,
+    pub pushover_user: Option<String>,
+    pub pushover_token: Option<String>,
+    pub trust_new_identities: Vec<String>,
+    /// `--container-config` per account, since it describes that account's data layout.
+    pub container_configs: Vec<(String, String)>,
+    pub scan_region: Option<String>,
+    pub recent_accounts: Vec<String>,
+    pub last_registration_mode: Option<RegistrationMode>,
+    pub last_link_desktop: Option<bool>,
+    pub language: Option<Language>,
+    pub charset: Option<Charset>,
+    /// Accounts whose registration lock PIN was deferred, with the unix time the reminder is due.
+    pub reglock_reminders: Vec<(String, u64)>,
+    /// Environment proxy the native image warning was last shown for.
+    pub native_proxy_warned: Option<String>,
+}
+
+pub fn settings_path() -> PathBuf {
+    // Never the data dir, which the vault encrypts and removes between runs.
+    let mut path = dirs::config_dir()
+        .or_else(|| dirs::home_dir().map(|home| home.join(".config")))
+        .unwrap_or_else(|| PathBuf::from(".config"));
+    path.push("signal-desktop-only");
+    path.push("config.json");
+    path
+}
+
+pub fn load_settings() -> Result<Settings> {
+    load_settings_from(&settings_path())
+}
+
+pub fn load_settings_from(path: &Path) -> Result<Settings> {
+    if !path.exists() {
+        return Ok(Settings::default());
+    }
+
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("failed to read config file {}", path.display()))?;
+    let json = serde_json::from_str::<Value>(&content)
+        .with_context(|| format!("invalid JSON in config file {}", path.display()))?;
+    Ok(settings_from_json(&json))
+}
+
+pub fn save_settings(settings: &Settings) -> Result<()> {
+    save_settings_to(&settings_path(), settings)
+}
+
+pub fn save_settings_to(path: &Path, settings: &Settings) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create config dir {}", parent.display()))?;
+    }
+
+    let content = serde_json::to_string_pretty(&settings_to_json(settings))
+        .context("failed to serialize config")?;
+    fs::write(path, format!("{content}\n"))
+        .with_context(|| format!("failed to write config file {}", path.display()))
+}
+
+pub fn update_settings(apply: impl FnOnce(&mut Settings)) -> Result<Settings> {
+    let current = load_settings()?;
+    let mut updated = current.clone();
+    apply(&mut updated);
+    if updated != current {
+        save_settings(&updated)?;
+    }
+    Ok(updated)
+}
+
+pub fn record_image_tag(tag: Option<String>) -> Result<()> {
+    update_settings(|settings| settings.image_tag = tag)?;
+    Ok(())
+}
+
+pub fn record_image_flavor(flavor: ImageFlavor) -> Result<()> {
+    update_settings(|settings| settings.image_flavor = Some(flavor))?;
+    Ok(())
+}
+
+pub fn set_trust_new_identities(account: &str, enabled: bool) -> Result<()> {
+    update_settings(|settings| {
+        settings
+            .trust_new_identities
+            .retain(|known| known != account);
+        if enabled {
+            settings.trust_new_identities.push(account.to_string());
+        }
+    })?;
+    Ok(())
+}
+
+pub fn record_wizard_registration(account: &str, mode: RegistrationMode) -> Result<()> {
+    update_settings(|settings| {
+        settings.recent_accounts.retain(|known| known != account);
+        settings.recent_accounts.insert(0, account.to_string());
+        settings
+            .recent_accounts
+            .truncate(crate::RECENT_ACCOUNTS_LIMIT);
+        settings.last_registration_mode = Some(mode);
+    })?;
+    Ok(())
+}
+
+pub fn record_wizard_link_choice(link_desktop: bool) -> Result<()> {
+    update_settings(|settings| settings.last_link_desktop = Some(link_desktop))?;
+    Ok(())
+}
+
+pub fn record_language(language: Language) -> Result<()> {
+    update_settings(|settings| settings.language = Some(language))?;
+    Ok(())
+}
+
+pub fn resolve_charset(flag: Option<Charset>) -> Result<Charset> {
+    let settings = match flag {
+        Some(charset) => update_settings(|settings| settings.charset = Some(charset))?,
+        None => load_settings()?,
+    };
+    Ok(settings.charset.unwrap_or_default())
+}
+
+fn settings_from_json(json: &Value) -> Settings {
+    Settings {
+        image_flavor: json
+            .get("image_flavor")
+            .and_then(Value::as_str)
+            .and_then(ImageFlavor::from_name),
+        registry_mirror: json
+            .get("registry_mirror")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        signature_key: json
+            .get("signature_key")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        image_tag: json
+            .get("image_tag")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        webhook_url: json
+            .get("webhook_url")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        ntfy_topic: json
+            .get("ntfy_topic")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        pushover_user: json
+            .get("pushover_user")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        pushover_token: json
+            .get("pushover_token")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        trust_new_identities: json
+            .get("trust_new_identities")
+            .and_then(Value::as_array)
+            .map(|accounts| {
+                accounts
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default(),
+        container_configs: json
+            .get("container_configs")
+            .and_then(Value::as_object)
+            .map(|paths| {
+                paths
+                    .iter()
+                    .filter_map(|(account, path)| {
+                        Some((account.clone(), path.as_str()?.to_string()))
+                    })
+                    .collect()
+            })
+            // A path saved before it was kept per account applies to every account.
+            .or_else(|| {
+                let path = json.get("container_config")?.as_str()?;
+                Some(vec![(String::new(), path.to_string())])
+            })
+            .unwrap_or_default(),
+        scan_region: json
+            .get("scan_region")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        recent_accounts: json
+            .get("recent_accounts")
+            .and_then(Value::as_array)
+            .map(|accounts| {
+                accounts
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default(),
+        last_registration_mode: json
+            .get("last_registration_mode")
+            .and_then(Value::as_str)
+            .and_then(RegistrationMode::from_name),
+        last_link_desktop: json.get("last_link_desktop").and_then(Value::as_bool),
+        language: json
+            .get("language")
+            .and_then(Value::as_str)
+            .and_then(Language::from_code),
+        charset: json
+            .get("charset")
+            .and_then(Value::as_str)
+            .and_then(Charset::from_name),
+        reglock_reminders: json
+            .get("reglock_reminders")
+            .and_then(Value::as_object)
+            .map(|reminders| {
+                reminders
+                    .iter()
+                    .filter_map(|(account, due)| Some((account.clone(), due.as_u64()?)))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        native_proxy_warned: json
+            .get("native_proxy_warned")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+    }
+}
+
+fn settings_to_json(settings: &Settings) -> Value {
+    let mut obj = Map::new();
+    if let Some(flavor) = settings.image_flavor {
+        obj.insert("image_flavor".to_string(), Value::from(flavor.as_str()));
+    }
+    if let Some(mirror) = &settings.registry_mirror {
+        obj.insert("registry_mirror".to_string(), Value::from(mirror.as_str()));
+    }
+    if let Some(key) = &settings.signature_key {
+        obj.insert("signature_key".to_string(), Value::from(key.as_str()));
+    }
+    if let Some(tag) = &settings.image_tag {
+        obj.insert("image_tag".to_string(), Value::from(tag.as_str()));
+    }
+    if let Some(url) = &settings.webhook_url {
+        obj.insert("webhook_url".to_string(), Value::from(url.as_str()));
+    }
+    if let Some(topic) = &settings.ntfy_topic {
+        obj.insert("ntfy_topic".to_string(), Value::from(topic.as_str()));
+    }
+    if let Some(user) = &settings.pushover_user {
+        obj.insert("pushover_user".to_string(), Value::from(user.as_str()));
+    }
+    if let Some(token) = &settings.pushover_token {
+        obj.insert("pushover_token".to_string(), Value::from(token.as_str()));
+    }
+    if !settings.trust_new_identities.is_empty() {
+        obj.insert(
+            "trust_new_identities".to_string(),
+            Value::from(settings.trust_new_identities.clone()),
+        );
+    }
+    if !settings.container_configs.is_empty() {
+        let paths = settings
+            .container_configs
+            .iter()
+            .map(|(account, path)| (account.clone(), Value::from(path.as_str())))
+            .collect::<Map<_, _>>();
+        obj.insert("container_configs".to_string(), Value::Object(paths));
+    }
+    if let Some(region) = &settings.scan_region {
+        obj.insert("scan_region".to_string(), Value::from(region.as_str()));
+    }
+    if !settings.recent_accounts.is_empty() {
+        obj.insert(
+            "recent_accounts".to_string(),
+            Value::from(settings.recent_accounts.clone()),
+        );
+    }
+    if let Some(mode) = settings.last_registration_mode {
+        obj.insert(
+            "last_registration_mode".to_string(),
+            Value::from(mode.as_str()),
+        );
+    }
+    if let Some(link) = settings.last_link_desktop {
+        obj.insert("last_link_desktop".to_string(), Value::from(link));
+    }
+    if let Some(language) = settings.language {
+        obj.insert("language".to_string(), Value::from(language.code()));
+    }
+    if let Some(charset) = settings.charset {
+        obj.insert("charset".to_string(), Value::from(charset.as_str()));
+    }
+    if !settings.reglock_reminders.is_empty() {
+        let reminders = settings
+            .reglock_reminders
+            .iter()
+            .map(|(account, due)| (account.clone(), Value::from(*due)))
+            .collect::<Map<_, _>>();
+        obj.insert("reglock_reminders".to_string(), Value::Object(reminders));
+    }
+    if let Some(url) = &settings.native_proxy_warned {
+        obj.insert("native_proxy_warned".to_string(), Value::from(url.as_str()));
+    }
+    Value::Object(obj)
+}
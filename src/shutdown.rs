@@ -0,0 +1,146 @@
+use anyhow::Result;
+use std::process::Child;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::errors::SignalSetupError;
+
+static TERMINATE_REQUESTED: AtomicBool = AtomicBool::new(false);
+static DEADLINE_EXCEEDED: AtomicBool = AtomicBool::new(false);
+static INTERRUPT_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_sigterm(_signal: libc::c_int) {
+    TERMINATE_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Long loops poll the flag so they unwind (reaping children, re-locking the vault).
+pub fn install_sigterm_handler() {
+    let handler = on_sigterm as extern "C" fn(libc::c_int);
+    unsafe {
+        libc::signal(libc::SIGTERM, handler as libc::sighandler_t);
+    }
+}
+
+extern "C" fn on_sigint(_signal: libc::c_int) {
+    INTERRUPT_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Dropping it restores the default, where Ctrl+C exits.
+pub struct InterruptGuard;
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        unsafe {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+        }
+        request_interrupt(false);
+    }
+}
+
+pub fn catch_interrupt() -> InterruptGuard {
+    request_interrupt(false);
+    let handler = on_sigint as extern "C" fn(libc::c_int);
+    unsafe {
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+    }
+    InterruptGuard
+}
+
+pub fn interrupt_requested() -> bool {
+    INTERRUPT_REQUESTED.load(Ordering::SeqCst)
+}
+
+pub fn request_interrupt(requested: bool) {
+    INTERRUPT_REQUESTED.store(requested, Ordering::SeqCst);
+}
+
+pub fn terminate_requested() -> bool {
+    TERMINATE_REQUESTED.load(Ordering::SeqCst)
+}
+
+pub fn request_terminate(requested: bool) {
+    TERMINATE_REQUESTED.store(requested, Ordering::SeqCst);
+}
+
+pub fn deadline_exceeded() -> bool {
+    DEADLINE_EXCEEDED.load(Ordering::SeqCst)
+}
+
+pub fn exceed_deadline(exceeded: bool) {
+    DEADLINE_EXCEEDED.store(exceeded, Ordering::SeqCst);
+    request_terminate(exceeded);
+}
+
+pub fn check_terminated() -> Result<()> {
+    if deadline_exceeded() {
+        return Err(SignalSetupError::MaxDurationExceeded.into());
+    }
+    if terminate_requested() {
+        return Err(SignalSetupError::Terminated.into());
+    }
+    Ok(())
+}
+
+pub fn is_terminated(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<SignalSetupError>(),
+        Some(SignalSetupError::Terminated | SignalSetupError::MaxDurationExceeded)
+    )
+}
+
+/// Still running `grace` later, `on_overrun` runs on the watchdog thread and should exit.
+pub fn start_deadline(
+    limit: Duration,
+    grace: Duration,
+    on_overrun: impl FnOnce() + Send + 'static,
+) {
+    thread::spawn(move || {
+        thread::sleep(limit);
+        exceed_deadline(true);
+        thread::sleep(grace);
+        on_overrun();
+    });
+}
+
+pub fn sleep_unless_terminated(duration: Duration) -> Result<()> {
+    let deadline = Instant::now() + duration;
+    loop {
+        check_terminated()?;
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Ok(());
+        }
+        thread::sleep(left.min(Duration::from_millis(crate::TERMINATE_POLL_MS)));
+    }
+}
+
+/// Drop it before reaping the child.
+pub struct TerminateForwarder {
+    done: Arc<AtomicBool>,
+}
+
+impl Drop for TerminateForwarder {
+    fn drop(&mut self) {
+        self.done.store(true, Ordering::SeqCst);
+    }
+}
+
+pub fn forward_terminate_to(child: &Child) -> TerminateForwarder {
+    let pid = child.id() as libc::pid_t;
+    let done = Arc::new(AtomicBool::new(false));
+    let watching = done.clone();
+    thread::spawn(move || {
+        while !watching.load(Ordering::SeqCst) {
+            if terminate_requested() {
+                unsafe {
+                    libc::kill(pid, libc::SIGTERM);
+                }
+                return;
+            }
+            thread::sleep(Duration::from_millis(crate::TERMINATE_POLL_MS));
+        }
+    });
+    TerminateForwarder { done }
+}
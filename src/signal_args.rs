@@ -0,0 +1,149 @@
+use anyhow::{bail, Result};
+
+use crate::cli::Commands;
+use crate::config::Config;
+use crate::sync::SyncOptions;
+
+/// Stand-ins for values only known while a command runs; `--print-args` masks them anyway.
+const CAPTCHA_PLACEHOLDER: &str = "signalcaptcha://<token>";
+const CODE_PLACEHOLDER: &str = "<code>";
+const PIN_PLACEHOLDER: &str = "<pin>";
+const LINK_URI_PLACEHOLDER: &str = "sgnl://linkdevice?<scanned>";
+
+pub fn register(token: &str, voice: bool) -> Vec<String> {
+    let mut args = vec![
+        "register".to_string(),
+        "--captcha".to_string(),
+        token.to_string(),
+    ];
+    if voice {
+        args.push("--voice".to_string());
+    }
+    args
+}
+
+pub fn verify(code: &str, pin: Option<&str>) -> Vec<String> {
+    let mut args = vec!["verify".to_string(), code.to_string()];
+    if let Some(pin) = pin {
+        args.push("--pin".to_string());
+        args.push(pin.to_string());
+    }
+    args
+}
+
+pub fn set_pin(pin: &str) -> Vec<String> {
+    vec!["setPin".to_string(), pin.to_string()]
+}
+
+pub fn list_devices() -> Vec<String> {
+    vec!["listDevices".to_string()]
+}
+
+pub fn add_device(uri: &str) -> Vec<String> {
+    vec![
+        "addDevice".to_string(),
+        "--uri".to_string(),
+        uri.trim().to_string(),
+    ]
+}
+
+/// `timeout_secs` of -1 keeps receiving until stopped.
+pub fn receive(timeout_secs: i64, max_messages: Option<u32>) -> Vec<String> {
+    let mut args = vec![
+        "receive".to_string(),
+        "--timeout".to_string(),
+        timeout_secs.to_string(),
+    ];
+    if let Some(max) = max_messages {
+        args.push("--max-messages".to_string());
+        args.push(max.to_string());
+    }
+    args
+}
+
+pub fn send_contacts() -> Vec<String> {
+    vec!["sendContacts".to_string()]
+}
+
+pub fn send_note_to_self(message: &str) -> Vec<String> {
+    vec![
+        "send".to_string(),
+        "--note-to-self".to_string(),
+        "--message".to_string(),
+        message.to_string(),
+    ]
+}
+
+pub fn planned(command: &Commands, cfg: &Config) -> Result<Vec<Vec<String>>> {
+    Ok(match command {
+        Commands::Register {
+            voice, landline, ..
+        } => {
+            if *landline {
+                vec![
+                    register(CAPTCHA_PLACEHOLDER, false),
+                    register(CAPTCHA_PLACEHOLDER, true),
+                ]
+            } else {
+                vec![register(CAPTCHA_PLACEHOLDER, *voice)]
+            }
+        }
+        Commands::Verify { pin, .. } => {
+            vec![verify(CODE_PLACEHOLDER, pin.as_ref().map(|_| PIN_PLACEHOLDER))]
+        }
+        Commands::SetPin => vec![set_pin(PIN_PLACEHOLDER)],
+        Commands::ListDevices => vec![list_devices()],
+        Commands::LinkDesktopLive { sync, .. } => {
+            linking(LINK_URI_PLACEHOLDER, &sync.options(), cfg)
+        }
+        Commands::LinkDesktopUri { uri, sync } => linking(uri, &sync.options(), cfg),
+        Commands::Keepalive { .. } => vec![receive(
+            crate::KEEPALIVE_RECEIVE_TIMEOUT_SECS as i64,
+            Some(crate::KEEPALIVE_RECEIVE_MAX_MESSAGES),
+        )],
+        Commands::Tail { .. } => vec![receive(-1, None)],
+        Commands::DiagnoseSync => {
+            let mut planned = Vec::new();
+            if cfg.daemon_addr.is_none() {
+                planned.push(receive(
+                    cfg.sync.timeout_secs as i64,
+                    Some(cfg.sync.max_messages),
+                ));
+            }
+            planned.extend([send_contacts(), list_devices()]);
+            planned
+        }
+        _ => bail!(
+            "--print-args covers register, verify, set-pin, list-devices, link-desktop-live, link-desktop-uri, keepalive, tail and diagnose-sync"
+        ),
+    })
+}
+
+/// A daemon receives by itself, so it only gets the contacts sync message.
+fn linking(uri: &str, sync: &SyncOptions, cfg: &Config) -> Vec<Vec<String>> {
+    let mut planned = vec![add_device(uri)];
+    if sync.enabled {
+        if cfg.daemon_addr.is_none() {
+            planned.push(receive(sync.timeout_secs as i64, Some(sync.max_messages)));
+        }
+        planned.push(send_contacts());
+    }
+    planned
+}
+
+pub fn shell_join(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| {
+            let plain = !arg.is_empty()
+                && arg
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_./:=@+,%".contains(c));
+            if plain {
+                arg.clone()
+            } else {
+                format!("'{}'", arg.replace('\'', "'\\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,130 @@
+use serde_json::Value;
+use std::process::Output;
+
+use crate::docker::{parse_linked_devices, LinkedDevice};
+
+const ENVELOPE_KINDS: [&str; 7] = [
+    "dataMessage",
+    "syncMessage",
+    "receiptMessage",
+    "typingMessage",
+    "callMessage",
+    "storyMessage",
+    "editMessage",
+];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SignalCliOutput {
+    pub command: String,
+    pub succeeded: bool,
+    pub stdout: String,
+    pub stderr: String,
+    /// stdout lines that parsed as JSON; with `-o json` signal-cli prints one value per line
+    pub json: Vec<Value>,
+}
+
+impl SignalCliOutput {
+    pub fn from_process(command: &str, output: &Output) -> Self {
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let json = stdout
+            .lines()
+            .filter_map(|line| serde_json::from_str::<Value>(line.trim()).ok())
+            .collect();
+        SignalCliOutput {
+            command: command.to_string(),
+            succeeded: output.status.success(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            stdout,
+            json,
+        }
+    }
+
+    /// First non-empty stderr line, the one that says why a command failed.
+    pub fn reason(&self) -> Option<&str> {
+        self.stderr
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+    }
+
+    pub fn failure_reason(&self) -> &str {
+        self.reason().unwrap_or("no error output")
+    }
+
+    pub fn linked_devices(&self) -> Option<Vec<LinkedDevice>> {
+        parse_linked_devices(&self.stdout)
+    }
+
+    pub fn envelopes(&self) -> Vec<Envelope> {
+        self.json.iter().filter_map(Envelope::from_json).collect()
+    }
+
+    pub fn render(&self) -> Option<String> {
+        let stdout = self.stdout.trim();
+        if stdout.is_empty() {
+            return None;
+        }
+        match serde_json::from_str::<Value>(stdout) {
+            Ok(json) if is_empty_result(&self.command, &json) => None,
+            Ok(json) => {
+                Some(serde_json::to_string_pretty(&json).unwrap_or_else(|_| json.to_string()))
+            }
+            Err(_) => Some(stdout.to_string()),
+        }
+    }
+
+    pub fn messages(&self) -> Option<&str> {
+        let stderr = self.stderr.trim();
+        (self.succeeded && !stderr.is_empty()).then_some(stderr)
+    }
+}
+
+fn is_empty_result(command: &str, json: &Value) -> bool {
+    command != "listDevices"
+        && (json.is_null() || json.as_object().is_some_and(|obj| obj.is_empty()))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    pub kind: String,
+    pub source_number: Option<String>,
+    pub source_name: Option<String>,
+    pub source_uuid: Option<String>,
+    pub timestamp_ms: Option<u64>,
+}
+
+impl Envelope {
+    pub fn from_json(json: &Value) -> Option<Self> {
+        let envelope = json.get("envelope")?;
+        let text = |key: &str| {
+            envelope
+                .get(key)
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        };
+        Some(Envelope {
+            kind: ENVELOPE_KINDS
+                .iter()
+                .find(|kind| envelope.get(**kind).is_some())
+                .copied()
+                .unwrap_or("envelope")
+                .to_string(),
+            source_number: text("sourceNumber").or_else(|| text("source")),
+            source_name: text("sourceName").filter(|name| !name.is_empty()),
+            source_uuid: text("sourceUuid"),
+            timestamp_ms: envelope.get("timestamp").and_then(Value::as_u64),
+        })
+    }
+
+    pub fn sender(&self) -> String {
+        match (&self.source_name, &self.source_number) {
+            (Some(name), Some(number)) => format!("{name} ({number})"),
+            (_, Some(number)) => number.clone(),
+            (Some(name), None) => name.clone(),
+            (None, None) => self
+                .source_uuid
+                .clone()
+                .unwrap_or_else(|| "unknown".to_string()),
+        }
+    }
+}
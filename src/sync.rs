@@ -0,0 +1,74 @@
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SyncProgress {
+    pub envelopes: u64,
+    pub sync_messages: u64,
+    pub contacts: u64,
+    pub groups: u64,
+}
+
+impl SyncProgress {
+    pub fn add(&mut self, other: SyncProgress) {
+        self.envelopes += other.envelopes;
+        self.sync_messages += other.sync_messages;
+        self.contacts += other.contacts;
+        self.groups += other.groups;
+    }
+
+    pub fn made_progress(&self) -> bool {
+        self.envelopes > 0
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "{} message(s), {} sync message(s) (contacts: {}, groups: {})",
+            self.envelopes, self.sync_messages, self.contacts, self.groups
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncOptions {
+    pub enabled: bool,
+    pub timeout_secs: u64,
+    pub max_messages: u32,
+    pub restart_desktop: bool,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        SyncOptions {
+            enabled: true,
+            timeout_secs: crate::POST_LINK_RECEIVE_TIMEOUT_SECS,
+            max_messages: crate::POST_LINK_RECEIVE_MAX_MESSAGES,
+            restart_desktop: false,
+        }
+    }
+}
+
+pub fn parse_sync_progress(stdout: &str) -> SyncProgress {
+    let mut progress = SyncProgress::default();
+    for json in stdout
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line.trim()).ok())
+    {
+        let Some(envelope) = json.get("envelope") else {
+            continue;
+        };
+        progress.envelopes += 1;
+
+        let Some(sync) = envelope.get("syncMessage") else {
+            continue;
+        };
+        progress.sync_messages += 1;
+        let kind = sync.get("type").and_then(Value::as_str).unwrap_or("");
+        if kind == "CONTACTS_SYNC" || sync.get("contacts").is_some() {
+            progress.contacts += 1;
+        }
+        if kind == "GROUPS_SYNC" || sync.get("groups").is_some() {
+            progress.groups += 1;
+        }
+    }
+    progress
+}
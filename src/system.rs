@@ -1,3 +1,6 @@
+use anyhow::{bail, Result};
+use std::io::IsTerminal;
+use std::path::{Component, Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::thread;
 use std::time::Duration;
@@ -5,15 +8,169 @@ use std::time::Duration;
 use sysinfo::{ProcessRefreshKind, RefreshKind, System};
 use which::which;
 
+use crate::audit::AuditedCommand;
+use crate::errors::SignalSetupError;
+
+/// The VM running containers under Docker Desktop or Lima cannot mount these.
+const NETWORK_FILESYSTEMS: &[&str] = &[
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "afpfs",
+    "webdav",
+    "davfs",
+    "fuse.sshfs",
+];
+
 pub fn command_exists(name: &str) -> bool {
     which(name).is_ok()
 }
 
+pub fn require_terminal(prompt: &str, hint: &str) -> Result<()> {
+    let interactive = std::io::stdin().is_terminal() && std::io::stderr().is_terminal();
+    check_terminal(interactive, prompt, hint)
+}
+
+pub fn check_terminal(interactive: bool, prompt: &str, hint: &str) -> Result<()> {
+    if interactive {
+        return Ok(());
+    }
+    Err(SignalSetupError::NotInteractive {
+        prompt: prompt.to_string(),
+        hint: hint.to_string(),
+    }
+    .into())
+}
+
+pub fn volume_root(path: &Path) -> Option<PathBuf> {
+    let parts: Vec<&str> = path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => part.to_str(),
+            _ => None,
+        })
+        .collect();
+    if !path.is_absolute() {
+        return None;
+    }
+    let depth = match parts.as_slice() {
+        ["Volumes", _, ..] | ["mnt", _, ..] => 2,
+        ["media", _, _, ..] => 3,
+        ["run", "media", _, _, ..] => 4,
+        _ => return None,
+    };
+    Some(
+        parts[..depth]
+            .iter()
+            .fold(PathBuf::from("/"), |acc, part| acc.join(part)),
+    )
+}
+
+#[cfg(target_os = "linux")]
+pub fn is_volume_mounted(volume: &Path) -> bool {
+    std::fs::read_to_string("/proc/self/mounts")
+        .map(|mounts| mount_points(&mounts).any(|point| point == volume))
+        .unwrap_or_else(|_| volume.exists())
+}
+
+/// /Volumes entries only exist while the volume is mounted.
+#[cfg(not(target_os = "linux"))]
+pub fn is_volume_mounted(volume: &Path) -> bool {
+    volume.exists()
+}
+
+pub fn mount_points(mounts: &str) -> impl Iterator<Item = PathBuf> + '_ {
+    mounts.lines().filter_map(|line| {
+        let raw = line.split_whitespace().nth(1)?;
+        Some(PathBuf::from(decode_mount_field(raw)))
+    })
+}
+
+fn decode_mount_field(raw: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = raw;
+    while let Some(idx) = rest.find('\\') {
+        decoded.push_str(&rest[..idx]);
+        let escape = rest.get(idx + 1..idx + 4);
+        match escape.and_then(|digits| u8::from_str_radix(digits, 8).ok()) {
+            Some(byte) => {
+                decoded.push(byte as char);
+                rest = &rest[idx + 4..];
+            }
+            None => {
+                decoded.push('\\');
+                rest = &rest[idx + 1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+pub fn proc_mount_table(mounts: &str) -> Vec<(PathBuf, String)> {
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace().skip(1);
+            let point = decode_mount_field(fields.next()?);
+            Some((PathBuf::from(point), fields.next()?.to_string()))
+        })
+        .collect()
+}
+
+pub fn bsd_mount_table(output: &str) -> Vec<(PathBuf, String)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (_, rest) = line.split_once(" on ")?;
+            let (point, options) = rest.rsplit_once(" (")?;
+            let fs_type = options.split([',', ')']).next()?.trim();
+            Some((PathBuf::from(point), fs_type.to_string()))
+        })
+        .collect()
+}
+
+pub fn filesystem_type_in<'a>(table: &'a [(PathBuf, String)], path: &Path) -> Option<&'a str> {
+    table
+        .iter()
+        .filter(|(point, _)| path.starts_with(point))
+        .max_by_key(|(point, _)| point.components().count())
+        .map(|(_, fs_type)| fs_type.as_str())
+}
+
+#[cfg(target_os = "linux")]
+pub fn filesystem_type(path: &Path) -> Option<String> {
+    let mounts = std::fs::read_to_string("/proc/self/mounts").ok()?;
+    filesystem_type_in(&proc_mount_table(&mounts), path).map(str::to_string)
+}
+
+#[cfg(target_os = "macos")]
+pub fn filesystem_type(path: &Path) -> Option<String> {
+    let output = Command::new("mount")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .audited_output()
+        .ok()?;
+    let table = bsd_mount_table(&String::from_utf8_lossy(&output.stdout));
+    filesystem_type_in(&table, path).map(str::to_string)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn filesystem_type(_path: &Path) -> Option<String> {
+    None
+}
+
+pub fn is_network_filesystem(fs_type: &str) -> bool {
+    NETWORK_FILESYSTEMS.contains(&fs_type.to_lowercase().as_str())
+}
+
 pub fn open_url_in_default_browser(url: &str) {
     #[cfg(target_os = "macos")]
     {
         if command_exists("open") {
-            let _ = Command::new("open").arg(url).status();
+            let _ = Command::new("open").arg(url).audited_status();
             return;
         }
     }
@@ -21,7 +178,7 @@ pub fn open_url_in_default_browser(url: &str) {
     #[cfg(not(target_os = "macos"))]
     {
         if command_exists("xdg-open") {
-            let _ = Command::new("xdg-open").arg(url).status();
+            let _ = Command::new("xdg-open").arg(url).audited_status();
             return;
         }
     }
@@ -29,13 +186,186 @@ pub fn open_url_in_default_browser(url: &str) {
     let _ = open::that(url);
 }
 
+#[derive(Debug, PartialEq, Eq)]
+pub struct TerminalApp {
+    pub name: &'static str,
+    pub path: &'static str,
+    bundle_id: &'static str,
+    term_program: Option<&'static str>,
+    /// Variable only this terminal sets, for those without `TERM_PROGRAM`
+    marker_var: Option<&'static str>,
+}
+
+const TERMINAL_APPS: &[TerminalApp] = &[
+    TerminalApp {
+        name: "Terminal",
+        path: "/System/Applications/Utilities/Terminal.app",
+        bundle_id: "com.apple.Terminal",
+        term_program: Some("Apple_Terminal"),
+        marker_var: None,
+    },
+    TerminalApp {
+        name: "iTerm2",
+        path: "/Applications/iTerm.app",
+        bundle_id: "com.googlecode.iterm2",
+        term_program: Some("iTerm.app"),
+        marker_var: Some("ITERM_SESSION_ID"),
+    },
+    TerminalApp {
+        name: "Visual Studio Code",
+        path: "/Applications/Visual Studio Code.app",
+        bundle_id: "com.microsoft.VSCode",
+        term_program: Some("vscode"),
+        marker_var: None,
+    },
+    TerminalApp {
+        name: "Alacritty",
+        path: "/Applications/Alacritty.app",
+        bundle_id: "org.alacritty",
+        term_program: None,
+        marker_var: Some("ALACRITTY_SOCKET"),
+    },
+    TerminalApp {
+        name: "WezTerm",
+        path: "/Applications/WezTerm.app",
+        bundle_id: "com.github.wez.wezterm",
+        term_program: Some("WezTerm"),
+        marker_var: None,
+    },
+    TerminalApp {
+        name: "kitty",
+        path: "/Applications/kitty.app",
+        bundle_id: "net.kovidgoyal.kitty",
+        term_program: None,
+        marker_var: Some("KITTY_WINDOW_ID"),
+    },
+    TerminalApp {
+        name: "Warp",
+        path: "/Applications/Warp.app",
+        bundle_id: "dev.warp.Warp-Stable",
+        term_program: Some("WarpTerminal"),
+        marker_var: None,
+    },
+    TerminalApp {
+        name: "Ghostty",
+        path: "/Applications/Ghostty.app",
+        bundle_id: "com.mitchellh.ghostty",
+        term_program: Some("ghostty"),
+        marker_var: None,
+    },
+];
+
+pub fn terminal_app() -> Option<&'static TerminalApp> {
+    terminal_app_from(|key| std::env::var(key).ok().filter(|value| !value.is_empty()))
+}
+
+/// tmux and screen replace `TERM_PROGRAM` but keep the app bundle macOS exports.
+pub fn terminal_app_from(env: impl Fn(&str) -> Option<String>) -> Option<&'static TerminalApp> {
+    if let Some(bundle_id) = env("__CFBundleIdentifier") {
+        if let Some(app) = TERMINAL_APPS.iter().find(|app| app.bundle_id == bundle_id) {
+            return Some(app);
+        }
+    }
+    if let Some(program) = env("TERM_PROGRAM") {
+        if let Some(app) = TERMINAL_APPS
+            .iter()
+            .find(|app| app.term_program == Some(program.as_str()))
+        {
+            return Some(app);
+        }
+    }
+    TERMINAL_APPS
+        .iter()
+        .find(|app| app.marker_var.is_some_and(|var| env(var).is_some()))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScreenAccessProblem {
+    /// Neither DISPLAY nor WAYLAND_DISPLAY is set: a text console, or SSH without X forwarding
+    NoGraphicalSession { over_ssh: bool },
+    /// Wayland only shares the screen through xdg-desktop-portal, and it is not available
+    NoScreenPortal,
+    /// DISPLAY names a local X server without a socket, often left over from another session
+    X11Unreachable { display: String },
+}
+
+impl ScreenAccessProblem {
+    pub fn describe(&self) -> String {
+        match self {
+            ScreenAccessProblem::NoGraphicalSession { over_ssh: true } => "this is an SSH session without a display (DISPLAY and WAYLAND_DISPLAY are unset), and Signal Desktop's screen is not reachable from it".to_string(),
+            ScreenAccessProblem::NoGraphicalSession { over_ssh: false } => "there is no graphical session (DISPLAY and WAYLAND_DISPLAY are unset)".to_string(),
+            ScreenAccessProblem::NoScreenPortal => "this Wayland session has no xdg-desktop-portal, which Wayland requires for screen capture; install it with the backend for your desktop, such as xdg-desktop-portal-gnome or xdg-desktop-portal-kde".to_string(),
+            ScreenAccessProblem::X11Unreachable { display } => format!(
+                "DISPLAY is {display}, but no X server runs there (its /tmp/.X11-unix socket is missing); the variable may be left over from another session"
+            ),
+        }
+    }
+}
+
+pub fn screen_access_problem() -> Option<ScreenAccessProblem> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    screen_access_problem_from(
+        |key| std::env::var(key).ok().filter(|value| !value.is_empty()),
+        screen_portal_available,
+        |display| Path::new(&format!("/tmp/.X11-unix/X{display}")).exists(),
+    )
+}
+
+pub fn screen_access_problem_from(
+    env: impl Fn(&str) -> Option<String>,
+    portal_available: impl FnOnce() -> bool,
+    x11_socket_exists: impl FnOnce(u32) -> bool,
+) -> Option<ScreenAccessProblem> {
+    let display = env("DISPLAY");
+    if env("WAYLAND_DISPLAY").is_none() && display.is_none() {
+        return Some(ScreenAccessProblem::NoGraphicalSession {
+            over_ssh: env("SSH_CONNECTION").is_some() || env("SSH_TTY").is_some(),
+        });
+    }
+    let wayland =
+        env("WAYLAND_DISPLAY").is_some() || env("XDG_SESSION_TYPE").as_deref() == Some("wayland");
+    if wayland {
+        return (!portal_available()).then_some(ScreenAccessProblem::NoScreenPortal);
+    }
+    let display = display?;
+    local_display_number(&display)
+        .filter(|number| !x11_socket_exists(*number))
+        .map(|_| ScreenAccessProblem::X11Unreachable { display })
+}
+
+/// Forwarded displays like `localhost:10.0` have no socket to look for.
+pub fn local_display_number(display: &str) -> Option<u32> {
+    let rest = display
+        .strip_prefix("unix:")
+        .or_else(|| display.strip_prefix(':'))?;
+    rest.split('.').next()?.parse().ok()
+}
+
+/// The portal runs, or D-Bus can start it on the first request.
+fn screen_portal_available() -> bool {
+    if process_running_fuzzy("xdg-desktop-portal") {
+        return true;
+    }
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    data_dirs.split(':').any(|dir| {
+        Path::new(dir)
+            .join("dbus-1/services/org.freedesktop.portal.Desktop.service")
+            .exists()
+    })
+}
+
 pub fn open_screen_recording_settings() {
     #[cfg(target_os = "macos")]
     {
         let settings_url =
             "x-apple.systempreferences:com.apple.preference.security?Privacy_ScreenCapture";
         if command_exists("open") {
-            let _ = Command::new("open").arg(settings_url).status();
+            let _ = Command::new("open").arg(settings_url).audited_status();
             return;
         }
         let _ = open::that(settings_url);
@@ -130,6 +460,117 @@ pub fn is_signal_desktop_running() -> bool {
         || process_running_fuzzy("signal-desktop")
 }
 
+pub fn quit_signal_desktop() -> Result<()> {
+    #[cfg(target_os = "macos")]
+    let asked = Command::new("osascript")
+        .args(["-e", "quit app \"Signal\""])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .audited_status()
+        .is_ok_and(|s| s.success());
+
+    #[cfg(not(target_os = "macos"))]
+    let asked = ["signal-desktop", "Signal"].iter().any(|name| {
+        Command::new("pkill")
+            .args(["-TERM", "-x", name])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .audited_status()
+            .is_ok_and(|s| s.success())
+    });
+
+    if !asked {
+        bail!("could not ask Signal Desktop to quit; quit it yourself and reopen it")
+    }
+    for _ in 0..crate::SIGNAL_QUIT_WAIT_LOOPS {
+        if !is_signal_desktop_running() {
+            return Ok(());
+        }
+        thread::sleep(Duration::from_millis(crate::SIGNAL_LAUNCH_WAIT_MS.max(1)));
+    }
+    bail!(
+        "Signal Desktop is still running after being asked to quit; quit it yourself and reopen it"
+    )
+}
+
+pub fn restart_signal_desktop() -> Result<()> {
+    if is_signal_desktop_running() {
+        quit_signal_desktop()?;
+    }
+    if !open_signal_desktop() {
+        bail!("Signal Desktop quit but could not be reopened; start it yourself")
+    }
+    Ok(())
+}
+
+pub fn signal_desktop_version() -> Option<String> {
+    #[cfg(target_os = "macos")]
+    {
+        let mut apps = vec![PathBuf::from("/Applications/Signal.app")];
+        if let Some(home) = dirs::home_dir() {
+            apps.push(home.join("Applications/Signal.app"));
+        }
+        apps.iter().find_map(|app| {
+            let plist = std::fs::read_to_string(app.join("Contents/Info.plist")).ok()?;
+            parse_info_plist_version(&plist)
+        })
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    {
+        let queries: [(&str, &[&str]); 3] = [
+            ("dpkg-query", &["-W", "-f=${Version}", "signal-desktop"]),
+            ("rpm", &["-q", "--qf", "%{VERSION}", "signal-desktop"]),
+            ("flatpak", &["info", "org.signal.Signal"]),
+        ];
+        queries.iter().find_map(|(program, args)| {
+            let output = Command::new(program)
+                .args(*args)
+                .stderr(Stdio::null())
+                .audited_output()
+                .ok()
+                .filter(|output| output.status.success())?;
+            let text = String::from_utf8_lossy(&output.stdout);
+            if *program == "flatpak" {
+                parse_flatpak_version(&text)
+            } else {
+                Some(text.trim().to_string()).filter(|version| !version.is_empty())
+            }
+        })
+    }
+}
+
+pub fn parse_info_plist_version(plist: &str) -> Option<String> {
+    let (_, rest) = plist.split_once("<key>CFBundleShortVersionString</key>")?;
+    let (_, rest) = rest.split_once("<string>")?;
+    let (version, _) = rest.split_once("</string>")?;
+    Some(version.trim().to_string()).filter(|version| !version.is_empty())
+}
+
+pub fn parse_flatpak_version(info: &str) -> Option<String> {
+    info.lines()
+        .find_map(|line| line.trim().strip_prefix("Version:"))
+        .map(|version| version.trim().to_string())
+        .filter(|version| !version.is_empty())
+}
+
+/// Older releases refuse new links or stall on "Syncing contacts and groups".
+pub fn desktop_version_problem(version: &str) -> Option<String> {
+    let found = crate::docker::parse_signal_cli_version(version)?;
+    let (major, minor, patch) = crate::MIN_SIGNAL_DESKTOP_VERSION;
+    if found >= (major, minor, patch) {
+        return None;
+    }
+    Some(format!(
+        "Signal Desktop {version} is older than {major}.{minor}.{patch} and may refuse to link or stay on \"Syncing contacts and groups\". Update it before scanning the QR code."
+    ))
+}
+
+/// AppImage and tarball installs are only found on PATH.
+pub fn signal_desktop_installed() -> bool {
+    signal_desktop_version().is_some() || command_exists("signal-desktop")
+}
+
 pub fn open_signal_desktop() -> bool {
     if is_signal_desktop_running() {
         return true;
@@ -142,7 +583,7 @@ pub fn open_signal_desktop() -> bool {
         if command_exists("open")
             && Command::new("open")
                 .args(["-a", "Signal"])
-                .status()
+                .audited_status()
                 .is_ok_and(|s| s.success())
         {
             launch_attempted = true;
@@ -150,7 +591,7 @@ pub fn open_signal_desktop() -> bool {
         if command_exists("open")
             && Command::new("open")
                 .args(["-a", "Signal Desktop"])
-                .status()
+                .audited_status()
                 .is_ok_and(|s| s.success())
         {
             launch_attempted = true;
@@ -158,7 +599,7 @@ pub fn open_signal_desktop() -> bool {
         if command_exists("open")
             && Command::new("open")
                 .arg("/Applications/Signal.app")
-                .status()
+                .audited_status()
                 .is_ok_and(|s| s.success())
         {
             launch_attempted = true;
@@ -175,7 +616,7 @@ pub fn open_signal_desktop() -> bool {
     if Command::new("signal-desktop")
         .stdout(Stdio::null())
         .stderr(Stdio::null())
-        .spawn()
+        .audited_spawn()
         .is_ok()
     {
         launch_attempted = true;
@@ -184,7 +625,7 @@ pub fn open_signal_desktop() -> bool {
     if Command::new("signal")
         .stdout(Stdio::null())
         .stderr(Stdio::null())
-        .spawn()
+        .audited_spawn()
         .is_ok()
     {
         launch_attempted = true;
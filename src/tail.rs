@@ -0,0 +1,97 @@
+use anyhow::{bail, Result};
+use serde_json::Value;
+use std::io::{BufRead, BufReader};
+use std::time::Duration;
+
+use crate::audit;
+use crate::command_queue;
+use crate::config::Config;
+use crate::docker::spawn_signal_cli_streaming;
+use crate::shutdown::{forward_terminate_to, sleep_unless_terminated, terminate_requested};
+use crate::signal_args;
+use crate::signal_output::Envelope;
+
+pub fn run_tail(cfg: &Config, count: Option<u64>, raw: bool) -> Result<()> {
+    let receive_args = signal_args::receive(-1, None);
+
+    let mut seen = 0_u64;
+    let mut failures = 0_u32;
+    loop {
+        // Held while receive runs, so keepalive and sends for the account wait for it.
+        let turn = command_queue::wait_turn(cfg)?;
+        let mut child = spawn_signal_cli_streaming(cfg, &receive_args)?;
+        let forwarder = forward_terminate_to(&child);
+        if let Some(stdout) = child.stdout.take() {
+            for line in BufReader::new(stdout).lines() {
+                let line = line?;
+                let Ok(json) = serde_json::from_str::<Value>(line.trim()) else {
+                    continue;
+                };
+                let Some(formatted) = format_envelope(&json) else {
+                    continue;
+                };
+                println!("{}", if raw { line.trim() } else { formatted.as_str() });
+                seen += 1;
+                failures = 0;
+                if count.is_some_and(|max| seen >= max) {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Ok(());
+                }
+            }
+        }
+
+        drop(forwarder);
+        let status = child.wait()?;
+        audit::record_exit(child.id(), &status);
+        drop(turn);
+        if terminate_requested() {
+            return Ok(());
+        }
+        if !status.success() {
+            failures += 1;
+            if failures >= crate::TAIL_MAX_FAILURES {
+                bail!("receive failed {failures} times in a row")
+            }
+            eprintln!("Warning: receive exited with {status}. Restarting...");
+        }
+        if sleep_unless_terminated(Duration::from_secs(crate::TAIL_RESTART_DELAY_SECS)).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+pub fn format_envelope(json: &Value) -> Option<String> {
+    let envelope = Envelope::from_json(json)?;
+    let time = envelope
+        .timestamp_ms
+        .map(|millis| format_unix_utc(millis / 1000))
+        .unwrap_or_else(|| "unknown time".to_string());
+    Some(format!(
+        "{time}  {:<14} from {}",
+        envelope.kind,
+        envelope.sender()
+    ))
+}
+
+pub fn format_unix_utc(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    // Civil-from-days conversion (proleptic Gregorian calendar).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
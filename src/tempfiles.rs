@@ -0,0 +1,116 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, Once};
+use std::time::Duration;
+use tempfile::TempDir;
+
+/// For the panic hook, which runs even when unwinding is skipped.
+static LIVE_SCAN_DIRS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+static PANIC_CLEANUP: Once = Once::new();
+
+/// The fixed name prefix lets the next run remove dirs a crashed run left behind.
+pub struct ScanDir {
+    dir: TempDir,
+}
+
+impl ScanDir {
+    pub fn create() -> Result<Self> {
+        Self::create_in(&std::env::temp_dir())
+    }
+
+    pub fn create_in(root: &Path) -> Result<Self> {
+        let dir = tempfile::Builder::new()
+            .prefix(crate::SCAN_TEMP_PREFIX)
+            .tempdir_in(root)
+            .context("failed to create temporary directory")?;
+        live_scan_dirs().push(dir.path().to_path_buf());
+        Ok(ScanDir { dir })
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+}
+
+impl Drop for ScanDir {
+    fn drop(&mut self) {
+        live_scan_dirs().retain(|path| path != self.dir.path());
+    }
+}
+
+fn live_scan_dirs() -> std::sync::MutexGuard<'static, Vec<PathBuf>> {
+    LIVE_SCAN_DIRS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+pub fn install_panic_cleanup() {
+    PANIC_CLEANUP.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if let Ok(dirs) = LIVE_SCAN_DIRS.try_lock() {
+                for dir in dirs.iter() {
+                    let _ = fs::remove_dir_all(dir);
+                }
+            }
+            previous(info);
+        }));
+    });
+}
+
+/// Includes the unprefixed `.tmp*` dirs that older versions left behind.
+pub fn cleanup_stale_scan_dirs(root: &Path, max_age: Duration) -> usize {
+    let Ok(entries) = fs::read_dir(root) else {
+        return 0;
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| is_stale_scan_dir(path, max_age))
+        .filter(|path| fs::remove_dir_all(path).is_ok())
+        .count()
+}
+
+fn is_stale_scan_dir(path: &Path, max_age: Duration) -> bool {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return false;
+    };
+    let old_enough = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|age| age >= max_age);
+    if !metadata.is_dir() || !old_enough {
+        return false;
+    }
+
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    if name.starts_with(crate::SCAN_TEMP_PREFIX) {
+        return true;
+    }
+    name.starts_with(".tmp") && holds_only_screenshots(path)
+}
+
+fn holds_only_screenshots(dir: &Path) -> bool {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return false;
+    };
+    let mut found = false;
+    for entry in entries {
+        let Ok(entry) = entry else {
+            return false;
+        };
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let is_file = entry.file_type().is_ok_and(|kind| kind.is_file());
+        if !(is_file && name.starts_with("screen-") && name.ends_with(".png")) {
+            return false;
+        }
+        found = true;
+    }
+    found
+}
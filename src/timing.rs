@@ -0,0 +1,96 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+static STAGES: Mutex<Vec<(&'static str, Duration)>> = Mutex::new(Vec::new());
+
+pub fn measure<T>(stage: &'static str, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    record(stage, start.elapsed());
+    result
+}
+
+pub fn record(stage: &'static str, elapsed: Duration) {
+    let mut stages = STAGES
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    match stages.iter_mut().find(|(name, _)| *name == stage) {
+        Some((_, total)) => *total += elapsed,
+        None => stages.push((stage, elapsed)),
+    }
+}
+
+pub fn take_stages() -> Vec<(&'static str, Duration)> {
+    let mut stages = STAGES
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    std::mem::take(&mut *stages)
+}
+
+pub fn render_summary(stages: &[(&'static str, Duration)]) -> String {
+    let width = stages
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0)
+        .max("total".len());
+    let mut out = String::from("Timing:\n");
+    for (name, elapsed) in stages {
+        out.push_str(&format!(
+            "  {name:<width$}  {}\n",
+            format_stage_duration(*elapsed)
+        ));
+    }
+    let total: Duration = stages.iter().map(|(_, elapsed)| *elapsed).sum();
+    out.push_str(&format!(
+        "  {:<width$}  {}",
+        "total",
+        format_stage_duration(total)
+    ));
+    out
+}
+
+pub fn format_stage_duration(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    if secs >= 60 {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{:.1}s", elapsed.as_secs_f64())
+    }
+}
+
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration '{value}', expected e.g. 90s, 30m or 1h30m");
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(invalid());
+    }
+    let mut total = 0u64;
+    let mut digits = String::new();
+    for c in value.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            _ => return Err(invalid()),
+        };
+        let amount: u64 = digits.parse().map_err(|_| invalid())?;
+        total = amount
+            .checked_mul(unit)
+            .and_then(|secs| total.checked_add(secs))
+            .ok_or_else(invalid)?;
+        digits.clear();
+    }
+    if !digits.is_empty() {
+        let amount: u64 = digits.parse().map_err(|_| invalid())?;
+        total = total.checked_add(amount).ok_or_else(invalid)?;
+    }
+    if total == 0 {
+        return Err("the duration must be longer than 0s".to_string());
+    }
+    Ok(Duration::from_secs(total))
+}
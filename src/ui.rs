@@ -0,0 +1,394 @@
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use dialoguer::console::{style, Term};
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::{Confirm, FuzzySelect, Input, Select};
+use indicatif::{ProgressBar, ProgressStyle};
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Screen readers cannot follow redrawn widgets.
+static ACCESSIBLE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_accessible(enabled: bool) {
+    ACCESSIBLE.store(enabled, Ordering::SeqCst);
+}
+
+pub fn accessible() -> bool {
+    ACCESSIBLE.load(Ordering::SeqCst)
+}
+
+static ASCII: AtomicBool = AtomicBool::new(false);
+const ASCII_SPINNER: &str = "|/-\\ ";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Charset {
+    /// ASCII when the locale is set and is not UTF-8, Unicode otherwise
+    #[default]
+    Auto,
+    Unicode,
+    /// Plain ASCII prompts, spinners and progress bars
+    Ascii,
+}
+
+impl Charset {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Charset::Auto => "auto",
+            Charset::Unicode => "unicode",
+            Charset::Ascii => "ascii",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(Charset::Auto),
+            "unicode" => Some(Charset::Unicode),
+            "ascii" => Some(Charset::Ascii),
+            _ => None,
+        }
+    }
+}
+
+static NARROW: AtomicBool = AtomicBool::new(false);
+
+pub fn set_narrow(enabled: bool) {
+    NARROW.store(enabled, Ordering::SeqCst);
+}
+
+pub fn narrow() -> bool {
+    NARROW.load(Ordering::SeqCst)
+}
+
+pub fn terminal_columns() -> Option<u16> {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.trim().parse::<u16>().ok())
+        .filter(|columns| *columns > 0)
+        .or_else(|| Term::stdout().size_checked().map(|(_, columns)| columns))
+}
+
+pub fn is_narrow(columns: Option<u16>) -> bool {
+    columns.is_some_and(|columns| columns < crate::NARROW_TERMINAL_COLUMNS)
+}
+
+pub fn narrow_template(template: &str) -> String {
+    let template = template
+        .replace("[{elapsed_precise}] ", "")
+        .replace("{msg}", "{wide_msg}");
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template.as_str();
+    while let Some(start) = rest.find("{bar:") {
+        let (before, after) = rest.split_at(start + "{bar:".len());
+        out.push_str(before);
+        let digits = after.chars().take_while(char::is_ascii_digit).count();
+        let (width, after) = after.split_at(digits);
+        match width.parse::<usize>() {
+            Ok(width) => out.push_str(&width.min(crate::NARROW_BAR_WIDTH).to_string()),
+            Err(_) => out.push_str(width),
+        }
+        rest = after;
+    }
+    out.push_str(rest);
+    out
+}
+
+pub fn wrap(text: &str) -> String {
+    if !narrow() {
+        return text.to_string();
+    }
+    let columns = terminal_columns().unwrap_or(crate::NARROW_TERMINAL_COLUMNS);
+    wrap_words(text, usize::from(columns).saturating_sub(1))
+}
+
+pub fn wrap_words(text: &str, width: usize) -> String {
+    let width = width.max(crate::MIN_WRAP_WIDTH);
+    let mut out = Vec::new();
+    for line in text.lines() {
+        let mut current = String::new();
+        for word in line.split_whitespace() {
+            if !current.is_empty() && current.chars().count() + 1 + word.chars().count() > width {
+                out.push(std::mem::take(&mut current));
+            }
+            if !current.is_empty() {
+                current.push(' ');
+            }
+            current.push_str(word);
+        }
+        out.push(current);
+    }
+    out.join("\n")
+}
+
+pub fn set_ascii(enabled: bool) {
+    ASCII.store(enabled, Ordering::SeqCst);
+}
+
+pub fn ascii() -> bool {
+    ASCII.load(Ordering::SeqCst)
+}
+
+pub fn current_locale() -> Option<String> {
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|key| std::env::var(key).ok())
+        .find(|value| !value.is_empty())
+}
+
+pub fn use_ascii(charset: Charset, locale: Option<&str>) -> bool {
+    match charset {
+        Charset::Ascii => true,
+        Charset::Unicode => false,
+        Charset::Auto => locale.is_some_and(|locale| {
+            let locale = locale.to_lowercase();
+            !(locale.contains("utf-8") || locale.contains("utf8"))
+        }),
+    }
+}
+
+pub fn theme() -> ColorfulTheme {
+    let theme = ColorfulTheme::default();
+    if !ascii() {
+        return theme;
+    }
+    ColorfulTheme {
+        prompt_suffix: style(">".to_string()).for_stderr().black().bright(),
+        success_prefix: style("+".to_string()).for_stderr().green(),
+        success_suffix: style("-".to_string()).for_stderr().black().bright(),
+        error_prefix: style("x".to_string()).for_stderr().red(),
+        active_item_prefix: style(">".to_string()).for_stderr().green(),
+        checked_item_prefix: style("[x]".to_string()).for_stderr().green(),
+        unchecked_item_prefix: style("[ ]".to_string()).for_stderr().magenta(),
+        picked_item_prefix: style(">".to_string()).for_stderr().green(),
+        ..theme
+    }
+}
+
+pub fn select<T: ToString>(
+    theme: &ColorfulTheme,
+    prompt: &str,
+    items: &[T],
+    default: usize,
+) -> Result<usize> {
+    if accessible() {
+        return numbered_select(
+            prompt,
+            items,
+            default,
+            &mut io::stdin().lock(),
+            &mut io::stdout(),
+        );
+    }
+    Ok(Select::with_theme(theme)
+        .with_prompt(wrap(prompt))
+        .items(items)
+        .default(default)
+        .interact()?)
+}
+
+pub fn fuzzy_select<T: ToString>(
+    theme: &ColorfulTheme,
+    prompt: &str,
+    items: &[T],
+    default: usize,
+) -> Result<usize> {
+    if accessible() {
+        return numbered_select(
+            prompt,
+            items,
+            default,
+            &mut io::stdin().lock(),
+            &mut io::stdout(),
+        );
+    }
+    Ok(FuzzySelect::with_theme(theme)
+        .with_prompt(wrap(&format!("{prompt} (type to filter)")))
+        .items(items)
+        .default(default)
+        .interact()?)
+}
+
+pub fn confirm(theme: &ColorfulTheme, prompt: &str, default: bool) -> Result<bool> {
+    if accessible() {
+        return text_confirm(prompt, default, &mut io::stdin().lock(), &mut io::stdout());
+    }
+    Ok(Confirm::with_theme(theme)
+        .with_prompt(prompt)
+        .default(default)
+        .interact()?)
+}
+
+pub fn input(theme: &ColorfulTheme, prompt: &str) -> Result<String> {
+    if accessible() {
+        return text_input(prompt, &mut io::stdin().lock(), &mut io::stdout());
+    }
+    Ok(Input::<String>::with_theme(theme)
+        .with_prompt(prompt)
+        .interact_text()?)
+}
+
+pub fn numbered_select<T: ToString>(
+    prompt: &str,
+    items: &[T],
+    default: usize,
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+) -> Result<usize> {
+    if items.is_empty() {
+        bail!("no choices for '{prompt}'")
+    }
+    let default = default.min(items.len() - 1);
+    writeln!(out, "{prompt}")?;
+    for (index, item) in items.iter().enumerate() {
+        writeln!(out, "  {}. {}", index + 1, item.to_string())?;
+    }
+    loop {
+        write!(
+            out,
+            "Enter a number from 1 to {} (default {}): ",
+            items.len(),
+            default + 1
+        )?;
+        out.flush()?;
+        let answer = read_answer(prompt, input)?;
+        if answer.is_empty() {
+            return Ok(default);
+        }
+        match answer.parse::<usize>() {
+            Ok(number) if (1..=items.len()).contains(&number) => return Ok(number - 1),
+            _ => writeln!(out, "'{answer}' is not one of the numbers listed.")?,
+        }
+    }
+}
+
+pub fn text_confirm(
+    prompt: &str,
+    default: bool,
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+) -> Result<bool> {
+    let default_word = if default { "yes" } else { "no" };
+    loop {
+        write!(out, "{prompt} Answer yes or no (default {default_word}): ")?;
+        out.flush()?;
+        let answer = read_answer(prompt, input)?.to_lowercase();
+        match answer.as_str() {
+            "" => return Ok(default),
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => writeln!(out, "Please answer yes or no.")?,
+        }
+    }
+}
+
+pub fn text_input(prompt: &str, input: &mut impl BufRead, out: &mut impl Write) -> Result<String> {
+    loop {
+        write!(out, "{prompt}: ")?;
+        out.flush()?;
+        let answer = read_answer(prompt, input)?;
+        if !answer.is_empty() {
+            return Ok(answer);
+        }
+        writeln!(out, "An answer is required.")?;
+    }
+}
+
+fn read_answer(prompt: &str, input: &mut impl BufRead) -> Result<String> {
+    let mut line = String::new();
+    let read = input
+        .read_line(&mut line)
+        .with_context(|| format!("failed to read the answer to '{prompt}'"))?;
+    if read == 0 {
+        bail!("input closed before answering '{prompt}'")
+    }
+    Ok(line.trim().to_string())
+}
+
+pub struct Progress {
+    bar: Option<ProgressBar>,
+    last_message: Mutex<String>,
+}
+
+impl Progress {
+    pub fn new(len: u64, template: &str) -> Self {
+        if accessible() {
+            return Self {
+                bar: None,
+                last_message: Mutex::new(String::new()),
+            };
+        }
+        let bar = ProgressBar::new(len);
+        let template = if narrow() {
+            narrow_template(template)
+        } else {
+            template.to_string()
+        };
+        let mut bar_style = ProgressStyle::with_template(&template)
+            .unwrap_or_else(|_| ProgressStyle::default_bar())
+            .progress_chars("=> ");
+        if ascii() {
+            bar_style = bar_style.tick_chars(ASCII_SPINNER);
+        }
+        bar.set_style(bar_style);
+        bar.enable_steady_tick(Duration::from_millis(120));
+        Self {
+            bar: Some(bar),
+            last_message: Mutex::new(String::new()),
+        }
+    }
+
+    pub fn set_message(&self, message: impl Into<String>) {
+        let message = message.into();
+        match &self.bar {
+            Some(bar) => bar.set_message(message),
+            None => self.announce(message),
+        }
+    }
+
+    pub fn set_length(&self, len: u64) {
+        if let Some(bar) = &self.bar {
+            bar.set_length(len);
+        }
+    }
+
+    pub fn set_position(&self, position: u64) {
+        if let Some(bar) = &self.bar {
+            bar.set_position(position);
+        }
+    }
+
+    pub fn inc(&self, delta: u64) {
+        if let Some(bar) = &self.bar {
+            bar.inc(delta);
+        }
+    }
+
+    pub fn finish_with_message(&self, message: impl Into<String>) {
+        let message = message.into();
+        match &self.bar {
+            Some(bar) => bar.finish_with_message(message),
+            None => self.announce(message),
+        }
+    }
+
+    pub fn abandon_with_message(&self, message: impl Into<String>) {
+        let message = message.into();
+        match &self.bar {
+            Some(bar) => bar.abandon_with_message(message),
+            None => self.announce(message),
+        }
+    }
+
+    fn announce(&self, message: String) {
+        let mut last = self
+            .last_message
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if *last != message {
+            println!("{message}");
+            *last = message;
+        }
+    }
+}
@@ -0,0 +1,315 @@
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+
+use crate::audit::{self, AuditedCommand};
+use crate::config::Config;
+use crate::errors::SignalSetupError;
+use crate::system::command_exists;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VaultPaths {
+    pub archive: PathBuf,
+    pub identity: PathBuf,
+    pub recipient: PathBuf,
+    /// Written once the archive is unpacked; a data dir without it was not unlocked by us.
+    pub marker: PathBuf,
+}
+
+impl VaultPaths {
+    pub fn for_data_dir(data_dir: &Path) -> Self {
+        let sibling = |suffix: &str| {
+            let mut name = data_dir.as_os_str().to_os_string();
+            name.push(suffix);
+            PathBuf::from(name)
+        };
+        VaultPaths {
+            archive: sibling(".tar.age"),
+            identity: sibling(".key.age"),
+            recipient: sibling(".recipient"),
+            marker: sibling(".unlocked"),
+        }
+    }
+
+    pub fn exists(&self) -> bool {
+        self.archive.exists()
+    }
+}
+
+/// Data dirs unlocked by this process, for exits that skip `Drop`.
+static UNLOCKED: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Re-encrypted when the last config clone is dropped.
+#[derive(Debug)]
+pub struct VaultSession {
+    data_dir: PathBuf,
+    paths: VaultPaths,
+    relock: bool,
+}
+
+impl VaultSession {
+    fn new(data_dir: &Path, paths: VaultPaths) -> Self {
+        unlocked().push(data_dir.to_path_buf());
+        VaultSession {
+            data_dir: data_dir.to_path_buf(),
+            paths,
+            relock: true,
+        }
+    }
+}
+
+impl Drop for VaultSession {
+    fn drop(&mut self) {
+        let mut unlocked = unlocked();
+        let Some(index) = unlocked.iter().position(|dir| *dir == self.data_dir) else {
+            // Already re-encrypted by `lock_all`.
+            return;
+        };
+        unlocked.remove(index);
+        drop(unlocked);
+        if self.relock {
+            relock(&self.data_dir, &self.paths);
+        }
+    }
+}
+
+fn unlocked() -> std::sync::MutexGuard<'static, Vec<PathBuf>> {
+    UNLOCKED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn relock(data_dir: &Path, paths: &VaultPaths) {
+    match lock_data_dir(data_dir, paths) {
+        Ok(()) => println!("Data dir re-encrypted."),
+        Err(err) => eprintln!(
+            "Warning: failed to re-encrypt the data dir: {err}. The plaintext copy is still at {}.",
+            data_dir.display()
+        ),
+    }
+}
+
+pub fn lock_all() {
+    let unlocked = std::mem::take(&mut *unlocked());
+    for data_dir in unlocked {
+        let paths = VaultPaths::for_data_dir(&data_dir);
+        relock(&data_dir, &paths);
+    }
+}
+
+pub fn open_vault(cfg: &mut Config) -> Result<()> {
+    let paths = VaultPaths::for_data_dir(&cfg.data_dir);
+    if !paths.exists() || cfg.vault.is_some() {
+        return Ok(());
+    }
+
+    cfg.vault = Some(Arc::new(unlock_data_dir(&cfg.data_dir, paths)?));
+    Ok(())
+}
+
+pub fn ensure_unlocked(cfg: &Config) -> Result<()> {
+    if cfg.vault.is_none() && VaultPaths::for_data_dir(&cfg.data_dir).exists() {
+        bail!(
+            "the data dir {} is encrypted and this command does not unlock it",
+            cfg.data_dir.display()
+        )
+    }
+    Ok(())
+}
+
+pub fn ensure_not_encrypted(cfg: &Config, what: &str) -> Result<()> {
+    if VaultPaths::for_data_dir(&cfg.data_dir).exists() {
+        bail!("the data dir is encrypted; {what} needs a plaintext data dir (run 'vault disable' first)")
+    }
+    Ok(())
+}
+
+pub fn enable_vault(data_dir: &Path) -> Result<()> {
+    require_age()?;
+    let paths = VaultPaths::for_data_dir(data_dir);
+    if paths.exists() {
+        bail!(
+            "the data dir is already encrypted ({})",
+            paths.archive.display()
+        )
+    }
+    if !data_dir.is_dir() {
+        bail!("data dir not found: {}", data_dir.display())
+    }
+
+    let keygen = Command::new("age-keygen")
+        .stdin(Stdio::null())
+        .audited_output()
+        .context("failed to run age-keygen")?;
+    if !keygen.status.success() {
+        bail!("age-keygen failed")
+    }
+    let identity = String::from_utf8_lossy(&keygen.stdout).to_string();
+    let Some(recipient) = parse_age_recipient(&identity) else {
+        bail!("age-keygen did not print a public key")
+    };
+
+    println!("Choose a passphrase protecting the data dir key.");
+    let mut child = Command::new("age")
+        .arg("-p")
+        .arg("-o")
+        .arg(&paths.identity)
+        .stdin(Stdio::piped())
+        .audited_spawn()
+        .context("failed to run age")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(identity.as_bytes())
+            .context("failed to send the key to age")?;
+    }
+    let status = child.wait().context("failed to wait for age")?;
+    audit::record_exit(child.id(), &status);
+    if !status.success() {
+        let _ = fs::remove_file(&paths.identity);
+        bail!("failed to protect the data dir key with a passphrase")
+    }
+
+    fs::write(&paths.recipient, format!("{recipient}\n"))
+        .with_context(|| format!("failed to write {}", paths.recipient.display()))?;
+    lock_data_dir(data_dir, &paths)
+}
+
+pub fn disable_vault(data_dir: &Path) -> Result<()> {
+    let paths = VaultPaths::for_data_dir(data_dir);
+    if !paths.exists() {
+        bail!("the data dir is not encrypted")
+    }
+
+    let mut session = unlock_data_dir(data_dir, paths.clone())?;
+    session.relock = false;
+    for path in [
+        &paths.archive,
+        &paths.identity,
+        &paths.recipient,
+        &paths.marker,
+    ] {
+        if path.exists() {
+            fs::remove_file(path)
+                .with_context(|| format!("failed to remove {}", path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+pub fn parse_age_recipient(keygen_output: &str) -> Option<String> {
+    keygen_output
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("# public key:"))
+        .map(str::trim)
+        .find(|key| key.starts_with("age1"))
+        .map(str::to_string)
+}
+
+pub(crate) fn require_age() -> Result<()> {
+    if !command_exists("age") || !command_exists("age-keygen") {
+        return Err(SignalSetupError::AgeNotInstalled.into());
+    }
+    Ok(())
+}
+
+fn unlock_data_dir(data_dir: &Path, paths: VaultPaths) -> Result<VaultSession> {
+    require_age()?;
+    if data_dir.exists() {
+        if paths.marker.exists() {
+            eprintln!(
+                "Warning: found a plaintext data dir left by an interrupted run at {}. Using it and re-encrypting afterwards.",
+                data_dir.display()
+            );
+            return Ok(VaultSession::new(data_dir, paths));
+        }
+        let empty = fs::read_dir(data_dir).is_ok_and(|mut entries| entries.next().is_none());
+        if !empty {
+            bail!(
+                "{} exists next to the encrypted {}, but was not unlocked from it. Move it away and retry; it is left untouched.",
+                data_dir.display(),
+                paths.archive.display()
+            )
+        }
+        fs::remove_dir(data_dir)
+            .with_context(|| format!("failed to remove empty data dir {}", data_dir.display()))?;
+    }
+
+    fs::create_dir_all(data_dir)
+        .with_context(|| format!("failed to create data dir {}", data_dir.display()))?;
+    println!("Unlocking the encrypted data dir...");
+    let mut decrypt = Command::new("age");
+    decrypt
+        .arg("-d")
+        .arg("-i")
+        .arg(&paths.identity)
+        .arg(&paths.archive);
+    let mut extract = Command::new("tar");
+    extract.arg("-xf").arg("-").arg("-C").arg(data_dir);
+    if let Err(err) = pipe_commands(decrypt, extract) {
+        let _ = fs::remove_dir_all(data_dir);
+        return Err(err.context("failed to unlock the encrypted data dir"));
+    }
+    fs::write(&paths.marker, "")
+        .with_context(|| format!("failed to write {}", paths.marker.display()))?;
+
+    Ok(VaultSession::new(data_dir, paths))
+}
+
+fn lock_data_dir(data_dir: &Path, paths: &VaultPaths) -> Result<()> {
+    let recipient = fs::read_to_string(&paths.recipient)
+        .with_context(|| format!("failed to read {}", paths.recipient.display()))?;
+    let mut staged = paths.archive.clone().into_os_string();
+    staged.push(".tmp");
+    let staged = PathBuf::from(staged);
+
+    let mut archive = Command::new("tar");
+    archive.arg("-cf").arg("-").arg("-C").arg(data_dir).arg(".");
+    let mut encrypt = Command::new("age");
+    encrypt
+        .arg("-e")
+        .arg("-r")
+        .arg(recipient.trim())
+        .arg("-o")
+        .arg(&staged);
+    if let Err(err) = pipe_commands(archive, encrypt) {
+        let _ = fs::remove_file(&staged);
+        return Err(err.context("failed to encrypt the data dir"));
+    }
+
+    fs::rename(&staged, &paths.archive)
+        .with_context(|| format!("failed to write {}", paths.archive.display()))?;
+    fs::remove_dir_all(data_dir)
+        .with_context(|| format!("failed to remove plaintext data dir {}", data_dir.display()))?;
+    match fs::remove_file(&paths.marker) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+            Err(err).with_context(|| format!("failed to remove {}", paths.marker.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn pipe_commands(mut producer: Command, mut consumer: Command) -> Result<()> {
+    let mut source = producer
+        .stdout(Stdio::piped())
+        .audited_spawn()
+        .context("failed to start archive pipeline")?;
+    let Some(stdout) = source.stdout.take() else {
+        bail!("failed to capture archive pipeline output")
+    };
+    let sink_status = consumer
+        .stdin(stdout)
+        .audited_status()
+        .context("failed to start archive pipeline")?;
+    let source_status = source
+        .wait()
+        .context("failed to wait for archive pipeline")?;
+    audit::record_exit(source.id(), &source_status);
+
+    if !source_status.success() || !sink_status.success() {
+        bail!("archive pipeline exited with an error")
+    }
+    Ok(())
+}
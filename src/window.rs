@@ -0,0 +1,292 @@
+use anyhow::{bail, Context, Result};
+#[cfg(not(test))]
+use dialoguer::theme::ColorfulTheme;
+use std::process::{Command, Stdio};
+
+use crate::audit::AuditedCommand;
+use crate::qr::Region;
+#[cfg(not(test))]
+use crate::ui;
+
+/// Cocoa puts the origin at the bottom left of the main display; regions use the top left.
+const CURSOR_SCRIPT: &str = "ObjC.import('AppKit'); var p = $.NSEvent.mouseLocation; var h = $.NSScreen.screens.objectAtIndex(0).frame.size.height; p.x + ',' + p.y + ',' + h";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowInfo {
+    /// CGWindowID on macOS, usable with `screencapture -l<id>`
+    pub id: u32,
+    pub app: String,
+    pub title: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub minimized: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisplayInfo {
+    pub index: usize,
+    pub id: u32,
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub primary: bool,
+}
+
+#[cfg(not(test))]
+pub fn list_displays() -> Result<Vec<DisplayInfo>> {
+    let monitors = xcap::Monitor::all().context("failed to enumerate displays")?;
+    monitors
+        .iter()
+        .enumerate()
+        .map(|(idx, monitor)| {
+            Ok(DisplayInfo {
+                index: idx + 1,
+                id: monitor.id()?,
+                name: monitor.name()?,
+                x: monitor.x()?,
+                y: monitor.y()?,
+                width: monitor.width()?,
+                height: monitor.height()?,
+                primary: monitor.is_primary()?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub fn list_displays() -> Result<Vec<DisplayInfo>> {
+    let raw = std::env::var("MOCK_DISPLAYS").context("no mock displays")?;
+    let value: serde_json::Value = serde_json::from_str(&raw).context("invalid mock displays")?;
+    let entries = value.as_array().context("mock displays must be an array")?;
+    entries
+        .iter()
+        .enumerate()
+        .map(|(idx, entry)| {
+            let int = |key: &str| entry.get(key).and_then(serde_json::Value::as_i64);
+            Ok(DisplayInfo {
+                index: idx + 1,
+                id: u32::try_from(int("id").unwrap_or(0))?,
+                name: entry
+                    .get("name")
+                    .and_then(serde_json::Value::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+                x: i32::try_from(int("x").unwrap_or(0))?,
+                y: i32::try_from(int("y").unwrap_or(0))?,
+                width: u32::try_from(int("width").unwrap_or(0))?,
+                height: u32::try_from(int("height").unwrap_or(0))?,
+                primary: entry
+                    .get("primary")
+                    .and_then(serde_json::Value::as_bool)
+                    .unwrap_or(false),
+            })
+        })
+        .collect()
+}
+
+#[cfg(not(test))]
+pub fn list_windows() -> Result<Vec<WindowInfo>> {
+    let windows = xcap::Window::all().context("failed to enumerate windows")?;
+    // A window that closes while it is being inspected is simply left out.
+    Ok(windows
+        .iter()
+        .filter_map(|window| window_info(window).ok())
+        .collect())
+}
+
+#[cfg(not(test))]
+fn window_info(window: &xcap::Window) -> Result<WindowInfo> {
+    Ok(WindowInfo {
+        id: window.id()?,
+        app: window.app_name()?,
+        title: window.title()?,
+        x: window.x()?,
+        y: window.y()?,
+        width: window.width()?,
+        height: window.height()?,
+        minimized: window.is_minimized()?,
+    })
+}
+
+#[cfg(test)]
+pub fn list_windows() -> Result<Vec<WindowInfo>> {
+    let raw = std::env::var("MOCK_WINDOWS").context("no mock windows")?;
+    let value: serde_json::Value = serde_json::from_str(&raw).context("invalid mock windows")?;
+    let entries = value.as_array().context("mock windows must be an array")?;
+    entries
+        .iter()
+        .map(|entry| {
+            let int = |key: &str| entry.get(key).and_then(serde_json::Value::as_i64);
+            let text = |key: &str| entry.get(key).and_then(serde_json::Value::as_str);
+            Ok(WindowInfo {
+                id: u32::try_from(int("id").context("mock window id")?)?,
+                app: text("app").unwrap_or_default().to_string(),
+                title: text("title").unwrap_or_default().to_string(),
+                x: i32::try_from(int("x").unwrap_or(0))?,
+                y: i32::try_from(int("y").unwrap_or(0))?,
+                width: u32::try_from(int("width").unwrap_or(0))?,
+                height: u32::try_from(int("height").unwrap_or(0))?,
+                minimized: entry
+                    .get("minimized")
+                    .and_then(serde_json::Value::as_bool)
+                    .unwrap_or(false),
+            })
+        })
+        .collect()
+}
+
+pub fn is_signal_app(app: &str) -> bool {
+    let app = app.trim().to_lowercase();
+    app == "signal" || app.starts_with("signal ")
+}
+
+/// Helper windows and status items are too small to hold the QR code.
+pub fn find_signal_window(windows: &[WindowInfo]) -> Option<&WindowInfo> {
+    windows
+        .iter()
+        .filter(|window| is_signal_app(&window.app) && !window.minimized)
+        .filter(|window| {
+            window.width >= crate::MIN_SIGNAL_WINDOW_SIDE
+                && window.height >= crate::MIN_SIGNAL_WINDOW_SIDE
+        })
+        .max_by_key(|window| u64::from(window.width) * u64::from(window.height))
+}
+
+/// Failures are shown in place of their list: a missing permission is what this is run to find.
+pub fn render_windows_report(
+    displays: &Result<Vec<DisplayInfo>>,
+    windows: &Result<Vec<WindowInfo>>,
+    screencapture_displays: Option<usize>,
+) -> String {
+    let mut lines = vec!["Displays (xcap):".to_string()];
+    match displays {
+        Ok(displays) if displays.is_empty() => lines.push("  none".to_string()),
+        Ok(displays) => {
+            for display in displays {
+                let mut line = format!(
+                    "  {}. id {} \"{}\" at {},{} size {}x{}",
+                    display.index,
+                    display.id,
+                    display.name,
+                    display.x,
+                    display.y,
+                    display.width,
+                    display.height
+                );
+                if display.primary {
+                    line.push_str(" (primary)");
+                }
+                lines.push(line);
+            }
+        }
+        Err(err) => lines.push(format!("  unavailable: {err:#}")),
+    }
+    if let Some(count) = screencapture_displays {
+        lines.push(format!("screencapture sees {count} display(s)."));
+    }
+
+    lines.push("Windows:".to_string());
+    match windows {
+        Ok(windows) if windows.is_empty() => lines.push("  none".to_string()),
+        Ok(windows) => {
+            let signal = find_signal_window(windows).map(|window| window.id);
+            for window in windows {
+                let app = if window.app.is_empty() {
+                    "?"
+                } else {
+                    window.app.as_str()
+                };
+                let mut line = format!(
+                    "  id {} {app} \"{}\" at {},{} size {}x{}",
+                    window.id, window.title, window.x, window.y, window.width, window.height
+                );
+                if window.minimized {
+                    line.push_str(" (minimized)");
+                }
+                if signal == Some(window.id) {
+                    line.push_str(" <- captured while scanning");
+                }
+                lines.push(line);
+            }
+            if signal.is_none() {
+                lines.push(
+                    "No Signal Desktop window to capture; scans capture whole displays."
+                        .to_string(),
+                );
+            }
+        }
+        Err(err) => lines.push(format!("  unavailable: {err:#}")),
+    }
+    lines.join("\n")
+}
+
+pub fn cursor_position() -> Result<(i32, i32)> {
+    let output = Command::new("osascript")
+        .args(["-l", "JavaScript", "-e", CURSOR_SCRIPT])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .audited_output()
+        .context("failed to run osascript (picking a region needs macOS)")?;
+    if !output.status.success() {
+        bail!("osascript could not read the mouse position");
+    }
+    parse_cursor_position(&String::from_utf8_lossy(&output.stdout))
+}
+
+pub fn parse_cursor_position(output: &str) -> Result<(i32, i32)> {
+    let values = output
+        .trim()
+        .split(',')
+        .map(|part| part.trim().parse::<f64>())
+        .collect::<std::result::Result<Vec<f64>, _>>()
+        .with_context(|| format!("unexpected mouse position '{}'", output.trim()))?;
+    let [x, y, height] = values.as_slice() else {
+        bail!("unexpected mouse position '{}'", output.trim())
+    };
+    Ok((x.round() as i32, (height - y).round() as i32))
+}
+
+/// Widened by `margin` so the QR code's quiet zone is not cropped.
+pub fn region_from_corners(first: (i32, i32), second: (i32, i32), margin: u32) -> Result<Region> {
+    if first.0 == second.0 || first.1 == second.1 {
+        bail!("the two corners must differ horizontally and vertically");
+    }
+    let left = i64::from(first.0.min(second.0)) - i64::from(margin);
+    let top = i64::from(first.1.min(second.1)) - i64::from(margin);
+    let width = i64::from(first.0.abs_diff(second.0)) + 2 * i64::from(margin);
+    let height = i64::from(first.1.abs_diff(second.1)) + 2 * i64::from(margin);
+    Ok(Region {
+        x: i32::try_from(left)?,
+        y: i32::try_from(top)?,
+        width: u32::try_from(width)?,
+        height: u32::try_from(height)?,
+    })
+}
+
+#[cfg(not(test))]
+pub fn pick_region(theme: &ColorfulTheme) -> Result<Region> {
+    println!("Select the part of the screen that shows Signal Desktop's QR code.");
+    if !ui::confirm(
+        theme,
+        "Point the mouse at the top-left corner of the QR code. Ready?",
+        true,
+    )? {
+        bail!("region selection cancelled");
+    }
+    let first = cursor_position()?;
+    if !ui::confirm(
+        theme,
+        "Now point it at the bottom-right corner. Ready?",
+        true,
+    )? {
+        bail!("region selection cancelled");
+    }
+    let second = cursor_position()?;
+    let region = region_from_corners(first, second, crate::REGION_PICK_MARGIN)?;
+    println!("Scanning only {} for this run.", region.spec());
+    Ok(region)
+}